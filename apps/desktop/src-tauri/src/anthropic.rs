@@ -0,0 +1,468 @@
+//! Anthropic messages API
+//!
+//! `/v1/messages` streams server-sent events: the message starts with the
+//! prompt's token count, then each content block (text or a tool call)
+//! starts, grows by deltas and stops, and a last delta carries the stop
+//! reason and the output token count.
+
+use std::collections::HashMap;
+
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::llm::{self, ChatMessage, ChatRequest, LlmError, LlmProvider, Role, Stream, Usage};
+
+pub const DEFAULT_BASE_URL: &str = "https://api.anthropic.com";
+
+const API_VERSION: &str = "2023-06-01";
+
+/// The API insists on a limit; enough for a long answer
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+#[derive(Serialize)]
+struct Body<'r> {
+    model: &'r str,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    system: String,
+    messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<serde_json::Value>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    stop_sequences: &'r [String],
+}
+
+#[derive(Serialize)]
+struct Message {
+    role: &'static str,
+    content: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Event {
+    MessageStart {
+        message: StartMessage,
+    },
+    ContentBlockStart {
+        index: usize,
+        content_block: ContentBlock,
+    },
+    ContentBlockDelta {
+        index: usize,
+        delta: BlockDelta,
+    },
+    MessageDelta {
+        delta: MessageDelta,
+        #[serde(default)]
+        usage: OutputUsage,
+    },
+    MessageStop {},
+    Error {
+        error: ApiError,
+    },
+    /// `ping`, `content_block_stop` and whatever comes later
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct StartMessage {
+    #[serde(default)]
+    usage: InputUsage,
+}
+
+#[derive(Default, Deserialize)]
+struct InputUsage {
+    #[serde(default)]
+    input_tokens: u32,
+    #[serde(default)]
+    output_tokens: u32,
+}
+
+#[derive(Default, Deserialize)]
+struct OutputUsage {
+    #[serde(default)]
+    output_tokens: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock {
+    ToolUse {
+        id: String,
+        name: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BlockDelta {
+    TextDelta {
+        text: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct MessageDelta {
+    stop_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ApiError {
+    #[serde(default, rename = "type")]
+    kind: String,
+    #[serde(default)]
+    message: String,
+}
+
+pub struct AnthropicClient {
+    base_url: String,
+    api_key: String,
+    http: reqwest::Client,
+}
+
+impl AnthropicClient {
+    pub fn new(base_url: &str, api_key: String) -> Result<Self, LlmError> {
+        Ok(Self {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            api_key,
+            http: llm::http_client()?,
+        })
+    }
+
+    pub async fn chat(
+        &self,
+        request: &ChatRequest,
+        stream: &mut Stream<'_>,
+    ) -> Result<(), LlmError> {
+        let options = &request.options;
+        let system = request
+            .messages
+            .iter()
+            .filter(|message| message.role == Role::System)
+            .map(|message| message.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let body = Body {
+            model: &request.model,
+            max_tokens: options.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            system,
+            messages: messages(&request.messages),
+            tools: request
+                .tools
+                .iter()
+                .map(|tool| {
+                    json!({
+                        "name": tool.name,
+                        "description": tool.description,
+                        "input_schema": tool.parameters,
+                    })
+                })
+                .collect(),
+            stream: true,
+            temperature: options.temperature,
+            top_p: options.top_p,
+            stop_sequences: &options.stop,
+        };
+        let http = self
+            .http
+            .post(format!("{}/v1/messages", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", API_VERSION)
+            .json(&body);
+        let response = llm::send(http, &self.base_url, &request.model).await?;
+
+        let mut usage = Usage::default();
+        let mut stop_reason = None;
+        let mut done = false;
+        // Content block indices to tool call indices
+        let mut tools = HashMap::new();
+        llm::read_events(response, &self.base_url, |data| {
+            let event: Event = serde_json::from_str(&data).map_err(|e| LlmError::Provider {
+                message: format!("unreadable answer: {}", e),
+            })?;
+            let listening = match event {
+                Event::MessageStart { message } => {
+                    usage.prompt_tokens = message.usage.input_tokens;
+                    usage.completion_tokens = message.usage.output_tokens;
+                    true
+                }
+                Event::ContentBlockStart {
+                    index,
+                    content_block: ContentBlock::ToolUse { id, name },
+                } => {
+                    let call = tools.len();
+                    tools.insert(index, call);
+                    stream.tool_call(call, Some(&id), Some(&name), "")?
+                }
+                Event::ContentBlockStart { .. } => true,
+                Event::ContentBlockDelta { index, delta } => match delta {
+                    BlockDelta::TextDelta { text } => stream.token(&text),
+                    BlockDelta::InputJsonDelta { partial_json } => match tools.get(&index) {
+                        Some(&call) => stream.tool_call(call, None, None, &partial_json)?,
+                        None => true,
+                    },
+                    BlockDelta::Other => true,
+                },
+                Event::MessageDelta {
+                    delta,
+                    usage: output,
+                } => {
+                    if delta.stop_reason.is_some() {
+                        stop_reason = delta.stop_reason;
+                    }
+                    usage.completion_tokens = output.output_tokens;
+                    true
+                }
+                Event::MessageStop {} => {
+                    done = true;
+                    return Ok(false);
+                }
+                Event::Error { error } => {
+                    return Err(LlmError::classify(None, &error.kind, error.message, None));
+                }
+                Event::Other => true,
+            };
+            if !listening {
+                stream.completion.cancelled = true;
+            }
+            Ok(listening)
+        })
+        .await?;
+
+        if stream.completion.cancelled {
+            return Ok(());
+        }
+        if !done {
+            return Err(llm::cut_short());
+        }
+        stream.done(usage, stop_reason);
+        Ok(())
+    }
+}
+
+impl LlmProvider for AnthropicClient {
+    fn id(&self) -> String {
+        match self.base_url.as_str() {
+            DEFAULT_BASE_URL => "anthropic".to_owned(),
+            base_url => format!("anthropic:{}", base_url),
+        }
+    }
+
+    fn chat<'a>(
+        &'a self,
+        request: &'a ChatRequest,
+        stream: &'a mut Stream<'_>,
+    ) -> BoxFuture<'a, Result<(), LlmError>> {
+        Box::pin(AnthropicClient::chat(self, request, stream))
+    }
+}
+
+/// `messages` as the API expects them: system prompts go separately, tool
+/// results are user content and turns of the same role are merged
+fn messages(messages: &[ChatMessage]) -> Vec<Message> {
+    let mut merged: Vec<Message> = Vec::new();
+    for message in messages {
+        let mut content = Vec::new();
+        let role = match message.role {
+            Role::System => continue,
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::Tool => {
+                content.push(json!({
+                    "type": "tool_result",
+                    "tool_use_id": message.tool_call_id,
+                    "content": message.content,
+                }));
+                "user"
+            }
+        };
+        if message.role != Role::Tool && !message.content.is_empty() {
+            content.push(json!({ "type": "text", "text": message.content }));
+        }
+        for (i, call) in message.tool_calls.iter().enumerate() {
+            let input: serde_json::Value =
+                serde_json::from_str(&call.arguments).unwrap_or_else(|_| json!({}));
+            content.push(json!({
+                "type": "tool_use",
+                "id": call.id.clone().unwrap_or_else(|| format!("toolu_{}", i)),
+                "name": call.name,
+                "input": input,
+            }));
+        }
+        match merged.last_mut() {
+            Some(last) if last.role == role => last.content.extend(content),
+            _ if content.is_empty() => {}
+            _ => merged.push(Message { role, content }),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::test_server::{ok, serve, status};
+    use crate::llm::{Completion, GenerateOptions, ToolCall};
+
+    fn user(content: &str) -> ChatMessage {
+        ChatMessage {
+            role: Role::User,
+            content: content.into(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+
+    fn chat(url: &str) -> (Result<(), LlmError>, Completion) {
+        let client = AnthropicClient::new(url, "key".into()).unwrap();
+        let request = ChatRequest {
+            model: "claude".into(),
+            messages: vec![user("read a.rs")],
+            tools: Vec::new(),
+            options: GenerateOptions::default(),
+        };
+        let mut completion = Completion::new("r1", "claude");
+        let result = tauri::async_runtime::block_on(async {
+            let mut stream = Stream::new(&mut completion, None);
+            client.chat(&request, &mut stream).await
+        });
+        (result, completion)
+    }
+
+    #[test]
+    fn tool_use_blocks_become_tool_calls() {
+        let url = serve(vec![ok(&[
+            "event: message_start\n",
+            r#"data: {"type":"message_start","message":{"usage":{"input_tokens":20,"output_tokens":1}}}"#,
+            "\n\n",
+            r#"data: {"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            "\n\n",
+            r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Reading"}}"#,
+            "\n\ndata: {\"type\":\"ping\"}\n\n",
+            r#"data: {"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_a","name":"read_file","input":{}}}"#,
+            "\n\n",
+            r#"data: {"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"pa"}}"#,
+            "\n\n",
+            r#"data: {"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"th\":\"a.rs\"}"}}"#,
+            "\n\n",
+            r#"data: {"type":"content_block_stop","index":1}"#,
+            "\n\n",
+            r#"data: {"type":"message_delta","delta":{"stop_reason":"tool_use"},"usage":{"output_tokens":15}}"#,
+            "\n\n",
+            "data: {\"type\":\"message_stop\"}\n\n",
+        ])]);
+        let (result, completion) = chat(&url);
+        result.unwrap();
+        assert_eq!(completion.text, "Reading");
+        assert_eq!(completion.tool_calls.len(), 1);
+        let call = &completion.tool_calls[0];
+        assert_eq!(call.id.as_deref(), Some("toolu_a"));
+        assert_eq!(call.name, "read_file");
+        assert_eq!(call.arguments, r#"{"path":"a.rs"}"#);
+        assert_eq!(completion.usage.prompt_tokens, 20);
+        assert_eq!(completion.usage.completion_tokens, 15);
+        assert_eq!(completion.finish_reason.as_deref(), Some("tool_use"));
+    }
+
+    #[test]
+    fn a_stream_without_message_stop_is_cut_short() {
+        let url = serve(vec![ok(&[
+            r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hel"}}"#,
+            "\n\n",
+        ])]);
+        let (result, completion) = chat(&url);
+        assert!(matches!(result, Err(LlmError::Provider { .. })));
+        assert_eq!(completion.text, "Hel");
+    }
+
+    #[test]
+    fn errors_map_to_their_kind() {
+        let url = serve(vec![
+            ok(&[
+                r#"data: {"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#,
+                "\n\n",
+            ]),
+            status(
+                401,
+                &[
+                    r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#,
+                ],
+            ),
+            status(
+                400,
+                &[
+                    r#"{"type":"error","error":{"type":"invalid_request_error","message":"prompt is too long: 210000 tokens > 200000 maximum"}}"#,
+                ],
+            ),
+            status(
+                404,
+                &[
+                    r#"{"type":"error","error":{"type":"not_found_error","message":"model: claude not found"}}"#,
+                ],
+            ),
+        ]);
+        assert!(matches!(
+            chat(&url).0,
+            Err(LlmError::RateLimited {
+                retry_after_ms: None,
+                ..
+            })
+        ));
+        assert!(matches!(chat(&url).0, Err(LlmError::Unauthorized { .. })));
+        assert!(matches!(
+            chat(&url).0,
+            Err(LlmError::ContextOverflow { .. })
+        ));
+        assert!(matches!(
+            chat(&url).0,
+            Err(LlmError::ModelNotFound { model }) if model == "claude"
+        ));
+    }
+
+    #[test]
+    fn messages_merge_turns_and_move_tool_results() {
+        let system = ChatMessage {
+            role: Role::System,
+            ..user("be brief")
+        };
+        let assistant = ChatMessage {
+            role: Role::Assistant,
+            content: "Reading".into(),
+            tool_calls: vec![ToolCall {
+                id: Some("toolu_a".into()),
+                name: "read_file".into(),
+                arguments: r#"{"path":"a.rs"}"#.into(),
+            }],
+            tool_call_id: None,
+        };
+        let result = ChatMessage {
+            role: Role::Tool,
+            content: "fn main() {}".into(),
+            tool_calls: Vec::new(),
+            tool_call_id: Some("toolu_a".into()),
+        };
+        let merged = messages(&[system, user("read a.rs"), assistant, result, user("thanks")]);
+
+        let roles: Vec<_> = merged.iter().map(|m| m.role).collect();
+        assert_eq!(roles, ["user", "assistant", "user"]);
+        assert_eq!(merged[1].content[1]["input"]["path"], "a.rs");
+        assert_eq!(merged[2].content[0]["type"], "tool_result");
+        assert_eq!(merged[2].content[0]["tool_use_id"], "toolu_a");
+        assert_eq!(merged[2].content[1]["text"], "thanks");
+    }
+}
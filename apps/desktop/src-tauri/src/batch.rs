@@ -0,0 +1,550 @@
+//! All-or-nothing application of multi-file agent plans
+//!
+//! `apply_edit_batch` validates every operation and stages new content in
+//! temp files before touching anything. Commits then run in order; if one
+//! fails, the ones already done are rolled back so the repo is never left
+//! half-edited.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, State};
+
+use crate::diff::{self, DiffAlgorithm, FileDiff};
+use crate::fs_edit::{self, FileEditError, Precondition, StagedWrite};
+use crate::journal::{FileChange, Journal};
+use crate::permissions::{self, GrantScope, GrantToken, PermissionBroker};
+use crate::workspace::Workspace;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum EditOp {
+    Create {
+        path: String,
+        content: String,
+    },
+    Edit {
+        path: String,
+        content: String,
+        expected_hash: Option<String>,
+    },
+    Delete {
+        path: String,
+        expected_hash: Option<String>,
+    },
+    Rename {
+        from: String,
+        to: String,
+        expected_hash: Option<String>,
+    },
+}
+
+impl EditOp {
+    fn path(&self) -> &str {
+        match self {
+            Self::Create { path, .. } | Self::Edit { path, .. } | Self::Delete { path, .. } => path,
+            Self::Rename { from, .. } => from,
+        }
+    }
+}
+
+/// Identifies one exact list of operations for the permission broker
+fn digest(ops: &[EditOp]) -> String {
+    let json = serde_json::to_vec(ops).expect("edit ops serialize");
+    format!("{:x}", Sha256::digest(json))
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum OpStatus {
+    Applied,
+    /// Committed, then reverted because a later operation failed
+    RolledBack,
+    /// Committed, but reverting it failed; the file needs attention
+    RollbackFailed {
+        error: FileEditError,
+    },
+    Failed {
+        error: FileEditError,
+    },
+    /// Skipped because another operation failed first
+    NotRun,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpResult {
+    pub path: String,
+    #[serde(flatten)]
+    pub status: OpStatus,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchResult {
+    /// Whether every operation landed
+    pub applied: bool,
+    /// Journal transaction holding the batch, if it was applied
+    pub transaction_id: Option<String>,
+    pub results: Vec<OpResult>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchPermission {
+    /// Present only if the user approved; pass it to `apply_edit_batch`
+    pub grant: Option<GrantToken>,
+    /// Diffs for created and edited files, in operation order
+    previews: Vec<FileDiff>,
+}
+
+/// An operation that passed validation and is ready to commit
+enum Planned {
+    Write(StagedWrite),
+    Delete {
+        target: PathBuf,
+        previous: Vec<u8>,
+    },
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+        bytes: Vec<u8>,
+    },
+}
+
+/// How to revert a committed operation
+enum Undo {
+    Restore {
+        target: PathBuf,
+        previous: Option<Vec<u8>>,
+    },
+    Unlink {
+        target: PathBuf,
+        backup: PathBuf,
+    },
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+    },
+}
+
+/// Ask the user to approve a batch in one native dialog
+/// The grant covers exactly this list of operations.
+#[tauri::command]
+pub async fn request_batch_permission(
+    app: AppHandle,
+    workspace: State<'_, Workspace>,
+    broker: State<'_, PermissionBroker>,
+    ops: Vec<EditOp>,
+) -> Result<BatchPermission, FileEditError> {
+    request_permission(&app, &workspace, &broker, &ops).await
+}
+
+/// Show the approval dialog for `ops` and issue a grant if approved
+pub async fn request_permission(
+    app: &AppHandle,
+    workspace: &Workspace,
+    broker: &PermissionBroker,
+    ops: &[EditOp],
+) -> Result<BatchPermission, FileEditError> {
+    let mut summary = Vec::new();
+    let mut previews = Vec::new();
+    for op in ops {
+        match op {
+            EditOp::Create { path, content } | EditOp::Edit { path, content, .. } => {
+                let target = workspace.resolve(Path::new(path))?;
+                let current = fs_edit::read_text_or_empty(&target)?;
+                let preview = diff::compute(
+                    path,
+                    &current,
+                    content,
+                    diff::DEFAULT_CONTEXT,
+                    DiffAlgorithm::default(),
+                );
+                let changes = preview.line_changes;
+                let verb = if matches!(op, EditOp::Create { .. }) {
+                    "create"
+                } else {
+                    "edit"
+                };
+                summary.push(format!(
+                    "{verb} {path} (+{} −{} ~{})",
+                    changes.added, changes.removed, changes.modified
+                ));
+                previews.push(preview);
+            }
+            EditOp::Delete { path, .. } => {
+                workspace.resolve(Path::new(path))?;
+                summary.push(format!("delete {path}"));
+            }
+            EditOp::Rename { from, to, .. } => {
+                workspace.resolve(Path::new(from))?;
+                workspace.resolve(Path::new(to))?;
+                summary.push(format!("rename {from} → {to}"));
+            }
+        }
+    }
+
+    let approved = permissions::confirm(
+        app,
+        "Apply agent plan",
+        format!(
+            "Apply {} file operations?\n\n{}",
+            ops.len(),
+            summary.join("\n")
+        ),
+        "Apply all",
+    )
+    .await;
+    let grant = approved.then(|| {
+        broker.issue(GrantScope::Batch {
+            digest: digest(ops),
+        })
+    });
+    Ok(BatchPermission { grant, previews })
+}
+
+/// Apply a list of create/edit/delete/rename operations atomically
+/// Either every operation lands, or none does. The grant must come from
+/// `request_batch_permission` for exactly these operations.
+#[tauri::command]
+pub async fn apply_edit_batch(
+    workspace: State<'_, Workspace>,
+    broker: State<'_, PermissionBroker>,
+    journal: State<'_, Journal>,
+    ops: Vec<EditOp>,
+    grant_token: String,
+    transaction_id: Option<String>,
+) -> Result<BatchResult, FileEditError> {
+    apply_granted(
+        &workspace,
+        &broker,
+        &journal,
+        &ops,
+        &grant_token,
+        transaction_id.as_deref(),
+    )
+}
+
+/// Redeem a grant from [`request_permission`] for exactly `ops`, then apply
+/// them
+pub fn apply_granted(
+    workspace: &Workspace,
+    broker: &PermissionBroker,
+    journal: &Journal,
+    ops: &[EditOp],
+    grant_token: &str,
+    transaction_id: Option<&str>,
+) -> Result<BatchResult, FileEditError> {
+    broker
+        .redeem(
+            grant_token,
+            &GrantScope::Batch {
+                digest: digest(ops),
+            },
+        )
+        .map_err(|reason| FileEditError::NotApproved {
+            path: format!("{} files", ops.len()),
+            reason,
+        })?;
+    apply(workspace, journal, ops, transaction_id)
+}
+
+/// Apply `ops` all-or-nothing, journaled under `transaction_id` or a new
+/// transaction, without checking for a grant
+fn apply(
+    workspace: &Workspace,
+    journal: &Journal,
+    ops: &[EditOp],
+    transaction_id: Option<&str>,
+) -> Result<BatchResult, FileEditError> {
+    let mut created_dirs = Vec::new();
+    let planned = plan(workspace, ops, &mut created_dirs);
+    if planned.iter().any(Result::is_err) {
+        // Dropping the staged writes deletes their temp files
+        let results = ops
+            .iter()
+            .zip(planned)
+            .map(|(op, plan)| OpResult {
+                path: op.path().to_string(),
+                status: match plan {
+                    Ok(_) => OpStatus::NotRun,
+                    Err(error) => OpStatus::Failed { error },
+                },
+            })
+            .collect();
+        remove_dirs(&created_dirs);
+        return Ok(BatchResult {
+            applied: false,
+            transaction_id: None,
+            results,
+        });
+    }
+    let planned: Vec<Planned> = planned.into_iter().map(Result::unwrap).collect();
+
+    let changes: Vec<FileChange<'_>> = planned.iter().flat_map(journal_changes).collect();
+    let (tx_id, first_entry) = journal.record_all(
+        transaction_id,
+        &format!("Apply {} file operations", ops.len()),
+        &changes,
+    )?;
+
+    let mut done = Vec::new();
+    let mut failure = None;
+    for (index, step) in planned.into_iter().enumerate() {
+        match commit(step) {
+            Ok(undo) => done.push(undo),
+            Err(error) => {
+                failure = Some((index, error));
+                break;
+            }
+        }
+    }
+
+    let Some((failed_at, error)) = failure else {
+        for undo in &done {
+            if let Undo::Unlink { backup, .. } = undo {
+                let _ = fs::remove_file(backup);
+            }
+        }
+        let results = ops
+            .iter()
+            .map(|op| OpResult {
+                path: op.path().to_string(),
+                status: OpStatus::Applied,
+            })
+            .collect();
+        return Ok(BatchResult {
+            applied: true,
+            transaction_id: Some(tx_id),
+            results,
+        });
+    };
+
+    let mut statuses: Vec<OpStatus> = done
+        .into_iter()
+        .rev()
+        .map(|undo| match rollback(undo) {
+            Ok(()) => OpStatus::RolledBack,
+            Err(error) => OpStatus::RollbackFailed { error },
+        })
+        .collect();
+    statuses.reverse();
+    statuses.push(OpStatus::Failed { error });
+    remove_dirs(&created_dirs);
+    journal.discard(&tx_id, first_entry)?;
+
+    let results = ops
+        .iter()
+        .enumerate()
+        .map(|(index, op)| OpResult {
+            path: op.path().to_string(),
+            status: if index <= failed_at {
+                std::mem::replace(&mut statuses[index], OpStatus::NotRun)
+            } else {
+                OpStatus::NotRun
+            },
+        })
+        .collect();
+    Ok(BatchResult {
+        applied: false,
+        transaction_id: None,
+        results,
+    })
+}
+
+/// Resolve, check and stage every operation without committing any
+fn plan(
+    workspace: &Workspace,
+    ops: &[EditOp],
+    created_dirs: &mut Vec<PathBuf>,
+) -> Vec<Result<Planned, FileEditError>> {
+    let mut touched: Vec<PathBuf> = Vec::new();
+    ops.iter()
+        .map(|op| {
+            let mut claim = |path: &str| -> Result<PathBuf, FileEditError> {
+                let target = workspace.resolve(Path::new(path))?;
+                if touched.contains(&target) {
+                    return Err(FileEditError::InvalidOperation {
+                        path: path.to_string(),
+                        message: "touched by more than one operation in the batch".into(),
+                    });
+                }
+                touched.push(target.clone());
+                Ok(target)
+            };
+            match op {
+                EditOp::Create { path, content } => {
+                    let target = claim(path)?;
+                    create_parents(&target, created_dirs)?;
+                    let write = fs_edit::prepare(&target, content, &Precondition::content(None))?;
+                    Ok(Planned::Write(write.stage()?))
+                }
+                EditOp::Edit {
+                    path,
+                    content,
+                    expected_hash,
+                } => {
+                    let target = claim(path)?;
+                    let expected = Precondition {
+                        hash: expected_hash.clone(),
+                        ..Precondition::default()
+                    };
+                    let write = fs_edit::prepare(&target, content, &expected)?;
+                    if write.previous.is_none() {
+                        return Err(FileEditError::PathMissing {
+                            path: path.to_string(),
+                        });
+                    }
+                    Ok(Planned::Write(write.stage()?))
+                }
+                EditOp::Delete {
+                    path,
+                    expected_hash,
+                } => {
+                    let target = claim(path)?;
+                    let previous = read_expected(&target, expected_hash)?;
+                    Ok(Planned::Delete { target, previous })
+                }
+                EditOp::Rename {
+                    from,
+                    to,
+                    expected_hash,
+                } => {
+                    let source = claim(from)?;
+                    let dest = claim(to)?;
+                    let bytes = read_expected(&source, expected_hash)?;
+                    if dest.exists() {
+                        return Err(FileEditError::Conflict {
+                            path: to.to_string(),
+                            current_hash: fs::read(&dest).ok().map(|b| fs_edit::content_hash(&b)),
+                            current_mtime_ms: None,
+                        });
+                    }
+                    create_parents(&dest, created_dirs)?;
+                    Ok(Planned::Rename {
+                        from: source,
+                        to: dest,
+                        bytes,
+                    })
+                }
+            }
+        })
+        .collect()
+}
+
+/// Read an existing file, checking it still has `expected_hash` if given
+fn read_expected(path: &Path, expected_hash: &Option<String>) -> Result<Vec<u8>, FileEditError> {
+    let bytes = fs::read(path).map_err(|e| FileEditError::from_io(path, e))?;
+    let hash = fs_edit::content_hash(&bytes);
+    match expected_hash {
+        Some(expected) if !expected.eq_ignore_ascii_case(&hash) => Err(FileEditError::Conflict {
+            path: path.display().to_string(),
+            current_hash: Some(hash),
+            current_mtime_ms: None,
+        }),
+        _ => Ok(bytes),
+    }
+}
+
+fn journal_changes(step: &Planned) -> Vec<FileChange<'_>> {
+    match step {
+        Planned::Write(staged) => vec![FileChange {
+            path: staged.target(),
+            before: staged.previous.as_deref(),
+            after: Some(&staged.bytes),
+        }],
+        Planned::Delete { target, previous } => vec![FileChange {
+            path: target,
+            before: Some(previous),
+            after: None,
+        }],
+        Planned::Rename { from, to, bytes } => vec![
+            FileChange {
+                path: from,
+                before: Some(bytes),
+                after: None,
+            },
+            FileChange {
+                path: to,
+                before: None,
+                after: Some(bytes),
+            },
+        ],
+    }
+}
+
+fn commit(step: Planned) -> Result<Undo, FileEditError> {
+    match step {
+        Planned::Write(staged) => {
+            let target = staged.target().to_path_buf();
+            let previous = staged.previous.clone();
+            staged.commit()?;
+            Ok(Undo::Restore { target, previous })
+        }
+        Planned::Delete { target, .. } => {
+            // Move aside rather than unlink, so rollback keeps metadata
+            let backup = target.with_file_name(format!(
+                ".henry-delete-{}.bak",
+                uuid::Uuid::new_v4().simple()
+            ));
+            fs::rename(&target, &backup).map_err(|e| FileEditError::from_io(&target, e))?;
+            fs_edit::sync_dir(parent(&target));
+            Ok(Undo::Unlink { target, backup })
+        }
+        Planned::Rename { from, to, .. } => {
+            fs::rename(&from, &to).map_err(|e| FileEditError::from_io(&from, e))?;
+            fs_edit::sync_dir(parent(&from));
+            fs_edit::sync_dir(parent(&to));
+            Ok(Undo::Rename { from, to })
+        }
+    }
+}
+
+fn rollback(undo: Undo) -> Result<(), FileEditError> {
+    match undo {
+        Undo::Restore {
+            target,
+            previous: Some(bytes),
+        } => fs_edit::prepare_bytes(&target, bytes, &Precondition::default())?.commit(),
+        Undo::Restore {
+            target,
+            previous: None,
+        } => fs::remove_file(&target).map_err(|e| FileEditError::from_io(&target, e)),
+        Undo::Unlink { target, backup } => {
+            fs::rename(&backup, &target).map_err(|e| FileEditError::from_io(&target, e))
+        }
+        Undo::Rename { from, to } => {
+            fs::rename(&to, &from).map_err(|e| FileEditError::from_io(&from, e))
+        }
+    }
+}
+
+/// Create missing parent directories of `path`, remembering which ones
+fn create_parents(path: &Path, created: &mut Vec<PathBuf>) -> Result<(), FileEditError> {
+    let mut missing = Vec::new();
+    let mut dir = parent(path);
+    while !dir.exists() {
+        missing.push(dir.to_path_buf());
+        dir = parent(dir);
+    }
+    for dir in missing.into_iter().rev() {
+        fs::create_dir(&dir).map_err(|e| FileEditError::from_io(&dir, e))?;
+        created.push(dir);
+    }
+    Ok(())
+}
+
+/// Remove directories created for a batch that did not land, deepest first
+fn remove_dirs(created: &[PathBuf]) {
+    for dir in created.iter().rev() {
+        // Fails harmlessly if something else has put files there meanwhile
+        let _ = fs::remove_dir(dir);
+    }
+}
+
+fn parent(path: &Path) -> &Path {
+    path.parent().unwrap_or(Path::new("/"))
+}
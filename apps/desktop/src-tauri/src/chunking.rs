@@ -0,0 +1,364 @@
+//! Symbol-aware chunking of source files for embedding
+//!
+//! Declarations are the natural unit: a function or class that fits in a
+//! chunk becomes one chunk. Containers too large for one are split along
+//! their members, and anything else too large is cut into overlapping
+//! windows. Short neighbouring pieces (small functions, fields, imports
+//! and other code between declarations) are packed together, so chunks
+//! are not mostly boilerplate. Files without symbols are just windowed.
+
+use crate::symbols::{Symbol, SymbolKind};
+
+pub const MAX_CHUNK_LINES: usize = 60;
+
+/// Lines repeated between consecutive windows of one declaration
+const OVERLAP_LINES: usize = 8;
+
+/// Declarations at least this long get a chunk of their own
+const STANDALONE_LINES: usize = 15;
+
+/// Longest chunk text in bytes; minified lines can be huge
+const MAX_CHUNK_BYTES: usize = 6000;
+
+/// Code between declarations shorter than this, in non-blank lines, is
+/// dropped unless packed with a declaration (stray braces, `impl` headers)
+const MIN_GAP_LINES: usize = 3;
+
+/// Packed chunks list at most this many of their declarations by name
+const MAX_PACKED_NAMES: usize = 3;
+
+/// Starts of lines that belong to the declaration below them: comments,
+/// attributes and decorators
+const PREAMBLE_PREFIXES: [&str; 5] = ["//", "/*", "*", "#", "@"];
+
+#[derive(Debug, Clone)]
+pub struct TextChunk {
+    /// Qualified with its containers, e.g. `Parser.parse`
+    pub symbol: Option<String>,
+    pub kind: Option<SymbolKind>,
+    /// 1-based, inclusive
+    pub start_line: usize,
+    pub end_line: usize,
+    pub text: String,
+}
+
+/// Split `text` into chunks along `symbols`, its outline
+pub fn chunk(text: &str, symbols: &[Symbol]) -> Vec<TextChunk> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut chunker = Chunker {
+        lines: &lines,
+        chunks: Vec::new(),
+    };
+    if !lines.is_empty() {
+        chunker.cover(symbols, 1, lines.len(), None);
+        if chunker.chunks.is_empty() {
+            // Too short to split; keep it whole
+            chunker.windows(1, lines.len(), None);
+        }
+    }
+    chunker.chunks
+}
+
+#[derive(Clone)]
+struct Label {
+    name: String,
+    /// `None` for several declarations packed together
+    kind: Option<SymbolKind>,
+}
+
+impl Label {
+    fn new(parent: Option<&Label>, symbol: &Symbol) -> Self {
+        let name = match parent {
+            Some(parent) => format!("{}.{}", parent.name, symbol.name),
+            None => symbol.name.clone(),
+        };
+        Self {
+            name,
+            kind: Some(symbol.kind),
+        }
+    }
+}
+
+/// A run of lines, either one declaration or the code between two
+struct Piece<'s> {
+    start: usize,
+    end: usize,
+    symbol: Option<&'s Symbol>,
+}
+
+/// Short pieces waiting to be packed into one chunk
+struct Pending {
+    start: usize,
+    end: usize,
+    symbols: Vec<Label>,
+}
+
+struct Chunker<'t> {
+    lines: &'t [&'t str],
+    chunks: Vec<TextChunk>,
+}
+
+impl Chunker<'_> {
+    /// Chunk lines `start..=end`, which hold `symbols`
+    fn cover(&mut self, symbols: &[Symbol], start: usize, end: usize, parent: Option<&Label>) {
+        let mut sorted: Vec<&Symbol> = symbols.iter().collect();
+        sorted.sort_by_key(|s| s.range.start.line);
+        let mut pieces = Vec::new();
+        let mut cursor = start;
+        for symbol in sorted {
+            let mut from = symbol.range.start.line.max(cursor);
+            while from > cursor && self.is_preamble(from - 1) {
+                from -= 1;
+            }
+            let to = symbol.range.end.line.min(end);
+            if from > to {
+                continue;
+            }
+            if from > cursor {
+                pieces.push(Piece {
+                    start: cursor,
+                    end: from - 1,
+                    symbol: None,
+                });
+            }
+            pieces.push(Piece {
+                start: from,
+                end: to,
+                symbol: Some(symbol),
+            });
+            cursor = to + 1;
+        }
+        if cursor <= end {
+            pieces.push(Piece {
+                start: cursor,
+                end,
+                symbol: None,
+            });
+        }
+
+        let mut pending: Option<Pending> = None;
+        for piece in pieces {
+            let len = piece.end + 1 - piece.start;
+            match piece.symbol {
+                Some(symbol) if len >= STANDALONE_LINES => {
+                    self.flush(pending.take(), parent);
+                    let label = Label::new(parent, symbol);
+                    if len > MAX_CHUNK_LINES
+                        && symbol.kind.is_container()
+                        && !symbol.children.is_empty()
+                    {
+                        self.cover(&symbol.children, piece.start, piece.end, Some(&label));
+                    } else {
+                        self.windows(piece.start, piece.end, Some(&label));
+                    }
+                }
+                _ => {
+                    if pending
+                        .as_ref()
+                        .is_some_and(|p| piece.end + 1 - p.start > MAX_CHUNK_LINES)
+                    {
+                        self.flush(pending.take(), parent);
+                    }
+                    let packed = pending.get_or_insert(Pending {
+                        start: piece.start,
+                        end: piece.end,
+                        symbols: Vec::new(),
+                    });
+                    packed.end = piece.end;
+                    if let Some(symbol) = piece.symbol {
+                        packed.symbols.push(Label::new(parent, symbol));
+                    }
+                }
+            }
+        }
+        self.flush(pending, parent);
+    }
+
+    /// Emit packed pieces, named after their only declaration, else after
+    /// the container they are in, else after the declarations they hold
+    fn flush(&mut self, pending: Option<Pending>, parent: Option<&Label>) {
+        let Some(mut pending) = pending else {
+            return;
+        };
+        let label = match pending.symbols.len() {
+            0 => {
+                let code = (pending.start..=pending.end)
+                    .filter(|&line| !self.lines[line - 1].trim().is_empty())
+                    .count();
+                if code < MIN_GAP_LINES {
+                    return;
+                }
+                parent.cloned()
+            }
+            1 => pending.symbols.pop(),
+            _ => parent.cloned().or_else(|| {
+                let mut names: Vec<&str> = pending
+                    .symbols
+                    .iter()
+                    .take(MAX_PACKED_NAMES)
+                    .map(|label| label.name.as_str())
+                    .collect();
+                if pending.symbols.len() > MAX_PACKED_NAMES {
+                    names.push("…");
+                }
+                Some(Label {
+                    name: names.join(", "),
+                    kind: None,
+                })
+            }),
+        };
+        self.windows(pending.start, pending.end, label.as_ref());
+    }
+
+    fn is_preamble(&self, line: usize) -> bool {
+        let text = self.lines[line - 1].trim_start();
+        PREAMBLE_PREFIXES
+            .iter()
+            .any(|prefix| text.starts_with(prefix))
+    }
+
+    /// Emit lines `start..=end` in overlapping windows of at most
+    /// `MAX_CHUNK_LINES`
+    fn windows(&mut self, start: usize, end: usize, label: Option<&Label>) {
+        let mut from = start;
+        loop {
+            let to = (from + MAX_CHUNK_LINES - 1).min(end);
+            self.push(from, to, label);
+            if to == end {
+                break;
+            }
+            from = to + 1 - OVERLAP_LINES;
+        }
+    }
+
+    fn push(&mut self, start: usize, end: usize, label: Option<&Label>) {
+        let blank = |line: &usize| self.lines[line - 1].trim().is_empty();
+        let Some(start) = (start..=end).find(|line| !blank(line)) else {
+            return;
+        };
+        let end = (start..=end)
+            .rev()
+            .find(|line| !blank(line))
+            .unwrap_or(start);
+        let mut text = self.lines[start - 1..end].join("\n");
+        if text.len() > MAX_CHUNK_BYTES {
+            let mut cut = MAX_CHUNK_BYTES;
+            while !text.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            text.truncate(cut);
+        }
+        self.chunks.push(TextChunk {
+            symbol: label.map(|l| l.name.clone()),
+            kind: label.and_then(|l| l.kind),
+            start_line: start,
+            end_line: end,
+            text,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbols;
+
+    /// `(symbol, start_line, end_line)` of each chunk
+    fn spans(chunks: &[TextChunk]) -> Vec<(Option<&str>, usize, usize)> {
+        chunks
+            .iter()
+            .map(|c| (c.symbol.as_deref(), c.start_line, c.end_line))
+            .collect()
+    }
+
+    /// A Rust function `name` spanning `lines` lines
+    fn function(name: &str, lines: usize) -> String {
+        let mut text = format!("fn {name}() {{\n");
+        for i in 0..lines - 2 {
+            text.push_str(&format!("    let v{i} = {i};\n"));
+        }
+        text.push_str("}\n");
+        text
+    }
+
+    fn rust(text: &str) -> Vec<TextChunk> {
+        chunk(text, &symbols::extract("rust", text))
+    }
+
+    #[test]
+    fn text_without_symbols_is_windowed() {
+        assert!(chunk("", &[]).is_empty());
+
+        let short = chunk("\n\nhello\nworld\n\n", &[]);
+        assert_eq!(spans(&short), [(None, 3, 4)]);
+        assert_eq!(short[0].text, "hello\nworld");
+
+        let text: String = (1..=150).map(|i| format!("line {i}\n")).collect();
+        assert_eq!(
+            spans(&chunk(&text, &[])),
+            [(None, 1, 60), (None, 53, 112), (None, 105, 150)]
+        );
+    }
+
+    #[test]
+    fn long_declarations_stand_alone_with_their_docs() {
+        let text = format!(
+            "/// Loads\n#[inline]\n{}\nfn small() {{}}\nfn tiny() {{}}\n",
+            function("load", STANDALONE_LINES)
+        );
+        let chunks = rust(&text);
+        assert_eq!(
+            spans(&chunks),
+            [
+                (Some("load"), 1, 2 + STANDALONE_LINES),
+                (
+                    Some("small, tiny"),
+                    4 + STANDALONE_LINES,
+                    5 + STANDALONE_LINES
+                ),
+            ]
+        );
+        assert!(chunks[0]
+            .text
+            .starts_with("/// Loads\n#[inline]\nfn load()"));
+        assert_eq!(chunks[0].kind, Some(SymbolKind::Function));
+        assert_eq!(chunks[1].kind, None);
+    }
+
+    #[test]
+    fn packed_names_are_capped() {
+        let text = "fn a() {}\nfn b() {}\nfn c() {}\nfn d() {}\n";
+        assert_eq!(spans(&rust(text)), [(Some("a, b, c, …"), 1, 4)]);
+        // One declaration with a little code around it keeps its name
+        let text = "use std::fs;\n\nfn only() {}\n";
+        assert_eq!(spans(&rust(text)), [(Some("only"), 1, 3)]);
+    }
+
+    #[test]
+    fn large_containers_split_along_their_members() {
+        let mut text = String::from("struct Big;\n\nimpl Big {\n");
+        text.push_str(&function("first", 40));
+        text.push_str(&function("second", 30));
+        text.push_str("    fn a() {}\n    fn b() {}\n}\n");
+        let chunks = rust(&text);
+        assert_eq!(
+            spans(&chunks),
+            [
+                (Some("Big"), 1, 1),
+                (Some("impl Big.first"), 4, 43),
+                (Some("impl Big.second"), 44, 73),
+                (Some("impl Big"), 74, 76),
+            ]
+        );
+        assert_eq!(chunks[1].kind, Some(SymbolKind::Method));
+    }
+
+    #[test]
+    fn huge_lines_are_cut_on_a_char_boundary() {
+        let text = "é".repeat(MAX_CHUNK_BYTES);
+        let chunks = chunk(&text, &[]);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].text.len(), MAX_CHUNK_BYTES);
+        assert_eq!(chunks[0].text.chars().count(), MAX_CHUNK_BYTES / 2);
+    }
+}
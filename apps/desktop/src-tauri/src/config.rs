@@ -0,0 +1,155 @@
+//! Project settings read from `.henryrc`
+//!
+//! Mirrors the schema in `packages/rules-engine/src/types.ts`. Only the
+//! sections the backend acts on are modelled; unknown keys are ignored.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// File names probed in the project root, in order
+const CONFIG_FILES: [&str; 2] = [".henryrc", ".henryrc.json"];
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct HenryConfig {
+    pub ai: AiConfig,
+    pub security: SecurityConfig,
+    pub embeddings: EmbeddingsConfig,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AiConfig {
+    /// Tried first
+    pub preferred_model: ModelChoice,
+    /// Tried when the preferred provider fails
+    pub fallback_model: Option<ModelChoice>,
+    pub providers: ProvidersConfig,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ModelChoice {
+    /// Ollama on this machine
+    #[default]
+    Local,
+    Openai,
+    Claude,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ProvidersConfig {
+    pub local: ProviderSettings,
+    pub openai: ProviderSettings,
+    pub claude: ProviderSettings,
+}
+
+impl ProvidersConfig {
+    pub fn get(&self, choice: ModelChoice) -> &ProviderSettings {
+        match choice {
+            ModelChoice::Local => &self.local,
+            ModelChoice::Openai => &self.openai,
+            ModelChoice::Claude => &self.claude,
+        }
+    }
+}
+
+/// How to reach one provider; API keys come from the environment
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ProviderSettings {
+    pub model: Option<String>,
+    /// Base URL of the provider's API, e.g. an OpenAI-compatible local
+    /// server for `openai`
+    pub url: Option<String>,
+    /// How long to wait for the provider to start answering
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SecurityConfig {
+    /// Extra glob patterns, relative to the project root, that file commands
+    /// must never touch
+    pub deny_paths: Vec<String>,
+    pub commands: CommandRules,
+}
+
+/// Rules for commands the agent runs, as globs over the command line with
+/// the program's bare name first, e.g. `npm test` or `git push *`
+///
+/// They are also matched against the command with global options like
+/// `git -C <dir>` left out, and against what `npx`, `pnpm exec` and the
+/// like run, e.g. `rm -rf .` for `pnpm exec rm -rf .`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CommandRules {
+    /// Run without asking where the autonomy level would ask; never
+    /// unblocks what the level blocks, and a wrapped command needs a rule
+    /// for the wrapper and for what it runs
+    pub allow: Vec<String>,
+    /// Never run; wins over `allow`
+    pub deny: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct EmbeddingsConfig {
+    pub provider: EmbeddingProvider,
+    /// Model name for providers that serve several
+    pub model: Option<String>,
+    /// Base URL of the provider's API
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EmbeddingProvider {
+    /// Offline feature hashing, no model needed
+    #[default]
+    Hashing,
+    Ollama,
+}
+
+/// A config file that exists but could not be read or parsed
+#[derive(Debug, thiserror::Error)]
+#[error("{}: {message}", path.display())]
+pub struct ConfigError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl HenryConfig {
+    /// Load the config from `root`, falling back to defaults if it is missing
+    /// or unreadable
+    pub fn load(root: &Path) -> Self {
+        Self::try_load(root).unwrap_or_default()
+    }
+
+    /// Load the config from `root`; defaults if there is none, an error if
+    /// the file is there but broken
+    pub fn try_load(root: &Path) -> Result<Self, ConfigError> {
+        for name in CONFIG_FILES {
+            let path = root.join(name);
+            let text = match fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(ConfigError {
+                        path,
+                        message: e.to_string(),
+                    })
+                }
+            };
+            return serde_json::from_str(&text).map_err(|e| ConfigError {
+                path,
+                message: e.to_string(),
+            });
+        }
+        Ok(Self::default())
+    }
+}
@@ -0,0 +1,215 @@
+//! Line and word level diffs for edit previews
+//!
+//! Replaces the naive line walker in `Sandbox.generateDiff`, which produced
+//! wrong hunks once more than one line moved.
+
+use serde::{Deserialize, Serialize};
+use similar::{Algorithm, ChangeTag, DiffOp, DiffTag, TextDiff};
+
+/// Default number of unchanged lines shown around each hunk
+pub const DEFAULT_CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DiffAlgorithm {
+    Myers,
+    #[default]
+    Patience,
+}
+
+impl From<DiffAlgorithm> for Algorithm {
+    fn from(algorithm: DiffAlgorithm) -> Self {
+        match algorithm {
+            DiffAlgorithm::Myers => Algorithm::Myers,
+            DiffAlgorithm::Patience => Algorithm::Patience,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileDiff {
+    pub file_path: String,
+    pub hunks: Vec<Hunk>,
+    pub line_changes: LineChanges,
+    /// The same diff rendered as `diff -u` text
+    pub unified_diff: String,
+}
+
+/// Added/removed/modified counts, matching the shape of the TS `FileDiff`
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct LineChanges {
+    pub added: usize,
+    pub removed: usize,
+    pub modified: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Hunk {
+    /// 1-based, as in a `@@ -a,b +c,d @@` header
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LineKind {
+    Context,
+    Added,
+    Removed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffLine {
+    pub kind: LineKind,
+    /// 1-based line number in the old file, absent for added lines
+    pub old_line: Option<usize>,
+    /// 1-based line number in the new file, absent for removed lines
+    pub new_line: Option<usize>,
+    pub content: String,
+    /// Word-level split of a modified line; empty when the whole line changed
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Segment {
+    pub text: String,
+    /// Whether this part of the line differs from its counterpart
+    pub changed: bool,
+}
+
+/// Diff `old` against `new` line by line, with word-level changes inside
+/// modified lines
+pub fn compute(
+    file_path: &str,
+    old: &str,
+    new: &str,
+    context: usize,
+    algorithm: DiffAlgorithm,
+) -> FileDiff {
+    let diff = TextDiff::configure()
+        .algorithm(algorithm.into())
+        .diff_lines(old, new);
+
+    let mut line_changes = LineChanges::default();
+    let mut hunks = Vec::new();
+
+    for group in diff.grouped_ops(context) {
+        let mut lines = Vec::new();
+        for op in &group {
+            count_op(op, &mut line_changes);
+            if op.tag() == DiffTag::Replace {
+                lines.extend(inline_lines(&diff, op));
+            } else {
+                for change in diff.iter_changes(op) {
+                    lines.push(DiffLine {
+                        kind: line_kind(change.tag()),
+                        old_line: change.old_index().map(|i| i + 1),
+                        new_line: change.new_index().map(|i| i + 1),
+                        content: trim_newline(change.value()).to_string(),
+                        segments: Vec::new(),
+                    });
+                }
+            }
+        }
+        hunks.push(hunk_header(&group, lines));
+    }
+
+    let unified_diff = diff
+        .unified_diff()
+        .context_radius(context)
+        .header(
+            &format!("{file_path} (original)"),
+            &format!("{file_path} (modified)"),
+        )
+        .to_string();
+
+    FileDiff {
+        file_path: file_path.to_string(),
+        hunks,
+        line_changes,
+        unified_diff,
+    }
+}
+
+/// A replaced block of n old and m new lines counts min(n, m) lines as
+/// modified and the rest as added or removed
+fn count_op(op: &DiffOp, counts: &mut LineChanges) {
+    let (tag, old, new) = op.as_tag_tuple();
+    match tag {
+        DiffTag::Equal => {}
+        DiffTag::Insert => counts.added += new.len(),
+        DiffTag::Delete => counts.removed += old.len(),
+        DiffTag::Replace => {
+            let modified = old.len().min(new.len());
+            counts.modified += modified;
+            counts.added += new.len() - modified;
+            counts.removed += old.len() - modified;
+        }
+    }
+}
+
+fn inline_lines<'a>(diff: &'a TextDiff<'a, 'a, 'a, str>, op: &DiffOp) -> Vec<DiffLine> {
+    diff.iter_inline_changes(op)
+        .map(|change| {
+            let mut segments: Vec<Segment> = change
+                .iter_strings_lossy()
+                .map(|(changed, text)| Segment {
+                    text: text.into_owned(),
+                    changed,
+                })
+                .collect();
+            if let Some(last) = segments.last_mut() {
+                last.text = trim_newline(&last.text).to_string();
+            }
+            segments.retain(|s| !s.text.is_empty());
+            let content: String = segments.iter().map(|s| s.text.as_str()).collect();
+            // A single emphasized run means nothing in the line was kept
+            if segments.iter().all(|s| s.changed) {
+                segments.clear();
+            }
+            DiffLine {
+                kind: line_kind(change.tag()),
+                old_line: change.old_index().map(|i| i + 1),
+                new_line: change.new_index().map(|i| i + 1),
+                content,
+                segments,
+            }
+        })
+        .collect()
+}
+
+fn hunk_header(group: &[DiffOp], lines: Vec<DiffLine>) -> Hunk {
+    let (first, last) = (&group[0], &group[group.len() - 1]);
+    let old_lines = last.old_range().end - first.old_range().start;
+    let new_lines = last.new_range().end - first.new_range().start;
+    // Unified diff convention: an empty range points at the line before it
+    let start = |index: usize, len: usize| if len == 0 { index } else { index + 1 };
+    Hunk {
+        old_start: start(first.old_range().start, old_lines),
+        old_lines,
+        new_start: start(first.new_range().start, new_lines),
+        new_lines,
+        lines,
+    }
+}
+
+fn line_kind(tag: ChangeTag) -> LineKind {
+    match tag {
+        ChangeTag::Equal => LineKind::Context,
+        ChangeTag::Insert => LineKind::Added,
+        ChangeTag::Delete => LineKind::Removed,
+    }
+}
+
+fn trim_newline(line: &str) -> &str {
+    line.strip_suffix('\n')
+        .map(|l| l.strip_suffix('\r').unwrap_or(l))
+        .unwrap_or(line)
+}
@@ -0,0 +1,835 @@
+//! Local embedding pipeline
+//!
+//! Embeds the chunks of indexed files into the vector store, so semantic
+//! search works without any cloud service. The embedder comes from the
+//! `embeddings` section of `.henryrc`: offline feature hashing by default,
+//! or a model served by Ollama. A run only reads files whose content hash
+//! changed since they were embedded, and only embeds chunks the store has
+//! no vector for yet, so moved code keeps its vectors. Once a root has been
+//! embedded, the watcher keeps its vectors up to date.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::chunking::{self, TextChunk};
+use crate::config::{EmbeddingProvider, EmbeddingsConfig, HenryConfig};
+use crate::fs_edit;
+use crate::indexer::{IndexError, IndexState, Indexer};
+use crate::ollama;
+use crate::symbols::Symbol;
+use crate::vectors::{NewChunk, VectorError, VectorStore};
+use crate::watcher::FsChanged;
+use crate::workspace::{Workspace, WorkspaceError};
+
+pub const PROGRESS_EVENT: &str = "embed://progress";
+
+/// Texts sent to the embedder at once
+const BATCH_SIZE: usize = 32;
+
+const HASHING_DIMENSION: usize = 512;
+
+/// Weight of terms that appear in almost any code
+const STOP_WEIGHT: f32 = 0.1;
+
+const STOP_WORDS: &[&str] = &[
+    "and",
+    "as",
+    "async",
+    "await",
+    "bool",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "def",
+    "default",
+    "else",
+    "export",
+    "false",
+    "fn",
+    "for",
+    "from",
+    "func",
+    "function",
+    "if",
+    "impl",
+    "import",
+    "in",
+    "int",
+    "interface",
+    "is",
+    "let",
+    "mut",
+    "new",
+    "nil",
+    "none",
+    "not",
+    "null",
+    "of",
+    "or",
+    "package",
+    "private",
+    "protected",
+    "pub",
+    "public",
+    "return",
+    "self",
+    "static",
+    "str",
+    "string",
+    "struct",
+    "the",
+    "this",
+    "to",
+    "true",
+    "try",
+    "type",
+    "use",
+    "var",
+    "void",
+    "while",
+];
+
+const DEFAULT_OLLAMA_MODEL: &str = "nomic-embed-text";
+const OLLAMA_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Debug, thiserror::Error, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum EmbedError {
+    #[error("embedding failed: {message}")]
+    Provider { message: String },
+    #[error("{root} is already being embedded")]
+    Busy { root: String },
+    #[error(transparent)]
+    Index { reason: IndexError },
+    #[error(transparent)]
+    Store { reason: VectorError },
+    #[error(transparent)]
+    Sandbox { reason: WorkspaceError },
+}
+
+impl From<IndexError> for EmbedError {
+    fn from(reason: IndexError) -> Self {
+        Self::Index { reason }
+    }
+}
+
+impl From<VectorError> for EmbedError {
+    fn from(reason: VectorError) -> Self {
+        Self::Store { reason }
+    }
+}
+
+impl From<WorkspaceError> for EmbedError {
+    fn from(reason: WorkspaceError) -> Self {
+        Self::Sandbox { reason }
+    }
+}
+
+/// Turns texts into vectors
+pub trait Embedder: Send + Sync {
+    /// Names the model and its settings; vectors from different ids are
+    /// never compared
+    fn id(&self) -> String;
+
+    /// One vector per text, in order
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, EmbedError>;
+}
+
+/// The embedder `config` asks for
+pub fn from_config(config: &EmbeddingsConfig) -> Result<Box<dyn Embedder>, EmbedError> {
+    Ok(match config.provider {
+        EmbeddingProvider::Hashing => Box::new(HashingEmbedder::new(HASHING_DIMENSION)),
+        EmbeddingProvider::Ollama => Box::new(OllamaEmbedder::new(
+            config.url.as_deref().unwrap_or(ollama::DEFAULT_URL),
+            config.model.as_deref().unwrap_or(DEFAULT_OLLAMA_MODEL),
+        )?),
+    })
+}
+
+/// Offline embedder hashing terms into a fixed number of buckets
+///
+/// Identifiers are split at underscores, case changes and digits, so
+/// `parseConfig` and `parse_config_file` share features; whole identifiers
+/// and neighbouring parts are features too. Term counts are dampened
+/// logarithmically and keywords weigh little, a corpus-free stand-in for
+/// IDF that keeps vectors stable while the files around them change.
+pub struct HashingEmbedder {
+    dimension: usize,
+}
+
+impl HashingEmbedder {
+    pub fn new(dimension: usize) -> Self {
+        Self { dimension }
+    }
+
+    fn vector(&self, text: &str) -> Vec<f32> {
+        let mut counts: HashMap<String, f32> = HashMap::new();
+        let words = text
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .filter(|word| !word.is_empty());
+        for word in words {
+            let parts = split_identifier(word);
+            for part in &parts {
+                if part.len() > 1 && !part.chars().all(|c| c.is_ascii_digit()) {
+                    *counts.entry(part.clone()).or_default() += 1.0;
+                }
+            }
+            if parts.len() > 1 {
+                *counts.entry(parts.concat()).or_default() += 1.0;
+                for pair in parts.windows(2) {
+                    *counts.entry(pair.join(" ")).or_default() += 0.5;
+                }
+            }
+        }
+
+        let mut vector = vec![0.0; self.dimension];
+        for (term, count) in counts {
+            let hash = fnv1a(term.as_bytes());
+            let mut weight = 1.0 + count.ln().max(0.0);
+            if STOP_WORDS.contains(&term.as_str()) {
+                weight *= STOP_WEIGHT;
+            }
+            let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+            vector[(hash % self.dimension as u64) as usize] += sign * weight;
+        }
+        let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|x| *x /= norm);
+        }
+        vector
+    }
+}
+
+impl Embedder for HashingEmbedder {
+    fn id(&self) -> String {
+        format!("hashing-{}", self.dimension)
+    }
+
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, EmbedError> {
+        Ok(texts.iter().map(|text| self.vector(text)).collect())
+    }
+}
+
+/// Lowercase parts of an identifier, e.g. `HTTPServer_v2` → `http`,
+/// `server`, `v`, `2`
+pub fn split_identifier(word: &str) -> Vec<String> {
+    let chars: Vec<char> = word.chars().collect();
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c == '_' {
+            parts.extend((!current.is_empty()).then(|| std::mem::take(&mut current)));
+            continue;
+        }
+        let boundary = i > 0 && {
+            let prev = chars[i - 1];
+            let next = chars.get(i + 1);
+            (prev.is_lowercase() && c.is_uppercase())
+                || (prev.is_uppercase()
+                    && c.is_uppercase()
+                    && next.is_some_and(|n| n.is_lowercase()))
+                || (prev != '_' && prev.is_ascii_digit() != c.is_ascii_digit())
+        };
+        if boundary && !current.is_empty() {
+            parts.push(std::mem::take(&mut current));
+        }
+        current.extend(c.to_lowercase());
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+    parts
+}
+
+/// FNV-1a, stable across builds unlike the std hasher
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Embedder calling Ollama's `/api/embeddings`
+pub struct OllamaEmbedder {
+    url: String,
+    model: String,
+    client: reqwest::blocking::Client,
+}
+
+#[derive(Serialize)]
+struct OllamaRequest<'r> {
+    model: &'r str,
+    prompt: &'r str,
+}
+
+#[derive(Deserialize)]
+struct OllamaResponse {
+    embedding: Vec<f32>,
+}
+
+impl OllamaEmbedder {
+    pub fn new(url: &str, model: &str) -> Result<Self, EmbedError> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(OLLAMA_TIMEOUT)
+            .build()
+            .map_err(|e| EmbedError::Provider {
+                message: e.to_string(),
+            })?;
+        Ok(Self {
+            url: url.trim_end_matches('/').to_owned(),
+            model: model.to_owned(),
+            client,
+        })
+    }
+
+    fn request(&self, text: &str) -> Result<Vec<f32>, reqwest::Error> {
+        let response: OllamaResponse = self
+            .client
+            .post(format!("{}/api/embeddings", self.url))
+            .json(&OllamaRequest {
+                model: &self.model,
+                prompt: text,
+            })
+            .send()?
+            .error_for_status()?
+            .json()?;
+        Ok(response.embedding)
+    }
+}
+
+impl Embedder for OllamaEmbedder {
+    fn id(&self) -> String {
+        format!("ollama:{}", self.model)
+    }
+
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, EmbedError> {
+        // The endpoint takes one prompt per request
+        texts
+            .iter()
+            .map(|text| {
+                self.request(text).map_err(|e| EmbedError::Provider {
+                    message: format!("Ollama at {}: {}", self.url, e),
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbedStatus {
+    pub root: PathBuf,
+    pub model: String,
+    /// Changed files read so far, out of `total`
+    pub processed: usize,
+    pub total: usize,
+    /// Chunks sent to the embedder
+    pub embedded: usize,
+    /// Chunks whose stored vector was reused
+    pub reused: usize,
+    /// Chunks dropped with their files
+    pub removed: usize,
+}
+
+/// An indexed file whose content changed since it was embedded
+struct Stale {
+    relative: PathBuf,
+    source: String,
+    symbols: Vec<Symbol>,
+}
+
+/// A changed file, chunked and waiting for vectors
+struct PendingFile {
+    relative: PathBuf,
+    source: String,
+    chunks: Vec<TextChunk>,
+    vectors: Vec<Option<Vec<f32>>>,
+}
+
+/// Roots being embedded, managed as Tauri state
+#[derive(Default)]
+pub struct Embeddings {
+    running: Mutex<HashSet<PathBuf>>,
+}
+
+impl Embeddings {
+    /// Embed the indexed files of `root` that changed since they were last
+    /// embedded, and drop the vectors of files gone from the index
+    ///
+    /// Only paths under `only` are considered, if given. Blocking. Vectors
+    /// of another model are cleared first, unless `only` is given.
+    pub fn sync(
+        &self,
+        indexer: &Indexer,
+        store: &VectorStore,
+        root: &Path,
+        embedder: &dyn Embedder,
+        only: Option<&[PathBuf]>,
+        progress: impl Fn(&EmbedStatus),
+    ) -> Result<EmbedStatus, EmbedError> {
+        if !self.running.lock().unwrap().insert(root.to_path_buf()) {
+            return Err(EmbedError::Busy {
+                root: root.display().to_string(),
+            });
+        }
+        let result = self.sync_inner(indexer, store, root, embedder, only, &progress);
+        self.running.lock().unwrap().remove(root);
+        result
+    }
+
+    fn sync_inner(
+        &self,
+        indexer: &Indexer,
+        store: &VectorStore,
+        root: &Path,
+        embedder: &dyn Embedder,
+        only: Option<&[PathBuf]>,
+        progress: &impl Fn(&EmbedStatus),
+    ) -> Result<EmbedStatus, EmbedError> {
+        let model = embedder.id();
+        if let Some(stored) = store.status(root).model.filter(|stored| *stored != model) {
+            if only.is_some() {
+                return Err(VectorError::ModelMismatch {
+                    expected: stored,
+                    found: model,
+                }
+                .into());
+            }
+            store.clear(root)?;
+        }
+
+        let wanted = |path: &Path| only.is_none_or(|only| only.iter().any(|p| path.starts_with(p)));
+        let sources = store.sources(root, &model);
+        let (stale, gone): (Vec<Stale>, Vec<PathBuf>) = indexer
+            .with_files(root, |_, files| {
+                let stale = files
+                    .iter()
+                    .filter(|(path, file)| wanted(path) && sources.get(*path) != Some(&file.hash))
+                    .map(|(path, file)| Stale {
+                        relative: path.clone(),
+                        source: file.hash.clone(),
+                        symbols: file.symbols.clone(),
+                    })
+                    .collect();
+                let gone = sources
+                    .keys()
+                    .filter(|path| wanted(path) && !files.contains_key(*path))
+                    .cloned()
+                    .collect();
+                (stale, gone)
+            })
+            .ok_or_else(|| IndexError::NotIndexed {
+                root: root.display().to_string(),
+            })?;
+
+        let mut status = EmbedStatus {
+            root: root.to_path_buf(),
+            model,
+            processed: 0,
+            total: stale.len(),
+            embedded: 0,
+            reused: 0,
+            removed: 0,
+        };
+        for relative in gone {
+            status.removed += store.delete(root, &relative)?;
+        }
+        progress(&status);
+
+        let mut batch = Vec::new();
+        let mut missing = 0;
+        for Stale {
+            relative,
+            source,
+            symbols,
+        } in stale
+        {
+            let Ok(text) = fs::read_to_string(root.join(&relative)) else {
+                // Gone or unreadable since it was indexed; the watcher will
+                // report it
+                status.processed += 1;
+                continue;
+            };
+            let chunks = chunking::chunk(&text, &symbols);
+            let hashes: Vec<String> = chunks
+                .iter()
+                .map(|chunk| fs_edit::content_hash(chunk.text.as_bytes()))
+                .collect();
+            let vectors = store.cached(root, &status.model, &hashes);
+            missing += vectors.iter().filter(|v| v.is_none()).count();
+            batch.push(PendingFile {
+                relative,
+                source,
+                chunks,
+                vectors,
+            });
+            if missing >= BATCH_SIZE {
+                self.store_batch(
+                    store,
+                    root,
+                    embedder,
+                    std::mem::take(&mut batch),
+                    &mut status,
+                )?;
+                missing = 0;
+                progress(&status);
+            }
+        }
+        self.store_batch(store, root, embedder, batch, &mut status)?;
+        progress(&status);
+        Ok(status)
+    }
+
+    /// Embed the chunks of `batch` that have no vector yet and store the
+    /// files
+    fn store_batch(
+        &self,
+        store: &VectorStore,
+        root: &Path,
+        embedder: &dyn Embedder,
+        mut batch: Vec<PendingFile>,
+        status: &mut EmbedStatus,
+    ) -> Result<(), EmbedError> {
+        let slots: Vec<(usize, usize)> = batch
+            .iter()
+            .enumerate()
+            .flat_map(|(f, file)| {
+                file.vectors
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, v)| v.is_none())
+                    .map(move |(c, _)| (f, c))
+            })
+            .collect();
+        status.reused += batch.iter().map(|file| file.vectors.len()).sum::<usize>() - slots.len();
+        for group in slots.chunks(BATCH_SIZE) {
+            let texts: Vec<String> = group
+                .iter()
+                .map(|&(f, c)| embed_input(&batch[f].relative, &batch[f].chunks[c]))
+                .collect();
+            let vectors = embedder.embed(&texts)?;
+            if vectors.len() != texts.len() {
+                return Err(EmbedError::Provider {
+                    message: format!("expected {} vectors, got {}", texts.len(), vectors.len()),
+                });
+            }
+            for (&(f, c), vector) in group.iter().zip(vectors) {
+                batch[f].vectors[c] = Some(vector);
+            }
+            status.embedded += group.len();
+        }
+
+        for file in batch {
+            let chunks: Vec<NewChunk> = file
+                .chunks
+                .into_iter()
+                .zip(file.vectors)
+                // Text without any terms has nothing to search by
+                .filter_map(|(chunk, vector)| {
+                    let vector = vector.filter(|v| v.iter().any(|x| *x != 0.0))?;
+                    Some(NewChunk {
+                        symbol: chunk.symbol,
+                        kind: chunk.kind.map(|kind| kind.label().to_owned()),
+                        language: None,
+                        start_line: chunk.start_line as u32,
+                        end_line: Some(chunk.end_line as u32),
+                        text: chunk.text,
+                        vector,
+                    })
+                })
+                .collect();
+            store.upsert(
+                root,
+                &file.relative,
+                &status.model,
+                Some(file.source),
+                chunks,
+            )?;
+            status.processed += 1;
+        }
+        Ok(())
+    }
+
+    /// Re-embed what a watcher batch touched, in roots already embedded with
+    /// the configured model
+    ///
+    /// Runs on the watcher's worker thread, after the index has taken the batch.
+    pub fn apply_changes(
+        &self,
+        indexer: &Indexer,
+        store: &VectorStore,
+        changed: &FsChanged,
+    ) -> Result<(), EmbedError> {
+        let root = &changed.root;
+        if store.status(root).model.is_none() || self.running.lock().unwrap().contains(root) {
+            return Ok(());
+        }
+        let embedder = from_config(&HenryConfig::load(root).embeddings)?;
+        if store.status(root).model != Some(embedder.id()) {
+            // The next full run switches models
+            return Ok(());
+        }
+        let paths: Vec<PathBuf> = changed
+            .removed
+            .iter()
+            .chain(changed.renamed.iter().flat_map(|r| [&r.from, &r.to]))
+            .chain(&changed.created)
+            .chain(&changed.modified)
+            .filter_map(|path| path.strip_prefix(root).ok().map(Path::to_path_buf))
+            .collect();
+        match self.sync(
+            indexer,
+            store,
+            root,
+            embedder.as_ref(),
+            Some(&paths),
+            |_| {},
+        ) {
+            Err(EmbedError::Busy { .. }) => Ok(()),
+            result => result.map(|_| ()),
+        }
+    }
+}
+
+/// What the embedder sees of a chunk: its location, then its code
+fn embed_input(relative: &Path, chunk: &TextChunk) -> String {
+    format!(
+        "{}\n{}\n{}",
+        relative.display(),
+        chunk.symbol.as_deref().unwrap_or_default(),
+        chunk.text
+    )
+}
+
+fn join_error(err: tauri::Error) -> EmbedError {
+    EmbedError::Provider {
+        message: err.to_string(),
+    }
+}
+
+/// Embed the changed files of a workspace root (the first open root by
+/// default), indexing it first if needed, and emit `embed://progress`
+/// events while it runs
+#[tauri::command]
+pub async fn embed_workspace(
+    app: AppHandle,
+    workspace: State<'_, Workspace>,
+    root: Option<PathBuf>,
+) -> Result<EmbedStatus, EmbedError> {
+    let root = workspace.pick_root(root.as_deref())?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let indexer = app.state::<Indexer>();
+        if indexer.status(&root.path).state == IndexState::Missing {
+            indexer.build(&root, false, |_| {})?;
+        }
+        let embedder = from_config(&HenryConfig::load(&root.path).embeddings)?;
+        app.state::<Embeddings>().sync(
+            &indexer,
+            &app.state::<VectorStore>(),
+            &root.path,
+            embedder.as_ref(),
+            None,
+            |status| {
+                let _ = app.emit(PROGRESS_EVENT, status);
+            },
+        )
+    })
+    .await
+    .map_err(join_error)?
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Embedded {
+    pub model: String,
+    pub vectors: Vec<Vec<f32>>,
+}
+
+/// Embed texts, such as a search query, with the embedder configured for
+/// `root` (the first open root by default)
+#[tauri::command]
+pub async fn embed_texts(
+    workspace: State<'_, Workspace>,
+    texts: Vec<String>,
+    root: Option<PathBuf>,
+) -> Result<Embedded, EmbedError> {
+    let root = workspace.pick_root(root.as_deref())?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let embedder = from_config(&HenryConfig::load(&root.path).embeddings)?;
+        Ok(Embedded {
+            model: embedder.id(),
+            vectors: embedder.embed(&texts)?,
+        })
+    })
+    .await
+    .map_err(join_error)?
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::workspace::Workspace;
+
+    /// Small hashing vectors under a fixed id, counting what it was asked
+    /// to embed
+    struct Counting {
+        id: &'static str,
+        texts: AtomicUsize,
+        hashing: HashingEmbedder,
+    }
+
+    impl Counting {
+        fn new(id: &'static str) -> Self {
+            Self {
+                id,
+                texts: AtomicUsize::new(0),
+                hashing: HashingEmbedder::new(16),
+            }
+        }
+    }
+
+    impl Embedder for Counting {
+        fn id(&self) -> String {
+            self.id.to_owned()
+        }
+
+        fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, EmbedError> {
+            self.texts.fetch_add(texts.len(), Ordering::SeqCst);
+            self.hashing.embed(texts)
+        }
+    }
+
+    /// Returns one vector too few
+    struct Short;
+
+    impl Embedder for Short {
+        fn id(&self) -> String {
+            "short".to_owned()
+        }
+
+        fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, EmbedError> {
+            Ok(vec![vec![1.0]; texts.len() - 1])
+        }
+    }
+
+    fn cosine(a: &[f32], b: &[f32]) -> f32 {
+        a.iter().zip(b).map(|(x, y)| x * y).sum()
+    }
+
+    #[test]
+    fn identifiers_split_into_parts() {
+        assert_eq!(
+            split_identifier("HTTPServer_v2"),
+            ["http", "server", "v", "2"]
+        );
+        assert_eq!(
+            split_identifier("parseConfigFile"),
+            ["parse", "config", "file"]
+        );
+        assert_eq!(split_identifier("__init__"), ["init"]);
+        assert_eq!(split_identifier("MAX_DEPTH"), ["max", "depth"]);
+    }
+
+    #[test]
+    fn hashing_vectors_are_deterministic_and_unit() {
+        let embedder = HashingEmbedder::new(HASHING_DIMENSION);
+        let texts = ["fn parseConfig(path: &Path)".to_string(), "  ".to_string()];
+        let first = embedder.embed(&texts).unwrap();
+        assert_eq!(first, embedder.embed(&texts).unwrap());
+        assert_eq!(first[0].len(), HASHING_DIMENSION);
+        assert!((cosine(&first[0], &first[0]) - 1.0).abs() < 1e-5);
+        // Nothing to hash stays zero rather than dividing by it
+        assert!(first[1].iter().all(|x| *x == 0.0));
+        assert_eq!(embedder.id(), "hashing-512");
+    }
+
+    #[test]
+    fn shared_identifier_parts_bring_vectors_closer() {
+        let embedder = HashingEmbedder::new(HASHING_DIMENSION);
+        let vectors = embedder
+            .embed(&[
+                "parseConfig".to_string(),
+                "parse_config_file".to_string(),
+                "renderWidget".to_string(),
+                "return renderWidget".to_string(),
+            ])
+            .unwrap();
+        assert!(cosine(&vectors[0], &vectors[1]) > 0.5);
+        assert!(cosine(&vectors[0], &vectors[2]).abs() < 0.2);
+        // A keyword barely moves the vector
+        assert!(cosine(&vectors[2], &vectors[3]) > 0.95);
+    }
+
+    #[test]
+    fn sync_embeds_only_what_changed() {
+        let project = tempfile::tempdir().unwrap();
+        let data = tempfile::tempdir().unwrap();
+        fs::write(project.path().join("a.rs"), "fn alpha() {}\n").unwrap();
+        fs::write(project.path().join("b.rs"), "fn beta() {}\n").unwrap();
+        let root = Workspace::default().open(project.path()).unwrap();
+        let indexer = Indexer::open(data.path().join("index")).unwrap();
+        indexer.build(&root, false, |_| {}).unwrap();
+        let store = VectorStore::open(data.path().join("vectors")).unwrap();
+        let embeddings = Embeddings::default();
+        let embedder = Counting::new("counting");
+        let sync = |embedder: &dyn Embedder, only: Option<&[PathBuf]>| {
+            embeddings.sync(&indexer, &store, &root.path, embedder, only, |_| {})
+        };
+
+        let status = sync(&embedder, None).unwrap();
+        assert_eq!((status.total, status.embedded, status.reused), (2, 2, 0));
+        assert_eq!(store.status(&root.path).chunks, 2);
+
+        // Nothing changed since
+        let status = sync(&embedder, None).unwrap();
+        assert_eq!((status.total, status.embedded), (0, 0));
+        assert_eq!(embedder.texts.load(Ordering::SeqCst), 2);
+
+        // A copy reuses the vector of the code it copied
+        let copy = root.path.join("c.rs");
+        fs::write(&copy, "fn alpha() {}\n").unwrap();
+        let gone = root.path.join("b.rs");
+        fs::remove_file(&gone).unwrap();
+        indexer
+            .apply_changes(&FsChanged {
+                root: root.path.clone(),
+                created: vec![copy],
+                removed: vec![gone],
+                ..FsChanged::default()
+            })
+            .unwrap();
+        let status = sync(&embedder, None).unwrap();
+        assert_eq!(
+            (status.total, status.embedded, status.reused, status.removed),
+            (1, 0, 1, 1)
+        );
+        assert_eq!(embedder.texts.load(Ordering::SeqCst), 2);
+
+        // Another model replaces every vector, but not for a partial run
+        let other = Counting::new("other");
+        assert!(matches!(
+            sync(&other, Some(&[PathBuf::from("a.rs")])),
+            Err(EmbedError::Store {
+                reason: VectorError::ModelMismatch { .. }
+            })
+        ));
+        let status = sync(&other, None).unwrap();
+        assert_eq!((status.total, status.embedded), (2, 2));
+        assert_eq!(store.status(&root.path).model.as_deref(), Some("other"));
+
+        assert!(matches!(
+            sync(&Short, None),
+            Err(EmbedError::Provider { .. })
+        ));
+    }
+}
@@ -0,0 +1,402 @@
+//! Structured command runner
+//!
+//! Runs one program from an argv, never through a shell, in a directory
+//! inside the workspace and with an environment built from an allowlist.
+//! The program gets its own process group; when the wall-clock timeout
+//! hits, or once the program has exited, the whole group is killed, so
+//! nothing it started lingers. Captured output keeps its head and tail up
+//! to a cap, with a marker where the middle was cut.
+//!
+//! The webview can't call the runner directly: the agent's commands go
+//! through the command policy (`run_agent_command`) and the terminal panel
+//! runs on a PTY.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::workspace::{Workspace, WorkspaceError};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Bytes kept of each of stdout and stderr unless the caller says
+const DEFAULT_MAX_OUTPUT: usize = 256 * 1024;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Variables passed through from the app's environment to every command
+#[cfg(unix)]
+const ENV_ALLOWLIST: [&str; 9] = [
+    "PATH", "HOME", "USER", "LOGNAME", "SHELL", "LANG", "LC_ALL", "TMPDIR", "TERM",
+];
+#[cfg(windows)]
+const ENV_ALLOWLIST: [&str; 12] = [
+    "PATH",
+    "PATHEXT",
+    "SYSTEMROOT",
+    "SYSTEMDRIVE",
+    "WINDIR",
+    "COMSPEC",
+    "TEMP",
+    "TMP",
+    "USERPROFILE",
+    "APPDATA",
+    "LOCALAPPDATA",
+    "PROGRAMDATA",
+];
+
+#[derive(Debug, thiserror::Error, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ExecError {
+    #[error("no program given")]
+    EmptyCommand,
+    #[error("cannot start {program}: {message}")]
+    Spawn { program: String, message: String },
+    #[error("waiting for {program} failed: {message}")]
+    Wait { program: String, message: String },
+    #[error("{command} was blocked: {reason}")]
+    Blocked { command: String, reason: String },
+    #[error("{command} was not approved")]
+    Refused { command: String },
+    #[error("invalid command rule {pattern}: {message}")]
+    InvalidRule { pattern: String, message: String },
+    #[error("cannot read the command rules in {path}: {message}")]
+    InvalidConfig { path: String, message: String },
+    #[error("command decision log failed: {message}")]
+    Log { message: String },
+    #[error(transparent)]
+    Sandbox { reason: WorkspaceError },
+}
+
+impl From<WorkspaceError> for ExecError {
+    fn from(reason: WorkspaceError) -> Self {
+        Self::Sandbox { reason }
+    }
+}
+
+impl ExecError {
+    pub fn log(err: impl std::fmt::Display) -> Self {
+        Self::Log {
+            message: err.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RunOptions {
+    /// Working directory inside an open root; the first root if unset
+    pub cwd: Option<PathBuf>,
+    /// Variables to set, on top of the allowlisted ones
+    pub env: HashMap<String, String>,
+    /// More variables to pass through from the app's environment
+    pub inherit_env: Vec<String>,
+    pub timeout_ms: Option<u64>,
+    /// Bytes kept of each of stdout and stderr
+    pub max_output_bytes: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandOutput {
+    /// `None` if a signal ended the program
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+    pub timed_out: bool,
+    pub duration_ms: u64,
+    pub stdout: String,
+    pub stderr: String,
+    /// Bytes cut from the middle of each stream
+    pub stdout_truncated: usize,
+    pub stderr_truncated: usize,
+}
+
+/// Keeps the first and last bytes of a stream, up to `limit` in all
+struct Capture {
+    limit: usize,
+    head: Vec<u8>,
+    tail: VecDeque<u8>,
+    dropped: usize,
+}
+
+impl Capture {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            head: Vec::new(),
+            tail: VecDeque::new(),
+            dropped: 0,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        let head_room = (self.limit / 2).saturating_sub(self.head.len());
+        let (head, rest) = bytes.split_at(head_room.min(bytes.len()));
+        self.head.extend_from_slice(head);
+        self.tail.extend(rest);
+        let tail_limit = self.limit - self.limit / 2;
+        if self.tail.len() > tail_limit {
+            let excess = self.tail.len() - tail_limit;
+            self.tail.drain(..excess);
+            self.dropped += excess;
+        }
+    }
+
+    /// The text kept and how many bytes were cut
+    fn finish(self) -> (String, usize) {
+        let mut text = String::from_utf8_lossy(&self.head).into_owned();
+        if self.dropped > 0 {
+            text.push_str(&format!("\n[... {} bytes truncated ...]\n", self.dropped));
+        }
+        text.push_str(&String::from_utf8_lossy(&Vec::from(self.tail)));
+        (text, self.dropped)
+    }
+}
+
+/// Read `stream` to its end on a thread of its own, so a full pipe never
+/// blocks the program
+fn capture(mut stream: impl Read + Send + 'static, limit: usize) -> JoinHandle<Capture> {
+    std::thread::spawn(move || {
+        let mut capture = Capture::new(limit);
+        let mut buffer = [0; 8192];
+        while let Ok(n @ 1..) = stream.read(&mut buffer) {
+            capture.push(&buffer[..n]);
+        }
+        capture
+    })
+}
+
+/// Run `argv` in `cwd` and wait for it, up to the timeout
+pub fn run(argv: &[String], cwd: &Path, options: &RunOptions) -> Result<CommandOutput, ExecError> {
+    let (program, args) = argv.split_first().ok_or(ExecError::EmptyCommand)?;
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .current_dir(cwd)
+        .env_clear()
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let inherited = ENV_ALLOWLIST
+        .iter()
+        .copied()
+        .chain(options.inherit_env.iter().map(String::as_str));
+    for name in inherited {
+        if let Some(value) = std::env::var_os(name) {
+            command.env(name, value);
+        }
+    }
+    command.envs(&options.env);
+    own_process_group(&mut command);
+
+    let started = Instant::now();
+    let mut child = command.spawn().map_err(|e| ExecError::Spawn {
+        program: program.clone(),
+        message: e.to_string(),
+    })?;
+    let limit = options.max_output_bytes.unwrap_or(DEFAULT_MAX_OUTPUT);
+    let stdout = capture(child.stdout.take().expect("stdout is piped"), limit);
+    let stderr = capture(child.stderr.take().expect("stderr is piped"), limit);
+
+    let timeout = options
+        .timeout_ms
+        .map_or(DEFAULT_TIMEOUT, Duration::from_millis);
+    let waited = wait(&mut child, started + timeout);
+    // Whatever it left running in the background goes too, or the pipes
+    // would never close. The program isn't reaped yet, so the group id
+    // can't have been handed to anyone else.
+    kill_group(&mut child);
+    let (status, timed_out) = match waited {
+        Ok(exited) => child.wait().map(|status| (status, !exited)),
+        Err(e) => Err(e),
+    }
+    .map_err(|e| ExecError::Wait {
+        program: program.clone(),
+        message: e.to_string(),
+    })?;
+    let duration_ms = started.elapsed().as_millis() as u64;
+
+    let (stdout, stdout_truncated) = stdout.join().map(Capture::finish).unwrap_or_default();
+    let (stderr, stderr_truncated) = stderr.join().map(Capture::finish).unwrap_or_default();
+    Ok(CommandOutput {
+        exit_code: status.code(),
+        signal: signal(&status),
+        timed_out,
+        duration_ms,
+        stdout,
+        stderr,
+        stdout_truncated,
+        stderr_truncated,
+    })
+}
+
+/// Wait for `child` to exit, without reaping it; `false` if it is still
+/// running at `deadline`
+fn wait(child: &mut Child, deadline: Instant) -> io::Result<bool> {
+    loop {
+        if has_exited(child)? {
+            return Ok(true);
+        }
+        if Instant::now() >= deadline {
+            return Ok(false);
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(unix)]
+fn own_process_group(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+    command.process_group(0);
+}
+
+#[cfg(windows)]
+fn own_process_group(command: &mut Command) {
+    use std::os::windows::process::CommandExt;
+    const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+    command.creation_flags(CREATE_NEW_PROCESS_GROUP | CREATE_NO_WINDOW);
+}
+
+/// Whether `child` has exited; it stays a zombie, holding on to its pid and
+/// so to its group id, until `Child::wait` reaps it
+#[cfg(unix)]
+fn has_exited(child: &mut Child) -> io::Result<bool> {
+    loop {
+        // Plain data for `waitid` to fill in
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let result = unsafe {
+            libc::waitid(
+                libc::P_PID,
+                child.id() as libc::id_t,
+                &mut info,
+                libc::WEXITED | libc::WNOHANG | libc::WNOWAIT,
+            )
+        };
+        if result == 0 {
+            // Left zeroed while the child is still running
+            return Ok(unsafe { info.si_pid() } != 0);
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+#[cfg(windows)]
+fn has_exited(child: &mut Child) -> io::Result<bool> {
+    // The open handle keeps the pid from being reused
+    Ok(child.try_wait()?.is_some())
+}
+
+#[cfg(unix)]
+fn kill_group(child: &mut Child) {
+    // The group is named after its leader, the program
+    unsafe {
+        libc::killpg(child.id() as libc::pid_t, libc::SIGKILL);
+    }
+}
+
+#[cfg(windows)]
+fn kill_group(child: &mut Child) {
+    // Ends the program and every process it started
+    let _ = Command::new("taskkill")
+        .args(["/T", "/F", "/PID", &child.id().to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    let _ = child.kill();
+}
+
+#[cfg(unix)]
+fn signal(status: &ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(windows)]
+fn signal(_status: &ExitStatus) -> Option<i32> {
+    None
+}
+
+/// [`run`] on a blocking thread
+pub async fn run_blocking(
+    argv: Vec<String>,
+    cwd: PathBuf,
+    options: RunOptions,
+) -> Result<CommandOutput, ExecError> {
+    tauri::async_runtime::spawn_blocking(move || run(&argv, &cwd, &options))
+        .await
+        .unwrap_or_else(|e| {
+            Err(ExecError::Wait {
+                program: String::new(),
+                message: e.to_string(),
+            })
+        })
+}
+
+/// `cwd` resolved to a directory inside the workspace; the first root if
+/// `None`
+pub fn working_dir(workspace: &Workspace, cwd: Option<&Path>) -> Result<PathBuf, ExecError> {
+    let Some(cwd) = cwd else {
+        return Ok(workspace.pick_root(None)?.path);
+    };
+    let resolved = workspace.resolve(cwd)?;
+    if !resolved.is_dir() {
+        return Err(WorkspaceError::NotADirectory {
+            path: cwd.display().to_string(),
+        }
+        .into());
+    }
+    Ok(resolved)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str, options: &RunOptions) -> CommandOutput {
+        let argv = ["sh", "-c", script].map(String::from);
+        run(&argv, Path::new("."), options).unwrap()
+    }
+
+    #[test]
+    fn background_processes_end_with_the_program() {
+        let started = Instant::now();
+        let output = sh("sleep 30 & echo started", &RunOptions::default());
+        assert_eq!(output.exit_code, Some(0));
+        assert_eq!(output.stdout, "started\n");
+        assert!(!output.timed_out);
+        // The pipes closed without waiting for the sleep
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn timeout_kills_the_group() {
+        let options = RunOptions {
+            timeout_ms: Some(200),
+            ..RunOptions::default()
+        };
+        let output = sh("sleep 30 & sleep 30", &options);
+        assert!(output.timed_out);
+        assert_eq!(output.signal, Some(libc::SIGKILL));
+        assert_eq!(output.exit_code, None);
+    }
+
+    #[test]
+    fn output_keeps_head_and_tail() {
+        let options = RunOptions {
+            max_output_bytes: Some(8),
+            ..RunOptions::default()
+        };
+        let output = sh("printf 0123456789abcdef", &options);
+        assert_eq!(output.stdout_truncated, 8);
+        assert_eq!(output.stdout, "0123\n[... 8 bytes truncated ...]\ncdef");
+    }
+}
@@ -0,0 +1,110 @@
+//! Saving generated code to places outside the workspace
+//!
+//! The webview has no write access to the file system. These commands show
+//! the native save or folder dialog themselves and write only where the user
+//! pointed it, so a path can't be slipped in from the frontend.
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use serde::Deserialize;
+use tauri::AppHandle;
+use tauri_plugin_dialog::DialogExt;
+
+use crate::fs_edit::{self, FileEditError, Precondition};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportFile {
+    /// Relative to the folder the user picks
+    pub path: String,
+    pub content: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportFilter {
+    pub name: String,
+    pub extensions: Vec<String>,
+}
+
+/// Ask where to save `content` and write it there
+/// Returns the chosen path, or `None` if the user cancelled.
+#[tauri::command]
+pub async fn save_file_as(
+    app: AppHandle,
+    content: String,
+    default_name: Option<String>,
+    filters: Option<Vec<ExportFilter>>,
+) -> Result<Option<PathBuf>, FileEditError> {
+    let mut dialog = app.dialog().file();
+    if let Some(name) = default_name {
+        dialog = dialog.set_file_name(name);
+    }
+    for filter in filters.unwrap_or_default() {
+        let extensions: Vec<&str> = filter.extensions.iter().map(String::as_str).collect();
+        dialog = dialog.add_filter(filter.name, &extensions);
+    }
+    let chosen = tauri::async_runtime::spawn_blocking(move || dialog.blocking_save_file())
+        .await
+        .ok()
+        .flatten()
+        .and_then(|path| path.into_path().ok());
+    let Some(target) = chosen else {
+        return Ok(None);
+    };
+    fs_edit::write_atomic(&target, &content, &Precondition::default())?;
+    Ok(Some(target))
+}
+
+/// Ask for a folder and write `files` into it
+/// Returns the written paths, or an empty list if the user cancelled.
+#[tauri::command]
+pub async fn save_project_files(
+    app: AppHandle,
+    files: Vec<ExportFile>,
+) -> Result<Vec<PathBuf>, FileEditError> {
+    // Check every path before asking, so nothing is written for a bad list
+    for file in &files {
+        check_relative(&file.path)?;
+    }
+    let dialog = app
+        .dialog()
+        .file()
+        .set_title("Choose a folder for the project");
+    let chosen = tauri::async_runtime::spawn_blocking(move || dialog.blocking_pick_folder())
+        .await
+        .ok()
+        .flatten()
+        .and_then(|path| path.into_path().ok());
+    let Some(dir) = chosen else {
+        return Ok(Vec::new());
+    };
+
+    let mut written = Vec::new();
+    for file in files {
+        let target = dir.join(&file.path);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| FileEditError::from_io(parent, e))?;
+        }
+        fs_edit::write_atomic(&target, &file.content, &Precondition::default())?;
+        written.push(target);
+    }
+    Ok(written)
+}
+
+/// Refuse paths that would land outside the chosen folder
+fn check_relative(path: &str) -> Result<(), FileEditError> {
+    let plain = !path.is_empty()
+        && Path::new(path)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)));
+    if plain {
+        Ok(())
+    } else {
+        Err(FileEditError::InvalidOperation {
+            path: path.to_string(),
+            message: "project file paths must be relative and stay inside the folder".into(),
+        })
+    }
+}
@@ -0,0 +1,547 @@
+//! Crash-safe file writes for agent edits
+//!
+//! Content is written to a temp file next to the target, fsynced and then
+//! renamed over the original, so a crash or a full disk never leaves a
+//! half-written source file behind.
+
+#[cfg(unix)]
+use std::fs::Permissions;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::permissions::GrantError;
+use crate::workspace::WorkspaceError;
+
+/// Errors returned to the frontend by file edit commands
+///
+/// Serialized as `{ "kind": "diskFull", "path": "..." }` so the UI can
+/// branch on `kind` instead of parsing messages.
+#[derive(Debug, thiserror::Error, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum FileEditError {
+    #[error("path not found: {path}")]
+    PathMissing { path: String },
+    #[error("permission denied: {path}")]
+    PermissionDenied { path: String },
+    #[error("no space left on device while writing {path}")]
+    DiskFull { path: String },
+    #[error("{path} changed on disk since it was read")]
+    #[serde(rename_all = "camelCase")]
+    Conflict {
+        path: String,
+        /// Hash of the content now on disk, `None` if the file was deleted
+        current_hash: Option<String>,
+        current_mtime_ms: Option<u64>,
+    },
+    #[error(transparent)]
+    Sandbox { reason: WorkspaceError },
+    #[error("write to {path} refused: {reason}")]
+    NotApproved { path: String, reason: GrantError },
+    #[error("{path}: {message}")]
+    InvalidOperation { path: String, message: String },
+    #[error("edit journal failed: {message}")]
+    Journal { message: String },
+    #[error("failed to write {path}: {message}")]
+    Io { path: String, message: String },
+}
+
+impl FileEditError {
+    pub fn from_io(path: &Path, err: io::Error) -> Self {
+        let path = path.display().to_string();
+        match err.kind() {
+            ErrorKind::NotFound | ErrorKind::NotADirectory => Self::PathMissing { path },
+            ErrorKind::PermissionDenied | ErrorKind::ReadOnlyFilesystem => {
+                Self::PermissionDenied { path }
+            }
+            ErrorKind::StorageFull | ErrorKind::QuotaExceeded => Self::DiskFull { path },
+            _ => Self::Io {
+                path,
+                message: err.to_string(),
+            },
+        }
+    }
+}
+
+/// Hex SHA-256 of file content, as used for conflict checks
+pub fn content_hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Current text of `path`, or an empty string if it doesn't exist yet
+pub fn read_text_or_empty(path: &Path) -> Result<String, FileEditError> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(String::from_utf8_lossy(&bytes).into_owned()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(FileEditError::from_io(path, e)),
+    }
+}
+
+/// On-disk version of a file that an edit was based on
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileVersion {
+    pub hash: String,
+    pub mtime_ms: u64,
+}
+
+impl FileVersion {
+    pub fn read(path: &Path) -> Result<Self, FileEditError> {
+        let bytes = fs::read(path).map_err(|e| FileEditError::from_io(path, e))?;
+        let meta = fs::metadata(path).map_err(|e| FileEditError::from_io(path, e))?;
+        Ok(Self {
+            hash: content_hash(&bytes),
+            mtime_ms: mtime_ms(&meta),
+        })
+    }
+}
+
+/// What the caller expects to find on disk before overwriting
+///
+/// Either field may be left out; an empty precondition always passes.
+#[derive(Debug, Clone, Default)]
+pub struct Precondition {
+    pub hash: Option<String>,
+    pub mtime_ms: Option<u64>,
+    /// Expect the file not to exist at all
+    pub absent: bool,
+}
+
+impl Precondition {
+    /// Expect content with `hash`, or no file at all for `None`
+    pub fn content(hash: Option<&str>) -> Self {
+        match hash {
+            Some(hash) => Self {
+                hash: Some(hash.to_string()),
+                ..Self::default()
+            },
+            None => Self {
+                absent: true,
+                ..Self::default()
+            },
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.hash.is_none() && self.mtime_ms.is_none() && !self.absent
+    }
+
+    fn check(&self, path: &Path, current: Option<&FileVersion>) -> Result<(), FileEditError> {
+        let matches = match current {
+            _ if self.absent => current.is_none(),
+            Some(version) => {
+                self.hash
+                    .as_ref()
+                    .is_none_or(|h| h.eq_ignore_ascii_case(&version.hash))
+                    && self.mtime_ms.is_none_or(|m| m == version.mtime_ms)
+            }
+            None => false,
+        };
+        if matches {
+            return Ok(());
+        }
+        Err(FileEditError::Conflict {
+            path: path.display().to_string(),
+            current_hash: current.map(|v| v.hash.clone()),
+            current_mtime_ms: current.map(|v| v.mtime_ms),
+        })
+    }
+}
+
+fn mtime_ms(meta: &fs::Metadata) -> u64 {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+impl From<WorkspaceError> for FileEditError {
+    fn from(reason: WorkspaceError) -> Self {
+        Self::Sandbox { reason }
+    }
+}
+
+/// Line-ending convention of a text file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    /// Detect the dominant line ending, or `None` if there are no line breaks
+    pub fn detect(text: &str) -> Option<Self> {
+        let total = text.matches('\n').count();
+        if total == 0 {
+            return None;
+        }
+        let crlf = text.matches("\r\n").count();
+        Some(if crlf * 2 > total {
+            Self::CrLf
+        } else {
+            Self::Lf
+        })
+    }
+
+    /// Rewrite every line break in `text` to this convention
+    pub fn apply(self, text: &str) -> String {
+        let normalized = text.replace("\r\n", "\n");
+        match self {
+            Self::Lf => normalized,
+            Self::CrLf => normalized.replace('\n', "\r\n"),
+        }
+    }
+}
+
+/// Atomically replace `path` with `content`
+///
+/// Keeps the permissions, owner (where we may set it) and line-ending style
+/// of an existing file. Symlinks are followed so the link itself is
+/// preserved. Fails with `Conflict` if the file on disk no longer matches
+/// `expected`.
+pub fn write_atomic(
+    path: &Path,
+    content: &str,
+    expected: &Precondition,
+) -> Result<(), FileEditError> {
+    prepare(path, content, expected)?.commit()
+}
+
+/// A checked write that has not touched the target yet
+///
+/// Lets callers record what is about to change (e.g. in the edit journal)
+/// between validating a write and committing it.
+pub struct PreparedWrite {
+    target: PathBuf,
+    original: Option<fs::Metadata>,
+    /// Content on disk before the write, `None` for a new file
+    pub previous: Option<Vec<u8>>,
+    /// Exact bytes that will be written
+    pub bytes: Vec<u8>,
+}
+
+/// Validate a text write and adapt `content` to the file's line endings
+pub fn prepare(
+    path: &Path,
+    content: &str,
+    expected: &Precondition,
+) -> Result<PreparedWrite, FileEditError> {
+    let mut write = prepare_bytes(path, Vec::new(), expected)?;
+    let ending = write
+        .previous
+        .as_deref()
+        .and_then(|bytes| std::str::from_utf8(bytes).ok())
+        .and_then(LineEnding::detect);
+    write.bytes = match ending {
+        Some(ending) => ending.apply(content).into_bytes(),
+        None => content.as_bytes().to_vec(),
+    };
+    Ok(write)
+}
+
+/// Validate a write of `bytes` exactly as given
+pub fn prepare_bytes(
+    path: &Path,
+    bytes: Vec<u8>,
+    expected: &Precondition,
+) -> Result<PreparedWrite, FileEditError> {
+    let target = resolve_target(path)?;
+    let (original, previous) = read_current(&target)?;
+    if !expected.is_empty() {
+        expected.check(&target, current_version(&original, &previous).as_ref())?;
+    }
+    Ok(PreparedWrite {
+        target,
+        original,
+        previous,
+        bytes,
+    })
+}
+
+impl PreparedWrite {
+    /// Write to a temp file, fsync it and rename it over the target
+    pub fn commit(self) -> Result<(), FileEditError> {
+        self.stage()?.commit()
+    }
+
+    /// Write the new content to a synced temp file next to the target,
+    /// without touching the target yet
+    pub fn stage(self) -> Result<StagedWrite, FileEditError> {
+        let target = self.target;
+        let parent = parent_dir(&target);
+
+        let mut builder = tempfile::Builder::new();
+        builder.prefix(".henry-edit-").suffix(".tmp");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            // Same mode `fs::write` would use for new files; the umask still applies
+            builder.permissions(Permissions::from_mode(0o666));
+        }
+        let mut temp = builder
+            .tempfile_in(parent)
+            .map_err(|e| FileEditError::from_io(parent, e))?;
+
+        temp.write_all(&self.bytes)
+            .and_then(|_| temp.as_file().sync_all())
+            .map_err(|e| FileEditError::from_io(&target, e))?;
+
+        if let Some(meta) = &self.original {
+            copy_metadata(temp.as_file(), meta).map_err(|e| FileEditError::from_io(&target, e))?;
+        }
+
+        Ok(StagedWrite {
+            target,
+            temp,
+            previous: self.previous,
+            bytes: self.bytes,
+        })
+    }
+}
+
+/// New content sitting in a temp file, ready to be renamed into place
+pub struct StagedWrite {
+    target: PathBuf,
+    temp: tempfile::NamedTempFile,
+    /// Content on disk when the write was prepared, `None` for a new file
+    pub previous: Option<Vec<u8>>,
+    /// Exact bytes in the temp file
+    pub bytes: Vec<u8>,
+}
+
+impl StagedWrite {
+    pub fn target(&self) -> &Path {
+        &self.target
+    }
+
+    /// Rename the temp file over the target; dropping instead discards it
+    pub fn commit(self) -> Result<(), FileEditError> {
+        let target = self.target;
+        self.temp
+            .persist(&target)
+            .map_err(|e| FileEditError::from_io(&target, e.error))?;
+        sync_dir(parent_dir(&target));
+        Ok(())
+    }
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    path.parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+}
+
+/// Delete `path` if it still matches `expected`
+pub fn remove(path: &Path, expected: &Precondition) -> Result<(), FileEditError> {
+    let target = resolve_target(path)?;
+    let (original, previous) = read_current(&target)?;
+    if original.is_none() {
+        return Ok(());
+    }
+    if !expected.is_empty() {
+        expected.check(&target, current_version(&original, &previous).as_ref())?;
+    }
+    fs::remove_file(&target).map_err(|e| FileEditError::from_io(&target, e))?;
+    if let Some(parent) = target.parent() {
+        sync_dir(parent);
+    }
+    Ok(())
+}
+
+fn read_current(target: &Path) -> Result<(Option<fs::Metadata>, Option<Vec<u8>>), FileEditError> {
+    match fs::metadata(target) {
+        Ok(meta) => {
+            let bytes = fs::read(target).map_err(|e| FileEditError::from_io(target, e))?;
+            Ok((Some(meta), Some(bytes)))
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok((None, None)),
+        Err(e) => Err(FileEditError::from_io(target, e)),
+    }
+}
+
+fn current_version(meta: &Option<fs::Metadata>, bytes: &Option<Vec<u8>>) -> Option<FileVersion> {
+    meta.as_ref()
+        .zip(bytes.as_ref())
+        .map(|(meta, bytes)| FileVersion {
+            hash: content_hash(bytes),
+            mtime_ms: mtime_ms(meta),
+        })
+}
+
+/// Canonical location a write to `path` ends up at
+///
+/// Follows symlinks for existing files; new files resolve against their
+/// canonicalized parent directory.
+pub fn resolve_target(path: &Path) -> Result<PathBuf, FileEditError> {
+    match fs::canonicalize(path) {
+        Ok(resolved) => Ok(resolved),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+                return Err(FileEditError::from_io(path, e));
+            };
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            match fs::canonicalize(parent) {
+                Ok(parent) if parent.is_dir() => Ok(parent.join(name)),
+                Ok(_) => Err(FileEditError::PathMissing {
+                    path: parent.display().to_string(),
+                }),
+                Err(e) => Err(FileEditError::from_io(parent, e)),
+            }
+        }
+        Err(e) => Err(FileEditError::from_io(path, e)),
+    }
+}
+
+/// Give the temp file the original's owner and mode, as `cp -p` would
+///
+/// Taking over the owner needs privileges we usually lack, so that is best
+/// effort: the file then stays ours, without setuid/setgid bits that would
+/// now apply to us. Owner comes first since changing it clears those bits.
+#[cfg(unix)]
+fn copy_metadata(file: &File, original: &fs::Metadata) -> io::Result<()> {
+    use std::os::unix::fs::{fchown, MetadataExt, PermissionsExt};
+
+    let current = file.metadata()?;
+    let mut mode = original.mode();
+    if current.uid() != original.uid() || current.gid() != original.gid() {
+        match fchown(file, Some(original.uid()), Some(original.gid())) {
+            Ok(()) => {}
+            Err(e) if e.raw_os_error() == Some(libc::EPERM) => {
+                // The group may still be one we belong to
+                let _ = fchown(file, None, Some(original.gid()));
+                // setuid and setgid
+                mode &= !0o6000;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    file.set_permissions(Permissions::from_mode(mode & 0o7777))
+}
+
+#[cfg(not(unix))]
+fn copy_metadata(file: &File, original: &fs::Metadata) -> io::Result<()> {
+    file.set_permissions(original.permissions())
+}
+
+/// Persist the rename itself; best effort since not every platform supports it
+pub fn sync_dir(dir: &Path) {
+    #[cfg(unix)]
+    if let Ok(handle) = File::open(dir) {
+        let _ = handle.sync_all();
+    }
+    #[cfg(not(unix))]
+    let _ = dir;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_the_dominant_line_ending() {
+        assert_eq!(LineEnding::detect("one line"), None);
+        assert_eq!(LineEnding::detect("a\nb\n"), Some(LineEnding::Lf));
+        assert_eq!(LineEnding::detect("a\r\nb\r\n"), Some(LineEnding::CrLf));
+        assert_eq!(LineEnding::detect("a\r\nb\nc\n"), Some(LineEnding::Lf));
+        assert_eq!(LineEnding::CrLf.apply("a\nb\r\n"), "a\r\nb\r\n");
+        assert_eq!(LineEnding::Lf.apply("a\r\nb\n"), "a\nb\n");
+    }
+
+    #[test]
+    fn write_replaces_the_file_and_leaves_no_temp_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.rs");
+        write_atomic(&path, "fn main() {}\n", &Precondition::content(None)).unwrap();
+        write_atomic(&path, "fn main() { run() }\n", &Precondition::default()).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fn main() { run() }\n");
+        let names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["main.rs"]);
+    }
+
+    #[test]
+    fn write_keeps_the_files_line_endings() {
+        let dir = tempfile::tempdir().unwrap();
+        let crlf = dir.path().join("crlf.txt");
+        let lf = dir.path().join("lf.txt");
+        let new = dir.path().join("new.txt");
+        fs::write(&crlf, "a\r\nb\r\n").unwrap();
+        fs::write(&lf, "a\nb\n").unwrap();
+
+        write_atomic(&crlf, "x\ny\n", &Precondition::default()).unwrap();
+        write_atomic(&lf, "x\r\ny\r\n", &Precondition::default()).unwrap();
+        write_atomic(&new, "x\r\ny\n", &Precondition::default()).unwrap();
+
+        assert_eq!(fs::read_to_string(&crlf).unwrap(), "x\r\ny\r\n");
+        assert_eq!(fs::read_to_string(&lf).unwrap(), "x\ny\n");
+        assert_eq!(fs::read_to_string(&new).unwrap(), "x\r\ny\n");
+    }
+
+    #[test]
+    fn write_conflicts_when_the_file_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "read").unwrap();
+        let read = content_hash(b"read");
+        fs::write(&path, "changed").unwrap();
+
+        let err = write_atomic(&path, "edit", &Precondition::content(Some(&read))).unwrap_err();
+        match err {
+            FileEditError::Conflict { current_hash, .. } => {
+                assert_eq!(current_hash, Some(content_hash(b"changed")));
+            }
+            other => panic!("expected a conflict, got {other:?}"),
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "changed");
+
+        // Hashes compare case-insensitively; a file expected absent must be
+        let current = content_hash(b"changed").to_uppercase();
+        write_atomic(&path, "edit", &Precondition::content(Some(&current))).unwrap();
+        assert!(matches!(
+            write_atomic(&path, "again", &Precondition::content(None)),
+            Err(FileEditError::Conflict { .. })
+        ));
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            write_atomic(&path, "again", &Precondition::content(Some(&read))),
+            Err(FileEditError::Conflict {
+                current_hash: None,
+                ..
+            })
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_keeps_the_mode_and_symlinks() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("script.sh");
+        let link = dir.path().join("link.sh");
+        fs::write(&path, "echo one\n").unwrap();
+        fs::set_permissions(&path, Permissions::from_mode(0o750)).unwrap();
+        std::os::unix::fs::symlink(&path, &link).unwrap();
+
+        write_atomic(&link, "echo two\n", &Precondition::default()).unwrap();
+
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "echo two\n");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o7777, 0o750);
+    }
+}
@@ -0,0 +1,1321 @@
+//! Import graph and cross-file references
+//!
+//! When a file is indexed, its imports and the identifiers it mentions are
+//! read from the same tree-sitter parse as its outline and stored in its
+//! `FileRecord`. The graph is derived from those records on demand and
+//! cached per root until the index changes, so it follows the watcher just
+//! like the index does. Only imports that resolve to a file of the same
+//! workspace become edges; third-party packages are left out.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use tree_sitter::{Node, Tree};
+
+use crate::indexer::{FileRecord, IndexError, Indexer};
+use crate::symbols::{self, SymbolKind};
+use crate::workspace::Workspace;
+
+/// Levels of dependents followed when the caller doesn't ask for a depth
+const DEFAULT_DEPTH: usize = 3;
+
+const MAX_DEPTH: usize = 16;
+
+/// References returned by one `find_references` call
+const MAX_REFERENCES: usize = 1000;
+
+/// Extensions tried, in order, for a script import written without one
+const SCRIPT_EXTENSIONS: &[&str] = &["ts", "tsx", "d.ts", "js", "jsx", "mjs", "cjs"];
+
+/// One import statement, as written
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Import {
+    /// Module specifier, e.g. `./utils`, `crate::fs_edit` or `os.path`
+    pub source: String,
+    /// Names imported from the module, `*` for a namespace or wildcard
+    /// import; empty when the module itself is imported
+    pub names: Vec<String>,
+    pub line: usize,
+}
+
+impl Import {
+    /// Whether every export of the module is reachable through this import
+    fn is_whole(&self) -> bool {
+        self.names.is_empty() || self.names.iter().any(|n| n == "*")
+    }
+}
+
+/// Imports of a parsed file, empty for languages without import rules
+pub fn imports(language: &str, tree: &Tree, text: &str) -> Vec<Import> {
+    let source = text.as_bytes();
+    let mut imports = Vec::new();
+    for_each_node(tree, |node| match language {
+        "typescript" | "typescriptreact" | "javascript" | "javascriptreact" => {
+            script_import(node, source, &mut imports)
+        }
+        "python" => python_import(node, source, &mut imports),
+        "rust" => rust_import(node, source, &mut imports),
+        "go" => go_import(node, source, &mut imports),
+        "java" => java_import(node, source, &mut imports),
+        "csharp" => csharp_import(node, source, &mut imports),
+        _ => {}
+    });
+    imports
+}
+
+/// Distinct identifiers a parsed file mentions, sorted
+pub fn identifiers(tree: &Tree, text: &str) -> Vec<String> {
+    let mut names = BTreeSet::new();
+    for_each_node(tree, |node| {
+        if is_identifier(node) {
+            names.insert(node_text(node, text.as_bytes()).to_string());
+        }
+    });
+    names.into_iter().collect()
+}
+
+/// Pre-order walk of the whole tree, without recursion
+fn for_each_node(tree: &Tree, mut visit: impl FnMut(Node)) {
+    let mut cursor = tree.walk();
+    loop {
+        visit(cursor.node());
+        if cursor.goto_first_child() {
+            continue;
+        }
+        loop {
+            if cursor.goto_next_sibling() {
+                break;
+            }
+            if !cursor.goto_parent() {
+                return;
+            }
+        }
+    }
+}
+
+fn is_identifier(node: Node) -> bool {
+    node.child_count() == 0 && node.kind().ends_with("identifier")
+}
+
+fn node_text<'s>(node: Node, source: &'s [u8]) -> &'s str {
+    node.utf8_text(source).unwrap_or_default()
+}
+
+fn unquote(text: &str) -> &str {
+    text.trim_matches(|c| matches!(c, '"' | '\'' | '`'))
+}
+
+fn line(node: Node) -> usize {
+    node.start_position().row + 1
+}
+
+fn script_import(node: Node, source: &[u8], out: &mut Vec<Import>) {
+    match node.kind() {
+        "import_statement" | "export_statement" => {
+            let Some(module) = node.child_by_field_name("source") else {
+                return;
+            };
+            let mut names = Vec::new();
+            let mut cursor = node.walk();
+            for child in node.named_children(&mut cursor) {
+                match child.kind() {
+                    "import_clause" => {
+                        let mut cursor = child.walk();
+                        for part in child.named_children(&mut cursor) {
+                            match part.kind() {
+                                // Default import, named after what it binds
+                                "identifier" => names.push(node_text(part, source).to_string()),
+                                "namespace_import" => names.push("*".to_string()),
+                                "named_imports" => specifiers(part, source, &mut names),
+                                _ => {}
+                            }
+                        }
+                    }
+                    "export_clause" => specifiers(child, source, &mut names),
+                    _ => {}
+                }
+            }
+            // `export * from` re-exports everything
+            if node.kind() == "export_statement" && names.is_empty() {
+                names.push("*".to_string());
+            }
+            out.push(Import {
+                source: unquote(node_text(module, source)).to_string(),
+                names,
+                line: line(node),
+            });
+        }
+        "call_expression" => {
+            let (Some(function), Some(arguments)) = (
+                node.child_by_field_name("function"),
+                node.child_by_field_name("arguments"),
+            ) else {
+                return;
+            };
+            if function.kind() != "import" && node_text(function, source) != "require" {
+                return;
+            }
+            if let Some(module) = arguments.named_child(0).filter(|a| a.kind() == "string") {
+                out.push(Import {
+                    source: unquote(node_text(module, source)).to_string(),
+                    names: Vec::new(),
+                    line: line(node),
+                });
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Original names of the `import_specifier`s or `export_specifier`s in `node`
+fn specifiers(node: Node, source: &[u8], names: &mut Vec<String>) {
+    let mut cursor = node.walk();
+    for specifier in node.named_children(&mut cursor) {
+        if let Some(name) = specifier.child_by_field_name("name") {
+            names.push(unquote(node_text(name, source)).to_string());
+        }
+    }
+}
+
+fn python_import(node: Node, source: &[u8], out: &mut Vec<Import>) {
+    // `import a.b as c` binds a module; the alias doesn't matter for edges
+    let dotted = |node: Node| match node.kind() {
+        "aliased_import" => node
+            .child_by_field_name("name")
+            .map_or("", |name| node_text(name, source)),
+        _ => node_text(node, source),
+    };
+    match node.kind() {
+        "import_statement" => {
+            let mut cursor = node.walk();
+            for module in node.children_by_field_name("name", &mut cursor) {
+                out.push(Import {
+                    source: dotted(module).to_string(),
+                    names: Vec::new(),
+                    line: line(node),
+                });
+            }
+        }
+        "import_from_statement" => {
+            let Some(module) = node.child_by_field_name("module_name") else {
+                return;
+            };
+            let mut cursor = node.walk();
+            let mut names: Vec<String> = node
+                .children_by_field_name("name", &mut cursor)
+                .map(|name| dotted(name).to_string())
+                .collect();
+            let mut cursor = node.walk();
+            if node
+                .named_children(&mut cursor)
+                .any(|child| child.kind() == "wildcard_import")
+            {
+                names.push("*".to_string());
+            }
+            out.push(Import {
+                source: node_text(module, source).to_string(),
+                names,
+                line: line(node),
+            });
+        }
+        _ => {}
+    }
+}
+
+fn rust_import(node: Node, source: &[u8], out: &mut Vec<Import>) {
+    match node.kind() {
+        // `mod foo;` pulls in a file; inline modules don't
+        "mod_item" if node.child_by_field_name("body").is_none() => {
+            if let Some(name) = node.child_by_field_name("name") {
+                out.push(Import {
+                    source: format!("self::{}", node_text(name, source)),
+                    names: Vec::new(),
+                    line: line(node),
+                });
+            }
+        }
+        "use_declaration" => {
+            let Some(argument) = node.child_by_field_name("argument") else {
+                return;
+            };
+            let mut paths = Vec::new();
+            use_paths(argument, "", source, &mut paths);
+            // One import per module, like the other languages
+            let mut modules: BTreeMap<String, Vec<String>> = BTreeMap::new();
+            for path in paths {
+                match path.rsplit_once("::") {
+                    Some((module, "self")) => modules.entry(module.to_string()).or_default(),
+                    Some((module, name)) => {
+                        let names = modules.entry(module.to_string()).or_default();
+                        names.push(name.to_string());
+                        names
+                    }
+                    None => modules.entry(path).or_default(),
+                };
+            }
+            out.extend(modules.into_iter().map(|(source, names)| Import {
+                source,
+                names,
+                line: line(node),
+            }));
+        }
+        _ => {}
+    }
+}
+
+/// Flatten a use tree like `crate::a::{b, c::*}` into full paths
+fn use_paths(node: Node, prefix: &str, source: &[u8], out: &mut Vec<String>) {
+    let join = |tail: &str| match prefix {
+        "" => tail.to_string(),
+        _ => format!("{}::{}", prefix, tail),
+    };
+    match node.kind() {
+        "scoped_use_list" => {
+            let prefix = node
+                .child_by_field_name("path")
+                .map_or_else(|| prefix.to_string(), |p| join(node_text(p, source)));
+            if let Some(list) = node.child_by_field_name("list") {
+                use_paths(list, &prefix, source, out);
+            }
+        }
+        "use_list" => {
+            let mut cursor = node.walk();
+            for item in node.named_children(&mut cursor) {
+                use_paths(item, prefix, source, out);
+            }
+        }
+        "use_as_clause" => {
+            if let Some(path) = node.child_by_field_name("path") {
+                use_paths(path, prefix, source, out);
+            }
+        }
+        _ => out.push(join(node_text(node, source))),
+    }
+}
+
+fn go_import(node: Node, source: &[u8], out: &mut Vec<Import>) {
+    if node.kind() != "import_spec" {
+        return;
+    }
+    if let Some(path) = node.child_by_field_name("path") {
+        out.push(Import {
+            source: unquote(node_text(path, source)).to_string(),
+            names: Vec::new(),
+            line: line(node),
+        });
+    }
+}
+
+fn java_import(node: Node, source: &[u8], out: &mut Vec<Import>) {
+    if node.kind() != "import_declaration" {
+        return;
+    }
+    let text = node_text(node, source);
+    let path: String = text
+        .trim_start_matches("import")
+        .trim_start()
+        .trim_start_matches("static ")
+        .trim_end_matches(';')
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    if let Some((package, name)) = path.rsplit_once('.') {
+        out.push(Import {
+            source: package.to_string(),
+            names: vec![name.to_string()],
+            line: line(node),
+        });
+    }
+}
+
+fn csharp_import(node: Node, source: &[u8], out: &mut Vec<Import>) {
+    if node.kind() != "using_directive" {
+        return;
+    }
+    // The namespace is last, after `static` or an alias
+    let mut cursor = node.walk();
+    if let Some(name) = node.named_children(&mut cursor).last() {
+        out.push(Import {
+            source: node_text(name, source).to_string(),
+            names: Vec::new(),
+            line: line(node),
+        });
+    }
+}
+
+/// Maps import specifiers to indexed files
+struct Resolver<'i> {
+    files: &'i BTreeMap<PathBuf, FileRecord>,
+    /// Files directly inside each directory
+    dirs: HashMap<&'i Path, Vec<&'i Path>>,
+    /// Files by file name, for Java's package-qualified imports
+    names: HashMap<&'i OsStr, Vec<&'i Path>>,
+    /// Files declaring each C# namespace
+    namespaces: HashMap<&'i str, Vec<&'i Path>>,
+    /// Module path from the root's `go.mod`
+    go_module: Option<String>,
+}
+
+impl<'i> Resolver<'i> {
+    fn new(root: &Path, files: &'i BTreeMap<PathBuf, FileRecord>) -> Self {
+        let mut dirs: HashMap<&Path, Vec<&Path>> = HashMap::new();
+        let mut names: HashMap<&OsStr, Vec<&Path>> = HashMap::new();
+        let mut namespaces: HashMap<&str, Vec<&Path>> = HashMap::new();
+        for (path, record) in files {
+            if let (Some(parent), Some(name)) = (path.parent(), path.file_name()) {
+                dirs.entry(parent).or_default().push(path);
+                names.entry(name).or_default().push(path);
+            }
+            if record.language.as_deref() == Some("csharp") {
+                for symbol in symbols::flatten(&record.symbols) {
+                    if symbol.kind == SymbolKind::Module {
+                        namespaces.entry(&symbol.name).or_default().push(path);
+                    }
+                }
+            }
+        }
+        let go_module = fs::read_to_string(root.join("go.mod"))
+            .ok()
+            .and_then(|text| {
+                text.lines().find_map(|l| {
+                    l.trim()
+                        .strip_prefix("module ")
+                        .map(|m| m.trim().to_string())
+                })
+            });
+        Self {
+            files,
+            dirs,
+            names,
+            namespaces,
+            go_module,
+        }
+    }
+
+    /// Indexed files `import` of `from` refers to
+    fn resolve(&self, from: &FileRecord, import: &Import) -> Vec<&'i Path> {
+        let found = match from.language.as_deref() {
+            Some("typescript" | "typescriptreact" | "javascript" | "javascriptreact") => self
+                .script(&from.path, &import.source)
+                .into_iter()
+                .collect(),
+            Some("python") => self.python(&from.path, import),
+            Some("rust") => self.rust(&from.path, import),
+            Some("go") => self.go(&import.source),
+            Some("java") => self.java(import),
+            Some("csharp") => self
+                .namespaces
+                .get(import.source.as_str())
+                .cloned()
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        found.into_iter().filter(|p| *p != from.path).collect()
+    }
+
+    fn existing(&self, path: &Path) -> Option<&'i Path> {
+        self.files.get_key_value(path).map(|(p, _)| p.as_path())
+    }
+
+    /// Relative specifiers only; bare ones name packages
+    fn script(&self, from: &Path, specifier: &str) -> Option<&'i Path> {
+        if !specifier.starts_with('.') {
+            return None;
+        }
+        let base = normalize(&from.parent()?.join(specifier))?;
+        let mut candidates = vec![base.clone()];
+        // ESM-style TypeScript imports name the emitted `.js` file
+        let stem = ["js", "jsx", "mjs", "cjs"]
+            .iter()
+            .any(|ext| base.extension() == Some(OsStr::new(ext)))
+            .then(|| base.with_extension(""));
+        for base in stem.iter().chain([&base]) {
+            candidates.extend(
+                SCRIPT_EXTENSIONS
+                    .iter()
+                    .map(|e| with_suffix(base, &format!(".{}", e))),
+            );
+        }
+        candidates.extend(
+            SCRIPT_EXTENSIONS
+                .iter()
+                .map(|e| base.join(format!("index.{}", e))),
+        );
+        candidates.iter().find_map(|c| self.existing(c))
+    }
+
+    fn python(&self, from: &Path, import: &Import) -> Vec<&'i Path> {
+        let source = import.source.as_str();
+        let dots = source.len() - source.trim_start_matches('.').len();
+        let module: PathBuf = source[dots..]
+            .split('.')
+            .filter(|s| !s.is_empty())
+            .collect();
+        // Relative imports climb from the package; absolute ones may be
+        // rooted at any ancestor, e.g. a `src` layout
+        let bases: Vec<&Path> = if dots > 0 {
+            from.ancestors().nth(dots).into_iter().collect()
+        } else {
+            from.ancestors().skip(1).collect()
+        };
+        for base in bases {
+            let module = base.join(&module);
+            let mut found: Vec<&Path> = self.python_module(&module).into_iter().collect();
+            // `from pkg import mod` imports a submodule
+            found.extend(
+                import
+                    .names
+                    .iter()
+                    .filter_map(|name| self.python_module(&module.join(name))),
+            );
+            if !found.is_empty() {
+                return found;
+            }
+        }
+        Vec::new()
+    }
+
+    fn python_module(&self, module: &Path) -> Option<&'i Path> {
+        self.existing(&with_suffix(module, ".py"))
+            .or_else(|| self.existing(&module.join("__init__.py")))
+    }
+
+    fn rust(&self, from: &Path, import: &Import) -> Vec<&'i Path> {
+        let mut segments: VecDeque<&str> = import.source.split("::").collect();
+        let (dir, anchored) = match segments.front().copied() {
+            Some("crate") => {
+                segments.pop_front();
+                let Some(dir) = self.crate_dir(from) else {
+                    return Vec::new();
+                };
+                (dir, true)
+            }
+            Some("self") => {
+                segments.pop_front();
+                (rust_module_dir(from), true)
+            }
+            Some("super") => {
+                let mut dir = rust_module_dir(from);
+                while segments.front() == Some(&"super") {
+                    segments.pop_front();
+                    match dir.parent() {
+                        Some(parent) => dir = parent.to_path_buf(),
+                        None => return Vec::new(),
+                    }
+                }
+                (dir, true)
+            }
+            // Anything else is an extern crate or a child module
+            _ => (rust_module_dir(from), false),
+        };
+
+        // A name may itself be a module; otherwise the longest module prefix
+        // of the path is the file that defines it
+        let names: Vec<Option<&str>> = match import.names.as_slice() {
+            [] => vec![None],
+            names => names.iter().map(|n| Some(n.as_str())).collect(),
+        };
+        let mut found = Vec::new();
+        for name in names {
+            let path: Vec<&str> = segments
+                .iter()
+                .copied()
+                .chain(name.filter(|n| *n != "*"))
+                .collect();
+            let shortest = usize::from(!anchored);
+            let module = (shortest..=path.len()).rev().find_map(|len| {
+                self.rust_module(&dir.join(path[..len].iter().collect::<PathBuf>()))
+            });
+            if let Some(module) = module.filter(|m| !found.contains(m)) {
+                found.push(module);
+            }
+        }
+        found
+    }
+
+    fn rust_module(&self, module: &Path) -> Option<&'i Path> {
+        ["mod.rs", "lib.rs", "main.rs"]
+            .iter()
+            .map(|file| module.join(file))
+            .chain([with_suffix(module, ".rs")])
+            .find_map(|candidate| self.existing(&candidate))
+    }
+
+    /// The nearest directory above `from` with a crate root
+    fn crate_dir(&self, from: &Path) -> Option<PathBuf> {
+        from.ancestors()
+            .skip(1)
+            .find(|dir| {
+                self.existing(&dir.join("lib.rs")).is_some()
+                    || self.existing(&dir.join("main.rs")).is_some()
+            })
+            .map(Path::to_path_buf)
+    }
+
+    /// Packages of the root module; every file of the package is an edge
+    fn go(&self, import: &str) -> Vec<&'i Path> {
+        let Some(package) = self
+            .go_module
+            .as_deref()
+            .and_then(|module| import.strip_prefix(module))
+        else {
+            return Vec::new();
+        };
+        let dir = Path::new(package.trim_start_matches('/'));
+        self.dirs
+            .get(dir)
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(|p| {
+                let name = p.to_string_lossy();
+                name.ends_with(".go") && !name.ends_with("_test.go")
+            })
+            .collect()
+    }
+
+    /// Packages can sit under any source root, so paths match by suffix
+    fn java(&self, import: &Import) -> Vec<&'i Path> {
+        let package: PathBuf = import.source.split('.').collect();
+        let mut found = Vec::new();
+        for name in &import.names {
+            if name == "*" {
+                for (dir, files) in &self.dirs {
+                    if dir.ends_with(&package) {
+                        found.extend(
+                            files
+                                .iter()
+                                .filter(|f| f.extension() == Some(OsStr::new("java"))),
+                        );
+                    }
+                }
+                continue;
+            }
+            // A class, or a member of the class `package` names in a
+            // static import
+            let class = package.join(format!("{}.java", name));
+            let outer = with_suffix(&package, ".java");
+            for target in [class, outer] {
+                let matches: Vec<&Path> = target
+                    .file_name()
+                    .and_then(|n| self.names.get(n))
+                    .into_iter()
+                    .flatten()
+                    .copied()
+                    .filter(|p| p.ends_with(&target))
+                    .collect();
+                if !matches.is_empty() {
+                    found.extend(matches);
+                    break;
+                }
+            }
+        }
+        found
+    }
+}
+
+/// Directory holding the child modules of the Rust file `path`
+fn rust_module_dir(path: &Path) -> PathBuf {
+    let parent = path.parent().unwrap_or(Path::new(""));
+    match path.file_stem().and_then(|s| s.to_str()) {
+        Some("mod" | "lib" | "main") | None => parent.to_path_buf(),
+        Some(stem) => parent.join(stem),
+    }
+}
+
+/// Resolve `.` and `..` lexically; `None` if the path leaves the root
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::Normal(part) => normalized.push(part),
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(normalized)
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+/// Imports from one file of another, merged across statements
+#[derive(Debug, Clone)]
+struct Edge {
+    from: PathBuf,
+    to: PathBuf,
+    names: BTreeSet<String>,
+    /// The whole module is imported, by namespace, wildcard or name
+    whole: bool,
+    line: usize,
+}
+
+impl Edge {
+    /// Whether the importer can see any of `symbols`; whole-module imports
+    /// count only if the importer mentions one of them
+    fn covers(&self, symbols: &[String], importer: Option<&FileRecord>) -> bool {
+        symbols.is_empty()
+            || symbols.iter().any(|s| self.names.contains(s))
+            || (self.whole
+                && importer.is_some_and(|f| {
+                    symbols
+                        .iter()
+                        .any(|s| f.identifiers.binary_search(s).is_ok())
+                }))
+    }
+}
+
+/// Resolved imports of one workspace root, in both directions
+pub struct ImportGraph {
+    root: PathBuf,
+    generation: u64,
+    imports: HashMap<PathBuf, Vec<Edge>>,
+    dependents: HashMap<PathBuf, Vec<Edge>>,
+}
+
+impl ImportGraph {
+    fn build(root: &Path, generation: u64, files: &BTreeMap<PathBuf, FileRecord>) -> Self {
+        let resolver = Resolver::new(root, files);
+        let mut imports: HashMap<PathBuf, Vec<Edge>> = HashMap::new();
+        for file in files.values() {
+            let mut edges: BTreeMap<&Path, Edge> = BTreeMap::new();
+            for import in &file.imports {
+                for target in resolver.resolve(file, import) {
+                    let edge = edges.entry(target).or_insert_with(|| Edge {
+                        from: file.path.clone(),
+                        to: target.to_path_buf(),
+                        names: BTreeSet::new(),
+                        whole: false,
+                        line: import.line,
+                    });
+                    edge.names
+                        .extend(import.names.iter().filter(|n| *n != "*").cloned());
+                    edge.whole |= import.is_whole();
+                    edge.line = edge.line.min(import.line);
+                }
+            }
+            if !edges.is_empty() {
+                imports.insert(file.path.clone(), edges.into_values().collect());
+            }
+        }
+        let mut dependents: HashMap<PathBuf, Vec<Edge>> = HashMap::new();
+        for edge in imports.values().flatten() {
+            dependents
+                .entry(edge.to.clone())
+                .or_default()
+                .push(edge.clone());
+        }
+        Self {
+            root: root.to_path_buf(),
+            generation,
+            imports,
+            dependents,
+        }
+    }
+
+    /// `path` reached through `edge` from `via`
+    fn link(&self, edge: &Edge, path: &Path, via: &Path, depth: usize) -> Link {
+        Link {
+            path: self.root.join(path),
+            depth,
+            via: self.root.join(via),
+            names: edge.names.iter().cloned().collect(),
+            line: edge.line,
+        }
+    }
+
+    /// Files that `path` imports
+    fn imports_of(&self, path: &Path) -> Vec<Link> {
+        self.imports
+            .get(path)
+            .into_iter()
+            .flatten()
+            .map(|edge| self.link(edge, &edge.to, path, 1))
+            .collect()
+    }
+
+    /// Files importing `start` directly or through up to `depth` levels,
+    /// breadth first; `seen` files are never reported
+    fn dependents_of(
+        &self,
+        start: Vec<(PathBuf, usize)>,
+        depth: usize,
+        seen: &mut HashSet<PathBuf>,
+    ) -> Vec<Link> {
+        let mut queue: VecDeque<(PathBuf, usize)> = start.into();
+        let mut found = Vec::new();
+        while let Some((path, level)) = queue.pop_front() {
+            if level >= depth {
+                continue;
+            }
+            for edge in self.dependents.get(&path).into_iter().flatten() {
+                if seen.insert(edge.from.clone()) {
+                    found.push(self.link(edge, &edge.from, &edge.to, level + 1));
+                    queue.push_back((edge.from.clone(), level + 1));
+                }
+            }
+        }
+        found
+    }
+
+    /// Files within `depth` imports of `path` in either direction, with
+    /// their distance; `path` itself is at 0
+    fn distances(&self, path: &Path, depth: usize) -> HashMap<PathBuf, usize> {
+        let mut distances = HashMap::from([(path.to_path_buf(), 0)]);
+        let mut queue = VecDeque::from([(path.to_path_buf(), 0)]);
+        while let Some((path, level)) = queue.pop_front() {
+            if level >= depth {
+                continue;
+            }
+            let imported = self.imports.get(&path).into_iter().flatten();
+            let importing = self.dependents.get(&path).into_iter().flatten();
+            for next in imported.map(|e| &e.to).chain(importing.map(|e| &e.from)) {
+                if !distances.contains_key(next) {
+                    distances.insert(next.clone(), level + 1);
+                    queue.push_back((next.clone(), level + 1));
+                }
+            }
+        }
+        distances
+    }
+
+    fn impact(
+        &self,
+        path: &Path,
+        symbols: Vec<String>,
+        files: &BTreeMap<PathBuf, FileRecord>,
+    ) -> Impact {
+        let mut seen = HashSet::from([path.to_path_buf()]);
+        let direct: Vec<Link> = self
+            .dependents
+            .get(path)
+            .into_iter()
+            .flatten()
+            .filter(|edge| edge.covers(&symbols, files.get(&edge.from)))
+            .filter(|edge| seen.insert(edge.from.clone()))
+            .map(|edge| self.link(edge, &edge.from, path, 1))
+            .collect();
+        let start = direct
+            .iter()
+            .filter_map(|link| link.path.strip_prefix(&self.root).ok())
+            .map(|p| (p.to_path_buf(), 1))
+            .collect();
+        let transitive = self.dependents_of(start, DEFAULT_DEPTH + 1, &mut seen);
+        let affected = direct.len() + transitive.len();
+        Impact {
+            path: self.root.join(path),
+            symbols,
+            direct,
+            transitive,
+            risk: match affected {
+                0..=2 => Risk::Low,
+                3..=10 => Risk::Medium,
+                _ => Risk::High,
+            },
+        }
+    }
+}
+
+/// Import graphs per workspace root, managed as Tauri state
+#[derive(Default)]
+pub struct ImportGraphs {
+    graphs: Mutex<HashMap<PathBuf, Arc<ImportGraph>>>,
+}
+
+impl ImportGraphs {
+    /// Run `query` against the graph of `root`, rebuilding it first if the
+    /// index changed since it was cached
+    fn query<T>(
+        &self,
+        indexer: &Indexer,
+        root: &Path,
+        query: impl FnOnce(&ImportGraph, &BTreeMap<PathBuf, FileRecord>) -> T,
+    ) -> Result<T, IndexError> {
+        indexer
+            .with_files(root, |generation, files| {
+                let cached = self.graphs.lock().unwrap().get(root).cloned();
+                let graph = match cached {
+                    Some(graph) if graph.generation == generation => graph,
+                    _ => {
+                        let graph = Arc::new(ImportGraph::build(root, generation, files));
+                        self.graphs
+                            .lock()
+                            .unwrap()
+                            .insert(root.to_path_buf(), Arc::clone(&graph));
+                        graph
+                    }
+                };
+                query(&graph, files)
+            })
+            .ok_or_else(|| IndexError::NotIndexed {
+                root: root.display().to_string(),
+            })
+    }
+
+    /// Import distance from `relative` to the files of `root` within `depth`
+    /// imports of it, whichever way they point
+    pub fn distances(
+        &self,
+        indexer: &Indexer,
+        root: &Path,
+        relative: &Path,
+        depth: usize,
+    ) -> Result<HashMap<PathBuf, usize>, IndexError> {
+        self.query(indexer, root, |graph, _| graph.distances(relative, depth))
+    }
+}
+
+/// A file related to the queried one through an import
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Link {
+    pub path: PathBuf,
+    /// Imports between `path` and the queried file; 1 is a direct import
+    pub depth: usize,
+    /// The file on the other end of the import, one step closer to the
+    /// queried file
+    pub via: PathBuf,
+    /// Names imported through this edge
+    pub names: Vec<String>,
+    /// Line of the import statement in the importing file
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Risk {
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Impact {
+    pub path: PathBuf,
+    pub symbols: Vec<String>,
+    /// Files importing one of the changed symbols
+    pub direct: Vec<Link>,
+    /// Files importing a direct dependent, and so on
+    pub transitive: Vec<Link>,
+    pub risk: Risk,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reference {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    /// The trimmed source line
+    pub text: String,
+    /// Whether this is the declaration itself rather than a use
+    pub definition: bool,
+}
+
+/// Occurrences of `symbol` as an identifier in `relative`, read from disk
+fn scan_references(
+    root: &Path,
+    relative: &Path,
+    language: &str,
+    symbol: &str,
+    out: &mut Vec<Reference>,
+) {
+    let Ok(text) = fs::read_to_string(root.join(relative)) else {
+        return;
+    };
+    let Some(tree) = symbols::parse(language, &text) else {
+        return;
+    };
+    let lines: Vec<&str> = text.lines().collect();
+    for_each_node(&tree, |node| {
+        if out.len() >= MAX_REFERENCES
+            || !is_identifier(node)
+            || node_text(node, text.as_bytes()) != symbol
+        {
+            return;
+        }
+        let position = node.start_position();
+        let definition = node.parent().is_some_and(|parent| {
+            is_declaration(parent.kind())
+                && parent
+                    .child_by_field_name("name")
+                    .is_some_and(|name| name.id() == node.id())
+        });
+        out.push(Reference {
+            path: root.join(relative),
+            line: position.row + 1,
+            column: position.column + 1,
+            text: lines
+                .get(position.row)
+                .map_or_else(String::new, |l| l.trim().to_string()),
+            definition,
+        });
+    });
+}
+
+fn is_declaration(kind: &str) -> bool {
+    [
+        "_declaration",
+        "_definition",
+        "_item",
+        "_declarator",
+        "_spec",
+        "_signature",
+    ]
+    .iter()
+    .any(|suffix| kind.ends_with(suffix))
+}
+
+/// Workspace files that `path` imports
+#[tauri::command]
+pub async fn find_imports(
+    workspace: State<'_, Workspace>,
+    indexer: State<'_, Indexer>,
+    graphs: State<'_, ImportGraphs>,
+    path: String,
+) -> Result<Vec<Link>, IndexError> {
+    let (root, relative) = workspace.locate(Path::new(&path))?;
+    graphs.query(&indexer, &root.path, |graph, _| graph.imports_of(&relative))
+}
+
+/// Files importing `path`, directly or through up to `depth` (default 3)
+/// levels of imports
+#[tauri::command]
+pub async fn find_dependents(
+    workspace: State<'_, Workspace>,
+    indexer: State<'_, Indexer>,
+    graphs: State<'_, ImportGraphs>,
+    path: String,
+    depth: Option<usize>,
+) -> Result<Vec<Link>, IndexError> {
+    let (root, relative) = workspace.locate(Path::new(&path))?;
+    let depth = depth.unwrap_or(DEFAULT_DEPTH).clamp(1, MAX_DEPTH);
+    graphs.query(&indexer, &root.path, |graph, _| {
+        let mut seen = HashSet::from([relative.clone()]);
+        graph.dependents_of(vec![(relative.clone(), 0)], depth, &mut seen)
+    })
+}
+
+/// Files affected by changing `symbols` in `path`, or by changing the file
+/// as a whole if no symbols are given
+#[tauri::command]
+pub async fn impact_of_change(
+    workspace: State<'_, Workspace>,
+    indexer: State<'_, Indexer>,
+    graphs: State<'_, ImportGraphs>,
+    path: String,
+    symbols: Option<Vec<String>>,
+) -> Result<Impact, IndexError> {
+    let (root, relative) = workspace.locate(Path::new(&path))?;
+    graphs.query(&indexer, &root.path, |graph, files| {
+        graph.impact(&relative, symbols.unwrap_or_default(), files)
+    })
+}
+
+/// Identifier occurrences of `symbol`
+///
+/// With `path`, only the file defining it and the files that can see it
+/// through imports are searched; without it, every file of `root` (the
+/// first open root by default) that mentions the name.
+#[tauri::command]
+pub async fn find_references(
+    app: AppHandle,
+    workspace: State<'_, Workspace>,
+    symbol: String,
+    path: Option<String>,
+    root: Option<PathBuf>,
+) -> Result<Vec<Reference>, IndexError> {
+    let (root, defined_in) = match path {
+        Some(path) => {
+            let (root, relative) = workspace.locate(Path::new(&path))?;
+            (root, Some(relative))
+        }
+        None => (workspace.pick_root(root.as_deref())?, None),
+    };
+    tauri::async_runtime::spawn_blocking(move || {
+        let indexer = app.state::<Indexer>();
+        let graphs = app.state::<ImportGraphs>();
+        let mentions = |file: &FileRecord| file.identifiers.binary_search(&symbol).is_ok();
+        let candidates: Vec<(PathBuf, String)> =
+            graphs.query(&indexer, &root.path, |graph, files| {
+                let paths: Vec<PathBuf> = match &defined_in {
+                    Some(defined_in) => {
+                        let impact = graph.impact(defined_in, vec![symbol.clone()], files);
+                        std::iter::once(defined_in.clone())
+                            .chain(
+                                impact
+                                    .direct
+                                    .iter()
+                                    .chain(&impact.transitive)
+                                    .filter_map(|l| l.path.strip_prefix(&root.path).ok())
+                                    .map(Path::to_path_buf),
+                            )
+                            .collect()
+                    }
+                    None => files.keys().cloned().collect(),
+                };
+                paths
+                    .into_iter()
+                    .filter_map(|path| {
+                        let file = files.get(&path).filter(|f| mentions(f))?;
+                        Some((path, file.language.clone()?))
+                    })
+                    .collect()
+            })?;
+        let mut references = Vec::new();
+        for (relative, language) in candidates {
+            scan_references(&root.path, &relative, &language, &symbol, &mut references);
+        }
+        Ok(references)
+    })
+    .await
+    .map_err(|e| IndexError::Storage {
+        message: e.to_string(),
+    })?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::language;
+
+    /// Index records for `files`, parsed as the indexer would
+    fn records(files: &[(&str, &str)]) -> BTreeMap<PathBuf, FileRecord> {
+        files
+            .iter()
+            .map(|(path, text)| {
+                let language = language::detect(Path::new(path)).unwrap();
+                let tree = symbols::parse(language, text).unwrap();
+                let record = FileRecord {
+                    path: PathBuf::from(path),
+                    hash: String::new(),
+                    size: text.len() as u64,
+                    mtime_ms: None,
+                    language: Some(language.to_string()),
+                    symbols: symbols::outline(language, &tree, text),
+                    imports: imports(language, &tree, text),
+                    identifiers: identifiers(&tree, text),
+                };
+                (record.path.clone(), record)
+            })
+            .collect()
+    }
+
+    /// `(path, depth, via)` of each link, relative to the root
+    fn hops(links: &[Link]) -> Vec<(String, usize, String)> {
+        let relative = |p: &Path| {
+            p.strip_prefix("/ws")
+                .unwrap()
+                .to_string_lossy()
+                .into_owned()
+        };
+        links
+            .iter()
+            .map(|l| (relative(&l.path), l.depth, relative(&l.via)))
+            .collect()
+    }
+
+    fn hop(path: &str, depth: usize, via: &str) -> (String, usize, String) {
+        (path.to_string(), depth, via.to_string())
+    }
+
+    fn dependents(graph: &ImportGraph, path: &str, depth: usize) -> Vec<Link> {
+        let mut seen = HashSet::from([PathBuf::from(path)]);
+        graph.dependents_of(vec![(PathBuf::from(path), 0)], depth, &mut seen)
+    }
+
+    #[test]
+    fn dependents_stop_at_the_depth() {
+        // core <- a <- b <- c, and core imports c back
+        let files = records(&[
+            (
+                "core.ts",
+                "import { run } from './c';\nexport const load = 1;\n",
+            ),
+            (
+                "a.ts",
+                "import { load } from './core';\nexport const a = load;\n",
+            ),
+            ("b.ts", "import { a } from './a';\nexport const b = a;\n"),
+            (
+                "c.ts",
+                "import * as b from './b.js';\nexport const run = b;\n",
+            ),
+        ]);
+        let graph = ImportGraph::build(Path::new("/ws"), 0, &files);
+
+        assert_eq!(
+            hops(&dependents(&graph, "core.ts", 1)),
+            [hop("a.ts", 1, "core.ts")]
+        );
+        assert_eq!(
+            hops(&dependents(&graph, "core.ts", 2)),
+            [hop("a.ts", 1, "core.ts"), hop("b.ts", 2, "a.ts")]
+        );
+        // The cycle back to `core.ts` is not followed
+        assert_eq!(
+            hops(&dependents(&graph, "core.ts", MAX_DEPTH)),
+            [
+                hop("a.ts", 1, "core.ts"),
+                hop("b.ts", 2, "a.ts"),
+                hop("c.ts", 3, "b.ts"),
+            ]
+        );
+        assert_eq!(
+            hops(&graph.imports_of(Path::new("c.ts"))),
+            [hop("b.ts", 1, "c.ts")]
+        );
+    }
+
+    #[test]
+    fn impact_follows_the_changed_symbols() {
+        let files = records(&[
+            (
+                "util.ts",
+                "export function parse() {}\nexport function format() {}\n",
+            ),
+            (
+                "x.ts",
+                "import { parse } from './util';\nexport const x = parse;\n",
+            ),
+            ("y.ts", "import { format } from './util';\nformat();\n"),
+            // A namespace import counts only where the name is used
+            ("z.ts", "import * as u from './util';\nu.format();\n"),
+            ("idle.ts", "import * as u from './util';\nconsole.log(u);\n"),
+            ("w.ts", "import { x } from './x';\n"),
+        ]);
+        let graph = ImportGraph::build(Path::new("/ws"), 0, &files);
+        let util = Path::new("util.ts");
+
+        let parse = graph.impact(util, vec!["parse".into()], &files);
+        assert_eq!(hops(&parse.direct), [hop("x.ts", 1, "util.ts")]);
+        assert_eq!(hops(&parse.transitive), [hop("w.ts", 2, "x.ts")]);
+        assert_eq!(parse.risk, Risk::Low);
+
+        let format = graph.impact(util, vec!["format".into()], &files);
+        let mut direct = hops(&format.direct);
+        direct.sort();
+        assert_eq!(
+            direct,
+            [hop("y.ts", 1, "util.ts"), hop("z.ts", 1, "util.ts")]
+        );
+        assert!(format.transitive.is_empty());
+
+        let whole = graph.impact(util, Vec::new(), &files);
+        assert_eq!(whole.direct.len(), 4);
+        assert_eq!(whole.transitive.len(), 1);
+        assert_eq!(whole.risk, Risk::Medium);
+    }
+
+    #[test]
+    fn impact_reaches_past_the_default_depth_once() {
+        // f0 <- f1 <- … <- f7
+        let texts: Vec<(String, String)> = (0..8)
+            .map(|i| {
+                let text = match i {
+                    0 => "export const v = 0;\n".to_string(),
+                    i => format!("import {{ v }} from './f{}';\n", i - 1),
+                };
+                (format!("f{i}.ts"), text)
+            })
+            .collect();
+        let files: Vec<(&str, &str)> = texts
+            .iter()
+            .map(|(p, t)| (p.as_str(), t.as_str()))
+            .collect();
+        let files = records(&files);
+        let graph = ImportGraph::build(Path::new("/ws"), 0, &files);
+
+        let impact = graph.impact(Path::new("f0.ts"), vec!["v".into()], &files);
+        assert_eq!(hops(&impact.direct), [hop("f1.ts", 1, "f0.ts")]);
+        let depths: Vec<usize> = impact.transitive.iter().map(|l| l.depth).collect();
+        assert_eq!(depths, [2, 3, 4]);
+        assert_eq!(impact.risk, Risk::Medium);
+    }
+
+    #[test]
+    fn distances_go_both_ways() {
+        let files = records(&[
+            ("a.ts", "import { b } from './b';\n"),
+            ("b.ts", "import { c } from './c';\nexport const b = 1;\n"),
+            ("c.ts", "export const c = 1;\n"),
+            ("d.ts", "import { b } from './b';\n"),
+        ]);
+        let graph = ImportGraph::build(Path::new("/ws"), 0, &files);
+        let distances = graph.distances(Path::new("b.ts"), 1);
+        let mut found: Vec<(&str, usize)> = distances
+            .iter()
+            .map(|(p, d)| (p.to_str().unwrap(), *d))
+            .collect();
+        found.sort();
+        assert_eq!(found, [("a.ts", 1), ("b.ts", 0), ("c.ts", 1), ("d.ts", 1)]);
+        assert_eq!(graph.distances(Path::new("a.ts"), 2).len(), 4);
+        assert_eq!(graph.distances(Path::new("a.ts"), 1).len(), 2);
+    }
+
+    #[test]
+    fn rust_and_python_imports_resolve() {
+        let files = records(&[
+            ("src/lib.rs", "mod fs_edit;\nmod journal;\n"),
+            ("src/fs_edit.rs", "pub fn write() {}\n"),
+            (
+                "src/journal.rs",
+                "use crate::fs_edit::{self, write};\nuse std::fs;\n",
+            ),
+            ("pkg/__init__.py", ""),
+            ("pkg/store.py", "from . import util\nfrom .util import *\n"),
+            ("pkg/util.py", "import os.path\n"),
+        ]);
+        let journal = &files[Path::new("src/journal.rs")];
+        assert_eq!(
+            journal.imports,
+            [
+                Import {
+                    source: "crate::fs_edit".into(),
+                    names: vec!["write".into()],
+                    line: 1,
+                },
+                Import {
+                    source: "std".into(),
+                    names: vec!["fs".into()],
+                    line: 2,
+                },
+            ]
+        );
+        let graph = ImportGraph::build(Path::new("/ws"), 0, &files);
+        assert_eq!(
+            hops(&graph.imports_of(Path::new("src/lib.rs"))),
+            [
+                hop("src/fs_edit.rs", 1, "src/lib.rs"),
+                hop("src/journal.rs", 1, "src/lib.rs"),
+            ]
+        );
+        assert_eq!(
+            hops(&graph.imports_of(Path::new("src/journal.rs"))),
+            [hop("src/fs_edit.rs", 1, "src/journal.rs")]
+        );
+        assert_eq!(
+            hops(&graph.imports_of(Path::new("pkg/store.py"))),
+            [
+                hop("pkg/__init__.py", 1, "pkg/store.py"),
+                hop("pkg/util.py", 1, "pkg/store.py"),
+            ]
+        );
+        assert!(graph.imports_of(Path::new("pkg/util.py")).is_empty());
+    }
+}
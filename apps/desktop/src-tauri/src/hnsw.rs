@@ -0,0 +1,373 @@
+//! Hierarchical navigable small world graph (Malkov & Yashunin, 2016)
+//!
+//! Approximate nearest neighbours over unit vectors by cosine distance.
+//! The vectors stay with the caller, flattened into one slice; the graph
+//! only stores links between node ids. Nodes are never unlinked, so
+//! callers tombstone deleted ones and rebuild once too many are dead.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+use serde::{Deserialize, Serialize};
+
+/// Links per node on the upper layers
+const M: usize = 16;
+
+/// Links per node on layer 0, which holds every node
+const M0: usize = 2 * M;
+
+/// Candidates considered when linking a new node
+const EF_CONSTRUCTION: usize = 128;
+
+const MAX_LEVEL: usize = 16;
+
+/// Vectors of the graph's nodes, `dimension` floats each
+#[derive(Clone, Copy)]
+pub struct Vectors<'v> {
+    pub data: &'v [f32],
+    pub dimension: usize,
+}
+
+impl<'v> Vectors<'v> {
+    fn get(&self, id: u32) -> &'v [f32] {
+        let start = id as usize * self.dimension;
+        &self.data[start..start + self.dimension]
+    }
+}
+
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    // Independent lanes let the compiler vectorize the sum
+    let mut lanes = [0.0f32; 8];
+    let (a_chunks, b_chunks) = (a.chunks_exact(8), b.chunks_exact(8));
+    let tail: f32 = a_chunks
+        .remainder()
+        .iter()
+        .zip(b_chunks.remainder())
+        .map(|(x, y)| x * y)
+        .sum();
+    for (x, y) in a_chunks.zip(b_chunks) {
+        for i in 0..8 {
+            lanes[i] += x[i] * y[i];
+        }
+    }
+    lanes.iter().sum::<f32>() + tail
+}
+
+/// Cosine distance between unit vectors
+fn distance(a: &[f32], b: &[f32]) -> f32 {
+    1.0 - dot(a, b)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Scored {
+    distance: f32,
+    id: u32,
+}
+
+impl Eq for Scored {}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.id.cmp(&other.id))
+    }
+}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Bitset of the nodes a search has reached
+struct Visited(Vec<u64>);
+
+impl Visited {
+    fn new(nodes: usize) -> Self {
+        Self(vec![0; nodes.div_ceil(64)])
+    }
+
+    /// Mark `id`, returning whether it was new
+    fn insert(&mut self, id: u32) -> bool {
+        let (word, bit) = (id as usize / 64, 1u64 << (id % 64));
+        let new = self.0[word] & bit == 0;
+        self.0[word] |= bit;
+        new
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hnsw {
+    /// Links of each node, from layer 0 up to the node's own level
+    links: Vec<Vec<Vec<u32>>>,
+    entry: Option<u32>,
+    /// xorshift state for drawing levels, kept so rebuilds are reproducible
+    rng: u64,
+}
+
+impl Default for Hnsw {
+    fn default() -> Self {
+        Self {
+            links: Vec::new(),
+            entry: None,
+            rng: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+}
+
+impl Hnsw {
+    /// Link node `links.len()`, whose vector is already in `vectors`
+    pub fn insert(&mut self, vectors: Vectors) {
+        let id = self.links.len() as u32;
+        let level = self.random_level();
+        self.links.push(vec![Vec::new(); level + 1]);
+        let query = vectors.get(id);
+        let Some(entry) = self.entry else {
+            self.entry = Some(id);
+            return;
+        };
+
+        let top = self.level(entry);
+        let mut nearest = vec![Scored {
+            distance: distance(query, vectors.get(entry)),
+            id: entry,
+        }];
+        for layer in (level + 1..=top).rev() {
+            nearest = self.search_layer(vectors, query, &nearest, 1, layer);
+        }
+        for layer in (0..=level.min(top)).rev() {
+            let candidates = self.search_layer(vectors, query, &nearest, EF_CONSTRUCTION, layer);
+            let selected = select(vectors, &candidates, M);
+            let max = if layer == 0 { M0 } else { M };
+            for &neighbour in &selected {
+                let links = &mut self.links[neighbour as usize][layer];
+                links.push(id);
+                if links.len() > max {
+                    // Keep the neighbour's best links, by the same heuristic
+                    let base = vectors.get(neighbour);
+                    let mut scored: Vec<Scored> = links
+                        .iter()
+                        .map(|&other| Scored {
+                            distance: distance(base, vectors.get(other)),
+                            id: other,
+                        })
+                        .collect();
+                    scored.sort();
+                    *links = select(vectors, &scored, max);
+                }
+            }
+            self.links[id as usize][layer] = selected;
+            nearest = candidates;
+        }
+        if level > top {
+            self.entry = Some(id);
+        }
+    }
+
+    /// Up to `ef` approximate nearest nodes to `query`, nearest first, with
+    /// their distances
+    pub fn search(&self, vectors: Vectors, query: &[f32], ef: usize) -> Vec<(u32, f32)> {
+        let Some(entry) = self.entry else {
+            return Vec::new();
+        };
+        let mut nearest = vec![Scored {
+            distance: distance(query, vectors.get(entry)),
+            id: entry,
+        }];
+        for layer in (1..=self.level(entry)).rev() {
+            nearest = self.search_layer(vectors, query, &nearest, 1, layer);
+        }
+        self.search_layer(vectors, query, &nearest, ef.max(1), 0)
+            .into_iter()
+            .map(|s| (s.id, s.distance))
+            .collect()
+    }
+
+    fn level(&self, id: u32) -> usize {
+        self.links[id as usize].len() - 1
+    }
+
+    /// Best-first search of one layer from `entries`, keeping the `ef`
+    /// nearest nodes seen, sorted nearest first
+    fn search_layer(
+        &self,
+        vectors: Vectors,
+        query: &[f32],
+        entries: &[Scored],
+        ef: usize,
+        layer: usize,
+    ) -> Vec<Scored> {
+        let mut visited = Visited::new(self.links.len());
+        for entry in entries {
+            visited.insert(entry.id);
+        }
+        let mut candidates: BinaryHeap<Reverse<Scored>> =
+            entries.iter().copied().map(Reverse).collect();
+        let mut found: BinaryHeap<Scored> = entries.iter().copied().collect();
+        while found.len() > ef {
+            found.pop();
+        }
+
+        while let Some(Reverse(candidate)) = candidates.pop() {
+            let worst = found.peek().map_or(f32::INFINITY, |s| s.distance);
+            if candidate.distance > worst && found.len() >= ef {
+                break;
+            }
+            for &neighbour in &self.links[candidate.id as usize][layer] {
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+                let scored = Scored {
+                    distance: distance(query, vectors.get(neighbour)),
+                    id: neighbour,
+                };
+                let worst = found.peek().map_or(f32::INFINITY, |s| s.distance);
+                if found.len() < ef || scored.distance < worst {
+                    candidates.push(Reverse(scored));
+                    found.push(scored);
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+        found.into_sorted_vec()
+    }
+
+    /// Exponentially distributed level, so each layer has about 1/M of the
+    /// nodes of the one below
+    fn random_level(&mut self) -> usize {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        let uniform = ((self.rng >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+        let level = -uniform.ln() / (M as f64).ln();
+        (level as usize).min(MAX_LEVEL)
+    }
+}
+
+/// Pick up to `m` links from `candidates` (sorted nearest first), skipping
+/// ones closer to an already picked link than to the base, so links spread
+/// in different directions; skipped ones fill any remaining slots
+fn select(vectors: Vectors, candidates: &[Scored], m: usize) -> Vec<u32> {
+    let mut selected: Vec<Scored> = Vec::with_capacity(m);
+    let mut skipped = Vec::new();
+    for &candidate in candidates {
+        if selected.len() >= m {
+            break;
+        }
+        let point = vectors.get(candidate.id);
+        if selected
+            .iter()
+            .all(|s| distance(point, vectors.get(s.id)) > candidate.distance)
+        {
+            selected.push(candidate);
+        } else {
+            skipped.push(candidate);
+        }
+    }
+    let missing = m.saturating_sub(selected.len());
+    selected.extend(skipped.into_iter().take(missing));
+    selected.into_iter().map(|s| s.id).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit vectors from a fixed seed
+    fn random_vectors(count: usize, dimension: usize, mut seed: u64) -> Vec<f32> {
+        let mut data = Vec::with_capacity(count * dimension);
+        for _ in 0..count {
+            let start = data.len();
+            for _ in 0..dimension {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                data.push((seed >> 40) as f32 / (1u64 << 24) as f32 - 0.5);
+            }
+            let norm = dot(&data[start..], &data[start..]).sqrt();
+            data[start..].iter_mut().for_each(|x| *x /= norm);
+        }
+        data
+    }
+
+    fn build(data: &[f32], dimension: usize) -> Hnsw {
+        let mut graph = Hnsw::default();
+        for id in 0..data.len() / dimension {
+            graph.insert(Vectors {
+                data: &data[..(id + 1) * dimension],
+                dimension,
+            });
+        }
+        graph
+    }
+
+    #[test]
+    fn empty_graph_finds_nothing() {
+        let vectors = Vectors {
+            data: &[],
+            dimension: 4,
+        };
+        assert!(Hnsw::default()
+            .search(vectors, &[1.0, 0.0, 0.0, 0.0], 10)
+            .is_empty());
+    }
+
+    #[test]
+    fn search_returns_nearest_first() {
+        let dimension = 8;
+        let data = random_vectors(300, dimension, 7);
+        let vectors = Vectors {
+            data: &data,
+            dimension,
+        };
+        let graph = build(&data, dimension);
+        let hits = graph.search(vectors, vectors.get(42), 20);
+        assert_eq!(hits[0].0, 42);
+        assert!(hits[0].1.abs() < 1e-5);
+        assert!(hits.windows(2).all(|w| w[0].1 <= w[1].1));
+    }
+
+    #[test]
+    fn recall_against_brute_force() {
+        let (dimension, k) = (24, 10);
+        let data = random_vectors(2000, dimension, 11);
+        let vectors = Vectors {
+            data: &data,
+            dimension,
+        };
+        let graph = build(&data, dimension);
+        let queries = random_vectors(50, dimension, 99);
+
+        let mut found = 0;
+        for query in queries.chunks_exact(dimension) {
+            let mut exact: Vec<(u32, f32)> = (0..2000u32)
+                .map(|id| (id, distance(query, vectors.get(id))))
+                .collect();
+            exact.sort_by(|a, b| a.1.total_cmp(&b.1));
+            let approximate: Vec<u32> = graph
+                .search(vectors, query, 64)
+                .into_iter()
+                .take(k)
+                .map(|(id, _)| id)
+                .collect();
+            found += exact[..k]
+                .iter()
+                .filter(|(id, _)| approximate.contains(id))
+                .count();
+        }
+        let recall = found as f32 / (50 * k) as f32;
+        assert!(recall >= 0.95, "recall {recall}");
+    }
+
+    #[test]
+    fn rebuilds_are_reproducible() {
+        let dimension = 8;
+        let data = random_vectors(200, dimension, 3);
+        let (a, b) = (build(&data, dimension), build(&data, dimension));
+        assert_eq!(a.links, b.links);
+        assert_eq!(a.entry, b.entry);
+    }
+}
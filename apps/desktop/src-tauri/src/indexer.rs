@@ -0,0 +1,767 @@
+//! Persistent code index per workspace
+//!
+//! Replaces the Node-only `CodeIndexer`. A full build walks the workspace
+//! with the same ignore rules as the project tree, and hashes and scans
+//! files in parallel. Unchanged files (same size and mtime, or same hash)
+//! are carried over from the previous index. Afterwards the index follows
+//! `fs://changed` batches from the watcher; batches that arrive during a
+//! build are queued and applied once it finishes. Each root's index is one
+//! JSON file under the app data directory, so it survives restarts. Saves
+//! after watcher batches are throttled, and `flush` writes out the rest.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::fs_edit::{self, FileEditError, Precondition};
+use crate::graph::{self, Import};
+use crate::language;
+use crate::symbols::{self, Symbol, SymbolKind};
+use crate::tree;
+use crate::watcher::FsChanged;
+use crate::workspace::{Workspace, WorkspaceError, WorkspaceRoot};
+
+/// Emitted while a build runs and once when it finishes
+pub const PROGRESS_EVENT: &str = "index://progress";
+
+/// Bumped whenever `FileRecord` changes shape; older indexes are rebuilt
+const INDEX_VERSION: u32 = 3;
+
+/// Larger files are generated or data, not code worth indexing
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+/// Bytes checked for NUL when deciding whether a file is binary
+const BINARY_SNIFF_LEN: usize = 8000;
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Watcher batches save the index at most this often
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+const DEFAULT_SEARCH_LIMIT: usize = 50;
+
+#[derive(Debug, thiserror::Error, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum IndexError {
+    #[error("{root} has not been indexed")]
+    NotIndexed { root: String },
+    #[error("{root} is already being indexed")]
+    Busy { root: String },
+    #[error(transparent)]
+    Sandbox { reason: WorkspaceError },
+    #[error("code index storage failed: {message}")]
+    Storage { message: String },
+}
+
+impl From<WorkspaceError> for IndexError {
+    fn from(reason: WorkspaceError) -> Self {
+        Self::Sandbox { reason }
+    }
+}
+
+impl From<io::Error> for IndexError {
+    fn from(err: io::Error) -> Self {
+        Self::Storage {
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for IndexError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Storage {
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<FileEditError> for IndexError {
+    fn from(err: FileEditError) -> Self {
+        Self::Storage {
+            message: err.to_string(),
+        }
+    }
+}
+
+/// What the index knows about one file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileRecord {
+    /// Relative to the workspace root
+    pub path: PathBuf,
+    pub hash: String,
+    pub size: u64,
+    pub mtime_ms: Option<u64>,
+    pub language: Option<String>,
+    pub symbols: Vec<Symbol>,
+    pub imports: Vec<Import>,
+    /// Distinct identifiers in the file, sorted, to find references by name
+    pub identifiers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WorkspaceIndex {
+    version: u32,
+    root: PathBuf,
+    indexed_at_ms: u64,
+    files: BTreeMap<PathBuf, FileRecord>,
+    /// Changes whenever the index does, so derived data can be cached
+    #[serde(skip)]
+    generation: u64,
+    #[serde(skip)]
+    dirty: bool,
+    #[serde(skip)]
+    saved: Option<Instant>,
+}
+
+impl WorkspaceIndex {
+    fn new(root: &Path) -> Self {
+        Self {
+            version: INDEX_VERSION,
+            root: root.to_path_buf(),
+            indexed_at_ms: now_ms(),
+            files: BTreeMap::new(),
+            generation: 0,
+            dirty: false,
+            saved: None,
+        }
+    }
+
+    /// Drop `relative` and everything below it
+    fn remove(&mut self, relative: &Path) {
+        self.files.retain(|path, _| !path.starts_with(relative));
+    }
+
+    /// Re-read `path` (a file or a directory to walk) into the index
+    fn refresh(&mut self, path: &Path) {
+        let Ok(relative) = path.strip_prefix(&self.root).map(Path::to_path_buf) else {
+            return;
+        };
+        if path.is_dir() {
+            for file in walk_files(path) {
+                if let Ok(relative) = file.strip_prefix(&self.root).map(Path::to_path_buf) {
+                    self.update(relative, &file);
+                }
+            }
+        } else {
+            self.update(relative, path);
+        }
+    }
+
+    fn update(&mut self, relative: PathBuf, path: &Path) {
+        match index_file(&relative, path, self.files.get(&relative)) {
+            Some(record) => self.files.insert(relative, record),
+            None => self.files.remove(&relative),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IndexState {
+    Missing,
+    Indexing,
+    Ready,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexStatus {
+    pub root: PathBuf,
+    pub state: IndexState,
+    pub files: usize,
+    pub symbols: usize,
+    pub indexed_at_ms: Option<u64>,
+    /// Files processed so far in a running build
+    pub processed: usize,
+    /// Files the running build will process
+    pub total: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HitKind {
+    File,
+    Symbol,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub root: PathBuf,
+    /// Relative to `root`
+    pub path: PathBuf,
+    pub kind: HitKind,
+    pub name: String,
+    pub symbol_kind: Option<SymbolKind>,
+    pub line: Option<usize>,
+    pub language: Option<String>,
+    pub score: f32,
+}
+
+type SharedIndex = Arc<RwLock<WorkspaceIndex>>;
+
+/// A running build
+#[derive(Default)]
+struct Build {
+    processed: usize,
+    total: usize,
+    /// Watcher batches that arrived meanwhile, applied when it finishes
+    queued: Vec<FsChanged>,
+}
+
+/// Loaded indexes and running builds, managed as Tauri state
+pub struct Indexer {
+    dir: PathBuf,
+    indexes: Mutex<HashMap<PathBuf, SharedIndex>>,
+    running: Mutex<HashMap<PathBuf, Build>>,
+    generations: AtomicU64,
+}
+
+impl Indexer {
+    pub fn open(dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            indexes: Mutex::new(HashMap::new()),
+            running: Mutex::new(HashMap::new()),
+            generations: AtomicU64::new(0),
+        })
+    }
+
+    /// (Re)build the index of `root`, reporting progress as it goes
+    ///
+    /// Blocking; files unchanged since the last build are reused unless
+    /// `force` is set.
+    pub fn build(
+        &self,
+        root: &WorkspaceRoot,
+        force: bool,
+        progress: impl Fn(&IndexStatus) + Sync,
+    ) -> Result<IndexStatus, IndexError> {
+        {
+            let mut running = self.running.lock().unwrap();
+            if running.contains_key(&root.path) {
+                return Err(IndexError::Busy {
+                    root: root.path.display().to_string(),
+                });
+            }
+            running.insert(root.path.clone(), Build::default());
+        }
+        let result = self.build_inner(root, force, &progress);
+        let queued = self
+            .running
+            .lock()
+            .unwrap()
+            .remove(&root.path)
+            .map(|build| build.queued)
+            .unwrap_or_default();
+        // The walk may have passed these files already; a failed build
+        // leaves the previous index, which needs them just the same
+        let applied = self.apply(&root.path, &queued);
+        let status = self.status(&root.path);
+        progress(&status);
+        result.and(applied).map(|_| status)
+    }
+
+    fn build_inner(
+        &self,
+        root: &WorkspaceRoot,
+        force: bool,
+        progress: &(impl Fn(&IndexStatus) + Sync),
+    ) -> Result<(), IndexError> {
+        let previous = match self.loaded(&root.path) {
+            Some(index) if !force => index.read().unwrap().files.clone(),
+            _ => BTreeMap::new(),
+        };
+        let files: Vec<(PathBuf, PathBuf)> = walk_files(&root.path)
+            .into_iter()
+            .filter_map(|path| {
+                let relative = path.strip_prefix(&root.path).ok()?.to_path_buf();
+                root.denied_by(&relative)
+                    .is_none()
+                    .then_some((relative, path))
+            })
+            .collect();
+        let total = files.len();
+
+        let next = AtomicUsize::new(0);
+        let processed = AtomicUsize::new(0);
+        let workers = thread::available_parallelism().map_or(4, |n| n.get());
+        let records: Vec<FileRecord> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut records = Vec::new();
+                        loop {
+                            let i = next.fetch_add(1, Ordering::Relaxed);
+                            let Some((relative, path)) = files.get(i) else {
+                                break;
+                            };
+                            if let Some(record) = index_file(relative, path, previous.get(relative))
+                            {
+                                records.push(record);
+                            }
+                            processed.fetch_add(1, Ordering::Relaxed);
+                        }
+                        records
+                    })
+                })
+                .collect();
+
+            // At least once, so the total is known even for a quick build
+            loop {
+                if let Some(build) = self.running.lock().unwrap().get_mut(&root.path) {
+                    build.processed = processed.load(Ordering::Relaxed);
+                    build.total = total;
+                }
+                progress(&self.status(&root.path));
+                if handles.iter().all(|h| h.is_finished()) {
+                    break;
+                }
+                thread::sleep(PROGRESS_INTERVAL);
+            }
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap_or_default())
+                .collect()
+        });
+
+        let mut index = WorkspaceIndex::new(&root.path);
+        index.files = records
+            .into_iter()
+            .map(|record| (record.path.clone(), record))
+            .collect();
+        index.generation = self.next_generation();
+        index.dirty = true;
+        self.persist(&mut index, true)?;
+        self.indexes
+            .lock()
+            .unwrap()
+            .insert(root.path.clone(), Arc::new(RwLock::new(index)));
+        Ok(())
+    }
+
+    pub fn status(&self, root: &Path) -> IndexStatus {
+        let running = self
+            .running
+            .lock()
+            .unwrap()
+            .get(root)
+            .map(|build| (build.processed, build.total));
+        let index = self.loaded(root);
+        let index = index.as_ref().map(|i| i.read().unwrap());
+        IndexStatus {
+            root: root.to_path_buf(),
+            state: match (running, &index) {
+                (Some(_), _) => IndexState::Indexing,
+                (None, Some(_)) => IndexState::Ready,
+                (None, None) => IndexState::Missing,
+            },
+            files: index.as_ref().map_or(0, |i| i.files.len()),
+            symbols: index.as_ref().map_or(0, |i| {
+                i.files
+                    .values()
+                    .map(|f| symbols::flatten(&f.symbols).len())
+                    .sum()
+            }),
+            indexed_at_ms: index.as_ref().map(|i| i.indexed_at_ms),
+            processed: running.map_or(0, |(done, _)| done),
+            total: running.map_or(0, |(_, total)| total),
+        }
+    }
+
+    /// Files and symbols of `roots` matching `query`, best first
+    pub fn search(&self, roots: &[PathBuf], query: &str, limit: usize) -> Vec<SearchHit> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let mut hits = Vec::new();
+        for root in roots {
+            let Some(index) = self.loaded(root) else {
+                continue;
+            };
+            let index = index.read().unwrap();
+            for file in index.files.values() {
+                let name = file
+                    .path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let path_score =
+                    (file.path.to_string_lossy().to_lowercase().contains(&query)).then_some(0.5);
+                if let Some(score) = match_score(&query, &name)
+                    .into_iter()
+                    .chain(path_score)
+                    .reduce(f32::max)
+                {
+                    hits.push(SearchHit {
+                        root: root.clone(),
+                        path: file.path.clone(),
+                        kind: HitKind::File,
+                        name,
+                        symbol_kind: None,
+                        line: None,
+                        language: file.language.clone(),
+                        score,
+                    });
+                }
+                for symbol in symbols::flatten(&file.symbols) {
+                    if let Some(score) = match_score(&query, &symbol.name) {
+                        hits.push(SearchHit {
+                            root: root.clone(),
+                            path: file.path.clone(),
+                            kind: HitKind::Symbol,
+                            name: symbol.name.clone(),
+                            symbol_kind: Some(symbol.kind),
+                            line: Some(symbol.range.start.line),
+                            language: file.language.clone(),
+                            // Definitions beat files of the same name
+                            score: score + 0.05,
+                        });
+                    }
+                }
+            }
+        }
+        hits.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.path.as_os_str().len().cmp(&b.path.as_os_str().len()))
+        });
+        hits.truncate(limit);
+        hits
+    }
+
+    /// Fold a watcher batch into the index of its root, if one is loaded,
+    /// or queue it for the build running there
+    pub fn apply_changes(&self, changed: &FsChanged) -> Result<(), IndexError> {
+        if let Some(build) = self.running.lock().unwrap().get_mut(&changed.root) {
+            build.queued.push(changed.clone());
+            return Ok(());
+        }
+        self.apply(&changed.root, std::slice::from_ref(changed))
+    }
+
+    /// Save every index with unsaved changes
+    pub fn flush(&self) -> Result<(), IndexError> {
+        let indexes: Vec<SharedIndex> = self.indexes.lock().unwrap().values().cloned().collect();
+        let mut result = Ok(());
+        for index in indexes {
+            let saved = self.persist(&mut index.write().unwrap(), true);
+            result = result.and(saved);
+        }
+        result
+    }
+
+    /// Fold `batches`, in order, into the index of `root` if one is loaded
+    fn apply(&self, root: &Path, batches: &[FsChanged]) -> Result<(), IndexError> {
+        if batches.is_empty() {
+            return Ok(());
+        }
+        let Some(index) = self.loaded(root) else {
+            return Ok(());
+        };
+        let mut index = index.write().unwrap();
+        let relative = |path: &Path| path.strip_prefix(root).map(Path::to_path_buf);
+
+        for changed in batches {
+            for path in &changed.removed {
+                if let Ok(relative) = relative(path) {
+                    index.remove(&relative);
+                }
+            }
+            for rename in &changed.renamed {
+                if let Ok(from) = relative(&rename.from) {
+                    index.remove(&from);
+                }
+                index.refresh(&rename.to);
+            }
+            for path in changed.created.iter().chain(&changed.modified) {
+                index.refresh(path);
+            }
+        }
+        index.indexed_at_ms = now_ms();
+        index.generation = self.next_generation();
+        index.dirty = true;
+        self.persist(&mut index, false)
+    }
+
+    /// Run `f` over the files of the index of `root` and its generation,
+    /// `None` if there is no index
+    pub fn with_files<T>(
+        &self,
+        root: &Path,
+        f: impl FnOnce(u64, &BTreeMap<PathBuf, FileRecord>) -> T,
+    ) -> Option<T> {
+        let index = self.loaded(root)?;
+        let index = index.read().unwrap();
+        Some(f(index.generation, &index.files))
+    }
+
+    fn next_generation(&self) -> u64 {
+        self.generations.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// The index of `root`, from memory or disk
+    fn loaded(&self, root: &Path) -> Option<SharedIndex> {
+        let mut indexes = self.indexes.lock().unwrap();
+        if let Some(index) = indexes.get(root) {
+            return Some(Arc::clone(index));
+        }
+        let bytes = fs::read(self.index_path(root)).ok()?;
+        // Unreadable or outdated indexes are simply rebuilt
+        let mut index: WorkspaceIndex = serde_json::from_slice(&bytes).ok()?;
+        if index.version != INDEX_VERSION || index.root != root {
+            return None;
+        }
+        index.generation = self.next_generation();
+        let index = Arc::new(RwLock::new(index));
+        indexes.insert(root.to_path_buf(), Arc::clone(&index));
+        Some(index)
+    }
+
+    fn index_path(&self, root: &Path) -> PathBuf {
+        let key = fs_edit::content_hash(root.as_os_str().as_encoded_bytes());
+        self.dir.join(format!("{}.json", &key[..16]))
+    }
+
+    /// Save `index` if it changed, unless it was saved less than
+    /// `SAVE_INTERVAL` ago and `force` is not set
+    fn persist(&self, index: &mut WorkspaceIndex, force: bool) -> Result<(), IndexError> {
+        let recent = index
+            .saved
+            .is_some_and(|saved| saved.elapsed() < SAVE_INTERVAL);
+        if !index.dirty || (recent && !force) {
+            return Ok(());
+        }
+        let json = serde_json::to_string(&*index)?;
+        fs_edit::write_atomic(
+            &self.index_path(&index.root),
+            &json,
+            &Precondition::default(),
+        )?;
+        index.dirty = false;
+        index.saved = Some(Instant::now());
+        Ok(())
+    }
+}
+
+/// Every file under `dir` that the project tree would show
+fn walk_files(dir: &Path) -> Vec<PathBuf> {
+    tree::walker(dir)
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+/// Index one file, reusing `previous` when it is unchanged
+///
+/// Returns `None` for files that don't belong in the index: missing, too
+/// large or binary.
+fn index_file(relative: &Path, path: &Path, previous: Option<&FileRecord>) -> Option<FileRecord> {
+    let metadata = fs::metadata(path).ok()?;
+    if !metadata.is_file() || metadata.len() > MAX_FILE_SIZE {
+        return None;
+    }
+    let mtime_ms = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64);
+    if let Some(previous) = previous {
+        if previous.size == metadata.len() && mtime_ms.is_some() && previous.mtime_ms == mtime_ms {
+            return Some(previous.clone());
+        }
+    }
+
+    let bytes = fs::read(path).ok()?;
+    if bytes[..bytes.len().min(BINARY_SNIFF_LEN)].contains(&0) {
+        return None;
+    }
+    let hash = fs_edit::content_hash(&bytes);
+    if let Some(previous) = previous.filter(|p| p.hash == hash) {
+        return Some(FileRecord {
+            mtime_ms,
+            ..previous.clone()
+        });
+    }
+    let language = language::detect(path);
+    let text = String::from_utf8_lossy(&bytes);
+    let mut record = FileRecord {
+        path: relative.to_path_buf(),
+        hash,
+        size: metadata.len(),
+        mtime_ms,
+        language: language.map(str::to_string),
+        symbols: Vec::new(),
+        imports: Vec::new(),
+        identifiers: Vec::new(),
+    };
+    // One parse serves the outline, the imports and the identifiers
+    if let Some((language, tree)) = language.and_then(|l| Some((l, symbols::parse(l, &text)?))) {
+        record.symbols = symbols::outline(language, &tree, &text);
+        record.imports = graph::imports(language, &tree, &text);
+        record.identifiers = graph::identifiers(&tree, &text);
+    }
+    Some(record)
+}
+
+/// How well `candidate` matches the lowercase `query`, in (0, 1]
+fn match_score(query: &str, candidate: &str) -> Option<f32> {
+    let candidate = candidate.to_lowercase();
+    if candidate == query {
+        Some(1.0)
+    } else if candidate.starts_with(query) {
+        Some(0.8)
+    } else if candidate.contains(query) {
+        Some(0.6)
+    } else {
+        // In-order subsequence, e.g. `gsym` for `get_symbols`
+        let mut chars = candidate.chars();
+        query
+            .chars()
+            .all(|q| chars.any(|c| c == q))
+            .then(|| 0.4 * query.len() as f32 / candidate.len() as f32)
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Build or refresh the index of a workspace root (the first open root by
+/// default), emitting `index://progress` events while it runs
+#[tauri::command]
+pub async fn index_workspace(
+    app: AppHandle,
+    workspace: State<'_, Workspace>,
+    root: Option<PathBuf>,
+    force: Option<bool>,
+) -> Result<IndexStatus, IndexError> {
+    let root = workspace.pick_root(root.as_deref())?;
+    tauri::async_runtime::spawn_blocking(move || {
+        app.state::<Indexer>()
+            .build(&root, force.unwrap_or(false), |status| {
+                let _ = app.emit(PROGRESS_EVENT, status);
+            })
+    })
+    .await
+    .map_err(|e| IndexError::Storage {
+        message: e.to_string(),
+    })?
+}
+
+#[tauri::command]
+pub async fn index_status(
+    workspace: State<'_, Workspace>,
+    indexer: State<'_, Indexer>,
+    root: Option<PathBuf>,
+) -> Result<IndexStatus, IndexError> {
+    let root = workspace.pick_root(root.as_deref())?;
+    Ok(indexer.status(&root.path))
+}
+
+/// Search file names and symbols in the index of `root`, or of every open
+/// root
+#[tauri::command]
+pub async fn index_search(
+    workspace: State<'_, Workspace>,
+    indexer: State<'_, Indexer>,
+    query: String,
+    root: Option<PathBuf>,
+    limit: Option<usize>,
+) -> Result<Vec<SearchHit>, IndexError> {
+    let roots = match root {
+        Some(root) => {
+            let root = workspace.pick_root(Some(&root))?.path;
+            if indexer.status(&root).state == IndexState::Missing {
+                return Err(IndexError::NotIndexed {
+                    root: root.display().to_string(),
+                });
+            }
+            vec![root]
+        }
+        None => workspace.roots().into_iter().map(|r| r.path).collect(),
+    };
+    Ok(indexer.search(&roots, &query, limit.unwrap_or(DEFAULT_SEARCH_LIMIT)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+
+    use super::*;
+
+    fn created(root: &Path, path: PathBuf) -> FsChanged {
+        FsChanged {
+            root: root.to_path_buf(),
+            created: vec![path],
+            ..FsChanged::default()
+        }
+    }
+
+    #[test]
+    fn batches_during_a_build_are_applied_after_it() {
+        let project = tempfile::tempdir().unwrap();
+        let data = tempfile::tempdir().unwrap();
+        fs::write(project.path().join("a.rs"), "fn a() {}\n").unwrap();
+        let root = Workspace::default().open(project.path()).unwrap();
+        let indexer = Indexer::open(data.path().to_path_buf()).unwrap();
+
+        // A file created after the walk, reported while the build runs
+        let late = root.path.join("late.rs");
+        let reported = AtomicBool::new(false);
+        let status = indexer
+            .build(&root, false, |status| {
+                if status.state == IndexState::Indexing && !reported.swap(true, Ordering::SeqCst) {
+                    fs::write(&late, "fn late() {}\n").unwrap();
+                    indexer
+                        .apply_changes(&created(&root.path, late.clone()))
+                        .unwrap();
+                }
+            })
+            .unwrap();
+        assert!(reported.load(Ordering::SeqCst));
+        assert_eq!(status.state, IndexState::Ready);
+        assert_eq!(status.files, 2);
+    }
+
+    #[test]
+    fn watcher_saves_are_throttled_until_flushed() {
+        let project = tempfile::tempdir().unwrap();
+        let data = tempfile::tempdir().unwrap();
+        fs::write(project.path().join("a.rs"), "fn a() {}\n").unwrap();
+        let root = Workspace::default().open(project.path()).unwrap();
+        let indexer = Indexer::open(data.path().to_path_buf()).unwrap();
+        indexer.build(&root, false, |_| {}).unwrap();
+
+        let b = root.path.join("b.rs");
+        fs::write(&b, "fn b() {}\n").unwrap();
+        indexer.apply_changes(&created(&root.path, b)).unwrap();
+        assert_eq!(indexer.status(&root.path).files, 2);
+        let on_disk = |data: &Path| {
+            Indexer::open(data.to_path_buf())
+                .unwrap()
+                .status(&root.path)
+                .files
+        };
+        // Saved by the build just now, so the batch waits
+        assert_eq!(on_disk(data.path()), 1);
+        indexer.flush().unwrap();
+        assert_eq!(on_disk(data.path()), 2);
+    }
+}
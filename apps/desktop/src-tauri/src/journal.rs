@@ -0,0 +1,574 @@
+//! Persistent undo/redo journal for agent edits
+//!
+//! Replaces the in-memory backups of `TaskExecutor.backupFile`. Every edit is
+//! recorded under a transaction id before it is written; file contents are
+//! stored once per content hash under `objects/`, and each transaction is a
+//! JSON file under `transactions/`, so rollback survives an app restart.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::fs_edit::{self, FileEditError, Precondition};
+
+/// Oldest transactions beyond this count are pruned along with their blobs
+const MAX_TRANSACTIONS: usize = 200;
+
+#[derive(Debug, thiserror::Error, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum JournalError {
+    #[error("unknown transaction {id}")]
+    NotFound { id: String },
+    #[error("transaction {id} is already {state:?}")]
+    InvalidState { id: String, state: TransactionState },
+    #[error(transparent)]
+    File { reason: FileEditError },
+    /// Replaying failed half-way and some files could not be put back
+    #[error("transaction {id} was only partly replayed ({message}); still changed: {}", paths.join(", "))]
+    Partial {
+        id: String,
+        paths: Vec<String>,
+        message: String,
+    },
+    #[error("edit journal storage failed: {message}")]
+    Storage { message: String },
+}
+
+impl From<FileEditError> for JournalError {
+    fn from(reason: FileEditError) -> Self {
+        Self::File { reason }
+    }
+}
+
+impl From<JournalError> for FileEditError {
+    fn from(err: JournalError) -> Self {
+        match err {
+            JournalError::File { reason } => reason,
+            other => Self::Journal {
+                message: other.to_string(),
+            },
+        }
+    }
+}
+
+impl From<io::Error> for JournalError {
+    fn from(err: io::Error) -> Self {
+        Self::Storage {
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for JournalError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Storage {
+            message: err.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TransactionState {
+    Applied,
+    Undone,
+}
+
+/// One file change; `None` means the file did not exist
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalEntry {
+    pub path: PathBuf,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// A change about to be made, as passed to [`Journal::record_all`]
+pub struct FileChange<'a> {
+    pub path: &'a Path,
+    pub before: Option<&'a [u8]>,
+    pub after: Option<&'a [u8]>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transaction {
+    pub id: String,
+    pub label: String,
+    pub created_at_ms: u64,
+    pub state: TransactionState,
+    pub entries: Vec<JournalEntry>,
+}
+
+impl Transaction {
+    /// Content each touched file had before and after the whole transaction
+    fn net_changes(&self) -> Vec<(PathBuf, Option<String>, Option<String>)> {
+        let mut order = Vec::new();
+        let mut net: HashMap<&Path, (Option<String>, Option<String>)> = HashMap::new();
+        for entry in &self.entries {
+            net.entry(&entry.path)
+                .and_modify(|(_, after)| after.clone_from(&entry.after))
+                .or_insert_with(|| {
+                    order.push(entry.path.clone());
+                    (entry.before.clone(), entry.after.clone())
+                });
+        }
+        order
+            .into_iter()
+            .map(|path| {
+                let (before, after) = net.remove(path.as_path()).unwrap_or_default();
+                (path, before, after)
+            })
+            .collect()
+    }
+}
+
+/// On-disk edit journal, managed as Tauri state
+pub struct Journal {
+    dir: PathBuf,
+    /// Serializes journal updates and undo/redo runs
+    lock: Mutex<()>,
+}
+
+impl Journal {
+    pub fn open(dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(dir.join("objects"))?;
+        fs::create_dir_all(dir.join("transactions"))?;
+        Ok(Self {
+            dir,
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// Start an empty transaction that later edits can be recorded under
+    pub fn begin(&self, label: &str) -> Result<Transaction, JournalError> {
+        let _guard = self.lock.lock().unwrap();
+        self.create(uuid::Uuid::new_v4().to_string(), label)
+    }
+
+    /// Record file changes before they are written
+    ///
+    /// Appends to transaction `id`, creating it if needed, or to a new
+    /// transaction when `id` is `None`. Returns the transaction id and the
+    /// index of the first new entry, for [`Journal::discard`] if the writes
+    /// then fail.
+    pub fn record_all(
+        &self,
+        id: Option<&str>,
+        label: &str,
+        changes: &[FileChange<'_>],
+    ) -> Result<(String, usize), JournalError> {
+        let _guard = self.lock.lock().unwrap();
+        let mut tx = match id {
+            Some(id) => match self.load(id) {
+                Ok(tx) => tx,
+                Err(JournalError::NotFound { .. }) => self.create(id.to_string(), label)?,
+                Err(e) => return Err(e),
+            },
+            None => self.create(uuid::Uuid::new_v4().to_string(), label)?,
+        };
+        if tx.state != TransactionState::Applied {
+            return Err(JournalError::InvalidState {
+                id: tx.id,
+                state: tx.state,
+            });
+        }
+        let first = tx.entries.len();
+        for change in changes {
+            tx.entries.push(JournalEntry {
+                path: change.path.to_path_buf(),
+                before: change.before.map(|b| self.store_object(b)).transpose()?,
+                after: change.after.map(|b| self.store_object(b)).transpose()?,
+            });
+        }
+        self.save(&tx)?;
+        Ok((tx.id, first))
+    }
+
+    /// Drop entries from index `from` on, for changes that were rolled back
+    /// before they landed; an emptied transaction is removed entirely
+    pub fn discard(&self, id: &str, from: usize) -> Result<(), JournalError> {
+        let _guard = self.lock.lock().unwrap();
+        let mut tx = self.load(id)?;
+        tx.entries.truncate(from);
+        if tx.entries.is_empty() {
+            fs::remove_file(self.transaction_path(id)?)?;
+            return Ok(());
+        }
+        self.save(&tx)
+    }
+
+    /// All transactions, newest first
+    pub fn list(&self) -> Result<Vec<Transaction>, JournalError> {
+        let _guard = self.lock.lock().unwrap();
+        self.load_all()
+    }
+
+    /// Restore every file in the transaction to its state before it ran
+    ///
+    /// Files already back in that state are skipped. Refuses with a
+    /// conflict, before touching anything, if any file has content from
+    /// neither side of the transaction. `resolve` maps journal paths
+    /// through the workspace sandbox.
+    pub fn undo(
+        &self,
+        id: &str,
+        resolve: impl Fn(&Path) -> Result<PathBuf, FileEditError>,
+    ) -> Result<Transaction, JournalError> {
+        self.replay(id, TransactionState::Applied, resolve)
+    }
+
+    /// Re-apply an undone transaction
+    pub fn redo(
+        &self,
+        id: &str,
+        resolve: impl Fn(&Path) -> Result<PathBuf, FileEditError>,
+    ) -> Result<Transaction, JournalError> {
+        self.replay(id, TransactionState::Undone, resolve)
+    }
+
+    fn replay(
+        &self,
+        id: &str,
+        from: TransactionState,
+        resolve: impl Fn(&Path) -> Result<PathBuf, FileEditError>,
+    ) -> Result<Transaction, JournalError> {
+        let _guard = self.lock.lock().unwrap();
+        let mut tx = self.load(id)?;
+        if tx.state != from {
+            return Err(JournalError::InvalidState {
+                id: tx.id,
+                state: tx.state,
+            });
+        }
+
+        // (path, content expected on disk now, content to restore)
+        let steps: Vec<(PathBuf, Option<String>, Option<String>)> = tx
+            .net_changes()
+            .into_iter()
+            .map(|(path, before, after)| {
+                let path = resolve(&path)?;
+                Ok(match from {
+                    TransactionState::Applied => (path, after, before),
+                    TransactionState::Undone => (path, before, after),
+                })
+            })
+            .collect::<Result<_, JournalError>>()?;
+
+        // Files already as they should end up, e.g. after an earlier replay
+        // stopped half-way or the user reverted them by hand, are left alone
+        let mut pending = Vec::with_capacity(steps.len());
+        for step @ (path, expected, target) in &steps {
+            let current = match fs::read(path) {
+                Ok(bytes) => Some(fs_edit::content_hash(&bytes)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => return Err(FileEditError::from_io(path, e).into()),
+            };
+            if current.as_deref() == target.as_deref() {
+                continue;
+            }
+            if current.as_deref() != expected.as_deref() {
+                return Err(FileEditError::Conflict {
+                    path: path.display().to_string(),
+                    current_hash: current,
+                    current_mtime_ms: None,
+                }
+                .into());
+            }
+            pending.push(step);
+        }
+
+        for (index, (path, expected, target)) in pending.iter().enumerate() {
+            let Err(err) = self.put(path, expected, target) else {
+                continue;
+            };
+            // Put back the files already replayed, so the state stays true
+            let stuck: Vec<String> = pending[..index]
+                .iter()
+                .rev()
+                .filter(|(path, expected, target)| self.put(path, target, expected).is_err())
+                .map(|(path, _, _)| path.display().to_string())
+                .collect();
+            if stuck.is_empty() {
+                return Err(err);
+            }
+            return Err(JournalError::Partial {
+                id: tx.id,
+                paths: stuck,
+                message: err.to_string(),
+            });
+        }
+
+        tx.state = match from {
+            TransactionState::Applied => TransactionState::Undone,
+            TransactionState::Undone => TransactionState::Applied,
+        };
+        self.save(&tx)?;
+        Ok(tx)
+    }
+
+    /// Write the object `target` to `path`, or remove it for `None`, if the
+    /// file still has the content `expected`
+    fn put(
+        &self,
+        path: &Path,
+        expected: &Option<String>,
+        target: &Option<String>,
+    ) -> Result<(), JournalError> {
+        let expected = Precondition::content(expected.as_deref());
+        match target {
+            Some(hash) => {
+                let bytes = self.load_object(hash)?;
+                fs_edit::prepare_bytes(path, bytes, &expected)?.commit()?;
+            }
+            None => fs_edit::remove(path, &expected)?,
+        }
+        Ok(())
+    }
+
+    fn create(&self, id: String, label: &str) -> Result<Transaction, JournalError> {
+        self.prune()?;
+        let tx = Transaction {
+            id,
+            label: label.to_string(),
+            created_at_ms: now_ms(),
+            state: TransactionState::Applied,
+            entries: Vec::new(),
+        };
+        self.save(&tx)?;
+        Ok(tx)
+    }
+
+    fn transaction_path(&self, id: &str) -> Result<PathBuf, JournalError> {
+        // Ids come from the webview; keep them from naming other files
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(JournalError::NotFound { id: id.to_string() });
+        }
+        Ok(self.dir.join("transactions").join(format!("{id}.json")))
+    }
+
+    fn load(&self, id: &str) -> Result<Transaction, JournalError> {
+        match fs::read(self.transaction_path(id)?) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Err(JournalError::NotFound { id: id.to_string() })
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn load_all(&self) -> Result<Vec<Transaction>, JournalError> {
+        let mut all = Vec::new();
+        for entry in fs::read_dir(self.dir.join("transactions"))? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                // A corrupt record shouldn't hide the rest of the history
+                if let Ok(tx) = serde_json::from_slice(&fs::read(&path)?) {
+                    all.push(tx);
+                }
+            }
+        }
+        all.sort_by_key(|tx: &Transaction| std::cmp::Reverse(tx.created_at_ms));
+        Ok(all)
+    }
+
+    fn save(&self, tx: &Transaction) -> Result<(), JournalError> {
+        let json = serde_json::to_string_pretty(tx)?;
+        fs_edit::write_atomic(
+            &self.transaction_path(&tx.id)?,
+            &json,
+            &Precondition::default(),
+        )?;
+        Ok(())
+    }
+
+    fn store_object(&self, bytes: &[u8]) -> Result<String, JournalError> {
+        let hash = fs_edit::content_hash(bytes);
+        let path = self.dir.join("objects").join(&hash);
+        if !path.exists() {
+            fs_edit::prepare_bytes(&path, bytes.to_vec(), &Precondition::default())?.commit()?;
+        }
+        Ok(hash)
+    }
+
+    fn load_object(&self, hash: &str) -> Result<Vec<u8>, JournalError> {
+        fs::read(self.dir.join("objects").join(hash)).map_err(|e| JournalError::Storage {
+            message: format!("missing journal object {hash}: {e}"),
+        })
+    }
+
+    /// Drop the oldest transactions past the cap and any blobs only they used
+    fn prune(&self) -> Result<(), JournalError> {
+        let all = self.load_all()?;
+        if all.len() < MAX_TRANSACTIONS {
+            return Ok(());
+        }
+        let (keep, drop) = all.split_at(MAX_TRANSACTIONS - 1);
+        for tx in drop {
+            fs::remove_file(self.transaction_path(&tx.id)?)?;
+        }
+        let live: HashSet<&str> = keep
+            .iter()
+            .flat_map(|tx| &tx.entries)
+            .flat_map(|e| [e.before.as_deref(), e.after.as_deref()])
+            .flatten()
+            .collect();
+        for entry in fs::read_dir(self.dir.join("objects"))? {
+            let entry = entry?;
+            if !live.contains(entry.file_name().to_string_lossy().as_ref()) {
+                fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(path: &Path) -> Result<PathBuf, FileEditError> {
+        Ok(path.to_path_buf())
+    }
+
+    /// Record a change of `path` to `after` and make it
+    fn edit(journal: &Journal, id: Option<&str>, path: &Path, after: Option<&str>) -> String {
+        let before = fs::read(path).ok();
+        let (id, _) = journal
+            .record_all(
+                id,
+                "edit",
+                &[FileChange {
+                    path,
+                    before: before.as_deref(),
+                    after: after.map(str::as_bytes),
+                }],
+            )
+            .unwrap();
+        match after {
+            Some(text) => fs::write(path, text).unwrap(),
+            None => fs::remove_file(path).unwrap(),
+        }
+        id
+    }
+
+    fn read(path: &Path) -> Option<String> {
+        fs::read_to_string(path).ok()
+    }
+
+    #[test]
+    fn undo_and_redo_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::open(dir.path().join("journal")).unwrap();
+        let changed = dir.path().join("changed.txt");
+        let created = dir.path().join("created.txt");
+        fs::write(&changed, "one").unwrap();
+
+        let id = edit(&journal, None, &changed, Some("two"));
+        edit(&journal, Some(&id), &changed, Some("three"));
+        edit(&journal, Some(&id), &created, Some("new"));
+
+        let tx = journal.undo(&id, resolve).unwrap();
+        assert_eq!(tx.state, TransactionState::Undone);
+        assert_eq!(read(&changed).as_deref(), Some("one"));
+        assert!(!created.exists());
+        assert!(matches!(
+            journal.undo(&id, resolve),
+            Err(JournalError::InvalidState { .. })
+        ));
+
+        journal.redo(&id, resolve).unwrap();
+        assert_eq!(read(&changed).as_deref(), Some("three"));
+        assert_eq!(read(&created).as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn contents_are_stored_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::open(dir.path().join("journal")).unwrap();
+        let a = dir.path().join("a.txt");
+        let b = dir.path().join("b.txt");
+        fs::write(&a, "same").unwrap();
+        fs::write(&b, "same").unwrap();
+        let id = edit(&journal, None, &a, Some("edited"));
+        edit(&journal, Some(&id), &b, Some("edited"));
+
+        let objects = fs::read_dir(dir.path().join("journal/objects"))
+            .unwrap()
+            .count();
+        assert_eq!(objects, 2);
+        let tx = &journal.list().unwrap()[0];
+        assert_eq!(tx.entries[0].before, tx.entries[1].before);
+    }
+
+    #[test]
+    fn undo_skips_files_already_restored() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::open(dir.path().join("journal")).unwrap();
+        let a = dir.path().join("a.txt");
+        let b = dir.path().join("b.txt");
+        fs::write(&a, "a1").unwrap();
+        fs::write(&b, "b1").unwrap();
+        let id = edit(&journal, None, &a, Some("a2"));
+        edit(&journal, Some(&id), &b, Some("b2"));
+
+        // As if an earlier undo stopped after the first file
+        fs::write(&a, "a1").unwrap();
+        journal.undo(&id, resolve).unwrap();
+        assert_eq!(read(&a).as_deref(), Some("a1"));
+        assert_eq!(read(&b).as_deref(), Some("b1"));
+    }
+
+    #[test]
+    fn content_from_neither_side_conflicts() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::open(dir.path().join("journal")).unwrap();
+        let a = dir.path().join("a.txt");
+        let b = dir.path().join("b.txt");
+        fs::write(&a, "a1").unwrap();
+        fs::write(&b, "b1").unwrap();
+        let id = edit(&journal, None, &a, Some("a2"));
+        edit(&journal, Some(&id), &b, Some("b2"));
+
+        fs::write(&b, "someone else").unwrap();
+        assert!(matches!(
+            journal.undo(&id, resolve),
+            Err(JournalError::File {
+                reason: FileEditError::Conflict { .. }
+            })
+        ));
+        // Nothing was touched
+        assert_eq!(read(&a).as_deref(), Some("a2"));
+        assert_eq!(journal.list().unwrap()[0].state, TransactionState::Applied);
+    }
+
+    #[test]
+    fn redo_after_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file.txt");
+        fs::write(&file, "before").unwrap();
+        let id = {
+            let journal = Journal::open(dir.path().join("journal")).unwrap();
+            let id = edit(&journal, None, &file, Some("after"));
+            journal.undo(&id, resolve).unwrap();
+            id
+        };
+        assert_eq!(read(&file).as_deref(), Some("before"));
+
+        let journal = Journal::open(dir.path().join("journal")).unwrap();
+        assert_eq!(journal.list().unwrap()[0].state, TransactionState::Undone);
+        journal.redo(&id, resolve).unwrap();
+        assert_eq!(read(&file).as_deref(), Some("after"));
+    }
+}
@@ -1,31 +1,356 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 
+mod anthropic;
+mod batch;
+mod chunking;
+mod config;
+mod diff;
+mod embeddings;
+mod exec;
+mod export;
+mod fs_edit;
+mod graph;
+mod hnsw;
+mod indexer;
+mod journal;
+mod language;
+mod llm;
+mod ollama;
+mod openai;
+mod patch;
+mod permissions;
+mod plan;
+mod policy;
+mod pty;
+mod retrieval;
+mod router;
+mod search;
+mod symbols;
+mod testing;
+mod tree;
+mod vectors;
+mod watcher;
+mod workspace;
+
+use std::path::{Path, PathBuf};
+
+use diff::{DiffAlgorithm, FileDiff};
+use embeddings::Embeddings;
+use fs_edit::{FileEditError, FileVersion, Precondition};
+use graph::ImportGraphs;
+use indexer::Indexer;
+use journal::{FileChange, Journal, JournalError, Transaction};
+use llm::Generations;
+use permissions::{GrantScope, GrantToken, PermissionBroker};
+use plan::Plans;
+use policy::CommandPolicy;
+use pty::PtySessions;
+use retrieval::KeywordIndexes;
+use router::Router;
+use search::Searches;
+use serde::Serialize;
+use tauri::{AppHandle, Manager, RunEvent, State};
+use tree::ProjectTree;
+use vectors::VectorStore;
+use watcher::Watchers;
+use workspace::{Workspace, WorkspaceError, WorkspaceRoot};
+
 #[tauri::command]
 fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OpenedWorkspace {
+    #[serde(flatten)]
+    root: WorkspaceRoot,
+    /// Why changes under the root won't be reported, if they won't
+    watch_error: Option<String>,
+}
+
+/// Open a project root; file commands only operate inside open roots
+/// Changes on disk under the root are reported as `fs://changed` events.
+///
+/// A root that isn't open yet is opened only once the user confirms it in
+/// a native dialog; `pick_workspace` opens what the user picks instead.
+#[tauri::command]
+async fn open_workspace(
+    app: AppHandle,
+    workspace: State<'_, Workspace>,
+    watchers: State<'_, Watchers>,
+    path: PathBuf,
+) -> Result<OpenedWorkspace, WorkspaceError> {
+    let canonical = std::fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+    let already_open = workspace.roots().iter().any(|root| root.path == canonical);
+    if !already_open {
+        let confirmed = permissions::confirm(
+            &app,
+            "Open workspace",
+            format!(
+                "Open {} as a workspace?\n\nThe agent will be able to read files in it and propose changes to them.",
+                canonical.display()
+            ),
+            "Open",
+        )
+        .await;
+        if !confirmed {
+            return Err(WorkspaceError::Refused {
+                path: canonical.display().to_string(),
+            });
+        }
+    }
+    open_root(&app, &workspace, &watchers, &path)
+}
+
+/// Let the user pick a folder in the native dialog and open it as a root;
+/// `None` if they cancel
+#[tauri::command]
+async fn pick_workspace(
+    app: AppHandle,
+    workspace: State<'_, Workspace>,
+    watchers: State<'_, Watchers>,
+) -> Result<Option<OpenedWorkspace>, WorkspaceError> {
+    match permissions::pick_folder(&app, "Open workspace").await {
+        Some(path) => open_root(&app, &workspace, &watchers, &path).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn open_root(
+    app: &AppHandle,
+    workspace: &Workspace,
+    watchers: &Watchers,
+    path: &Path,
+) -> Result<OpenedWorkspace, WorkspaceError> {
+    let root = workspace.open(path)?;
+    // A project too large for the OS watch limit still opens, just unwatched
+    let watch_error = watchers.watch(app, &root).err().map(|e| e.to_string());
+    Ok(OpenedWorkspace { root, watch_error })
+}
+
+#[tauri::command]
+fn close_workspace(
+    workspace: State<'_, Workspace>,
+    tree: State<'_, ProjectTree>,
+    watchers: State<'_, Watchers>,
+    path: PathBuf,
+) -> bool {
+    let root = std::fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+    watchers.unwatch(&root);
+    tree.invalidate(&root);
+    workspace.close(&path)
+}
+
+#[tauri::command]
+fn list_workspaces(workspace: State<'_, Workspace>) -> Vec<WorkspaceRoot> {
+    workspace.roots()
+}
+
+/// Add patterns to the denylist of an open root
+/// Patterns are only ever added; the built-in ones (`.git/`, `.env`, keys)
+/// and those from `.henryrc` always stay in effect.
+#[tauri::command]
+async fn add_workspace_denylist(
+    workspace: State<'_, Workspace>,
+    root: PathBuf,
+    patterns: Vec<String>,
+) -> Result<WorkspaceRoot, WorkspaceError> {
+    workspace.extend_denylist(&root, patterns)
+}
+
+/// Diff two versions of a file into structured hunks
+#[tauri::command]
+async fn compute_diff(
+    old_content: String,
+    new_content: String,
+    file_path: Option<String>,
+    context: Option<usize>,
+    algorithm: Option<DiffAlgorithm>,
+) -> FileDiff {
+    diff::compute(
+        file_path.as_deref().unwrap_or(""),
+        &old_content,
+        &new_content,
+        context.unwrap_or(diff::DEFAULT_CONTEXT),
+        algorithm.unwrap_or_default(),
+    )
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WritePermission {
+    /// Present only if the user approved; pass it to `apply_file_edit`
+    grant: Option<GrantToken>,
+    /// Hash of the file when the preview was taken, `None` for new files
+    base_hash: Option<String>,
+    /// Preview of the edit, diffed against what is on disk right now
+    diff: FileDiff,
+}
+
 /// Request permission to write a file
-/// Note: File operations in Tauri v2 are handled via the fs plugin
-/// which respects the capabilities configuration
+/// The preview is built here from the file on disk and the proposed content,
+/// and the user approves it in a native dialog. Approval yields a single-use
+/// grant scoped to this path and content.
 #[tauri::command]
 async fn request_file_write_permission(
-    _file_path: String,
-    _diff_preview: String,
-) -> Result<bool, String> {
-    // Permission is handled by Tauri's capabilities system
-    // The frontend should show a confirmation dialog before calling this
-    // This just returns true to indicate the request is valid
-    Ok(true)
+    app: AppHandle,
+    workspace: State<'_, Workspace>,
+    broker: State<'_, PermissionBroker>,
+    file_path: String,
+    new_content: String,
+) -> Result<WritePermission, FileEditError> {
+    let target = workspace.resolve(Path::new(&file_path))?;
+    let current = fs_edit::read_text_or_empty(&target)?;
+    let base_hash = target
+        .exists()
+        .then(|| fs_edit::content_hash(current.as_bytes()));
+    let diff = diff::compute(
+        &file_path,
+        &current,
+        &new_content,
+        diff::DEFAULT_CONTEXT,
+        DiffAlgorithm::default(),
+    );
+
+    let approved = permissions::confirm(
+        &app,
+        "Apply agent edit",
+        permissions::write_prompt(&target, &diff),
+        "Apply",
+    )
+    .await;
+    let grant = approved.then(|| {
+        broker.issue(GrantScope::file(
+            &target,
+            fs_edit::content_hash(new_content.as_bytes()),
+        ))
+    });
+
+    Ok(WritePermission {
+        grant,
+        base_hash,
+        diff,
+    })
+}
+
+/// Get the content hash and mtime of a file, to pass back to `apply_file_edit`
+#[tauri::command]
+async fn get_file_version(
+    workspace: State<'_, Workspace>,
+    file_path: String,
+) -> Result<FileVersion, FileEditError> {
+    let target = workspace.resolve(Path::new(&file_path))?;
+    FileVersion::read(&target)
+}
+
+/// Read a text file inside an open workspace
+#[tauri::command]
+async fn read_text_file(
+    workspace: State<'_, Workspace>,
+    file_path: String,
+) -> Result<String, FileEditError> {
+    let target = workspace.resolve(Path::new(&file_path))?;
+    let bytes = std::fs::read(&target).map_err(|e| FileEditError::from_io(&target, e))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AppliedEdit {
+    /// Journal transaction the edit was recorded under
+    transaction_id: String,
 }
 
 /// Apply a file edit after permission has been granted
+/// `grant_token` must come from an approved `request_file_write_permission`
+/// for this path and content; it is spent by this call.
+/// The write is atomic: readers see either the old or the new content.
+/// If `expected_hash` or `expected_mtime` is given and the file changed since,
+/// the edit is refused with a `Conflict` error carrying the current hash.
+/// The edit is journaled under `transaction_id` (or a new transaction) so it
+/// can be undone later, even after a restart.
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+async fn apply_file_edit(
+    workspace: State<'_, Workspace>,
+    broker: State<'_, PermissionBroker>,
+    journal: State<'_, Journal>,
+    file_path: String,
+    content: String,
+    grant_token: String,
+    expected_hash: Option<String>,
+    expected_mtime: Option<u64>,
+    transaction_id: Option<String>,
+) -> Result<AppliedEdit, FileEditError> {
+    let target = workspace.resolve(Path::new(&file_path))?;
+    broker
+        .redeem(
+            &grant_token,
+            &GrantScope::file(&target, fs_edit::content_hash(content.as_bytes())),
+        )
+        .map_err(|reason| FileEditError::NotApproved {
+            path: target.display().to_string(),
+            reason,
+        })?;
+
+    let expected = Precondition {
+        hash: expected_hash,
+        mtime_ms: expected_mtime,
+        ..Precondition::default()
+    };
+    let write = fs_edit::prepare(&target, &content, &expected)?;
+    let change = FileChange {
+        path: &target,
+        before: write.previous.as_deref(),
+        after: Some(&write.bytes),
+    };
+    let (transaction_id, first_entry) = journal.record_all(
+        transaction_id.as_deref(),
+        &format!("Edit {}", file_path),
+        &[change],
+    )?;
+    if let Err(e) = write.commit() {
+        // The edit never happened, so it must not be undoable
+        journal.discard(&transaction_id, first_entry)?;
+        return Err(e);
+    }
+    Ok(AppliedEdit { transaction_id })
+}
+
+/// Start a named journal transaction to group several edits
+#[tauri::command]
+async fn begin_transaction(
+    journal: State<'_, Journal>,
+    label: String,
+) -> Result<Transaction, JournalError> {
+    journal.begin(&label)
+}
+
+/// Journaled transactions, newest first
+#[tauri::command]
+async fn list_transactions(journal: State<'_, Journal>) -> Result<Vec<Transaction>, JournalError> {
+    journal.list()
+}
+
+/// Restore the files a transaction touched to their previous content
+#[tauri::command]
+async fn undo_transaction(
+    workspace: State<'_, Workspace>,
+    journal: State<'_, Journal>,
+    transaction_id: String,
+) -> Result<Transaction, JournalError> {
+    journal.undo(&transaction_id, |p| Ok(workspace.resolve(p)?))
+}
+
+/// Re-apply a transaction that was undone
 #[tauri::command]
-async fn apply_file_edit(file_path: String, content: String) -> Result<(), String> {
-    use std::fs;
-    fs::write(&file_path, content)
-        .map_err(|e| format!("Failed to write file {}: {}", file_path, e))?;
-    Ok(())
+async fn redo_transaction(
+    workspace: State<'_, Workspace>,
+    journal: State<'_, Journal>,
+    transaction_id: String,
+) -> Result<Transaction, JournalError> {
+    journal.redo(&transaction_id, |p| Ok(workspace.resolve(p)?))
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -35,11 +360,106 @@ pub fn run() {
         .plugin(tauri_plugin_shell::init()) // Shell plugin for terminal execution
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
+        .manage(Workspace::default())
+        .manage(PermissionBroker::default())
+        .manage(ProjectTree::default())
+        .manage(Watchers::default())
+        .manage(ImportGraphs::default())
+        .manage(Searches::default())
+        .manage(Embeddings::default())
+        .manage(KeywordIndexes::default())
+        .manage(Generations::default())
+        .manage(Router::default())
+        .manage(PtySessions::default())
+        .setup(|app| {
+            let data_dir = app.path().app_data_dir()?;
+            app.manage(Journal::open(data_dir.join("journal"))?);
+            app.manage(CommandPolicy::open(data_dir.join("commands.log"))?);
+            app.manage(Plans::open(data_dir.join("plans"))?);
+            app.manage(Indexer::open(data_dir.join("index"))?);
+            app.manage(VectorStore::open(data_dir.join("vectors"))?);
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             greet,
+            open_workspace,
+            pick_workspace,
+            close_workspace,
+            list_workspaces,
+            add_workspace_denylist,
+            compute_diff,
             request_file_write_permission,
-            apply_file_edit
+            get_file_version,
+            read_text_file,
+            apply_file_edit,
+            begin_transaction,
+            list_transactions,
+            undo_transaction,
+            redo_transaction,
+            export::save_file_as,
+            export::save_project_files,
+            batch::request_batch_permission,
+            batch::apply_edit_batch,
+            patch::apply_patch,
+            tree::list_project_tree,
+            indexer::index_workspace,
+            indexer::index_status,
+            indexer::index_search,
+            symbols::get_symbols,
+            graph::find_imports,
+            graph::find_dependents,
+            graph::impact_of_change,
+            graph::find_references,
+            search::search_text,
+            search::cancel_search,
+            vectors::vector_upsert,
+            vectors::vector_delete_file,
+            vectors::vector_search,
+            vectors::vector_status,
+            vectors::vector_clear,
+            embeddings::embed_workspace,
+            embeddings::embed_texts,
+            retrieval::retrieve_context,
+            llm::llm_chat,
+            llm::cancel_generation,
+            llm::llm_usage,
+            router::route_chat,
+            router::router_status,
+            pty::pty_spawn,
+            pty::pty_write,
+            pty::pty_resize,
+            pty::pty_kill,
+            policy::set_autonomy_level,
+            policy::check_command,
+            policy::run_agent_command,
+            policy::command_decisions,
+            testing::detect_tests,
+            testing::run_tests,
+            plan::start_plan,
+            plan::approve_plan_step,
+            plan::resume_plan,
+            plan::cancel_plan,
+            plan::rollback_plan,
+            plan::list_plans,
+            plan::get_plan,
+            ollama::ollama_generate,
+            ollama::ollama_chat,
+            ollama::ollama_health
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            if !matches!(event, RunEvent::Exit) {
+                return;
+            }
+            // Indexes and vector collections are saved lazily, so write out
+            // the rest. No window is left to tell of a failure, and the next
+            // index or embedding run catches up on anything lost here.
+            if let Some(indexer) = app.try_state::<Indexer>() {
+                let _ = indexer.flush();
+            }
+            if let Some(store) = app.try_state::<VectorStore>() {
+                let _ = store.flush();
+            }
+        });
 }
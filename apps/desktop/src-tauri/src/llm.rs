@@ -0,0 +1,805 @@
+//! Shared pieces of the native LLM clients
+//!
+//! Generations run in the backend, so they outlive a reloading webview and
+//! need no HTTP access from it. Every provider (Ollama, OpenAI-compatible
+//! servers, Anthropic) implements `LlmProvider` and reports its answer to a
+//! `Stream`: text and tool-call deltas go to the caller through a
+//! `tauri::ipc::Channel`, and the command resolves with the whole
+//! completion once the model is done or the generation is cancelled with
+//! `cancel_generation`. Token usage is totalled per provider and model.
+
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures_util::future::{AbortHandle, Abortable, BoxFuture};
+use serde::{Deserialize, Serialize};
+use tauri::ipc::Channel;
+use tauri::State;
+
+use crate::anthropic::{self, AnthropicClient};
+use crate::ollama::{self, OllamaClient};
+use crate::openai::{self, OpenAiClient};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest wait for the next piece of an answer; loading a large local
+/// model takes a while
+const READ_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// What providers say when a prompt doesn't fit the model
+const OVERFLOW_HINTS: [&str; 5] = [
+    "context_length_exceeded",
+    "context length",
+    "context window",
+    "maximum context",
+    "prompt is too long",
+];
+
+#[derive(Debug, thiserror::Error, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum LlmError {
+    #[error("{url} is unreachable: {message}")]
+    Unavailable { url: String, message: String },
+    #[error("model {model} is not available")]
+    ModelNotFound { model: String },
+    #[error("rate limited: {message}")]
+    #[serde(rename_all = "camelCase")]
+    RateLimited {
+        message: String,
+        /// How long the provider asked to wait, if it said
+        retry_after_ms: Option<u64>,
+    },
+    #[error("not authorized: {message}")]
+    Unauthorized { message: String },
+    #[error("the prompt doesn't fit the context window: {message}")]
+    ContextOverflow { message: String },
+    #[error("no answer within {seconds}s")]
+    Timeout { seconds: u64 },
+    #[error("provider answered {status}: {message}")]
+    Http { status: u16, message: String },
+    #[error("provider failed: {message}")]
+    Provider { message: String },
+    #[error("generation {id} is already running")]
+    Duplicate { id: String },
+}
+
+impl LlmError {
+    /// The error a provider reported, from its HTTP status if it came with
+    /// one, and its error code and message
+    pub fn classify(
+        status: Option<u16>,
+        code: &str,
+        message: String,
+        retry_after_ms: Option<u64>,
+    ) -> Self {
+        let text = format!("{} {}", code, message).to_lowercase();
+        if OVERFLOW_HINTS.iter().any(|hint| text.contains(hint)) {
+            return Self::ContextOverflow { message };
+        }
+        let rate_limited = code.contains("rate_limit") || code.contains("overloaded");
+        let refused = code.contains("authentication") || code.contains("permission");
+        match status {
+            Some(401 | 403) => Self::Unauthorized { message },
+            Some(429 | 529) => Self::RateLimited {
+                message,
+                retry_after_ms,
+            },
+            _ if rate_limited => Self::RateLimited {
+                message,
+                retry_after_ms,
+            },
+            _ if refused => Self::Unauthorized { message },
+            Some(status) => Self::Http { status, message },
+            None => Self::Provider { message },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+    Tool,
+}
+
+/// A tool the model asked to call
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolCall {
+    /// Matches the result to the call; Ollama assigns none
+    pub id: Option<String>,
+    pub name: String,
+    /// JSON object, as the model wrote it
+    pub arguments: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMessage {
+    pub role: Role,
+    #[serde(default)]
+    pub content: String,
+    /// Calls made by an assistant message
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// The call a tool message answers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+/// A tool the model may call
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolSpec {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// JSON schema of the arguments
+    pub parameters: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    #[serde(default)]
+    pub tools: Vec<ToolSpec>,
+    #[serde(default)]
+    pub options: GenerateOptions,
+}
+
+/// Sampling settings; anything unset is left to the model's defaults
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GenerateOptions {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub stop: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Usage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+}
+
+/// Sent through the channel of a generation as it runs
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+pub enum StreamEvent {
+    Token {
+        text: String,
+    },
+    /// A piece of the `index`th tool call: `id` and `name` come with the
+    /// first piece, the arguments bit by bit
+    ToolCall {
+        index: usize,
+        id: Option<String>,
+        name: Option<String>,
+        arguments: String,
+    },
+    Done {
+        usage: Usage,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Completion {
+    pub request_id: String,
+    pub model: String,
+    /// Everything streamed so far, all of it unless cancelled
+    pub text: String,
+    pub tool_calls: Vec<ToolCall>,
+    pub usage: Usage,
+    /// Why the model stopped, in the provider's words, e.g. `stop`,
+    /// `length` or `tool_use`
+    pub finish_reason: Option<String>,
+    pub cancelled: bool,
+}
+
+impl Completion {
+    pub fn new(request_id: &str, model: &str) -> Self {
+        Self {
+            request_id: request_id.to_owned(),
+            model: model.to_owned(),
+            text: String::new(),
+            tool_calls: Vec::new(),
+            usage: Usage::default(),
+            finish_reason: None,
+            cancelled: false,
+        }
+    }
+}
+
+/// Where a generation reports its answer
+pub struct Stream<'c> {
+    pub completion: &'c mut Completion,
+    channel: Option<&'c Channel<StreamEvent>>,
+    output: Option<&'c AtomicUsize>,
+}
+
+impl<'c> Stream<'c> {
+    pub fn new(completion: &'c mut Completion, channel: Option<&'c Channel<StreamEvent>>) -> Self {
+        Self {
+            completion,
+            channel,
+            output: None,
+        }
+    }
+
+    /// Count the pieces of text and tool calls passed on in `pieces`,
+    /// which is visible while the generation holds the stream
+    pub fn watched(self, pieces: &'c AtomicUsize) -> Self {
+        Self {
+            output: Some(pieces),
+            ..self
+        }
+    }
+
+    /// Append `text` and pass it on; `false` once nobody is listening any
+    /// more, e.g. after the window reloaded
+    pub fn token(&mut self, text: &str) -> bool {
+        if text.is_empty() {
+            return true;
+        }
+        self.completion.text.push_str(text);
+        self.mark_output();
+        self.send(StreamEvent::Token {
+            text: text.to_owned(),
+        })
+    }
+
+    /// Add a piece to the `index`th tool call, like `token`; calls are
+    /// numbered from 0 without gaps, so the index comes from the provider
+    /// but can't make us allocate more than one new call
+    pub fn tool_call(
+        &mut self,
+        index: usize,
+        id: Option<&str>,
+        name: Option<&str>,
+        arguments: &str,
+    ) -> Result<bool, LlmError> {
+        let calls = &mut self.completion.tool_calls;
+        if index > calls.len() {
+            return Err(LlmError::Provider {
+                message: format!("tool call {} came before call {}", index, calls.len()),
+            });
+        }
+        if index == calls.len() {
+            calls.push(ToolCall::default());
+        }
+        let call = &mut calls[index];
+        if let Some(id) = id {
+            call.id = Some(id.to_owned());
+        }
+        if let Some(name) = name {
+            call.name.push_str(name);
+        }
+        call.arguments.push_str(arguments);
+        self.mark_output();
+        Ok(self.send(StreamEvent::ToolCall {
+            index,
+            id: id.map(str::to_owned),
+            name: name.map(str::to_owned),
+            arguments: arguments.to_owned(),
+        }))
+    }
+
+    pub fn done(&mut self, usage: Usage, finish_reason: Option<String>) {
+        self.completion.usage = usage;
+        self.completion.finish_reason = finish_reason;
+        self.send(StreamEvent::Done { usage });
+    }
+
+    fn mark_output(&self) {
+        if let Some(pieces) = self.output {
+            pieces.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn send(&self, event: StreamEvent) -> bool {
+        self.channel
+            .is_none_or(|channel| channel.send(event).is_ok())
+    }
+}
+
+/// A chat model behind some API
+pub trait LlmProvider: Send + Sync {
+    /// Names the provider in usage totals, e.g. `anthropic`
+    fn id(&self) -> String;
+
+    /// Stream the next message of `request` to `stream`
+    fn chat<'a>(
+        &'a self,
+        request: &'a ChatRequest,
+        stream: &'a mut Stream<'_>,
+    ) -> BoxFuture<'a, Result<(), LlmError>>;
+}
+
+/// Which provider to use and where to reach it
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ProviderConfig {
+    Ollama {
+        url: Option<String>,
+    },
+    /// The OpenAI API, or a server speaking it such as LM Studio, the
+    /// llama.cpp server or vLLM
+    #[serde(rename_all = "camelCase")]
+    OpenAi {
+        base_url: Option<String>,
+        api_key: Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Anthropic {
+        base_url: Option<String>,
+        api_key: Option<String>,
+    },
+}
+
+impl ProviderConfig {
+    /// A client for the provider; keys not given are read from
+    /// `OPENAI_API_KEY` and `ANTHROPIC_API_KEY`, but only for the providers'
+    /// own default URLs
+    pub fn build(&self) -> Result<Box<dyn LlmProvider>, LlmError> {
+        let env = |name: &str| std::env::var(name).ok().filter(|key| !key.is_empty());
+        Ok(match self {
+            Self::Ollama { url } => Box::new(OllamaClient::new(
+                url.as_deref().unwrap_or(ollama::DEFAULT_URL),
+            )?),
+            Self::OpenAi { base_url, api_key } => {
+                // The OpenAI key is no business of other servers
+                let api_key = match base_url {
+                    Some(_) => api_key.clone(),
+                    None => api_key.clone().or_else(|| env("OPENAI_API_KEY")),
+                };
+                Box::new(OpenAiClient::new(
+                    base_url.as_deref().unwrap_or(openai::DEFAULT_BASE_URL),
+                    api_key,
+                )?)
+            }
+            Self::Anthropic { base_url, api_key } => {
+                // Nor is the Anthropic key
+                let api_key = match base_url {
+                    Some(_) => api_key.clone(),
+                    None => api_key.clone().or_else(|| env("ANTHROPIC_API_KEY")),
+                };
+                let api_key = api_key.ok_or_else(|| LlmError::Unauthorized {
+                    message: "no Anthropic API key".to_owned(),
+                })?;
+                Box::new(AnthropicClient::new(
+                    base_url.as_deref().unwrap_or(anthropic::DEFAULT_BASE_URL),
+                    api_key,
+                )?)
+            }
+        })
+    }
+}
+
+/// HTTP client for streamed answers
+pub fn http_client() -> Result<reqwest::Client, LlmError> {
+    reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .read_timeout(READ_TIMEOUT)
+        .build()
+        .map_err(|e| LlmError::Provider {
+            message: describe(&e),
+        })
+}
+
+/// `err` and its causes, which for HTTP errors say what actually failed,
+/// e.g. that the connection was refused
+pub fn describe(err: &dyn std::error::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        message = format!("{}: {}", message, cause);
+        source = cause.source();
+    }
+    message
+}
+
+/// Send `request` to the provider at `url`, turning a refusal into the
+/// matching `LlmError`
+pub async fn send(
+    request: reqwest::RequestBuilder,
+    url: &str,
+    model: &str,
+) -> Result<reqwest::Response, LlmError> {
+    let response = request.send().await.map_err(|e| LlmError::Unavailable {
+        url: url.to_owned(),
+        message: describe(&e),
+    })?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let header = |name: &str| {
+        let value = response.headers().get(name)?.to_str().ok()?;
+        value.trim().parse::<f64>().ok()
+    };
+    let retry_after_ms = header("retry-after-ms")
+        .or_else(|| header("retry-after").map(|seconds| seconds * 1000.0))
+        .map(|ms| ms as u64);
+    let text = response.text().await.unwrap_or_default();
+    // `{"error": "..."}` from Ollama, `{"error": {"message", "code" or
+    // "type"}}` from the others
+    let body: serde_json::Value = serde_json::from_str(&text).unwrap_or_default();
+    let error = &body["error"];
+    let message = error
+        .as_str()
+        .or_else(|| error["message"].as_str())
+        .map_or(text.clone(), str::to_owned);
+    let code = error["code"]
+        .as_str()
+        .or_else(|| error["type"].as_str())
+        .unwrap_or_default();
+
+    let missing = status == reqwest::StatusCode::NOT_FOUND
+        && (message.contains("not found") || message.contains("does not exist"));
+    if code == "model_not_found" || missing {
+        return Err(LlmError::ModelNotFound {
+            model: model.to_owned(),
+        });
+    }
+    Err(LlmError::classify(
+        Some(status.as_u16()),
+        code,
+        message,
+        retry_after_ms,
+    ))
+}
+
+/// The error for an answer that stopped before the model was done
+pub fn cut_short() -> LlmError {
+    LlmError::Provider {
+        message: "the answer ended before the model was done".to_owned(),
+    }
+}
+
+/// Splits a stream of server-sent events as its bytes arrive; only their
+/// data matters, both APIs repeat the event name in it
+#[derive(Default)]
+struct SseDecoder {
+    buffer: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseDecoder {
+    /// The data of the events completed by `bytes`
+    fn feed(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                events.extend(self.dispatch());
+                continue;
+            }
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            // Skip comments, event names, ids and retry hints
+            if field == "data" {
+                self.data.push(value.to_owned());
+            }
+        }
+        events
+    }
+
+    /// Whatever the stream ended with, even without a blank line after it
+    fn finish(&mut self) -> Vec<String> {
+        let mut events = self.feed(b"\n");
+        events.extend(self.dispatch());
+        events
+    }
+
+    fn dispatch(&mut self) -> Option<String> {
+        if self.data.is_empty() {
+            return None;
+        }
+        Some(std::mem::take(&mut self.data).join("\n"))
+    }
+}
+
+/// Pass the data of each event of `response` to `on_event` until it
+/// returns `false` or the stream ends
+pub async fn read_events(
+    mut response: reqwest::Response,
+    url: &str,
+    mut on_event: impl FnMut(String) -> Result<bool, LlmError>,
+) -> Result<(), LlmError> {
+    let mut decoder = SseDecoder::default();
+    while let Some(bytes) = response.chunk().await.map_err(|e| LlmError::Unavailable {
+        url: url.to_owned(),
+        message: describe(&e),
+    })? {
+        for event in decoder.feed(&bytes) {
+            if !on_event(event)? {
+                return Ok(());
+            }
+        }
+    }
+    for event in decoder.finish() {
+        if !on_event(event)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Tokens used with one model of one provider since the app started
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageTotals {
+    pub provider: String,
+    pub model: String,
+    pub requests: u32,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+/// Abort handles of running generations and the tokens used so far,
+/// managed as Tauri state
+#[derive(Default)]
+pub struct Generations {
+    running: Mutex<HashMap<String, AbortHandle>>,
+    usage: Mutex<BTreeMap<(String, String), UsageTotals>>,
+}
+
+impl Generations {
+    /// Run `task` as generation `id` until it finishes or is cancelled,
+    /// returning whether it was cancelled
+    pub async fn run(
+        &self,
+        id: &str,
+        task: impl Future<Output = Result<(), LlmError>>,
+    ) -> Result<bool, LlmError> {
+        let (handle, registration) = AbortHandle::new_pair();
+        {
+            let mut running = self.running.lock().unwrap();
+            if running.contains_key(id) {
+                return Err(LlmError::Duplicate { id: id.to_owned() });
+            }
+            running.insert(id.to_owned(), handle);
+        }
+        let result = Abortable::new(task, registration).await;
+        self.running.lock().unwrap().remove(id);
+        match result {
+            Ok(result) => result.map(|_| false),
+            Err(_) => Ok(true),
+        }
+    }
+
+    /// Stream the next message of `request` from `provider` as generation
+    /// `id`, counting the tokens it used
+    pub async fn chat(
+        &self,
+        provider: &dyn LlmProvider,
+        id: &str,
+        request: &ChatRequest,
+        channel: Option<&Channel<StreamEvent>>,
+    ) -> Result<Completion, LlmError> {
+        let mut completion = Completion::new(id, &request.model);
+        let mut stream = Stream::new(&mut completion, channel);
+        let cancelled = self.run(id, provider.chat(request, &mut stream)).await?;
+        completion.cancelled |= cancelled;
+        self.record(&provider.id(), &completion);
+        Ok(completion)
+    }
+
+    /// Add the tokens of `completion` to the totals of `provider`
+    pub fn record(&self, provider: &str, completion: &Completion) {
+        let mut usage = self.usage.lock().unwrap();
+        let totals = usage
+            .entry((provider.to_owned(), completion.model.clone()))
+            .or_insert_with(|| UsageTotals {
+                provider: provider.to_owned(),
+                model: completion.model.clone(),
+                ..UsageTotals::default()
+            });
+        totals.requests += 1;
+        totals.prompt_tokens += completion.usage.prompt_tokens as u64;
+        totals.completion_tokens += completion.usage.completion_tokens as u64;
+    }
+
+    /// Stop generation `id`; `false` if it isn't running
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.running.lock().unwrap().get(id) {
+            Some(handle) => {
+                handle.abort();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Stream the next message of a chat from `provider`, token by token, to
+/// `on_event`
+///
+/// `request_id` is chosen by the caller, to cancel the generation with
+/// `cancel_generation`.
+#[tauri::command]
+pub async fn llm_chat(
+    generations: State<'_, Generations>,
+    request_id: String,
+    provider: ProviderConfig,
+    request: ChatRequest,
+    on_event: Channel<StreamEvent>,
+) -> Result<Completion, LlmError> {
+    let provider = provider.build()?;
+    generations
+        .chat(provider.as_ref(), &request_id, &request, Some(&on_event))
+        .await
+}
+
+/// Stop a running generation; `false` if it already finished
+#[tauri::command]
+pub fn cancel_generation(generations: State<'_, Generations>, request_id: String) -> bool {
+    generations.cancel(&request_id)
+}
+
+/// Tokens used per provider and model since the app started
+#[tauri::command]
+pub fn llm_usage(generations: State<'_, Generations>) -> Vec<UsageTotals> {
+    generations
+        .usage
+        .lock()
+        .unwrap()
+        .values()
+        .cloned()
+        .collect()
+}
+
+/// A local HTTP server with canned answers, for the provider tests
+#[cfg(test)]
+pub(crate) mod test_server {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    /// One canned HTTP answer, written in pieces
+    pub struct Reply {
+        pub status: u16,
+        /// Extra header lines, e.g. `retry-after: 2`
+        pub headers: Vec<&'static str>,
+        pub pieces: Vec<&'static str>,
+        /// Keep the connection open this long after the last piece
+        pub hold: Duration,
+    }
+
+    pub fn ok(pieces: &[&'static str]) -> Reply {
+        status(200, pieces)
+    }
+
+    pub fn status(status: u16, pieces: &[&'static str]) -> Reply {
+        Reply {
+            status,
+            headers: Vec::new(),
+            pieces: pieces.to_vec(),
+            hold: Duration::ZERO,
+        }
+    }
+
+    /// Answer one connection per reply, in order; returns the base URL
+    pub fn serve(replies: Vec<Reply>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for reply in replies {
+                let (mut socket, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(socket.try_clone().unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                reader.read_exact(&mut vec![0; length]).unwrap();
+
+                let mut head = format!("HTTP/1.1 {} X\r\nConnection: close\r\n", reply.status);
+                for header in reply.headers {
+                    head.push_str(header);
+                    head.push_str("\r\n");
+                }
+                head.push_str("\r\n");
+                let _ = socket.write_all(head.as_bytes());
+                for piece in reply.pieces {
+                    let _ = socket.write_all(piece.as_bytes());
+                    let _ = socket.flush();
+                    thread::sleep(Duration::from_millis(20));
+                }
+                thread::sleep(reply.hold);
+            }
+        });
+        url
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tool_calls_come_in_order() {
+        let mut completion = Completion::new("r1", "gpt-4");
+        let mut stream = Stream::new(&mut completion, None);
+        assert!(stream.tool_call(0, Some("a"), Some("read"), "{").unwrap());
+        assert!(stream.tool_call(1, Some("b"), Some("list"), "{}").unwrap());
+        assert!(stream.tool_call(0, None, None, "}").unwrap());
+        assert!(matches!(
+            stream.tool_call(3, None, Some("write"), ""),
+            Err(LlmError::Provider { .. })
+        ));
+        assert!(matches!(
+            stream.tool_call(usize::MAX, None, None, ""),
+            Err(LlmError::Provider { .. })
+        ));
+        assert_eq!(completion.tool_calls.len(), 2);
+        assert_eq!(completion.tool_calls[0].arguments, "{}");
+        assert_eq!(completion.tool_calls[1].name, "list");
+    }
+
+    #[test]
+    fn sse_events_split_across_chunks() {
+        let mut decoder = SseDecoder::default();
+        assert!(decoder.feed(b"event: message\ndata: {\"a\"").is_empty());
+        assert!(decoder.feed(b":1}\r\n").is_empty());
+        assert_eq!(decoder.feed(b"\r\ndata:2\n\n"), ["{\"a\":1}", "2"]);
+    }
+
+    #[test]
+    fn sse_joins_data_lines_and_skips_other_fields() {
+        let mut decoder = SseDecoder::default();
+        let events =
+            decoder.feed(b": keep-alive\n\nid: 7\nretry: 100\ndata: one\ndata:  two\nevent: x\n\n");
+        assert_eq!(events, ["one\n two"]);
+    }
+
+    #[test]
+    fn sse_finish_flushes_the_last_event() {
+        let mut decoder = SseDecoder::default();
+        assert!(decoder.feed(b"data: [DONE]").is_empty());
+        assert_eq!(decoder.finish(), ["[DONE]"]);
+        assert!(decoder.finish().is_empty());
+    }
+
+    #[test]
+    fn classify_and_keys_for_custom_urls() {
+        assert!(matches!(
+            LlmError::classify(Some(429), "", "slow down".into(), Some(1000)),
+            LlmError::RateLimited {
+                retry_after_ms: Some(1000),
+                ..
+            }
+        ));
+        let custom = ProviderConfig::Anthropic {
+            base_url: Some("http://localhost:1".into()),
+            api_key: None,
+        };
+        assert!(matches!(
+            custom.build().err(),
+            Some(LlmError::Unauthorized { .. })
+        ));
+    }
+}
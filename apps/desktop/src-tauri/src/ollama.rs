@@ -0,0 +1,561 @@
+//! Native Ollama client
+//!
+//! Replaces the axios calls of `packages/local-ai`. `/api/generate` and
+//! `/api/chat` answer with one JSON object per line; each carries a piece
+//! of the answer and the last one the token counts. Tool calls come whole,
+//! with their arguments as an object.
+
+use std::time::Duration;
+
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use tauri::ipc::Channel;
+use tauri::State;
+
+use crate::llm::{
+    self, ChatMessage, ChatRequest, Completion, GenerateOptions, Generations, LlmError,
+    LlmProvider, Role, Stream, StreamEvent, Usage,
+};
+
+pub const DEFAULT_URL: &str = "http://localhost:11434";
+
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerateRequest {
+    pub model: String,
+    pub prompt: String,
+    pub system: Option<String>,
+    #[serde(default)]
+    pub options: GenerateOptions,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OllamaHealth {
+    pub url: String,
+    pub running: bool,
+    pub version: Option<String>,
+    /// Names of the pulled models
+    pub models: Vec<String>,
+}
+
+/// Ollama's names for `GenerateOptions`
+#[derive(Serialize)]
+struct ModelOptions<'o> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    stop: &'o [String],
+}
+
+impl<'o> From<&'o GenerateOptions> for ModelOptions<'o> {
+    fn from(options: &'o GenerateOptions) -> Self {
+        Self {
+            temperature: options.temperature,
+            top_p: options.top_p,
+            num_predict: options.max_tokens,
+            stop: &options.stop,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct GenerateBody<'r> {
+    model: &'r str,
+    prompt: &'r str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<&'r str>,
+    stream: bool,
+    options: ModelOptions<'r>,
+}
+
+#[derive(Serialize)]
+struct ChatBody<'r> {
+    model: &'r str,
+    messages: Vec<Message<'r>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<serde_json::Value>,
+    stream: bool,
+    options: ModelOptions<'r>,
+}
+
+#[derive(Serialize)]
+struct Message<'r> {
+    role: Role,
+    content: &'r str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<serde_json::Value>,
+}
+
+impl<'r> From<&'r ChatMessage> for Message<'r> {
+    fn from(message: &'r ChatMessage) -> Self {
+        let tool_calls = message
+            .tool_calls
+            .iter()
+            .map(|call| {
+                let arguments: serde_json::Value =
+                    serde_json::from_str(&call.arguments).unwrap_or_default();
+                serde_json::json!({ "function": { "name": call.name, "arguments": arguments } })
+            })
+            .collect();
+        Self {
+            role: message.role,
+            content: &message.content,
+            tool_calls,
+        }
+    }
+}
+
+/// One line of a streamed answer
+#[derive(Deserialize)]
+struct Line {
+    /// From `/api/generate`
+    #[serde(default)]
+    response: String,
+    /// From `/api/chat`
+    message: Option<LineMessage>,
+    #[serde(default)]
+    done: bool,
+    done_reason: Option<String>,
+    #[serde(default)]
+    prompt_eval_count: u32,
+    #[serde(default)]
+    eval_count: u32,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LineMessage {
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    tool_calls: Vec<LineToolCall>,
+}
+
+#[derive(Deserialize)]
+struct LineToolCall {
+    function: LineFunction,
+}
+
+#[derive(Deserialize)]
+struct LineFunction {
+    name: String,
+    #[serde(default)]
+    arguments: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct VersionBody {
+    version: String,
+}
+
+#[derive(Deserialize)]
+struct TagsBody {
+    #[serde(default)]
+    models: Vec<Tag>,
+}
+
+#[derive(Deserialize)]
+struct Tag {
+    name: String,
+}
+
+pub struct OllamaClient {
+    url: String,
+    http: reqwest::Client,
+}
+
+impl OllamaClient {
+    pub fn new(url: &str) -> Result<Self, LlmError> {
+        Ok(Self {
+            url: url.trim_end_matches('/').to_owned(),
+            http: llm::http_client()?,
+        })
+    }
+
+    /// Whether the server answers, its version and its models
+    pub async fn health(&self) -> OllamaHealth {
+        let version = self
+            .get::<VersionBody>("/api/version")
+            .await
+            .map(|body| body.version);
+        let models = match version {
+            Some(_) => self
+                .get::<TagsBody>("/api/tags")
+                .await
+                .map(|body| body.models.into_iter().map(|tag| tag.name).collect())
+                .unwrap_or_default(),
+            None => Vec::new(),
+        };
+        OllamaHealth {
+            url: self.url.clone(),
+            running: version.is_some(),
+            version,
+            models,
+        }
+    }
+
+    async fn get<T: for<'de> Deserialize<'de>>(&self, path: &str) -> Option<T> {
+        let response = self
+            .http
+            .get(format!("{}{}", self.url, path))
+            .timeout(HEALTH_TIMEOUT)
+            .send()
+            .await
+            .ok()?;
+        response.error_for_status().ok()?.json().await.ok()
+    }
+
+    pub async fn generate(
+        &self,
+        request: &GenerateRequest,
+        stream: &mut Stream<'_>,
+    ) -> Result<(), LlmError> {
+        let body = GenerateBody {
+            model: &request.model,
+            prompt: &request.prompt,
+            system: request.system.as_deref(),
+            stream: true,
+            options: (&request.options).into(),
+        };
+        self.stream("/api/generate", &request.model, &body, stream)
+            .await
+    }
+
+    pub async fn chat(
+        &self,
+        request: &ChatRequest,
+        stream: &mut Stream<'_>,
+    ) -> Result<(), LlmError> {
+        let tools = request
+            .tools
+            .iter()
+            .map(|tool| {
+                serde_json::json!({
+                    "type": "function",
+                    "function": {
+                        "name": tool.name,
+                        "description": tool.description,
+                        "parameters": tool.parameters,
+                    },
+                })
+            })
+            .collect();
+        let body = ChatBody {
+            model: &request.model,
+            messages: request.messages.iter().map(Message::from).collect(),
+            tools,
+            stream: true,
+            options: (&request.options).into(),
+        };
+        self.stream("/api/chat", &request.model, &body, stream)
+            .await
+    }
+
+    /// POST `body` to `path` and pass the streamed answer on to `stream`
+    async fn stream(
+        &self,
+        path: &str,
+        model: &str,
+        body: &impl Serialize,
+        stream: &mut Stream<'_>,
+    ) -> Result<(), LlmError> {
+        let request = self.http.post(format!("{}{}", self.url, path)).json(body);
+        let mut response = llm::send(request, &self.url, model).await?;
+
+        let mut buffer = Vec::new();
+        while let Some(bytes) = response.chunk().await.map_err(|e| LlmError::Unavailable {
+            url: self.url.clone(),
+            message: llm::describe(&e),
+        })? {
+            buffer.extend_from_slice(&bytes);
+            while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                if !read_line(&line, stream)? {
+                    return Ok(());
+                }
+            }
+        }
+        if !read_line(&buffer, stream)? {
+            return Ok(());
+        }
+        Err(llm::cut_short())
+    }
+}
+
+impl LlmProvider for OllamaClient {
+    fn id(&self) -> String {
+        match self.url.as_str() {
+            DEFAULT_URL => "ollama".to_owned(),
+            url => format!("ollama:{}", url),
+        }
+    }
+
+    fn chat<'a>(
+        &'a self,
+        request: &'a ChatRequest,
+        stream: &'a mut Stream<'_>,
+    ) -> BoxFuture<'a, Result<(), LlmError>> {
+        Box::pin(OllamaClient::chat(self, request, stream))
+    }
+}
+
+/// Pass one line of an answer on to `stream`; `false` once there is no
+/// more to read
+fn read_line(line: &[u8], stream: &mut Stream<'_>) -> Result<bool, LlmError> {
+    if line.trim_ascii().is_empty() {
+        return Ok(true);
+    }
+    let line: Line = serde_json::from_slice(line).map_err(|e| LlmError::Provider {
+        message: format!("unreadable answer: {}", e),
+    })?;
+    if let Some(message) = line.error {
+        return Err(LlmError::classify(None, "", message, None));
+    }
+    let listening = match &line.message {
+        Some(message) => {
+            let mut listening = stream.token(&message.content);
+            for call in &message.tool_calls {
+                let index = stream.completion.tool_calls.len();
+                let arguments = call.function.arguments.to_string();
+                let name = Some(call.function.name.as_str());
+                listening &= stream.tool_call(index, None, name, &arguments)?;
+            }
+            listening
+        }
+        None => stream.token(&line.response),
+    };
+    if !listening {
+        stream.completion.cancelled = true;
+        return Ok(false);
+    }
+    if line.done {
+        let usage = Usage {
+            prompt_tokens: line.prompt_eval_count,
+            completion_tokens: line.eval_count,
+        };
+        stream.done(usage, line.done_reason);
+    }
+    Ok(!line.done)
+}
+
+/// Stream a completion of `request.prompt`, token by token, to `on_event`
+///
+/// `request_id` is chosen by the caller, to cancel the generation with
+/// `cancel_generation`. Ollama is expected at `url`, or on its default
+/// port.
+#[tauri::command]
+pub async fn ollama_generate(
+    generations: State<'_, Generations>,
+    request_id: String,
+    request: GenerateRequest,
+    url: Option<String>,
+    on_event: Channel<StreamEvent>,
+) -> Result<Completion, LlmError> {
+    let client = OllamaClient::new(url.as_deref().unwrap_or(DEFAULT_URL))?;
+    let mut completion = Completion::new(&request_id, &request.model);
+    let mut stream = Stream::new(&mut completion, Some(&on_event));
+    let cancelled = generations
+        .run(&request_id, client.generate(&request, &mut stream))
+        .await?;
+    completion.cancelled |= cancelled;
+    generations.record(&client.id(), &completion);
+    Ok(completion)
+}
+
+/// Stream the next message of a chat, token by token, to `on_event`
+#[tauri::command]
+pub async fn ollama_chat(
+    generations: State<'_, Generations>,
+    request_id: String,
+    request: ChatRequest,
+    url: Option<String>,
+    on_event: Channel<StreamEvent>,
+) -> Result<Completion, LlmError> {
+    let client = OllamaClient::new(url.as_deref().unwrap_or(DEFAULT_URL))?;
+    generations
+        .chat(&client, &request_id, &request, Some(&on_event))
+        .await
+}
+
+/// Whether Ollama answers at `url`, or on its default port, and which
+/// models it has
+#[tauri::command]
+pub async fn ollama_health(url: Option<String>) -> Result<OllamaHealth, LlmError> {
+    let client = OllamaClient::new(url.as_deref().unwrap_or(DEFAULT_URL))?;
+    Ok(client.health().await)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+    use crate::llm::test_server::{ok, serve, status, Reply};
+    use crate::llm::Role;
+
+    fn request() -> ChatRequest {
+        ChatRequest {
+            model: "llama3".into(),
+            messages: vec![ChatMessage {
+                role: Role::User,
+                content: "hi".into(),
+                tool_calls: Vec::new(),
+                tool_call_id: None,
+            }],
+            tools: Vec::new(),
+            options: GenerateOptions::default(),
+        }
+    }
+
+    fn chat(url: &str) -> (Result<(), LlmError>, Completion) {
+        let client = OllamaClient::new(url).unwrap();
+        let mut completion = Completion::new("r1", "llama3");
+        let result = tauri::async_runtime::block_on(async {
+            let mut stream = Stream::new(&mut completion, None);
+            client.chat(&request(), &mut stream).await
+        });
+        (result, completion)
+    }
+
+    #[test]
+    fn chat_reads_lines_split_across_chunks() {
+        let url = serve(vec![ok(&[
+            "{\"message\":{\"content\":\"Hel\"}}\n{\"mess",
+            "age\":{\"content\":\"lo\"}}\n",
+            "{\"message\":{\"content\":\"\",\"tool_calls\":[{\"function\":{\"name\":\"read\",\"arguments\":{\"path\":\"a.rs\"}}}]}}\n",
+            "{\"done\":true,\"done_reason\":\"stop\",\"prompt_eval_count\":7,\"eval_count\":3}",
+        ])]);
+        let (result, completion) = chat(&url);
+        result.unwrap();
+        assert_eq!(completion.text, "Hello");
+        assert_eq!(completion.tool_calls.len(), 1);
+        assert_eq!(completion.tool_calls[0].name, "read");
+        assert_eq!(completion.tool_calls[0].arguments, r#"{"path":"a.rs"}"#);
+        assert_eq!(completion.usage.prompt_tokens, 7);
+        assert_eq!(completion.usage.completion_tokens, 3);
+        assert_eq!(completion.finish_reason.as_deref(), Some("stop"));
+        assert!(!completion.cancelled);
+    }
+
+    #[test]
+    fn generate_reads_the_response_field() {
+        let url = serve(vec![ok(&[
+            "{\"response\":\"4\"}\n\n{\"response\":\"2\"}\n",
+            "{\"response\":\"\",\"done\":true}\n",
+        ])]);
+        let client = OllamaClient::new(&url).unwrap();
+        let request = GenerateRequest {
+            model: "llama3".into(),
+            prompt: "6*7".into(),
+            system: None,
+            options: GenerateOptions::default(),
+        };
+        let mut completion = Completion::new("r1", "llama3");
+        tauri::async_runtime::block_on(async {
+            let mut stream = Stream::new(&mut completion, None);
+            client.generate(&request, &mut stream).await
+        })
+        .unwrap();
+        assert_eq!(completion.text, "42");
+    }
+
+    #[test]
+    fn stream_without_done_is_cut_short() {
+        let url = serve(vec![ok(&["{\"message\":{\"content\":\"Hel\"}}\n"])]);
+        let (result, completion) = chat(&url);
+        assert!(matches!(result, Err(LlmError::Provider { .. })));
+        assert_eq!(completion.text, "Hel");
+    }
+
+    #[test]
+    fn errors_in_the_stream_and_status_are_reported() {
+        let url = serve(vec![
+            ok(&["{\"error\":\"out of memory\"}\n"]),
+            status(
+                404,
+                &["{\"error\":\"model \\\"llama3\\\" not found, try pulling it first\"}"],
+            ),
+        ]);
+        let (result, _) = chat(&url);
+        assert!(result.is_err());
+        let (result, _) = chat(&url);
+        assert!(matches!(result, Err(LlmError::ModelNotFound { model }) if model == "llama3"));
+    }
+
+    #[test]
+    fn cancelling_stops_a_running_generation() {
+        let url = serve(vec![Reply {
+            hold: Duration::from_secs(5),
+            ..ok(&["{\"message\":{\"content\":\"Hel\"}}\n"])
+        }]);
+        let client = OllamaClient::new(&url).unwrap();
+        let generations = Generations::default();
+        let started = AtomicUsize::new(0);
+        let mut completion = Completion::new("r1", "llama3");
+        let request = request();
+        let cancelled = tauri::async_runtime::block_on(async {
+            let mut stream = Stream::new(&mut completion, None).watched(&started);
+            let run = generations.run("r1", client.chat(&request, &mut stream));
+            let cancel = async {
+                while started.load(std::sync::atomic::Ordering::Relaxed) == 0 {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                assert!(generations.cancel("r1"));
+            };
+            futures_util::join!(run, cancel).0
+        })
+        .unwrap();
+        assert!(cancelled);
+        assert_eq!(completion.text, "Hel");
+        assert!(!generations.cancel("r1"));
+    }
+
+    #[test]
+    fn a_closed_channel_stops_reading() {
+        let url = serve(vec![Reply {
+            hold: Duration::from_secs(5),
+            ..ok(&["{\"message\":{\"content\":\"Hel\"}}\n"])
+        }]);
+        let client = OllamaClient::new(&url).unwrap();
+        let channel = Channel::new(|_| Err(tauri::Error::FailedToReceiveMessage));
+        let mut completion = Completion::new("r1", "llama3");
+        tauri::async_runtime::block_on(async {
+            let mut stream = Stream::new(&mut completion, Some(&channel));
+            client.chat(&request(), &mut stream).await
+        })
+        .unwrap();
+        assert!(completion.cancelled);
+        assert_eq!(completion.text, "Hel");
+    }
+
+    #[test]
+    fn health_lists_models() {
+        let url = serve(vec![
+            ok(&["{\"version\":\"0.5.1\"}"]),
+            ok(&["{\"models\":[{\"name\":\"llama3:latest\"},{\"name\":\"qwen2.5-coder\"}]}"]),
+        ]);
+        let health = tauri::async_runtime::block_on(OllamaClient::new(&url).unwrap().health());
+        assert!(health.running);
+        assert_eq!(health.version.as_deref(), Some("0.5.1"));
+        assert_eq!(health.models, ["llama3:latest", "qwen2.5-coder"]);
+    }
+
+    #[test]
+    fn health_of_a_closed_port() {
+        let url = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+        let health = tauri::async_runtime::block_on(OllamaClient::new(&url).unwrap().health());
+        assert!(!health.running);
+        assert!(health.version.is_none());
+        assert!(health.models.is_empty());
+    }
+}
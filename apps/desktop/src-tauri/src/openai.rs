@@ -0,0 +1,376 @@
+//! OpenAI-compatible chat completions
+//!
+//! Speaks `/chat/completions` as served by OpenAI and by local servers
+//! that copy it: LM Studio, the llama.cpp server and vLLM. The answer is a
+//! stream of server-sent events, each a chunk with a delta of the message,
+//! ended by `[DONE]`; the token counts come in a last chunk of their own
+//! when asked for with `stream_options`.
+
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::llm::{self, ChatMessage, ChatRequest, LlmError, LlmProvider, Role, Stream, Usage};
+
+pub const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+#[derive(Serialize)]
+struct Body<'r> {
+    model: &'r str,
+    messages: Vec<serde_json::Value>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<serde_json::Value>,
+    stream: bool,
+    stream_options: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    stop: &'r [String],
+}
+
+#[derive(Deserialize)]
+struct Chunk {
+    #[serde(default)]
+    choices: Vec<Choice>,
+    usage: Option<ChunkUsage>,
+    error: Option<ChunkError>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    #[serde(default)]
+    delta: Delta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct Delta {
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ToolCallDelta>,
+}
+
+#[derive(Deserialize)]
+struct ToolCallDelta {
+    #[serde(default)]
+    index: usize,
+    id: Option<String>,
+    #[serde(default)]
+    function: FunctionDelta,
+}
+
+#[derive(Default, Deserialize)]
+struct FunctionDelta {
+    name: Option<String>,
+    #[serde(default)]
+    arguments: String,
+}
+
+#[derive(Deserialize)]
+struct ChunkUsage {
+    #[serde(default)]
+    prompt_tokens: u32,
+    #[serde(default)]
+    completion_tokens: u32,
+}
+
+#[derive(Deserialize)]
+struct ChunkError {
+    #[serde(default)]
+    message: String,
+    #[serde(default, alias = "type")]
+    code: Option<serde_json::Value>,
+}
+
+pub struct OpenAiClient {
+    base_url: String,
+    api_key: Option<String>,
+    http: reqwest::Client,
+}
+
+impl OpenAiClient {
+    /// A client for the API at `base_url`, e.g. `http://localhost:1234/v1`
+    /// for LM Studio; local servers need no `api_key`
+    pub fn new(base_url: &str, api_key: Option<String>) -> Result<Self, LlmError> {
+        Ok(Self {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            api_key,
+            http: llm::http_client()?,
+        })
+    }
+
+    pub async fn chat(
+        &self,
+        request: &ChatRequest,
+        stream: &mut Stream<'_>,
+    ) -> Result<(), LlmError> {
+        let options = &request.options;
+        let body = Body {
+            model: &request.model,
+            messages: request.messages.iter().map(message).collect(),
+            tools: request
+                .tools
+                .iter()
+                .map(|tool| {
+                    json!({
+                        "type": "function",
+                        "function": {
+                            "name": tool.name,
+                            "description": tool.description,
+                            "parameters": tool.parameters,
+                        },
+                    })
+                })
+                .collect(),
+            stream: true,
+            stream_options: json!({ "include_usage": true }),
+            temperature: options.temperature,
+            top_p: options.top_p,
+            max_tokens: options.max_tokens,
+            stop: &options.stop,
+        };
+        let mut http = self
+            .http
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(&body);
+        if let Some(key) = &self.api_key {
+            http = http.bearer_auth(key);
+        }
+        let response = llm::send(http, &self.base_url, &request.model).await?;
+
+        let mut usage = Usage::default();
+        let mut finish_reason = None;
+        let mut done = false;
+        llm::read_events(response, &self.base_url, |data| {
+            if data == "[DONE]" {
+                done = true;
+                return Ok(false);
+            }
+            let chunk: Chunk = serde_json::from_str(&data).map_err(|e| LlmError::Provider {
+                message: format!("unreadable answer: {}", e),
+            })?;
+            if let Some(error) = chunk.error {
+                let code = match &error.code {
+                    Some(serde_json::Value::String(code)) => code.clone(),
+                    _ => String::new(),
+                };
+                return Err(LlmError::classify(None, &code, error.message, None));
+            }
+            if let Some(counts) = chunk.usage {
+                usage = Usage {
+                    prompt_tokens: counts.prompt_tokens,
+                    completion_tokens: counts.completion_tokens,
+                };
+            }
+            let mut listening = true;
+            for choice in chunk.choices {
+                if let Some(text) = &choice.delta.content {
+                    listening &= stream.token(text);
+                }
+                for call in &choice.delta.tool_calls {
+                    listening &= stream.tool_call(
+                        call.index,
+                        call.id.as_deref(),
+                        call.function.name.as_deref(),
+                        &call.function.arguments,
+                    )?;
+                }
+                if choice.finish_reason.is_some() {
+                    finish_reason = choice.finish_reason;
+                }
+            }
+            if !listening {
+                stream.completion.cancelled = true;
+            }
+            Ok(listening)
+        })
+        .await?;
+
+        if stream.completion.cancelled {
+            return Ok(());
+        }
+        // Some servers close the stream without `[DONE]`
+        if !done && finish_reason.is_none() {
+            return Err(llm::cut_short());
+        }
+        stream.done(usage, finish_reason);
+        Ok(())
+    }
+}
+
+impl LlmProvider for OpenAiClient {
+    fn id(&self) -> String {
+        match self.base_url.as_str() {
+            DEFAULT_BASE_URL => "openai".to_owned(),
+            base_url => format!("openai:{}", base_url),
+        }
+    }
+
+    fn chat<'a>(
+        &'a self,
+        request: &'a ChatRequest,
+        stream: &'a mut Stream<'_>,
+    ) -> BoxFuture<'a, Result<(), LlmError>> {
+        Box::pin(OpenAiClient::chat(self, request, stream))
+    }
+}
+
+/// `message` as the API expects it
+fn message(message: &ChatMessage) -> serde_json::Value {
+    let mut value = json!({ "role": message.role, "content": message.content });
+    if message.role == Role::Assistant && !message.tool_calls.is_empty() {
+        value["tool_calls"] = message
+            .tool_calls
+            .iter()
+            .enumerate()
+            .map(|(i, call)| {
+                json!({
+                    "id": call.id.clone().unwrap_or_else(|| format!("call_{}", i)),
+                    "type": "function",
+                    "function": { "name": call.name, "arguments": call.arguments },
+                })
+            })
+            .collect();
+    }
+    if let Some(id) = &message.tool_call_id {
+        value["tool_call_id"] = json!(id);
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::test_server::{ok, serve, status, Reply};
+    use crate::llm::{Completion, GenerateOptions};
+
+    fn chat(url: &str) -> (Result<(), LlmError>, Completion) {
+        let client = OpenAiClient::new(url, None).unwrap();
+        let request = ChatRequest {
+            model: "gpt-4".into(),
+            messages: vec![ChatMessage {
+                role: Role::User,
+                content: "read a.rs".into(),
+                tool_calls: Vec::new(),
+                tool_call_id: None,
+            }],
+            tools: Vec::new(),
+            options: GenerateOptions::default(),
+        };
+        let mut completion = Completion::new("r1", "gpt-4");
+        let result = tauri::async_runtime::block_on(async {
+            let mut stream = Stream::new(&mut completion, None);
+            client.chat(&request, &mut stream).await
+        });
+        (result, completion)
+    }
+
+    #[test]
+    fn tool_call_deltas_are_assembled() {
+        let url = serve(vec![ok(&[
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Reading\"}}]}\n\n",
+            r#"data: {"choices":[{"delta":{"tool_calls":[{"index":0,"id":"call_a","function":{"name":"read_file","arguments":""}}]}}]}"#,
+            "\n\n",
+            r#"data: {"choices":[{"delta":{"tool_calls":[{"index":0,"function":{"arguments":"{\"pa"}}]}}]}"#,
+            "\n\n",
+            r#"data: {"choices":[{"delta":{"tool_calls":[{"index":1,"id":"call_b","function":{"name":"list_dir","arguments":"{}"}}]}}]}"#,
+            "\n\n",
+            r#"data: {"choices":[{"delta":{"tool_calls":[{"index":0,"function":{"arguments":"th\":\"a.rs\"}"}}]}}]}"#,
+            "\n\n",
+            "data: {\"choices\":[{\"delta\":{},\"finish_reason\":\"tool_calls\"}]}\n\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":12,\"completion_tokens\":5}}\n\n",
+            "data: [DONE]\n\n",
+        ])]);
+        let (result, completion) = chat(&url);
+        result.unwrap();
+        assert_eq!(completion.text, "Reading");
+        assert_eq!(completion.tool_calls.len(), 2);
+        let read = &completion.tool_calls[0];
+        assert_eq!(read.id.as_deref(), Some("call_a"));
+        assert_eq!(read.name, "read_file");
+        assert_eq!(read.arguments, r#"{"path":"a.rs"}"#);
+        assert_eq!(completion.tool_calls[1].name, "list_dir");
+        assert_eq!(completion.usage.prompt_tokens, 12);
+        assert_eq!(completion.usage.completion_tokens, 5);
+        assert_eq!(completion.finish_reason.as_deref(), Some("tool_calls"));
+    }
+
+    #[test]
+    fn a_tool_call_index_past_the_next_is_refused() {
+        let url = serve(vec![ok(&[
+            r#"data: {"choices":[{"delta":{"tool_calls":[{"index":4000000000,"function":{"name":"x"}}]}}]}"#,
+            "\n\ndata: [DONE]\n\n",
+        ])]);
+        let (result, completion) = chat(&url);
+        assert!(matches!(result, Err(LlmError::Provider { .. })));
+        assert!(completion.tool_calls.is_empty());
+    }
+
+    #[test]
+    fn a_stream_without_an_end_is_cut_short() {
+        let url = serve(vec![ok(&[
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\n",
+        ])]);
+        let (result, completion) = chat(&url);
+        assert!(matches!(result, Err(LlmError::Provider { .. })));
+        assert_eq!(completion.text, "Hel");
+    }
+
+    #[test]
+    fn refusals_map_to_errors() {
+        let url = serve(vec![
+            Reply {
+                headers: vec!["retry-after: 2"],
+                ..status(
+                    429,
+                    &[r#"{"error":{"message":"slow down","type":"requests"}}"#],
+                )
+            },
+            status(
+                401,
+                &[r#"{"error":{"message":"bad key","code":"invalid_api_key"}}"#],
+            ),
+            status(
+                404,
+                &[r#"{"error":{"message":"no such model","code":"model_not_found"}}"#],
+            ),
+            status(500, &["upstream failed"]),
+            ok(&[
+                r#"data: {"error":{"message":"This model's maximum context length is 8192 tokens","code":"context_length_exceeded"}}"#,
+                "\n\n",
+            ]),
+        ]);
+        assert!(matches!(
+            chat(&url).0,
+            Err(LlmError::RateLimited {
+                retry_after_ms: Some(2000),
+                ..
+            })
+        ));
+        assert!(matches!(chat(&url).0, Err(LlmError::Unauthorized { .. })));
+        assert!(matches!(
+            chat(&url).0,
+            Err(LlmError::ModelNotFound { model }) if model == "gpt-4"
+        ));
+        assert!(matches!(
+            chat(&url).0,
+            Err(LlmError::Http { status: 500, message }) if message == "upstream failed"
+        ));
+        assert!(matches!(
+            chat(&url).0,
+            Err(LlmError::ContextOverflow { .. })
+        ));
+    }
+
+    #[test]
+    fn ids_name_custom_servers() {
+        let openai = OpenAiClient::new(DEFAULT_BASE_URL, None).unwrap();
+        assert_eq!(LlmProvider::id(&openai), "openai");
+        let local = OpenAiClient::new("http://localhost:1234/v1/", None).unwrap();
+        assert_eq!(LlmProvider::id(&local), "openai:http://localhost:1234/v1");
+    }
+}
@@ -0,0 +1,1044 @@
+//! Unified diff and SEARCH/REPLACE patch application
+//!
+//! Models often answer with a patch instead of whole files. `apply_patch`
+//! parses either format, applies it to the files on disk with offset and
+//! fuzz tolerance like GNU `patch`, and returns the resulting content for
+//! preview. Nothing is written; the frontend passes the result on to
+//! `request_file_write_permission` / `apply_file_edit`.
+
+use std::path::Path;
+
+use serde::Serialize;
+use tauri::State;
+
+use crate::fs_edit::{self, FileEditError, LineEnding};
+use crate::workspace::Workspace;
+
+/// Lines a hunk may drift from its stated position and still apply
+pub const DEFAULT_MAX_OFFSET: usize = 200;
+
+/// Context lines that may be ignored at each end of a hunk
+pub const DEFAULT_FUZZ: usize = 2;
+
+const NULL_PATH: &str = "/dev/null";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum HunkLine {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+#[derive(Debug, Clone)]
+struct Hunk {
+    header: String,
+    /// 1-based start in the old file; `None` for `@@ @@` without numbers
+    old_start: Option<usize>,
+    lines: Vec<HunkLine>,
+    /// `\ No newline at end of file` after the last new-side line
+    new_no_eol: bool,
+}
+
+#[derive(Debug, Clone)]
+struct SearchReplace {
+    search: String,
+    replace: String,
+}
+
+#[derive(Debug, Clone)]
+enum Changes {
+    Hunks(Vec<Hunk>),
+    Blocks(Vec<SearchReplace>),
+}
+
+/// All changes a patch makes to one file
+#[derive(Debug, Clone)]
+struct FilePatch {
+    /// `None` when the patch didn't name the file
+    path: Option<String>,
+    created: bool,
+    deleted: bool,
+    changes: Changes,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RejectReason {
+    /// No position in the file matched the hunk, even with fuzz
+    ContextNotFound,
+    /// The hunk's result is already present in the file
+    AlreadyApplied,
+    /// The SEARCH text appears more than once
+    Ambiguous,
+    /// An empty SEARCH block can only create a new file
+    EmptySearch,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppliedHunk {
+    pub index: usize,
+    /// 1-based line in the original file where the hunk matched
+    pub line: usize,
+    /// Distance from the position stated in the hunk header
+    pub offset: isize,
+    /// Context lines ignored at each end to make it match
+    pub fuzz: usize,
+    /// Whether the match ignored whitespace differences
+    pub whitespace_insensitive: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RejectedHunk {
+    pub index: usize,
+    pub reason: RejectReason,
+    /// The hunk or SEARCH block as given, for display
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PatchedFile {
+    pub path: String,
+    /// Hash of the file the patch was applied to, `None` if it didn't exist;
+    /// pass it as `expectedHash` when writing the result
+    pub base_hash: Option<String>,
+    pub deleted: bool,
+    pub content: String,
+    pub applied: Vec<AppliedHunk>,
+    pub rejected: Vec<RejectedHunk>,
+}
+
+/// Parse `patch` and apply it to files in the workspace, without writing
+/// `file_path` names the target when the patch itself doesn't.
+#[tauri::command]
+pub async fn apply_patch(
+    workspace: State<'_, Workspace>,
+    patch: String,
+    file_path: Option<String>,
+    max_offset: Option<usize>,
+    fuzz: Option<usize>,
+) -> Result<Vec<PatchedFile>, FileEditError> {
+    let invalid = |message: String| FileEditError::InvalidOperation {
+        path: file_path.clone().unwrap_or_else(|| "patch".into()),
+        message,
+    };
+    let files = parse(&patch).map_err(invalid)?;
+    let tolerance = Tolerance {
+        max_offset: max_offset.unwrap_or(DEFAULT_MAX_OFFSET),
+        fuzz: fuzz.unwrap_or(DEFAULT_FUZZ),
+    };
+
+    let mut results = Vec::new();
+    for file in files {
+        let path = file
+            .path
+            .clone()
+            .or_else(|| file_path.clone())
+            .ok_or_else(|| invalid("the patch does not name a file".into()))?;
+        let target = workspace.resolve(Path::new(&path))?;
+        let original = match std::fs::read(&target) {
+            Ok(bytes) => Some(bytes),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(FileEditError::from_io(&target, e)),
+        };
+        if original.is_none() && !file.created && !matches!(file.changes, Changes::Blocks(_)) {
+            return Err(FileEditError::PathMissing { path });
+        }
+        let base_hash = original.as_deref().map(fs_edit::content_hash);
+        let text = original
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+            .unwrap_or_default();
+        let outcome = apply(&text, &file, tolerance);
+        results.push(PatchedFile {
+            path,
+            base_hash,
+            deleted: file.deleted && outcome.rejected.is_empty(),
+            content: outcome.content,
+            applied: outcome.applied,
+            rejected: outcome.rejected,
+        });
+    }
+    Ok(results)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Tolerance {
+    max_offset: usize,
+    fuzz: usize,
+}
+
+struct Outcome {
+    content: String,
+    applied: Vec<AppliedHunk>,
+    rejected: Vec<RejectedHunk>,
+}
+
+/// Split a patch into per-file changes, detecting the format
+fn parse(patch: &str) -> Result<Vec<FilePatch>, String> {
+    let files = if is_search_replace(patch) {
+        parse_search_replace(patch)?
+    } else {
+        parse_unified(patch)?
+    };
+    if files.is_empty() {
+        return Err("no hunks or SEARCH/REPLACE blocks found".into());
+    }
+    Ok(files)
+}
+
+/// A SEARCH marker on its own line before any diff header; a diff that
+/// adds such a line is still a diff
+fn is_search_replace(patch: &str) -> bool {
+    for line in patch.lines() {
+        let line = line.trim_end();
+        if line == "<<<<<<< SEARCH" {
+            return true;
+        }
+        if line.starts_with("@@") || line.starts_with("--- ") {
+            return false;
+        }
+    }
+    false
+}
+
+fn parse_unified(patch: &str) -> Result<Vec<FilePatch>, String> {
+    let mut files: Vec<FilePatch> = Vec::new();
+    let lines: Vec<&str> = patch
+        .lines()
+        .map(|l| l.strip_suffix('\r').unwrap_or(l))
+        .collect();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+        i += 1;
+        if let Some(old) = line.strip_prefix("--- ") {
+            let Some(new) = lines.get(i).and_then(|l| l.strip_prefix("+++ ")) else {
+                return Err(format!("expected a +++ line after `{line}`"));
+            };
+            i += 1;
+            let (old, new) = (header_path(old), header_path(new));
+            let (created, deleted) = (old == NULL_PATH, new == NULL_PATH);
+            files.push(FilePatch {
+                path: Some(if deleted { old } else { new }),
+                created,
+                deleted,
+                changes: Changes::Hunks(Vec::new()),
+            });
+        } else if line.starts_with("@@") {
+            if files.is_empty() {
+                files.push(FilePatch {
+                    path: None,
+                    created: false,
+                    deleted: false,
+                    changes: Changes::Hunks(Vec::new()),
+                });
+            }
+            let (old_start, counts) = parse_hunk_header(line);
+            // Hunk line counts from models are often wrong, so only trust
+            // them when the body adds up
+            let body = counts
+                .and_then(|(old, new)| read_counted(&lines[i..], old, new))
+                .unwrap_or_else(|| read_to_header(&lines[i..]));
+            i += body.used;
+            let hunk = Hunk {
+                header: line.to_string(),
+                old_start,
+                lines: body.lines,
+                new_no_eol: body.new_no_eol,
+            };
+            if let Some(FilePatch {
+                changes: Changes::Hunks(hunks),
+                ..
+            }) = files.last_mut()
+            {
+                hunks.push(hunk);
+            }
+        }
+    }
+    files.retain(|f| matches!(&f.changes, Changes::Hunks(h) if !h.is_empty()));
+    Ok(files)
+}
+
+/// The lines of one hunk and how many patch lines they took up
+#[derive(Default)]
+struct HunkBody {
+    lines: Vec<HunkLine>,
+    new_no_eol: bool,
+    used: usize,
+}
+
+impl HunkBody {
+    /// `\ No newline at end of file` applies to the line before it
+    fn mark_no_eol(&mut self) {
+        if matches!(
+            self.lines.last(),
+            Some(HunkLine::Add(_) | HunkLine::Context(_))
+        ) {
+            self.new_no_eol = true;
+        }
+    }
+}
+
+/// Read exactly `old` old-side and `new` new-side lines, so a removed
+/// `-- comment` isn't taken for a file header. `None` if the body doesn't
+/// match the counts.
+fn read_counted(lines: &[&str], old: usize, new: usize) -> Option<HunkBody> {
+    let (mut old_left, mut new_left) = (old, new);
+    let mut body = HunkBody::default();
+    for &line in lines {
+        if line.starts_with('\\') {
+            body.mark_no_eol();
+            body.used += 1;
+            continue;
+        }
+        if old_left == 0 && new_left == 0 {
+            break;
+        }
+        let text = line.get(1..).unwrap_or_default().to_string();
+        match line.chars().next() {
+            Some('-') if old_left > 0 => {
+                old_left -= 1;
+                body.lines.push(HunkLine::Remove(text));
+            }
+            Some('+') if new_left > 0 => {
+                new_left -= 1;
+                body.lines.push(HunkLine::Add(text));
+            }
+            Some(' ') | None if old_left > 0 && new_left > 0 => {
+                old_left -= 1;
+                new_left -= 1;
+                body.lines.push(HunkLine::Context(text));
+            }
+            _ => return None,
+        }
+        body.used += 1;
+    }
+    if old_left > 0 || new_left > 0 {
+        return None;
+    }
+    // More body lines right after mean the counts were too small
+    let rest = &lines[body.used..];
+    let more = match rest.first() {
+        Some(next) if next.starts_with("--- ") => !is_file_header(rest),
+        Some(next) => next.starts_with(['+', '-', ' ']),
+        None => false,
+    };
+    (!more).then_some(body)
+}
+
+/// Read until the next header or prose line, ignoring the counts
+fn read_to_header(lines: &[&str]) -> HunkBody {
+    let mut body = HunkBody::default();
+    for (at, &line) in lines.iter().enumerate() {
+        if line.starts_with("@@") || line.starts_with("diff ") || is_file_header(&lines[at..]) {
+            break;
+        }
+        let text = line.get(1..).unwrap_or_default().to_string();
+        match line.chars().next() {
+            Some('+') => body.lines.push(HunkLine::Add(text)),
+            Some('-') => body.lines.push(HunkLine::Remove(text)),
+            Some(' ') => body.lines.push(HunkLine::Context(text)),
+            Some('\\') => body.mark_no_eol(),
+            None => body.lines.push(HunkLine::Context(String::new())),
+            // Stray prose between hunks
+            Some(_) => break,
+        }
+        body.used += 1;
+    }
+    while body.lines.last() == Some(&HunkLine::Context(String::new())) {
+        body.lines.pop();
+    }
+    body
+}
+
+/// A `---` line followed by `+++` starts the next file
+fn is_file_header(lines: &[&str]) -> bool {
+    matches!(lines, [old, new, ..] if old.starts_with("--- ") && new.starts_with("+++ "))
+}
+
+/// `a/src/main.rs\t2024-01-01` → `src/main.rs`
+fn header_path(raw: &str) -> String {
+    let path = raw.split('\t').next().unwrap_or(raw).trim();
+    if path == NULL_PATH {
+        return path.to_string();
+    }
+    path.strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .unwrap_or(path)
+        .to_string()
+}
+
+/// `@@ -12,7 +12,8 @@` → `(Some(12), Some((7, 8)))`; a range without a
+/// count covers one line
+fn parse_hunk_header(header: &str) -> (Option<usize>, Option<(usize, usize)>) {
+    let range = |prefix: char| {
+        let part = header
+            .split_whitespace()
+            .find_map(|part| part.strip_prefix(prefix))?;
+        let (start, count) = part.split_once(',').unwrap_or((part, "1"));
+        Some((start.parse::<usize>().ok()?, count.parse::<usize>().ok()?))
+    };
+    let (old, new) = (range('-'), range('+'));
+    let counts = old.zip(new).map(|((_, old), (_, new))| (old, new));
+    (old.map(|(start, _)| start), counts)
+}
+
+fn parse_search_replace(patch: &str) -> Result<Vec<FilePatch>, String> {
+    let mut files: Vec<FilePatch> = Vec::new();
+    let mut last_path: Option<String> = None;
+    let mut lines = patch.lines().map(|l| l.strip_suffix('\r').unwrap_or(l));
+
+    while let Some(line) = lines.next() {
+        if line.trim_end() != "<<<<<<< SEARCH" {
+            if let Some(path) = block_path(line) {
+                last_path = Some(path.to_string());
+            }
+            continue;
+        }
+
+        let mut search = Vec::new();
+        let mut replace = Vec::new();
+        let mut in_replace = false;
+        let mut closed = false;
+        for line in lines.by_ref() {
+            match line.trim_end() {
+                "=======" if !in_replace => in_replace = true,
+                ">>>>>>> REPLACE" if in_replace => {
+                    closed = true;
+                    break;
+                }
+                _ if in_replace => replace.push(line),
+                _ => search.push(line),
+            }
+        }
+        if !closed {
+            return Err("unterminated SEARCH/REPLACE block".into());
+        }
+
+        let block = SearchReplace {
+            search: join_block(&search),
+            replace: join_block(&replace),
+        };
+        match files.last_mut() {
+            Some(FilePatch {
+                path,
+                changes: Changes::Blocks(blocks),
+                ..
+            }) if *path == last_path => blocks.push(block),
+            _ => files.push(FilePatch {
+                path: last_path.clone(),
+                created: false,
+                deleted: false,
+                changes: Changes::Blocks(vec![block]),
+            }),
+        }
+    }
+    Ok(files)
+}
+
+/// A line naming the file for the next block: `src/main.rs`, `` `lib.rs` ``
+/// or `src/main.rs:`. Prose such as `Explanation:` is not a path.
+fn block_path(line: &str) -> Option<&str> {
+    if line.starts_with("```") {
+        return None;
+    }
+    let candidate = line.trim().trim_matches('`').trim().trim_end_matches(':');
+    let path_like =
+        candidate.contains(['/', '\\']) || (candidate.contains('.') && !candidate.ends_with('.'));
+    let plain = candidate
+        .chars()
+        .all(|c| c.is_alphanumeric() || "._-/\\@+~".contains(c));
+    (path_like && plain).then_some(candidate)
+}
+
+fn join_block(lines: &[&str]) -> String {
+    if lines.is_empty() {
+        String::new()
+    } else {
+        let mut text = lines.join("\n");
+        text.push('\n');
+        text
+    }
+}
+
+fn apply(text: &str, file: &FilePatch, tolerance: Tolerance) -> Outcome {
+    let ending = LineEnding::detect(text).unwrap_or(LineEnding::Lf);
+    let normalized = text.replace("\r\n", "\n");
+    let mut outcome = match &file.changes {
+        Changes::Hunks(hunks) => apply_hunks(&normalized, hunks, tolerance),
+        Changes::Blocks(blocks) => apply_blocks(&normalized, blocks),
+    };
+    outcome.content = ending.apply(&outcome.content);
+    outcome
+}
+
+/// Compares a file line with a patch line
+type Matcher = fn(&str, &str) -> bool;
+
+/// Ways of comparing lines, strictest first, and whether each is loose
+const MATCHERS: [(Matcher, bool); 3] = [
+    (|a, b| a == b, false),
+    (|a, b| a.trim_end() == b.trim_end(), false),
+    (|a, b| a.split_whitespace().eq(b.split_whitespace()), true),
+];
+
+fn apply_hunks(text: &str, hunks: &[Hunk], tolerance: Tolerance) -> Outcome {
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    let mut trailing_newline = text.is_empty() || text.ends_with('\n');
+    let mut applied = Vec::new();
+    let mut rejected = Vec::new();
+    // Shift between old-file line numbers and the buffer after earlier hunks
+    let mut delta: isize = 0;
+    // Hunks apply in order and must not overlap
+    let mut min_start = 0;
+
+    for (index, hunk) in hunks.iter().enumerate() {
+        let expected = hunk
+            .old_start
+            .map(|s| (s.saturating_sub(1) as isize + delta).max(0) as usize);
+
+        match locate(&lines, hunk, expected, min_start, tolerance) {
+            Some(found) => {
+                let old_len = found.old.len();
+                let mut replacement = Vec::new();
+                let mut cursor = found.start;
+                for line in &hunk.lines[found.skip_front..hunk.lines.len() - found.skip_back] {
+                    match line {
+                        // Keep the file's own text for context matched loosely
+                        HunkLine::Context(_) => {
+                            replacement.push(lines[cursor].clone());
+                            cursor += 1;
+                        }
+                        HunkLine::Remove(_) => cursor += 1,
+                        HunkLine::Add(text) => replacement.push(text.clone()),
+                    }
+                }
+                let new_len = replacement.len();
+                let touches_end = found.start + old_len == lines.len();
+                lines.splice(found.start..found.start + old_len, replacement);
+                if touches_end && found.skip_back == 0 {
+                    trailing_newline = !hunk.new_no_eol;
+                }
+
+                applied.push(AppliedHunk {
+                    index,
+                    line: (found.start as isize - delta) as usize + 1,
+                    offset: expected.map_or(0, |e| {
+                        found.start as isize - (e + found.skip_front) as isize
+                    }),
+                    fuzz: found.skip_front.max(found.skip_back),
+                    whitespace_insensitive: found.loose,
+                });
+                delta += new_len as isize - old_len as isize;
+                min_start = found.start + new_len;
+            }
+            None => {
+                let new_side: Vec<&str> = side(&hunk.lines, false);
+                let reason = if !new_side.is_empty()
+                    && find(&lines, &new_side, expected, 0, usize::MAX, MATCHERS[1].0).is_some()
+                {
+                    RejectReason::AlreadyApplied
+                } else {
+                    RejectReason::ContextNotFound
+                };
+                rejected.push(RejectedHunk {
+                    index,
+                    reason,
+                    text: render_hunk(hunk),
+                });
+            }
+        }
+    }
+
+    let mut content = lines.join("\n");
+    if trailing_newline && !lines.is_empty() {
+        content.push('\n');
+    }
+    Outcome {
+        content,
+        applied,
+        rejected,
+    }
+}
+
+struct Located<'h> {
+    start: usize,
+    old: Vec<&'h str>,
+    skip_front: usize,
+    skip_back: usize,
+    loose: bool,
+}
+
+/// Find where a hunk applies, relaxing context and whitespace step by step
+fn locate<'h>(
+    lines: &[String],
+    hunk: &'h Hunk,
+    expected: Option<usize>,
+    min_start: usize,
+    tolerance: Tolerance,
+) -> Option<Located<'h>> {
+    let leading = hunk
+        .lines
+        .iter()
+        .take_while(|l| matches!(l, HunkLine::Context(_)))
+        .count();
+    let trailing = hunk
+        .lines
+        .iter()
+        .rev()
+        .take_while(|l| matches!(l, HunkLine::Context(_)))
+        .count();
+
+    for fuzz in 0..=tolerance.fuzz {
+        let skip_front = fuzz.min(leading);
+        let skip_back = fuzz.min(trailing);
+        if fuzz > 0 && skip_front == 0 && skip_back == 0 {
+            break;
+        }
+        if skip_front + skip_back >= hunk.lines.len() {
+            break;
+        }
+        let trimmed = &hunk.lines[skip_front..hunk.lines.len() - skip_back];
+        let old = side(trimmed, true);
+        let hint = expected.map(|e| e + skip_front);
+        for (matches, loose) in MATCHERS {
+            if let Some(start) = find(lines, &old, hint, min_start, tolerance.max_offset, matches) {
+                return Some(Located {
+                    start,
+                    old,
+                    skip_front,
+                    skip_back,
+                    loose,
+                });
+            }
+        }
+    }
+    None
+}
+
+/// The old (context + removed) or new (context + added) side of a hunk
+fn side(lines: &[HunkLine], old: bool) -> Vec<&str> {
+    lines
+        .iter()
+        .filter_map(|l| match l {
+            HunkLine::Context(t) => Some(t.as_str()),
+            HunkLine::Remove(t) if old => Some(t.as_str()),
+            HunkLine::Add(t) if !old => Some(t.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Nearest position to `hint` at or after `min_start` where `pattern`
+/// matches, searching at most `max_offset` lines away. Without a hint the
+/// first match wins.
+fn find(
+    lines: &[String],
+    pattern: &[&str],
+    hint: Option<usize>,
+    min_start: usize,
+    max_offset: usize,
+    matches: Matcher,
+) -> Option<usize> {
+    let last = lines.len().checked_sub(pattern.len())?;
+    if min_start > last {
+        return None;
+    }
+    let fits = |start: usize| {
+        lines[start..start + pattern.len()]
+            .iter()
+            .zip(pattern)
+            .all(|(a, b)| matches(a, b))
+    };
+    let Some(hint) = hint else {
+        return (min_start..=last).find(|&s| fits(s));
+    };
+    let hint = hint.clamp(min_start, last);
+    for distance in 0..=max_offset {
+        let below = hint.checked_sub(distance).filter(|&s| s >= min_start);
+        let above = hint.checked_add(distance).filter(|&s| s <= last);
+        if below.is_none() && above.is_none() {
+            break;
+        }
+        for start in [below, above].into_iter().flatten() {
+            if fits(start) {
+                return Some(start);
+            }
+        }
+    }
+    None
+}
+
+fn render_hunk(hunk: &Hunk) -> String {
+    let mut text = hunk.header.clone();
+    for line in &hunk.lines {
+        let (prefix, body) = match line {
+            HunkLine::Context(t) => (' ', t),
+            HunkLine::Remove(t) => ('-', t),
+            HunkLine::Add(t) => ('+', t),
+        };
+        text.push('\n');
+        text.push(prefix);
+        text.push_str(body);
+    }
+    text
+}
+
+fn apply_blocks(text: &str, blocks: &[SearchReplace]) -> Outcome {
+    let mut content = text.to_string();
+    let mut applied = Vec::new();
+    let mut rejected = Vec::new();
+
+    for (index, block) in blocks.iter().enumerate() {
+        let reject = |reason| RejectedHunk {
+            index,
+            reason,
+            text: format!(
+                "<<<<<<< SEARCH\n{}=======\n{}>>>>>>> REPLACE",
+                block.search, block.replace
+            ),
+        };
+        if block.search.is_empty() {
+            if content.is_empty() {
+                content = block.replace.clone();
+                applied.push(block_applied(index, 1, false));
+            } else {
+                rejected.push(reject(RejectReason::EmptySearch));
+            }
+            continue;
+        }
+
+        let exact: Vec<usize> = content
+            .match_indices(&block.search)
+            .map(|(i, _)| i)
+            .filter(|&i| i == 0 || content.as_bytes()[i - 1] == b'\n')
+            .collect();
+        match exact.as_slice() {
+            [at] => {
+                let line = content[..*at].matches('\n').count() + 1;
+                content.replace_range(*at..*at + block.search.len(), &block.replace);
+                applied.push(block_applied(index, line, false));
+            }
+            [_, _, ..] => rejected.push(reject(RejectReason::Ambiguous)),
+            [] => match replace_loose(&content, block) {
+                Ok((replaced, line)) => {
+                    content = replaced;
+                    applied.push(block_applied(index, line, true));
+                }
+                Err(reason) => rejected.push(reject(reason)),
+            },
+        }
+    }
+    Outcome {
+        content,
+        applied,
+        rejected,
+    }
+}
+
+fn block_applied(index: usize, line: usize, loose: bool) -> AppliedHunk {
+    AppliedHunk {
+        index,
+        line,
+        offset: 0,
+        fuzz: 0,
+        whitespace_insensitive: loose,
+    }
+}
+
+/// Line-wise SEARCH match ignoring whitespace differences
+fn replace_loose(content: &str, block: &SearchReplace) -> Result<(String, usize), RejectReason> {
+    let lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let search: Vec<&str> = block.search.lines().collect();
+    let (matches, _) = MATCHERS[2];
+    let Some(start) = find(&lines, &search, None, 0, 0, matches) else {
+        let replace: Vec<&str> = block.replace.lines().collect();
+        let already = !replace.is_empty() && find(&lines, &replace, None, 0, 0, matches).is_some();
+        return Err(if already {
+            RejectReason::AlreadyApplied
+        } else {
+            RejectReason::ContextNotFound
+        });
+    };
+    if find(&lines, &search, None, start + 1, 0, matches).is_some() {
+        return Err(RejectReason::Ambiguous);
+    }
+
+    let mut result: Vec<&str> = lines[..start].iter().map(String::as_str).collect();
+    result.extend(block.replace.lines());
+    result.extend(lines[start + search.len()..].iter().map(String::as_str));
+    let mut text = result.join("\n");
+    if content.ends_with('\n') && !result.is_empty() {
+        text.push('\n');
+    }
+    Ok((text, start + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOLERANCE: Tolerance = Tolerance {
+        max_offset: DEFAULT_MAX_OFFSET,
+        fuzz: DEFAULT_FUZZ,
+    };
+
+    fn hunks(file: &FilePatch) -> &[Hunk] {
+        match &file.changes {
+            Changes::Hunks(hunks) => hunks,
+            Changes::Blocks(_) => panic!("expected hunks"),
+        }
+    }
+
+    fn blocks(file: &FilePatch) -> &[SearchReplace] {
+        match &file.changes {
+            Changes::Blocks(blocks) => blocks,
+            Changes::Hunks(_) => panic!("expected blocks"),
+        }
+    }
+
+    fn apply_one(text: &str, patch: &str) -> Outcome {
+        let files = parse(patch).unwrap();
+        assert_eq!(files.len(), 1);
+        apply(text, &files[0], TOLERANCE)
+    }
+
+    #[test]
+    fn unified_splits_files_and_reads_headers() {
+        let patch = "diff --git a/src/a.rs b/src/a.rs\n\
+                     --- a/src/a.rs\t2024-01-01\n\
+                     +++ b/src/a.rs\n\
+                     @@ -1,2 +1,2 @@\n\
+                     -one\n\
+                     +uno\n \
+                     two\n\
+                     --- /dev/null\n\
+                     +++ b/new.txt\n\
+                     @@ -0,0 +1 @@\n\
+                     +hello\n\
+                     --- a/old.txt\n\
+                     +++ /dev/null\n\
+                     @@ -1 +0,0 @@\n\
+                     -bye\n";
+        let files = parse(patch).unwrap();
+        let summary: Vec<_> = files
+            .iter()
+            .map(|f| (f.path.as_deref().unwrap(), f.created, f.deleted))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("src/a.rs", false, false),
+                ("new.txt", true, false),
+                ("old.txt", false, true)
+            ]
+        );
+        assert_eq!(hunks(&files[0])[0].old_start, Some(1));
+        assert_eq!(
+            hunks(&files[0])[0].lines,
+            [
+                HunkLine::Remove("one".into()),
+                HunkLine::Add("uno".into()),
+                HunkLine::Context("two".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn unified_counts_keep_removed_sql_comments() {
+        let patch = "--- a/q.sql\n\
+                     +++ b/q.sql\n\
+                     @@ -1,3 +1,2 @@\n \
+                     select 1;\n\
+                     --- drop this comment\n \
+                     select 2;\n";
+        let files = parse(patch).unwrap();
+        assert_eq!(
+            hunks(&files[0])[0].lines,
+            [
+                HunkLine::Context("select 1;".into()),
+                HunkLine::Remove("-- drop this comment".into()),
+                HunkLine::Context("select 2;".into())
+            ]
+        );
+        let outcome = apply(
+            "select 1;\n-- drop this comment\nselect 2;\n",
+            &files[0],
+            TOLERANCE,
+        );
+        assert_eq!(outcome.content, "select 1;\nselect 2;\n");
+    }
+
+    #[test]
+    fn unified_falls_back_when_counts_are_wrong() {
+        // Claims one line each side but carries three
+        let patch = "@@ -1,1 +1,1 @@\n \
+                     a\n\
+                     -b\n\
+                     +c\n";
+        let files = parse(patch).unwrap();
+        assert_eq!(files[0].path, None);
+        assert_eq!(hunks(&files[0])[0].lines.len(), 3);
+
+        // Claims more lines than it has
+        let patch = "@@ -1,5 +1,5 @@\n-x\n+y\n";
+        let files = parse(patch).unwrap();
+        assert_eq!(hunks(&files[0])[0].lines.len(), 2);
+    }
+
+    #[test]
+    fn unified_reads_hunks_without_numbers_and_eol_markers() {
+        let patch = "@@ @@\n-a\n+b\n\\ No newline at end of file\n\nSome prose after.\n";
+        let files = parse(patch).unwrap();
+        let hunk = &hunks(&files[0])[0];
+        assert_eq!(hunk.old_start, None);
+        assert!(hunk.new_no_eol);
+        assert_eq!(hunk.lines.len(), 2);
+        assert_eq!(apply("a\n", &files[0], TOLERANCE).content, "b");
+    }
+
+    #[test]
+    fn unified_requires_plus_header() {
+        assert!(parse("--- a/x\nnot a header\n").is_err());
+        assert!(parse("just some text\n").is_err());
+    }
+
+    #[test]
+    fn diff_adding_a_marker_line_is_not_search_replace() {
+        let patch = "--- a/x.txt\n+++ b/x.txt\n@@ -1 +1,2 @@\n a\n+<<<<<<< SEARCH\n";
+        let outcome = apply_one("a\n", patch);
+        assert_eq!(outcome.content, "a\n<<<<<<< SEARCH\n");
+    }
+
+    #[test]
+    fn search_replace_picks_paths_not_prose() {
+        let patch = "Explanation:\n\
+                     `src/lib.rs`\n\
+                     ```rust\n\
+                     <<<<<<< SEARCH\n\
+                     fn a() {}\n\
+                     =======\n\
+                     fn b() {}\n\
+                     >>>>>>> REPLACE\n\
+                     ```\n\
+                     Done.\n\
+                     <<<<<<< SEARCH\n\
+                     x\n\
+                     =======\n\
+                     y\n\
+                     >>>>>>> REPLACE\n\
+                     Cargo.toml:\n\
+                     <<<<<<< SEARCH\n\
+                     =======\n\
+                     [package]\n\
+                     >>>>>>> REPLACE\n";
+        let files = parse(patch).unwrap();
+        let paths: Vec<_> = files.iter().map(|f| f.path.as_deref()).collect();
+        assert_eq!(paths, [Some("src/lib.rs"), Some("Cargo.toml")]);
+        assert_eq!(blocks(&files[0]).len(), 2);
+        assert_eq!(blocks(&files[0])[0].search, "fn a() {}\n");
+        assert_eq!(blocks(&files[0])[0].replace, "fn b() {}\n");
+        assert_eq!(blocks(&files[1])[0].search, "");
+    }
+
+    #[test]
+    fn search_replace_without_a_path_line() {
+        let patch = "Here you go:\n<<<<<<< SEARCH\na\n=======\nb\n>>>>>>> REPLACE\n";
+        assert_eq!(parse(patch).unwrap()[0].path, None);
+        assert!(parse("<<<<<<< SEARCH\na\n=======\n").is_err());
+    }
+
+    #[test]
+    fn hunk_applies_at_an_offset() {
+        let text = "0\n1\n2\n3\n4\n5\n6\n";
+        let outcome = apply_one(text, "@@ -2,3 +2,3 @@\n 4\n-5\n+five\n 6\n");
+        assert_eq!(outcome.content, "0\n1\n2\n3\n4\nfive\n6\n");
+        let hunk = &outcome.applied[0];
+        assert_eq!((hunk.line, hunk.offset, hunk.fuzz), (5, 3, 0));
+    }
+
+    #[test]
+    fn hunk_applies_with_fuzz() {
+        let text = "a\nb\nc\nd\n";
+        let outcome = apply_one(text, "@@ -1,4 +1,4 @@\n x\n b\n-c\n+C\n d\n");
+        assert_eq!(outcome.content, "a\nb\nC\nd\n");
+        assert_eq!(outcome.applied[0].fuzz, 1);
+        assert!(outcome.rejected.is_empty());
+    }
+
+    #[test]
+    fn hunk_matches_ignoring_whitespace_and_keeps_file_context() {
+        let text = "fn main() {\n    let  x = 1;\n}\n";
+        let outcome = apply_one(
+            text,
+            "@@ -1,3 +1,3 @@\n fn main() {\n-let x = 1;\n+    let x = 2;\n }\n",
+        );
+        assert_eq!(outcome.content, "fn main() {\n    let x = 2;\n}\n");
+        assert!(outcome.applied[0].whitespace_insensitive);
+    }
+
+    #[test]
+    fn hunks_report_already_applied_and_missing_context() {
+        let patch = "@@ -1,2 +1,2 @@\n-a\n+b\n c\n@@ -9,1 +9,1 @@\n-nowhere\n+gone\n";
+        let outcome = apply_one("b\nc\n", patch);
+        assert_eq!(outcome.content, "b\nc\n");
+        let reasons: Vec<_> = outcome
+            .rejected
+            .iter()
+            .map(|r| (r.index, format!("{:?}", r.reason)))
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                (0, "AlreadyApplied".to_string()),
+                (1, "ContextNotFound".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn hunk_keeps_crlf_endings() {
+        let outcome = apply_one("a\r\nb\r\n", "@@ -1,2 +1,2 @@\n a\n-b\n+c\n");
+        assert_eq!(outcome.content, "a\r\nc\r\n");
+    }
+
+    #[test]
+    fn blocks_apply_exactly_then_loosely() {
+        let text = "one\n  two  three\nfour\n";
+        let patch = "<<<<<<< SEARCH\none\n=======\n1\n>>>>>>> REPLACE\n\
+                     <<<<<<< SEARCH\ntwo three\n=======\n2 3\n>>>>>>> REPLACE\n";
+        let outcome = apply_one(text, patch);
+        assert_eq!(outcome.content, "1\n2 3\nfour\n");
+        let loose: Vec<_> = outcome
+            .applied
+            .iter()
+            .map(|a| (a.line, a.whitespace_insensitive))
+            .collect();
+        assert_eq!(loose, [(1, false), (2, true)]);
+    }
+
+    #[test]
+    fn blocks_reject_ambiguous_and_empty_search() {
+        let patch = "<<<<<<< SEARCH\nx\n=======\ny\n>>>>>>> REPLACE\n\
+                     <<<<<<< SEARCH\n=======\nnew\n>>>>>>> REPLACE\n";
+        let outcome = apply_one("x\nx\n", patch);
+        assert_eq!(outcome.content, "x\nx\n");
+        let reasons: Vec<_> = outcome
+            .rejected
+            .iter()
+            .map(|r| format!("{:?}", r.reason))
+            .collect();
+        assert_eq!(reasons, ["Ambiguous", "EmptySearch"]);
+    }
+
+    #[test]
+    fn empty_search_creates_a_file() {
+        let patch = "new.txt\n<<<<<<< SEARCH\n=======\nhello\n>>>>>>> REPLACE\n";
+        let outcome = apply_one("", patch);
+        assert_eq!(outcome.content, "hello\n");
+        assert_eq!(outcome.applied.len(), 1);
+    }
+}
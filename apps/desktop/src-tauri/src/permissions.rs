@@ -0,0 +1,161 @@
+//! Permission broker for agent file writes
+//!
+//! The backend owns the approval decision: the user confirms in a native
+//! dialog, and only then is a single-use grant issued for the exact path and
+//! content that was shown. `apply_file_edit` redeems the grant before writing,
+//! so neither the webview nor the agent can skip the prompt.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Runtime};
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+
+/// How long a grant stays valid after the user approves it
+const GRANT_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Diff lines shown in the approval dialog before truncating
+const PREVIEW_LINES: usize = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GrantError {
+    #[error("no write permission was granted")]
+    Missing,
+    #[error("the write permission has expired")]
+    Expired,
+    #[error("the write permission was granted for a different file or content")]
+    Mismatch,
+}
+
+/// Approval handed back to the frontend
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GrantToken {
+    pub token: String,
+    pub expires_in_ms: u64,
+}
+
+/// What a grant allows: one exact write, or one exact batch of edits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GrantScope {
+    File { path: PathBuf, content_hash: String },
+    Batch { digest: String },
+}
+
+impl GrantScope {
+    pub fn file(path: &Path, content_hash: String) -> Self {
+        Self::File {
+            path: path.to_path_buf(),
+            content_hash: content_hash.to_ascii_lowercase(),
+        }
+    }
+}
+
+struct Grant {
+    scope: GrantScope,
+    expires_at: Instant,
+}
+
+/// Outstanding write grants, managed as Tauri state
+#[derive(Default)]
+pub struct PermissionBroker {
+    grants: Mutex<HashMap<String, Grant>>,
+}
+
+impl PermissionBroker {
+    /// Issue a single-use grant for exactly `scope`
+    pub fn issue(&self, scope: GrantScope) -> GrantToken {
+        let token = uuid::Uuid::new_v4().to_string();
+        let mut grants = self.grants.lock().unwrap();
+        let now = Instant::now();
+        grants.retain(|_, g| g.expires_at > now);
+        grants.insert(
+            token.clone(),
+            Grant {
+                scope,
+                expires_at: now + GRANT_TTL,
+            },
+        );
+        GrantToken {
+            token,
+            expires_in_ms: GRANT_TTL.as_millis() as u64,
+        }
+    }
+
+    /// Consume `token`, checking it covers exactly `scope`
+    ///
+    /// The token is spent even when the check fails, so a leaked token
+    /// cannot be replayed against another file.
+    pub fn redeem(&self, token: &str, scope: &GrantScope) -> Result<(), GrantError> {
+        let grant = self
+            .grants
+            .lock()
+            .unwrap()
+            .remove(token)
+            .ok_or(GrantError::Missing)?;
+        if grant.expires_at <= Instant::now() {
+            return Err(GrantError::Expired);
+        }
+        if grant.scope != *scope {
+            return Err(GrantError::Mismatch);
+        }
+        Ok(())
+    }
+}
+
+/// Ask the user to approve something in a native OK/Cancel dialog
+///
+/// Runs on a blocking thread since the dialog must not block the async
+/// runtime; a dialog that fails to show counts as a refusal.
+pub async fn confirm<R: Runtime>(
+    app: &AppHandle<R>,
+    title: &str,
+    message: String,
+    ok_label: &str,
+) -> bool {
+    let dialog = app
+        .dialog()
+        .message(message)
+        .title(title)
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            ok_label.to_string(),
+            "Cancel".to_string(),
+        ));
+    tauri::async_runtime::spawn_blocking(move || dialog.blocking_show())
+        .await
+        .unwrap_or(false)
+}
+
+/// Ask the user to pick a folder in the native dialog; `None` if they
+/// cancel
+pub async fn pick_folder<R: Runtime>(app: &AppHandle<R>, title: &str) -> Option<PathBuf> {
+    let dialog = app.dialog().file().set_title(title);
+    tauri::async_runtime::spawn_blocking(move || dialog.blocking_pick_folder())
+        .await
+        .ok()
+        .flatten()
+        .and_then(|folder| folder.into_path().ok())
+}
+
+/// Dialog text for a file write: change counts and the head of the diff
+pub fn write_prompt(path: &Path, diff: &crate::diff::FileDiff) -> String {
+    let changes = diff.line_changes;
+    let mut lines = diff.unified_diff.lines().skip(2);
+    let mut preview: Vec<&str> = lines.by_ref().take(PREVIEW_LINES).collect();
+    if lines.next().is_some() {
+        preview.push("…");
+    }
+    format!(
+        "Apply changes to {}?\n\n+{} −{} ~{}\n\n{}",
+        path.display(),
+        changes.added,
+        changes.removed,
+        changes.modified,
+        preview.join("\n")
+    )
+}
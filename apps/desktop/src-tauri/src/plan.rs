@@ -0,0 +1,1089 @@
+//! Checkpointed execution of structured agent plans
+//!
+//! Replaces the step loop of `TaskExecutor.executeTask`, which guessed file
+//! paths from step descriptions. A plan here is a list of typed operations
+//! with explicit targets, run one step at a time:
+//!
+//! - file steps need the same native approval as `apply_edit_batch`, and
+//!   are journaled under their own transaction before anything is written,
+//!   so a failed step or validation rolls back just that step and
+//!   `rollback_plan` unwinds the whole plan;
+//! - commands, and the commands of the native test runner, go through the
+//!   command policy; neither is checkpointed, so their side effects stay;
+//! - steps marked `requiresApproval` pause the plan until
+//!   `approve_plan_step` is called, and so does a command or test step
+//!   about to run again after an attempt that failed or was interrupted,
+//!   since nothing undid what that attempt did.
+//!
+//! Plan state is saved under `plans/` after every transition. A plan that
+//! was running when the app quit comes back paused, and `resume_plan` picks
+//! it up at the step it stopped in, rolling back a file step's partial
+//! changes first. Saved plans that can't be read are listed by
+//! `list_plans` rather than loaded. Transitions are reported as
+//! `plan://progress` events.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::batch::{self, EditOp, OpStatus};
+use crate::exec::{self, RunOptions};
+use crate::fs_edit::{self, FileEditError, Precondition};
+use crate::journal::{Journal, JournalError};
+use crate::permissions::PermissionBroker;
+use crate::policy::{self, CommandPolicy};
+use crate::testing::{self, TestRun};
+use crate::workspace::{Workspace, WorkspaceError};
+
+pub const PROGRESS_EVENT: &str = "plan://progress";
+
+/// Characters of command and test output kept in a step's result
+const MAX_OUTPUT_CHARS: usize = 4000;
+
+#[derive(Debug, thiserror::Error, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum PlanError {
+    #[error("unknown plan {id}")]
+    NotFound { id: String },
+    #[error("invalid plan: {message}")]
+    Invalid { message: String },
+    #[error("plan {id} is {status:?}")]
+    InvalidState { id: String, status: PlanStatus },
+    #[error("plan {id} is running")]
+    Busy { id: String },
+    #[error(transparent)]
+    Sandbox { reason: WorkspaceError },
+    #[error(transparent)]
+    Journal { reason: JournalError },
+    #[error("plan storage failed: {message}")]
+    Storage { message: String },
+}
+
+impl PlanError {
+    fn invalid(message: impl Into<String>) -> Self {
+        Self::Invalid {
+            message: message.into(),
+        }
+    }
+}
+
+impl From<WorkspaceError> for PlanError {
+    fn from(reason: WorkspaceError) -> Self {
+        Self::Sandbox { reason }
+    }
+}
+
+impl From<JournalError> for PlanError {
+    fn from(reason: JournalError) -> Self {
+        Self::Journal { reason }
+    }
+}
+
+impl From<io::Error> for PlanError {
+    fn from(err: io::Error) -> Self {
+        Self::Storage {
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for PlanError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Storage {
+            message: err.to_string(),
+        }
+    }
+}
+
+/// A plan as submitted by the agent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Plan {
+    /// Chosen by the caller: ASCII letters, digits and `-`
+    pub id: String,
+    pub goal: String,
+    /// Open root commands and tests run in; the first root if unset
+    #[serde(default)]
+    pub root: Option<PathBuf>,
+    pub steps: Vec<PlanStep>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlanStep {
+    pub id: String,
+    pub description: String,
+    pub operation: Operation,
+    /// Pause before this step until the user approves it
+    #[serde(default)]
+    pub requires_approval: bool,
+    /// Checks run after the operation; if one fails the step is rolled back
+    #[serde(default)]
+    pub validate: Vec<Validation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Operation {
+    /// File operations, applied all-or-nothing
+    Files { ops: Vec<EditOp> },
+    /// A program run through the command policy; must exit 0
+    Command {
+        argv: Vec<String>,
+        #[serde(default)]
+        cwd: Option<PathBuf>,
+        #[serde(default)]
+        timeout_ms: Option<u64>,
+    },
+    /// Tests affected by `paths`, or by every file the plan has changed
+    Test {
+        #[serde(default)]
+        paths: Vec<PathBuf>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Validation {
+    /// Tests affected by the files the step changed must pass; all of the
+    /// root's tests for steps that change none
+    Tests,
+    /// A program run in the plan's root through the command policy; must exit 0
+    Command { argv: Vec<String> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PlanStatus {
+    Running,
+    AwaitingApproval,
+    /// Interrupted by an app restart
+    Paused,
+    Completed,
+    Failed,
+    Cancelled,
+    RolledBack,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StepStatus {
+    Pending,
+    AwaitingApproval,
+    Running,
+    Completed,
+    Failed,
+    RolledBack,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StepState {
+    pub id: String,
+    pub status: StepStatus,
+    pub approved: bool,
+    /// Journal transaction holding the step's file changes
+    pub checkpoint: Option<String>,
+    /// The end of command or test output, or what was applied
+    pub output: Option<String>,
+    pub error: Option<String>,
+    pub started_at_ms: Option<u64>,
+    pub finished_at_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlanState {
+    pub plan: Plan,
+    pub status: PlanStatus,
+    /// The open root the plan runs in
+    pub root: PathBuf,
+    pub steps: Vec<StepState>,
+    pub created_at_ms: u64,
+    pub updated_at_ms: u64,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Progress<'a> {
+    plan_id: &'a str,
+    status: PlanStatus,
+    /// The step that changed, if one did
+    step_index: Option<usize>,
+    step: Option<&'a StepState>,
+    /// Why the plan stopped, when no step says so
+    error: Option<&'a str>,
+}
+
+/// A saved plan that could not be loaded
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnreadablePlan {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlanList {
+    /// Newest first
+    pub plans: Vec<PlanState>,
+    pub unreadable: Vec<UnreadablePlan>,
+}
+
+/// Saved plans, managed as Tauri state
+pub struct Plans {
+    dir: PathBuf,
+    plans: Mutex<HashMap<String, PlanState>>,
+    /// Plans a task is currently driving
+    active: Mutex<HashSet<String>>,
+    /// Active plans asked to stop after their current step
+    cancelled: Mutex<HashSet<String>>,
+    /// Files in `dir` skipped at startup
+    unreadable: Vec<UnreadablePlan>,
+}
+
+impl Plans {
+    /// Load the plans saved in `dir`; ones that were running are paused
+    pub fn open(dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let mut plans = HashMap::new();
+        let mut unreadable = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let mut state: PlanState = match fs::read(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| serde_json::from_slice(&bytes).map_err(|e| e.to_string()))
+            {
+                Ok(state) => state,
+                Err(error) => {
+                    unreadable.push(UnreadablePlan { path, error });
+                    continue;
+                }
+            };
+            if state.status == PlanStatus::Running {
+                state.status = PlanStatus::Paused;
+            }
+            plans.insert(state.plan.id.clone(), state);
+        }
+        Ok(Self {
+            dir,
+            plans: Mutex::new(plans),
+            active: Mutex::new(HashSet::new()),
+            cancelled: Mutex::new(HashSet::new()),
+            unreadable,
+        })
+    }
+
+    fn get(&self, id: &str) -> Result<PlanState, PlanError> {
+        self.plans
+            .lock()
+            .unwrap()
+            .get(id)
+            .cloned()
+            .ok_or_else(|| PlanError::NotFound { id: id.to_string() })
+    }
+
+    fn insert(&self, app: &AppHandle, state: PlanState) -> Result<(), PlanError> {
+        let mut plans = self.plans.lock().unwrap();
+        if plans.contains_key(&state.plan.id) {
+            return Err(PlanError::invalid(format!(
+                "a plan with id {} already exists",
+                state.plan.id
+            )));
+        }
+        self.save(&state)?;
+        emit(app, &state, None);
+        plans.insert(state.plan.id.clone(), state);
+        Ok(())
+    }
+
+    /// Change a plan, save it and report the change
+    fn update(
+        &self,
+        app: &AppHandle,
+        id: &str,
+        step_index: Option<usize>,
+        change: impl FnOnce(&mut PlanState),
+    ) -> Result<PlanState, PlanError> {
+        let mut plans = self.plans.lock().unwrap();
+        let state = plans
+            .get_mut(id)
+            .ok_or_else(|| PlanError::NotFound { id: id.to_string() })?;
+        change(state);
+        state.updated_at_ms = now_ms();
+        self.save(state)?;
+        emit(app, state, step_index);
+        Ok(state.clone())
+    }
+
+    fn update_step(
+        &self,
+        app: &AppHandle,
+        id: &str,
+        index: usize,
+        change: impl FnOnce(&mut PlanStatus, &mut StepState),
+    ) -> Result<PlanState, PlanError> {
+        self.update(app, id, Some(index), |state| {
+            change(&mut state.status, &mut state.steps[index])
+        })
+    }
+
+    /// Mark a plan whose task stopped on `error` as failed and report why,
+    /// even if that can't be saved
+    fn fail(&self, app: &AppHandle, id: &str, error: &str) {
+        let mut plans = self.plans.lock().unwrap();
+        let Some(state) = plans.get_mut(id) else {
+            return;
+        };
+        state.status = PlanStatus::Failed;
+        state.updated_at_ms = now_ms();
+        let saved = self.save(state);
+        let error = match saved {
+            Ok(()) => error.to_string(),
+            Err(e) => format!("{error}; saving the plan failed: {e}"),
+        };
+        let _ = app.emit(
+            PROGRESS_EVENT,
+            Progress {
+                plan_id: id,
+                status: state.status,
+                step_index: None,
+                step: None,
+                error: Some(&error),
+            },
+        );
+    }
+
+    fn save(&self, state: &PlanState) -> Result<(), PlanError> {
+        let json = serde_json::to_string_pretty(state)?;
+        fs_edit::write_atomic(
+            &self.dir.join(format!("{}.json", state.plan.id)),
+            &json,
+            &Precondition::default(),
+        )
+        .map_err(|e| PlanError::Storage {
+            message: e.to_string(),
+        })?;
+        Ok(())
+    }
+
+    /// Mark a plan as driven, failing if a task already drives it
+    fn claim(&self, id: &str) -> Result<(), PlanError> {
+        if !self.active.lock().unwrap().insert(id.to_string()) {
+            return Err(PlanError::Busy { id: id.to_string() });
+        }
+        self.cancelled.lock().unwrap().remove(id);
+        Ok(())
+    }
+
+    fn release(&self, id: &str) {
+        self.active.lock().unwrap().remove(id);
+        self.cancelled.lock().unwrap().remove(id);
+    }
+
+    fn is_active(&self, id: &str) -> bool {
+        self.active.lock().unwrap().contains(id)
+    }
+
+    fn take_cancel(&self, id: &str) -> bool {
+        self.cancelled.lock().unwrap().remove(id)
+    }
+}
+
+fn emit(app: &AppHandle, state: &PlanState, step_index: Option<usize>) {
+    let _ = app.emit(
+        PROGRESS_EVENT,
+        Progress {
+            plan_id: &state.plan.id,
+            status: state.status,
+            step_index,
+            step: step_index.map(|i| &state.steps[i]),
+            error: None,
+        },
+    );
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Reject plans that could not run as written
+fn check(plan: &Plan) -> Result<(), PlanError> {
+    // The id names the plan's file, like journal transaction ids
+    if plan.id.is_empty()
+        || !plan
+            .id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return Err(PlanError::invalid(format!(
+            "plan id {:?} must be ASCII letters, digits and -",
+            plan.id
+        )));
+    }
+    let mut ids = HashSet::new();
+    for step in &plan.steps {
+        if !ids.insert(step.id.as_str()) {
+            return Err(PlanError::invalid(format!("duplicate step id {}", step.id)));
+        }
+        let empty_command = match &step.operation {
+            Operation::Command { argv, .. } => argv.is_empty(),
+            Operation::Files { ops } => {
+                if ops.is_empty() {
+                    return Err(PlanError::invalid(format!(
+                        "step {} has no file operations",
+                        step.id
+                    )));
+                }
+                false
+            }
+            Operation::Test { .. } => false,
+        } || step
+            .validate
+            .iter()
+            .any(|v| matches!(v, Validation::Command { argv } if argv.is_empty()));
+        if empty_command {
+            return Err(PlanError::invalid(format!(
+                "step {} has an empty command",
+                step.id
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Files an operation list writes, deletes or renames to
+fn targets(ops: &[EditOp]) -> impl Iterator<Item = &str> {
+    ops.iter().map(|op| match op {
+        EditOp::Create { path, .. } | EditOp::Edit { path, .. } | EditOp::Delete { path, .. } => {
+            path.as_str()
+        }
+        EditOp::Rename { to, .. } => to.as_str(),
+    })
+}
+
+/// Drive a claimed plan on a background task until it finishes, fails or
+/// waits for approval
+fn spawn(app: AppHandle, id: String) {
+    tauri::async_runtime::spawn(async move {
+        let plans = app.state::<Plans>();
+        if let Err(e) = drive(&app, &plans, &id).await {
+            plans.fail(&app, &id, &e.to_string());
+        }
+        plans.release(&id);
+    });
+}
+
+async fn drive(app: &AppHandle, plans: &Plans, id: &str) -> Result<(), PlanError> {
+    loop {
+        let state = plans.get(id)?;
+        if plans.take_cancel(id) {
+            plans.update(app, id, None, |s| s.status = PlanStatus::Cancelled)?;
+            return Ok(());
+        }
+        let Some(index) = state
+            .steps
+            .iter()
+            .position(|s| s.status != StepStatus::Completed)
+        else {
+            plans.update(app, id, None, |s| s.status = PlanStatus::Completed)?;
+            return Ok(());
+        };
+        let step = &state.plan.steps[index];
+        if needs_approval(step, &state.steps[index]) {
+            plans.update_step(app, id, index, |status, s| {
+                *status = PlanStatus::AwaitingApproval;
+                s.status = StepStatus::AwaitingApproval;
+            })?;
+            return Ok(());
+        }
+
+        // A checkpoint left by an interrupted or failed attempt is undone
+        // before the step runs again
+        let mut error = None;
+        if let Some(checkpoint) = &state.steps[index].checkpoint {
+            error = restore(app, checkpoint).err();
+        }
+        let checkpointed = matches!(step.operation, Operation::Files { .. });
+        let checkpoint = match (&error, checkpointed) {
+            (None, true) => {
+                let label = format!("Plan {}: {}", state.plan.goal, step.description);
+                let journal = app.state::<Journal>();
+                Some(journal.begin(&label)?.id)
+            }
+            _ => None,
+        };
+        plans.update_step(app, id, index, |status, s| {
+            *status = PlanStatus::Running;
+            s.status = StepStatus::Running;
+            s.checkpoint.clone_from(&checkpoint);
+            // Used up by this attempt, as nothing can undo it
+            s.approved &= checkpointed;
+            s.output = None;
+            s.error = None;
+            s.started_at_ms = Some(now_ms());
+            s.finished_at_ms = None;
+        })?;
+
+        let outcome = match error {
+            Some(error) => Err(error),
+            None => run_step(app, &state, index, checkpoint.as_deref()).await,
+        };
+        match outcome {
+            Ok(output) => {
+                plans.update_step(app, id, index, |_, s| {
+                    s.status = StepStatus::Completed;
+                    s.output = output;
+                    s.finished_at_ms = Some(now_ms());
+                })?;
+            }
+            Err(mut error) => {
+                if let Some(checkpoint) = &checkpoint {
+                    if let Err(e) = restore(app, checkpoint) {
+                        error = format!("{error}; rolling the step back failed: {e}");
+                    }
+                }
+                plans.update_step(app, id, index, |status, s| {
+                    *status = PlanStatus::Failed;
+                    s.status = StepStatus::Failed;
+                    s.error = Some(error);
+                    s.finished_at_ms = Some(now_ms());
+                })?;
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Whether `step` waits for approval before it runs: it asks to, or it
+/// isn't checkpointed and an earlier attempt of it already started
+fn needs_approval(step: &PlanStep, state: &StepState) -> bool {
+    let rerun = state.started_at_ms.is_some() && !matches!(step.operation, Operation::Files { .. });
+    (step.requires_approval || rerun) && !state.approved
+}
+
+/// Undo a step's checkpoint; one that holds nothing or was already undone
+/// is fine
+fn restore(app: &AppHandle, checkpoint: &str) -> Result<(), String> {
+    let workspace = app.state::<Workspace>();
+    undo_checkpoint(&app.state::<Journal>(), checkpoint, |p| {
+        Ok(workspace.resolve(p)?)
+    })
+}
+
+fn undo_checkpoint(
+    journal: &Journal,
+    checkpoint: &str,
+    resolve: impl Fn(&Path) -> Result<PathBuf, FileEditError>,
+) -> Result<(), String> {
+    match journal.undo(checkpoint, resolve) {
+        Ok(_) | Err(JournalError::NotFound { .. }) | Err(JournalError::InvalidState { .. }) => {
+            Ok(())
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Run one step's operation and validations, returning its output or why
+/// it failed
+async fn run_step(
+    app: &AppHandle,
+    state: &PlanState,
+    index: usize,
+    checkpoint: Option<&str>,
+) -> Result<Option<String>, String> {
+    let step = &state.plan.steps[index];
+    let root = state.root.as_path();
+    let mut changed = Vec::new();
+    let output = match &step.operation {
+        Operation::Files { ops } => {
+            let workspace = app.state::<Workspace>();
+            let broker = app.state::<PermissionBroker>();
+            let journal = app.state::<Journal>();
+            // The same grant as `apply_edit_batch`: shown in the native
+            // dialog and redeemed for exactly these operations
+            let grant = batch::request_permission(app, &workspace, &broker, ops)
+                .await
+                .map_err(|e| e.to_string())?
+                .grant
+                .ok_or("the file operations were not approved")?;
+            let result =
+                batch::apply_granted(&workspace, &broker, &journal, ops, &grant.token, checkpoint)
+                    .map_err(|e| e.to_string())?;
+            if !result.applied {
+                let failure = result.results.iter().find_map(|r| match &r.status {
+                    OpStatus::Failed { error } => Some(format!("{}: {}", r.path, error)),
+                    _ => None,
+                });
+                return Err(failure.unwrap_or_else(|| "file operations failed".into()));
+            }
+            changed.extend(targets(ops).map(PathBuf::from));
+            Some(format!("applied {} file operations", ops.len()))
+        }
+        Operation::Command {
+            argv,
+            cwd,
+            timeout_ms,
+        } => {
+            let cwd = cwd.as_deref().unwrap_or(root);
+            Some(run_command(app, argv, cwd, *timeout_ms).await?)
+        }
+        Operation::Test { paths } if paths.is_empty() => {
+            let touched = state
+                .plan
+                .steps
+                .iter()
+                .zip(&state.steps)
+                .filter(|(_, s)| s.status == StepStatus::Completed)
+                .filter_map(|(step, _)| match &step.operation {
+                    Operation::Files { ops } => Some(targets(ops).map(PathBuf::from)),
+                    _ => None,
+                })
+                .flatten()
+                .collect::<Vec<_>>();
+            Some(run_tests(app, root, &touched).await?)
+        }
+        Operation::Test { paths } => Some(run_tests(app, root, paths).await?),
+    };
+
+    for validation in &step.validate {
+        match validation {
+            Validation::Tests => run_tests(app, root, &changed).await,
+            Validation::Command { argv } => run_command(app, argv, root, None).await,
+        }
+        .map_err(|e| format!("validation failed: {e}"))?;
+    }
+    Ok(output)
+}
+
+/// Run `argv` through the command policy; fails unless it exits 0
+async fn run_command(
+    app: &AppHandle,
+    argv: &[String],
+    cwd: &Path,
+    timeout_ms: Option<u64>,
+) -> Result<String, String> {
+    let workspace = app.state::<Workspace>();
+    let policy = app.state::<CommandPolicy>();
+    let cwd = exec::working_dir(&workspace, Some(cwd)).map_err(|e| e.to_string())?;
+    let options = RunOptions {
+        timeout_ms,
+        ..RunOptions::default()
+    };
+    let resolved = policy::authorize(app, &workspace, &policy, argv, &cwd, &options)
+        .await
+        .map_err(|e| e.to_string())?;
+    let output = exec::run_blocking(resolved, cwd, options)
+        .await
+        .map_err(|e| e.to_string())?;
+    let text = tail(&format!("{}{}", output.stdout, output.stderr));
+    let command = argv.join(" ");
+    if output.timed_out {
+        Err(format!(
+            "{command} timed out after {}ms\n{text}",
+            output.duration_ms
+        ))
+    } else if output.exit_code != Some(0) {
+        let code = output
+            .exit_code
+            .map_or_else(|| "a signal".to_string(), |c| c.to_string());
+        Err(format!("{command} exited with {code}\n{text}"))
+    } else {
+        Ok(text)
+    }
+}
+
+/// Run the tests affected by `paths`, each test command through the
+/// command policy; fails unless every run succeeds
+async fn run_tests(app: &AppHandle, root: &Path, paths: &[PathBuf]) -> Result<String, String> {
+    let workspace = app.state::<Workspace>();
+    let policy = app.state::<CommandPolicy>();
+    let changed = paths
+        .iter()
+        .map(|path| workspace.resolve(path))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    let plans = testing::plan(root, &changed).map_err(|e| e.to_string())?;
+    let plans = testing::authorize(app, &workspace, &policy, plans)
+        .await
+        .map_err(|e| e.to_string())?;
+    let runs = testing::run_plans(plans, None)
+        .await
+        .map_err(|e| e.to_string())?;
+    let report = runs.iter().map(describe).collect::<Vec<_>>().join("\n");
+    if runs.iter().all(|run| run.success) {
+        Ok(report)
+    } else {
+        Err(tail(&report))
+    }
+}
+
+/// One line per test run, plus the first failure's message
+fn describe(run: &TestRun) -> String {
+    let mut line = format!(
+        "{}: {} passed, {} failed, {} skipped",
+        run.plan.argv.join(" "),
+        run.summary.passed,
+        run.summary.failed,
+        run.summary.skipped
+    );
+    if run.timed_out {
+        line.push_str(" (timed out)");
+    }
+    let failure = run
+        .tests
+        .iter()
+        .find(|t| t.message.is_some())
+        .map(|t| format!("{}: {}", t.name, t.message.as_deref().unwrap_or_default()));
+    if let Some(failure) = failure.or_else(|| (!run.success).then(|| run.output.clone())) {
+        line.push('\n');
+        line.push_str(&failure);
+    }
+    line
+}
+
+/// The last [`MAX_OUTPUT_CHARS`] characters of `text`
+fn tail(text: &str) -> String {
+    let count = text.chars().count();
+    text.chars()
+        .skip(count.saturating_sub(MAX_OUTPUT_CHARS))
+        .collect()
+}
+
+/// Start running a plan in the background
+/// Progress is reported as `plan://progress` events; the returned state is
+/// the plan before its first step.
+#[tauri::command]
+pub async fn start_plan(
+    app: AppHandle,
+    workspace: State<'_, Workspace>,
+    plans: State<'_, Plans>,
+    plan: Plan,
+) -> Result<PlanState, PlanError> {
+    check(&plan)?;
+    let root = workspace.pick_root(plan.root.as_deref())?.path;
+    let now = now_ms();
+    let state = PlanState {
+        steps: plan
+            .steps
+            .iter()
+            .map(|step| StepState {
+                id: step.id.clone(),
+                status: StepStatus::Pending,
+                approved: false,
+                checkpoint: None,
+                output: None,
+                error: None,
+                started_at_ms: None,
+                finished_at_ms: None,
+            })
+            .collect(),
+        plan,
+        status: PlanStatus::Running,
+        root,
+        created_at_ms: now,
+        updated_at_ms: now,
+    };
+    let id = state.plan.id.clone();
+    plans.insert(&app, state.clone())?;
+    plans.claim(&id)?;
+    spawn(app, id);
+    Ok(state)
+}
+
+/// Approve or decline the step a plan is waiting on
+/// Declining cancels the plan; steps already done stay done.
+#[tauri::command]
+pub async fn approve_plan_step(
+    app: AppHandle,
+    plans: State<'_, Plans>,
+    plan_id: String,
+    step_id: String,
+    approved: bool,
+) -> Result<PlanState, PlanError> {
+    let state = plans.get(&plan_id)?;
+    let index = state
+        .steps
+        .iter()
+        .position(|s| s.id == step_id && s.status == StepStatus::AwaitingApproval)
+        .filter(|_| state.status == PlanStatus::AwaitingApproval)
+        .ok_or(PlanError::InvalidState {
+            id: plan_id.clone(),
+            status: state.status,
+        })?;
+    if !approved {
+        return plans.update_step(&app, &plan_id, index, |status, s| {
+            *status = PlanStatus::Cancelled;
+            s.status = StepStatus::Pending;
+        });
+    }
+    plans.claim(&plan_id)?;
+    let state = plans.update_step(&app, &plan_id, index, |status, s| {
+        *status = PlanStatus::Running;
+        s.status = StepStatus::Pending;
+        s.approved = true;
+    });
+    if state.is_err() {
+        plans.release(&plan_id);
+    } else {
+        spawn(app, plan_id);
+    }
+    state
+}
+
+/// Continue a paused or failed plan from its first unfinished step
+/// A failed file step is retried after its checkpoint is rolled back; a
+/// failed or interrupted command or test step waits for approval first.
+#[tauri::command]
+pub async fn resume_plan(
+    app: AppHandle,
+    plans: State<'_, Plans>,
+    plan_id: String,
+) -> Result<PlanState, PlanError> {
+    let state = plans.get(&plan_id)?;
+    if !matches!(state.status, PlanStatus::Paused | PlanStatus::Failed) {
+        return Err(PlanError::InvalidState {
+            id: plan_id,
+            status: state.status,
+        });
+    }
+    plans.claim(&plan_id)?;
+    let state = plans.update(&app, &plan_id, None, |s| s.status = PlanStatus::Running);
+    if state.is_err() {
+        plans.release(&plan_id);
+    } else {
+        spawn(app, plan_id);
+    }
+    state
+}
+
+/// Stop a plan; a running plan stops once its current step ends
+#[tauri::command]
+pub async fn cancel_plan(
+    app: AppHandle,
+    plans: State<'_, Plans>,
+    plan_id: String,
+) -> Result<PlanState, PlanError> {
+    let state = plans.get(&plan_id)?;
+    if plans.is_active(&plan_id) {
+        plans.cancelled.lock().unwrap().insert(plan_id);
+        return Ok(state);
+    }
+    match state.status {
+        PlanStatus::AwaitingApproval | PlanStatus::Paused | PlanStatus::Failed => {
+            plans.update(&app, &plan_id, None, |s| s.status = PlanStatus::Cancelled)
+        }
+        status => Err(PlanError::InvalidState {
+            id: plan_id,
+            status,
+        }),
+    }
+}
+
+/// Undo the file changes of every step, newest first
+/// Commands the plan ran are not undone.
+#[tauri::command]
+pub async fn rollback_plan(
+    app: AppHandle,
+    workspace: State<'_, Workspace>,
+    journal: State<'_, Journal>,
+    plans: State<'_, Plans>,
+    plan_id: String,
+) -> Result<PlanState, PlanError> {
+    if plans.is_active(&plan_id) {
+        return Err(PlanError::Busy { id: plan_id });
+    }
+    let state = plans.get(&plan_id)?;
+    if state.status == PlanStatus::RolledBack {
+        return Err(PlanError::InvalidState {
+            id: plan_id,
+            status: state.status,
+        });
+    }
+    for (index, step) in state.steps.iter().enumerate().rev() {
+        let Some(checkpoint) = &step.checkpoint else {
+            continue;
+        };
+        if !matches!(step.status, StepStatus::Completed | StepStatus::Running) {
+            continue;
+        }
+        match journal.undo(checkpoint, |p| Ok(workspace.resolve(p)?)) {
+            Ok(_) | Err(JournalError::NotFound { .. }) | Err(JournalError::InvalidState { .. }) => {
+            }
+            Err(e) => return Err(e.into()),
+        }
+        plans.update_step(&app, &plan_id, index, |_, s| {
+            s.status = StepStatus::RolledBack;
+        })?;
+    }
+    plans.update(&app, &plan_id, None, |s| s.status = PlanStatus::RolledBack)
+}
+
+/// Saved plans, and the files that could not be read as one
+#[tauri::command]
+pub fn list_plans(plans: State<'_, Plans>) -> PlanList {
+    let mut all: Vec<PlanState> = plans.plans.lock().unwrap().values().cloned().collect();
+    all.sort_by_key(|state| std::cmp::Reverse(state.created_at_ms));
+    PlanList {
+        plans: all,
+        unreadable: plans.unreadable.clone(),
+    }
+}
+
+#[tauri::command]
+pub fn get_plan(plans: State<'_, Plans>, plan_id: String) -> Result<PlanState, PlanError> {
+    plans.get(&plan_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journal::FileChange;
+
+    fn step(operation: Operation, requires_approval: bool) -> PlanStep {
+        PlanStep {
+            id: "step".into(),
+            description: "a step".into(),
+            operation,
+            requires_approval,
+            validate: Vec::new(),
+        }
+    }
+
+    fn command() -> Operation {
+        Operation::Command {
+            argv: vec!["npm".into(), "install".into()],
+            cwd: None,
+            timeout_ms: None,
+        }
+    }
+
+    fn files() -> Operation {
+        Operation::Files { ops: Vec::new() }
+    }
+
+    fn state(status: StepStatus, started: bool, approved: bool) -> StepState {
+        StepState {
+            id: "step".into(),
+            status,
+            approved,
+            checkpoint: None,
+            output: None,
+            error: None,
+            started_at_ms: started.then_some(1),
+            finished_at_ms: None,
+        }
+    }
+
+    #[test]
+    fn rerunning_an_uncheckpointed_step_needs_approval() {
+        let pending = state(StepStatus::Pending, false, false);
+        let interrupted = state(StepStatus::Running, true, false);
+        let failed = state(StepStatus::Failed, true, false);
+        let approved = state(StepStatus::Pending, true, true);
+
+        assert!(!needs_approval(&step(command(), false), &pending));
+        assert!(needs_approval(&step(command(), false), &interrupted));
+        assert!(needs_approval(&step(command(), false), &failed));
+        assert!(needs_approval(
+            &step(Operation::Test { paths: Vec::new() }, false),
+            &failed
+        ));
+        assert!(!needs_approval(&step(command(), false), &approved));
+
+        // File steps are rolled back instead
+        assert!(!needs_approval(&step(files(), false), &interrupted));
+        assert!(!needs_approval(&step(files(), false), &failed));
+        assert!(needs_approval(&step(files(), true), &pending));
+        assert!(!needs_approval(
+            &step(files(), true),
+            &state(StepStatus::Failed, true, true)
+        ));
+    }
+
+    #[test]
+    fn running_plans_reopen_paused() {
+        let dir = tempfile::tempdir().unwrap();
+        let plan = PlanState {
+            plan: Plan {
+                id: "install".into(),
+                goal: "install".into(),
+                root: None,
+                steps: vec![step(files(), false), step(command(), false)],
+            },
+            status: PlanStatus::Running,
+            root: dir.path().to_path_buf(),
+            steps: vec![
+                state(StepStatus::Completed, true, false),
+                state(StepStatus::Running, true, false),
+            ],
+            created_at_ms: 1,
+            updated_at_ms: 1,
+        };
+        fs::write(
+            dir.path().join("install.json"),
+            serde_json::to_vec(&plan).unwrap(),
+        )
+        .unwrap();
+        fs::write(dir.path().join("broken.json"), "{").unwrap();
+
+        let plans = Plans::open(dir.path().to_path_buf()).unwrap();
+        let state = plans.get("install").unwrap();
+        assert_eq!(state.status, PlanStatus::Paused);
+        assert_eq!(plans.unreadable.len(), 1);
+        // The command it stopped in doesn't run again unasked
+        assert!(needs_approval(&state.plan.steps[1], &state.steps[1]));
+    }
+
+    #[test]
+    fn restoring_a_checkpoint_undoes_it_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::open(dir.path().join("journal")).unwrap();
+        let resolve = |p: &Path| Ok(p.to_path_buf());
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "before").unwrap();
+
+        let checkpoint = journal.begin("step").unwrap().id;
+        journal
+            .record_all(
+                Some(&checkpoint),
+                "step",
+                &[FileChange {
+                    path: &path,
+                    before: Some(b"before"),
+                    after: Some(b"after"),
+                }],
+            )
+            .unwrap();
+        fs::write(&path, "after").unwrap();
+
+        undo_checkpoint(&journal, &checkpoint, resolve).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "before");
+        // Already undone, or never recorded into
+        undo_checkpoint(&journal, &checkpoint, resolve).unwrap();
+        let empty = journal.begin("empty").unwrap().id;
+        undo_checkpoint(&journal, &empty, resolve).unwrap();
+        undo_checkpoint(&journal, "missing", resolve).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "before");
+    }
+}
@@ -0,0 +1,1379 @@
+//! Command policy for commands the agent runs
+//!
+//! Each command is sorted into a class (read-only, build/test, network,
+//! destructive or unrecognised) from its program and subcommand. Only a
+//! bare program name that the policy itself finds on `PATH`, outside the
+//! open workspace, is classified; an explicit path is unrecognised, and
+//! the resolved program is what runs. Commands run through `npx`,
+//! `pnpm exec` and the like are classified as what they run. The autonomy
+//! level picked in the UI, together with the `security.commands`
+//! allow/deny rules in `.henryrc`, then decides whether it runs straight
+//! away, needs the user's OK in a native dialog, or is blocked. A deny rule
+//! always blocks; an allow rule only spares the dialog. A command that sets
+//! variables like `LD_PRELOAD` or `GIT_PAGER`, which make programs run other
+//! code, is blocked. Every decision is appended to a log in the app's data
+//! directory before the command runs; a command whose decision cannot be
+//! logged does not run.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use globset::{Glob, GlobMatcher};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::config::{CommandRules, HenryConfig};
+use crate::exec::{self, CommandOutput, ExecError, RunOptions};
+use crate::permissions;
+use crate::workspace::Workspace;
+
+/// The log is rotated once it grows past this, keeping one old file
+const MAX_LOG_BYTES: u64 = 1024 * 1024;
+
+/// Decisions returned by `command_decisions` unless the caller says
+const DEFAULT_RECENT: usize = 100;
+
+/// Mirrors `AutonomyLevel` in `services/ai/agent.ts`; ordered by how much
+/// the agent may do
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AutonomyLevel {
+    /// Autocomplete only; the agent runs nothing
+    Tab,
+    /// Single edits; anything beyond reading needs approval
+    #[default]
+    CmdK,
+    /// Multi-step work; builds and tests run unattended
+    FullAgent,
+}
+
+impl AutonomyLevel {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Tab => "tab",
+            Self::CmdK => "cmd-k",
+            Self::FullAgent => "full-agent",
+        }
+    }
+}
+
+/// Ordered from least to most dangerous
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CommandClass {
+    ReadOnly,
+    BuildTest,
+    Network,
+    Unknown,
+    Destructive,
+}
+
+impl CommandClass {
+    fn name(self) -> &'static str {
+        match self {
+            Self::ReadOnly => "read-only",
+            Self::BuildTest => "build/test",
+            Self::Network => "network",
+            Self::Destructive => "destructive",
+            Self::Unknown => "unrecognised",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Verdict {
+    Approve,
+    Prompt,
+    Block,
+}
+
+/// What the policy says about one command, before anyone is asked
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Assessment {
+    pub class: CommandClass,
+    pub verdict: Verdict,
+    /// The `.henryrc` rule that decided, if one did
+    pub rule: Option<String>,
+    pub reason: String,
+}
+
+/// One logged decision
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Decision {
+    pub at_ms: u64,
+    pub argv: Vec<String>,
+    pub cwd: PathBuf,
+    /// Variables the command set, and ones it passed on from the app
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub inherit_env: Vec<String>,
+    pub level: AutonomyLevel,
+    pub class: CommandClass,
+    pub verdict: Verdict,
+    pub rule: Option<String>,
+    pub reason: String,
+    /// Whether the command went on to run
+    pub approved: bool,
+}
+
+/// The autonomy level and the decision log, managed as Tauri state
+pub struct CommandPolicy {
+    level: Mutex<AutonomyLevel>,
+    log: PathBuf,
+    /// Serializes appends and rotation
+    lock: Mutex<()>,
+}
+
+impl CommandPolicy {
+    pub fn open(log: PathBuf) -> io::Result<Self> {
+        if let Some(dir) = log.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        Ok(Self {
+            level: Mutex::new(AutonomyLevel::default()),
+            log,
+            lock: Mutex::new(()),
+        })
+    }
+
+    pub fn level(&self) -> AutonomyLevel {
+        *self.level.lock().unwrap()
+    }
+
+    pub fn set_level(&self, level: AutonomyLevel) {
+        *self.level.lock().unwrap() = level;
+    }
+
+    /// Append `decision` to the log
+    pub fn record(&self, decision: &Decision) -> io::Result<()> {
+        let _guard = self.lock.lock().unwrap();
+        if fs::metadata(&self.log).is_ok_and(|m| m.len() > MAX_LOG_BYTES) {
+            fs::rename(&self.log, self.log.with_extension("log.1"))?;
+        }
+        let mut line = serde_json::to_vec(decision)?;
+        line.push(b'\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.log)?
+            .write_all(&line)
+    }
+
+    /// The last `limit` decisions, newest first
+    pub fn recent(&self, limit: usize) -> io::Result<Vec<Decision>> {
+        let _guard = self.lock.lock().unwrap();
+        let text = match fs::read_to_string(&self.log) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        Ok(text
+            .lines()
+            .rev()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .take(limit)
+            .collect())
+    }
+}
+
+/// `.henryrc` rules compiled for matching
+struct Rules {
+    allow: Vec<(String, GlobMatcher)>,
+    deny: Vec<(String, GlobMatcher)>,
+}
+
+impl Rules {
+    fn compile(rules: &CommandRules) -> Result<Self, ExecError> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    Glob::new(pattern)
+                        .map(|glob| (pattern.clone(), glob.compile_matcher()))
+                        .map_err(|e| ExecError::InvalidRule {
+                            pattern: pattern.clone(),
+                            message: e.to_string(),
+                        })
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(Self {
+            allow: compile(&rules.allow)?,
+            deny: compile(&rules.deny)?,
+        })
+    }
+}
+
+fn first_match<'r>(rules: &'r [(String, GlobMatcher)], line: &str) -> Option<&'r str> {
+    rules
+        .iter()
+        .find(|(_, matcher)| matcher.is_match(line))
+        .map(|(pattern, _)| pattern.as_str())
+}
+
+/// Variables that load code into a program or name programs for it to run;
+/// compared in upper case
+const EXEC_HOOK_ENV: [&str; 34] = [
+    "PATH",
+    "PATHEXT",
+    "COMSPEC",
+    "SHELL",
+    "BASH_ENV",
+    "ENV",
+    "PROMPT_COMMAND",
+    "SHELLOPTS",
+    "EDITOR",
+    "VISUAL",
+    "PAGER",
+    "GIT_PAGER",
+    "GIT_EDITOR",
+    "GIT_EXTERNAL_DIFF",
+    "GIT_SSH",
+    "GIT_SSH_COMMAND",
+    "GIT_ASKPASS",
+    "SSH_ASKPASS",
+    "GIT_EXEC_PATH",
+    "GIT_DIR",
+    "GIT_WORK_TREE",
+    "NODE_OPTIONS",
+    "NODE_PATH",
+    "PYTHONPATH",
+    "PYTHONSTARTUP",
+    "PYTHONHOME",
+    "PERL5OPT",
+    "PERL5LIB",
+    "RUBYOPT",
+    "RUBYLIB",
+    "RUSTC_WRAPPER",
+    "RUSTC_WORKSPACE_WRAPPER",
+    "JAVA_TOOL_OPTIONS",
+    "GOFLAGS",
+];
+
+/// Prefixes of more such variables
+const EXEC_HOOK_ENV_PREFIXES: [&str; 6] = [
+    "LD_",
+    "DYLD_",
+    "GIT_CONFIG",
+    "NPM_CONFIG_",
+    "CARGO_BUILD_",
+    "CARGO_TARGET_",
+];
+
+fn is_exec_hook(name: &str) -> bool {
+    let name = name.to_ascii_uppercase();
+    EXEC_HOOK_ENV.contains(&name.as_str())
+        || EXEC_HOOK_ENV_PREFIXES
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+}
+
+/// What the policy knows about where and how a command would run
+#[derive(Debug, Default)]
+struct Site {
+    /// Whether the program is a bare name found by the policy's own PATH
+    /// lookup; any other program is unrecognised
+    resolved: bool,
+    /// Scripts in the `package.json` of the working directory
+    scripts: HashSet<String>,
+    /// Variables the command sets
+    env: Vec<String>,
+    /// Variables passed through from the app's environment that are set
+    inherited: Vec<String>,
+}
+
+/// The program's bare name: lowercase, without a Windows extension; `None`
+/// for a path, which names a program wherever it is, the workspace included
+fn bare_name(program: &str) -> Option<String> {
+    if program.is_empty() || program.contains(['/', '\\']) {
+        return None;
+    }
+    let name = program.to_ascii_lowercase();
+    for ext in [".exe", ".cmd", ".bat", ".com"] {
+        if let Some(stem) = name.strip_suffix(ext) {
+            return Some(stem.to_owned());
+        }
+    }
+    Some(name)
+}
+
+/// `program` looked up in the app's `PATH`, skipping relative directories and
+/// ones inside an open workspace, whose programs the repository controls
+fn which(program: &str, workspace: &Workspace) -> Option<PathBuf> {
+    bare_name(program)?;
+    let roots = workspace.roots();
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .filter(|dir| dir.is_absolute())
+        .filter_map(|dir| fs::canonicalize(dir).ok())
+        .filter(|dir| !roots.iter().any(|root| dir.starts_with(&root.path)))
+        .flat_map(|dir| executable_names(program).map(move |name| dir.join(name)))
+        .find(|candidate| is_executable(candidate))
+}
+
+#[cfg(unix)]
+fn executable_names(program: &str) -> impl Iterator<Item = String> {
+    std::iter::once(program.to_owned())
+}
+
+#[cfg(windows)]
+fn executable_names(program: &str) -> impl Iterator<Item = String> {
+    let bare = Path::new(program).extension().is_none();
+    let exts: &[&str] = if bare {
+        &[".exe", ".com", ".cmd", ".bat"]
+    } else {
+        &[""]
+    };
+    let program = program.to_owned();
+    exts.iter().map(move |ext| format!("{}{}", program, ext))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(windows)]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Names of the scripts in `dir/package.json`
+fn package_scripts(dir: &Path) -> HashSet<String> {
+    fs::read(dir.join("package.json"))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<serde_json::Value>(&bytes).ok())
+        .and_then(|json| {
+            json.get("scripts")?
+                .as_object()
+                .map(|scripts| scripts.keys().cloned().collect())
+        })
+        .unwrap_or_default()
+}
+
+/// Whether `args` has the flag `long`, or `short` in a bundle like `-rf`
+fn has_flag(args: &[&str], short: Option<char>, long: &str) -> bool {
+    args.iter().any(|arg| {
+        *arg == long
+            || short.is_some_and(|c| {
+                arg.len() > 1 && arg.starts_with('-') && !arg.starts_with("--") && arg.contains(c)
+            })
+    })
+}
+
+/// Whether `args` has `--long` or `--long=value`
+fn has_long(args: &[&str], long: &str) -> bool {
+    args.iter().any(|arg| {
+        arg.strip_prefix(long)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('='))
+    })
+}
+
+/// Sort a command into its class; the most dangerous class that fits wins
+///
+/// `name` is the program's bare name. Commands run through `npx`, `npm exec`
+/// and the like are classified as what they wrap, and each command line
+/// the class was decided on is added to `lines`, for the rules to match.
+fn classify(
+    name: &str,
+    args: &[&str],
+    scripts: &HashSet<String>,
+    lines: &mut Vec<String>,
+) -> CommandClass {
+    if let Some((wrapper, inner)) = unwrap(name, args, scripts) {
+        let Some((program, rest)) = inner.and_then(|inner| inner.split_first()) else {
+            // `npx -c '…'` runs a shell line, plain `npx` a prompt
+            return CommandClass::Unknown;
+        };
+        let Some(inner_name) = bare_name(program) else {
+            return CommandClass::Unknown;
+        };
+        lines.push(command_line(&inner_name, rest));
+        return wrapper.max(classify(&inner_name, rest, scripts, lines));
+    }
+
+    // Global options go before the subcommand; rules are written without
+    let (globals, args) = match name {
+        "git" => split_globals(
+            args,
+            &["-C", "-c", "--git-dir", "--work-tree", "--namespace"],
+        ),
+        "cargo" => split_globals(args, &["-C", "-Z", "--config", "--color"]),
+        _ => (&[][..], args),
+    };
+    if !globals.is_empty() {
+        lines.push(command_line(name, args));
+    }
+    let sub = args.first().copied().unwrap_or("");
+    let class = if is_destructive(name, sub, args) {
+        CommandClass::Destructive
+    } else if is_network(name, sub, args) {
+        CommandClass::Network
+    } else if is_build_test(name, sub, args, scripts) {
+        CommandClass::BuildTest
+    } else if is_read_only(name, sub, args) && !runs_or_writes(name, sub, args) {
+        CommandClass::ReadOnly
+    } else {
+        CommandClass::Unknown
+    };
+    // Another repository's configuration, or configuration given here, can
+    // make any git subcommand run programs
+    let reconfigured = globals.iter().any(|arg| {
+        [
+            "-C",
+            "-c",
+            "--git-dir",
+            "--work-tree",
+            "--config-env",
+            "--exec-path",
+        ]
+        .iter()
+        .any(|option| {
+            arg.strip_prefix(option)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('='))
+        })
+    });
+    if name == "git" && reconfigured {
+        return class.max(CommandClass::Unknown);
+    }
+    class
+}
+
+/// `name` and `args` as one line
+fn command_line(name: &str, args: &[&str]) -> String {
+    std::iter::once(name)
+        .chain(args.iter().copied())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// For a command that runs another, the class of the wrapper itself and the
+/// wrapped argv; `Some((_, None))` if what it runs can't be told
+fn unwrap<'a>(
+    name: &str,
+    args: &'a [&'a str],
+    scripts: &HashSet<String>,
+) -> Option<(CommandClass, Option<&'a [&'a str]>)> {
+    let sub = args.first().copied().unwrap_or("");
+    let rest = args.get(1..).unwrap_or_default();
+    match (name, sub) {
+        // May download the package first
+        ("npx" | "bunx", _) => Some((CommandClass::Network, exec_target(args))),
+        ("pnpm" | "yarn", "dlx") | ("bun", "x") => Some((CommandClass::Network, exec_target(rest))),
+        // Installs what is missing unless told not to
+        ("npm", "exec" | "x") => {
+            let offline = rest
+                .iter()
+                .any(|arg| *arg == "--no" || *arg == "--no-install");
+            let class = if offline {
+                CommandClass::BuildTest
+            } else {
+                CommandClass::Network
+            };
+            Some((class, exec_target(rest)))
+        }
+        ("pnpm" | "yarn", "exec") => Some((CommandClass::BuildTest, exec_target(rest))),
+        // A package script, or else a binary of the project's dependencies
+        ("pnpm" | "yarn", "run" | "run-script") => match rest.first() {
+            Some(script) if scripts.contains(*script) => None,
+            _ => Some((CommandClass::BuildTest, exec_target(rest))),
+        },
+        ("yarn" | "pnpm", sub)
+            if !sub.is_empty()
+                && !sub.starts_with('-')
+                && !scripts.contains(sub)
+                && !is_package_manager_command(sub) =>
+        {
+            Some((CommandClass::BuildTest, Some(args)))
+        }
+        _ => None,
+    }
+}
+
+/// The argv `npx` or `… exec` runs, after its own options
+fn exec_target<'a>(args: &'a [&'a str]) -> Option<&'a [&'a str]> {
+    let mut i = 0;
+    while let Some(arg) = args.get(i) {
+        match *arg {
+            "--" => return Some(&args[i + 1..]),
+            "-c" | "--call" => return None,
+            "-p" | "--package" | "--workspace" | "-w" | "--filter" | "-F" | "--shell-mode" => {
+                i += 2
+            }
+            a if a.starts_with("--call=") => return None,
+            a if a.starts_with('-') => i += 1,
+            _ => return Some(&args[i..]),
+        }
+    }
+    None
+}
+
+/// Subcommands of pnpm and yarn themselves, which are not scripts or binaries
+fn is_package_manager_command(sub: &str) -> bool {
+    matches!(
+        sub,
+        "install"
+            | "i"
+            | "ci"
+            | "add"
+            | "remove"
+            | "rm"
+            | "update"
+            | "up"
+            | "upgrade"
+            | "publish"
+            | "create"
+            | "login"
+            | "ls"
+            | "list"
+            | "outdated"
+            | "why"
+            | "test"
+            | "t"
+            | "start"
+            | "config"
+            | "init"
+            | "link"
+            | "unlink"
+            | "store"
+            | "cache"
+            | "audit"
+            | "info"
+            | "view"
+            | "pack"
+            | "prune"
+            | "rebuild"
+            | "version"
+            | "workspace"
+            | "workspaces"
+    )
+}
+
+/// A command's global options, and its arguments from the subcommand on;
+/// `with_value` are the options whose value is a separate argument
+fn split_globals<'a>(args: &'a [&'a str], with_value: &[&str]) -> (&'a [&'a str], &'a [&'a str]) {
+    let mut i = 0;
+    while let Some(arg) = args.get(i) {
+        if with_value.contains(arg) {
+            i += 2;
+        } else if arg.starts_with('-') || arg.starts_with('+') {
+            // `+nightly` picks cargo's toolchain
+            i += 1;
+        } else {
+            break;
+        }
+    }
+    args.split_at(i.min(args.len()))
+}
+
+fn is_destructive(program: &str, sub: &str, args: &[&str]) -> bool {
+    match program {
+        "rm" | "rmdir" | "del" | "erase" | "rd" | "shred" | "dd" | "format" | "truncate"
+        | "sudo" | "su" | "doas" | "kill" | "killall" | "pkill" | "taskkill" | "shutdown"
+        | "reboot" => true,
+        p if p.starts_with("mkfs") => true,
+        "chmod" | "chown" | "chgrp" => has_flag(args, Some('R'), "--recursive"),
+        // Deletes, writes files or runs programs
+        "find" => args.iter().any(|arg| {
+            matches!(
+                *arg,
+                "-delete"
+                    | "-exec"
+                    | "-execdir"
+                    | "-ok"
+                    | "-okdir"
+                    | "-fprint"
+                    | "-fprint0"
+                    | "-fprintf"
+                    | "-fls"
+            )
+        }),
+        "git" => {
+            let rest = args.get(1..).unwrap_or_default();
+            match sub {
+                "push" => {
+                    has_flag(rest, Some('f'), "--force")
+                        || has_flag(rest, Some('d'), "--delete")
+                        || rest.iter().any(|arg| {
+                            arg.starts_with("--force-with-lease")
+                                || *arg == "--mirror"
+                                || arg.starts_with('+')
+                                || arg.starts_with(':')
+                        })
+                }
+                "reset" => has_flag(rest, None, "--hard"),
+                "clean" => has_flag(rest, Some('f'), "--force"),
+                "checkout" => rest.iter().any(|arg| matches!(*arg, "--" | "." | "-f")),
+                "restore" | "filter-branch" | "filter-repo" => true,
+                "branch" => {
+                    has_flag(rest, Some('D'), "-D")
+                        || has_flag(rest, Some('d'), "--delete")
+                            && has_flag(rest, Some('f'), "--force")
+                }
+                "stash" => matches!(rest.first(), Some(&"drop" | &"clear")),
+                "reflog" => rest.first() == Some(&"expire"),
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+fn is_network(program: &str, sub: &str, args: &[&str]) -> bool {
+    match program {
+        "curl" | "wget" | "ssh" | "scp" | "sftp" | "rsync" | "ftp" | "nc" | "ncat" | "telnet"
+        | "pipx" | "gh" => true,
+        "git" => matches!(
+            sub,
+            "push" | "pull" | "fetch" | "clone" | "ls-remote" | "submodule"
+        ),
+        "npm" | "pnpm" | "yarn" | "bun" => {
+            matches!(
+                sub,
+                "install"
+                    | "i"
+                    | "ci"
+                    | "add"
+                    | "update"
+                    | "up"
+                    | "upgrade"
+                    | "publish"
+                    | "create"
+                    | "login"
+            ) || (program == "yarn" && args.is_empty())
+        }
+        "pip" | "pip3" | "uv" | "poetry" => {
+            matches!(
+                sub,
+                "install" | "download" | "add" | "sync" | "lock" | "publish"
+            )
+        }
+        "cargo" => matches!(
+            sub,
+            "install" | "publish" | "add" | "update" | "fetch" | "search" | "login"
+        ),
+        "go" => matches!(sub, "get" | "install") || args.starts_with(&["mod", "download"]),
+        "docker" | "podman" => matches!(sub, "pull" | "push" | "login"),
+        "brew" | "apt" | "apt-get" | "winget" | "choco" => {
+            matches!(sub, "install" | "update" | "upgrade")
+        }
+        _ => false,
+    }
+}
+
+fn is_build_test(program: &str, sub: &str, args: &[&str], scripts: &HashSet<String>) -> bool {
+    match program {
+        "tsc" | "vitest" | "jest" | "mocha" | "pytest" | "tox" | "eslint" | "prettier" | "make"
+        | "cmake" | "ninja" | "mvn" | "gradle" | "gradlew" | "turbo" | "vite" | "webpack"
+        | "rustc" | "gcc" | "clang" | "javac" | "playwright" | "mypy" | "ruff" => true,
+        "cargo" => matches!(
+            sub,
+            "build"
+                | "b"
+                | "test"
+                | "t"
+                | "check"
+                | "c"
+                | "clippy"
+                | "fmt"
+                | "run"
+                | "r"
+                | "bench"
+                | "doc"
+                | "nextest"
+        ),
+        "npm" => matches!(sub, "test" | "t" | "run" | "run-script" | "start"),
+        "pnpm" | "yarn" => {
+            matches!(sub, "test" | "t" | "start")
+                || matches!(sub, "run" | "run-script")
+                    && args.get(1).is_some_and(|script| scripts.contains(*script))
+                || scripts.contains(sub)
+        }
+        "bun" => sub == "test" || scripts.contains(sub),
+        "go" => matches!(sub, "build" | "test" | "vet" | "run" | "fmt"),
+        "dotnet" => matches!(sub, "build" | "test" | "run" | "format"),
+        "python" | "python3" | "py" => {
+            sub == "-m"
+                && matches!(
+                    args.get(1).copied(),
+                    Some("pytest" | "unittest" | "mypy" | "ruff" | "black")
+                )
+        }
+        _ => false,
+    }
+}
+
+fn is_read_only(program: &str, sub: &str, args: &[&str]) -> bool {
+    if matches!(args, [flag] if matches!(*flag, "--version" | "-V" | "--help" | "-h")) {
+        return true;
+    }
+    match program {
+        "ls" | "dir" | "cat" | "type" | "head" | "tail" | "wc" | "pwd" | "echo" | "grep" | "rg"
+        | "ag" | "find" | "fd" | "tree" | "stat" | "file" | "du" | "df" | "which" | "where"
+        | "whereis" | "whoami" | "date" | "uname" | "printenv" | "diff" | "cmp" | "sort"
+        | "uniq" | "cut" | "basename" | "dirname" | "realpath" | "readlink" | "hostname" | "ps" => {
+            true
+        }
+        "git" => match sub {
+            "status" | "log" | "diff" | "show" | "blame" | "rev-parse" | "ls-files"
+            | "describe" | "shortlog" | "grep" => true,
+            "branch" | "tag" | "remote" => args[1..]
+                .iter()
+                .all(|arg| matches!(*arg, "-a" | "-r" | "-v" | "-vv" | "-l" | "--list")),
+            _ => false,
+        },
+        "npm" | "pnpm" | "yarn" => matches!(sub, "ls" | "list" | "outdated" | "why"),
+        "cargo" => matches!(sub, "tree" | "metadata"),
+        _ => false,
+    }
+}
+
+/// Whether a read-only program is told to write files or run programs
+fn runs_or_writes(program: &str, sub: &str, args: &[&str]) -> bool {
+    match program {
+        // A preprocessor for every file searched
+        "rg" => has_long(args, "--pre"),
+        "sort" => has_flag(args, Some('o'), "--output") || has_long(args, "--output"),
+        "tree" => has_flag(args, Some('o'), "-o"),
+        "date" => has_flag(args, Some('s'), "--set") || has_long(args, "--set"),
+        "hostname" => args.iter().any(|arg| !arg.starts_with('-')),
+        "git" => {
+            let rest = args.get(1..).unwrap_or_default();
+            has_long(rest, "--output")
+                || has_long(rest, "--ext-diff")
+                || sub == "grep"
+                    && (has_flag(rest, Some('O'), "-O") || has_long(rest, "--open-files-in-pager"))
+        }
+        _ => false,
+    }
+}
+
+/// Decide what to do with `argv` at `level` under `rules`
+fn assess(argv: &[String], level: AutonomyLevel, rules: &Rules, site: &Site) -> Assessment {
+    let program = argv.first().map_or("", String::as_str);
+    let args: Vec<&str> = argv.iter().skip(1).map(String::as_str).collect();
+    // Rules name programs by their bare name, wherever they are
+    let shown = bare_name(program).unwrap_or_else(|| {
+        let name = program.rsplit(['/', '\\']).next().unwrap_or(program);
+        bare_name(name).unwrap_or_default()
+    });
+    let mut lines = vec![command_line(&shown, &args)];
+    let class = match bare_name(program) {
+        Some(name) if site.resolved => classify(&name, &args, &site.scripts, &mut lines),
+        _ => CommandClass::Unknown,
+    };
+
+    let decided = |verdict, rule: Option<&str>, reason: String| Assessment {
+        class,
+        verdict,
+        rule: rule.map(str::to_owned),
+        reason,
+    };
+    // Matched against the command as given and against what it runs
+    if let Some(rule) = lines.iter().find_map(|line| first_match(&rules.deny, line)) {
+        return decided(
+            Verdict::Block,
+            Some(rule),
+            format!("matches the deny rule `{}`", rule),
+        );
+    }
+    if level == AutonomyLevel::Tab {
+        return decided(
+            Verdict::Block,
+            None,
+            "the agent runs no commands at autonomy level tab".to_owned(),
+        );
+    }
+    let hooks: Vec<&str> = site
+        .env
+        .iter()
+        .map(String::as_str)
+        .filter(|name| is_exec_hook(name))
+        .collect();
+    if !hooks.is_empty() {
+        return decided(
+            Verdict::Block,
+            None,
+            format!(
+                "the agent may not set {}, which can make the command run other programs",
+                hooks.join(", ")
+            ),
+        );
+    }
+    let inherited_hooks: Vec<&str> = site
+        .inherited
+        .iter()
+        .map(String::as_str)
+        .filter(|name| is_exec_hook(name))
+        .collect();
+
+    let verdict = match (level, class) {
+        (_, CommandClass::ReadOnly) => Verdict::Approve,
+        (AutonomyLevel::FullAgent, CommandClass::BuildTest) => Verdict::Approve,
+        (AutonomyLevel::CmdK, CommandClass::Destructive) => Verdict::Block,
+        _ => Verdict::Prompt,
+    };
+    if !inherited_hooks.is_empty() && verdict != Verdict::Block {
+        return decided(
+            Verdict::Prompt,
+            None,
+            format!(
+                "it passes on {} from the app's environment",
+                inherited_hooks.join(", ")
+            ),
+        );
+    }
+    // The rules come from the repository, so an allow rule only spares the
+    // prompt; it never lets through what the level blocks. A wrapped command
+    // needs every layer allowed.
+    if verdict == Verdict::Prompt {
+        let allowed: Option<Vec<&str>> = lines
+            .iter()
+            .map(|line| first_match(&rules.allow, line))
+            .collect();
+        if let Some(rule) = allowed.and_then(|rules| rules.last().copied()) {
+            return decided(
+                Verdict::Approve,
+                Some(rule),
+                format!("matches the allow rule `{}`", rule),
+            );
+        }
+    }
+    let reason = match verdict {
+        Verdict::Approve => "run unattended",
+        Verdict::Prompt => "need approval",
+        Verdict::Block => "are blocked",
+    };
+    decided(
+        verdict,
+        None,
+        format!(
+            "{} commands {} at autonomy level {}",
+            class.name(),
+            reason,
+            level.name()
+        ),
+    )
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Set the autonomy level the policy applies, returning the level in effect
+///
+/// Raising the level gives the agent more to run unattended, so the user
+/// confirms it in a native dialog; a level the webview asks for alone
+/// does not count.
+#[tauri::command]
+pub async fn set_autonomy_level(app: AppHandle, level: AutonomyLevel) -> AutonomyLevel {
+    let policy = app.state::<CommandPolicy>();
+    let current = policy.level();
+    if level > current {
+        let confirmed = permissions::confirm(
+            &app,
+            "Raise autonomy level",
+            format!(
+                "Let the agent work at autonomy level {} instead of {}?\n\n{}",
+                level.name(),
+                current.name(),
+                match level {
+                    AutonomyLevel::FullAgent => "Builds and tests will run without asking.",
+                    _ => "Commands that need approval will be shown to you first.",
+                }
+            ),
+            "Raise",
+        )
+        .await;
+        if !confirmed {
+            return current;
+        }
+    }
+    policy.set_level(level);
+    level
+}
+
+/// What the policy would do with `argv` in `cwd`, without asking or running
+#[tauri::command]
+pub fn check_command(
+    workspace: State<'_, Workspace>,
+    policy: State<'_, CommandPolicy>,
+    argv: Vec<String>,
+    options: Option<RunOptions>,
+) -> Result<Assessment, ExecError> {
+    let options = options.unwrap_or_default();
+    let cwd = exec::working_dir(&workspace, options.cwd.as_deref())?;
+    let rules = rules_for(&workspace, &cwd)?;
+    let (site, _) = site(&workspace, &argv, &cwd, &options);
+    Ok(assess(&argv, policy.level(), &rules, &site))
+}
+
+/// Run a command on the agent's behalf, if the policy lets it
+///
+/// Commands that need approval are shown in a native dialog first. Blocked
+/// and refused commands fail with `blocked` and `refused` errors; either
+/// way the decision is logged.
+#[tauri::command]
+pub async fn run_agent_command(
+    app: AppHandle,
+    workspace: State<'_, Workspace>,
+    policy: State<'_, CommandPolicy>,
+    argv: Vec<String>,
+    options: Option<RunOptions>,
+) -> Result<CommandOutput, ExecError> {
+    let options = options.unwrap_or_default();
+    let cwd = exec::working_dir(&workspace, options.cwd.as_deref())?;
+    let argv = authorize(&app, &workspace, &policy, &argv, &cwd, &options).await?;
+    exec::run_blocking(argv, cwd, options).await
+}
+
+/// Apply the policy to `argv` run in `cwd` with `options`, asking the user if
+/// it says to
+///
+/// Returns the argv to run, with the program the policy resolved on `PATH`
+/// in place of its bare name. Fails with `blocked` or `refused` unless the
+/// command may run; either way the decision is logged.
+pub async fn authorize(
+    app: &AppHandle,
+    workspace: &Workspace,
+    policy: &CommandPolicy,
+    argv: &[String],
+    cwd: &Path,
+    options: &RunOptions,
+) -> Result<Vec<String>, ExecError> {
+    if argv.is_empty() {
+        return Err(ExecError::EmptyCommand);
+    }
+    let rules = rules_for(workspace, cwd)?;
+    let level = policy.level();
+    let (site, program) = site(workspace, argv, cwd, options);
+    let assessment = assess(argv, level, &rules, &site);
+    let command = argv.join(" ");
+
+    let approved = match assessment.verdict {
+        Verdict::Approve => true,
+        Verdict::Block => false,
+        Verdict::Prompt => {
+            let program = program.as_deref().map_or_else(
+                || "not found on PATH".to_owned(),
+                |path| path.display().to_string(),
+            );
+            let mut env: Vec<String> = options
+                .env
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect();
+            env.sort();
+            env.extend(
+                site.inherited
+                    .iter()
+                    .map(|name| format!("{} (inherited)", name)),
+            );
+            let env = if env.is_empty() {
+                String::new()
+            } else {
+                format!("\n\nwith\n\n{}", env.join("\n"))
+            };
+            permissions::confirm(
+                app,
+                "Run agent command",
+                format!(
+                    "The agent wants to run:\n\n{}\n\nin {}{}\n\nProgram: {}\n\nThis is a {} command: {}.",
+                    command,
+                    cwd.display(),
+                    env,
+                    program,
+                    assessment.class.name(),
+                    assessment.reason
+                ),
+                "Run",
+            )
+            .await
+        }
+    };
+    let decision = Decision {
+        at_ms: now_ms(),
+        argv: argv.to_vec(),
+        cwd: cwd.to_path_buf(),
+        env: options.env.clone().into_iter().collect(),
+        inherit_env: options.inherit_env.clone(),
+        level,
+        class: assessment.class,
+        verdict: assessment.verdict,
+        rule: assessment.rule,
+        reason: assessment.reason,
+        approved,
+    };
+    policy.record(&decision).map_err(ExecError::log)?;
+
+    match decision.verdict {
+        Verdict::Block => Err(ExecError::Blocked {
+            command,
+            reason: decision.reason,
+        }),
+        _ if !approved => Err(ExecError::Refused { command }),
+        _ => {
+            let mut argv = argv.to_vec();
+            if let Some(program) = program {
+                argv[0] = program.to_string_lossy().into_owned();
+            }
+            Ok(argv)
+        }
+    }
+}
+
+/// What the policy needs to know about running `argv` in `cwd`, and the
+/// program it resolved
+fn site(
+    workspace: &Workspace,
+    argv: &[String],
+    cwd: &Path,
+    options: &RunOptions,
+) -> (Site, Option<PathBuf>) {
+    let program = argv.first().and_then(|program| which(program, workspace));
+    let mut env: Vec<String> = options.env.keys().cloned().collect();
+    env.sort();
+    let site = Site {
+        resolved: program.is_some(),
+        scripts: package_scripts(cwd),
+        env,
+        inherited: options
+            .inherit_env
+            .iter()
+            .filter(|name| std::env::var_os(name).is_some())
+            .cloned()
+            .collect(),
+    };
+    (site, program)
+}
+
+/// Logged decisions, newest first
+#[tauri::command]
+pub fn command_decisions(
+    policy: State<'_, CommandPolicy>,
+    limit: Option<usize>,
+) -> Result<Vec<Decision>, ExecError> {
+    policy
+        .recent(limit.unwrap_or(DEFAULT_RECENT))
+        .map_err(ExecError::log)
+}
+
+/// The command rules of the root containing `cwd`
+///
+/// A `.henryrc` that can't be parsed is an error rather than no rules, so a
+/// typo can't silently drop the deny list.
+fn rules_for(workspace: &Workspace, cwd: &Path) -> Result<Rules, ExecError> {
+    let config = match workspace.root_of(cwd) {
+        Some(root) => HenryConfig::try_load(&root.path).map_err(|e| ExecError::InvalidConfig {
+            path: e.path.display().to_string(),
+            message: e.message,
+        })?,
+        None => HenryConfig::default(),
+    };
+    Rules::compile(&config.security.commands)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(allow: &[&str], deny: &[&str]) -> Rules {
+        Rules::compile(&CommandRules {
+            allow: allow.iter().map(|s| s.to_string()).collect(),
+            deny: deny.iter().map(|s| s.to_string()).collect(),
+        })
+        .unwrap()
+    }
+
+    /// A site where the program was found on `PATH` and `package.json` has
+    /// `test` and `lint` scripts
+    fn site() -> Site {
+        Site {
+            resolved: true,
+            scripts: ["test", "lint"].into_iter().map(str::to_owned).collect(),
+            ..Site::default()
+        }
+    }
+
+    fn argv(line: &str) -> Vec<String> {
+        line.split(' ').map(str::to_owned).collect()
+    }
+
+    fn verdict(line: &str, level: AutonomyLevel, rules: &Rules) -> Verdict {
+        assess(&argv(line), level, rules, &site()).verdict
+    }
+
+    fn class(line: &str) -> CommandClass {
+        assess(&argv(line), AutonomyLevel::CmdK, &rules(&[], &[]), &site()).class
+    }
+
+    #[test]
+    fn classes() {
+        use CommandClass::*;
+        let table = [
+            ("ls -la", ReadOnly),
+            ("git status", ReadOnly),
+            ("git log --oneline", ReadOnly),
+            ("rg TODO src", ReadOnly),
+            ("cargo test", BuildTest),
+            ("npm test", BuildTest),
+            ("npm run lint", BuildTest),
+            ("yarn lint", BuildTest),
+            ("pnpm run test", BuildTest),
+            ("python -m pytest", BuildTest),
+            ("curl example.com", Network),
+            ("git push origin main", Network),
+            ("npm install", Network),
+            ("rm -rf build", Destructive),
+            ("git push --force origin", Destructive),
+            ("git reset --hard HEAD~1", Destructive),
+            ("chmod -R 777 .", Destructive),
+            ("terraform apply", Unknown),
+            ("git commit -m wip", Unknown),
+        ];
+        for (line, expected) in table {
+            assert_eq!(class(line), expected, "{}", line);
+        }
+    }
+
+    #[test]
+    fn paths_are_unknown() {
+        let rules = rules(&["ls *"], &[]);
+        for program in ["./evil/ls", "/tmp/cat", "bin\\ls.exe", "../git"] {
+            let argv = vec![program.to_owned(), "-la".to_owned()];
+            let assessment = assess(&argv, AutonomyLevel::FullAgent, &rules, &site());
+            assert_eq!(assessment.class, CommandClass::Unknown, "{}", program);
+        }
+        // An allow rule still names it by its bare name
+        let assessment = assess(&argv("./evil/ls -la"), AutonomyLevel::CmdK, &rules, &site());
+        assert_eq!(assessment.verdict, Verdict::Approve);
+
+        let unresolved = Site {
+            resolved: false,
+            ..site()
+        };
+        let assessment = assess(
+            &argv("ls -la"),
+            AutonomyLevel::FullAgent,
+            &self::rules(&[], &[]),
+            &unresolved,
+        );
+        assert_eq!(assessment.class, CommandClass::Unknown);
+        assert_eq!(assessment.verdict, Verdict::Prompt);
+    }
+
+    #[test]
+    fn wrapped_commands() {
+        use CommandClass::*;
+        let table = [
+            ("pnpm exec rm -rf .", Destructive),
+            ("npm exec --no -- rm -rf .", Destructive),
+            ("npm exec --no -- vitest --run", BuildTest),
+            ("npm exec vitest", Network),
+            ("npx jest", Network),
+            ("npx -c rm", Unknown),
+            ("npx ./evil", Unknown),
+            ("yarn dlx tsc", Network),
+            ("yarn dlx cowsay", Unknown),
+            ("yarn exec git status", BuildTest),
+            ("yarn rm -rf .", Unknown),
+            ("yarn tsc --noEmit", BuildTest),
+            ("yarn curl example.com", Network),
+            ("yarn run shred secrets", Destructive),
+            ("pnpm git push --force", Destructive),
+            ("yarn terraform apply", Unknown),
+        ];
+        for (line, expected) in table {
+            assert_eq!(class(line), expected, "{}", line);
+        }
+    }
+
+    #[test]
+    fn flags_that_write_or_run() {
+        use CommandClass::*;
+        let table = [
+            ("rg --pre ./evil TODO", Unknown),
+            ("rg --pre=./evil TODO", Unknown),
+            ("sort -o out.txt in.txt", Unknown),
+            ("sort --output=out.txt in.txt", Unknown),
+            ("git diff --output=patch", Unknown),
+            ("git diff --ext-diff", Unknown),
+            ("git grep -O TODO", Unknown),
+            ("git grep --open-files-in-pager=vim TODO", Unknown),
+            ("find . -fprint list", Destructive),
+            ("find . -fls list", Destructive),
+            ("find . -exec rm {} ;", Destructive),
+            ("find . -delete", Destructive),
+            ("date -s 10:00", Unknown),
+            ("hostname evil", Unknown),
+            ("sort in.txt", ReadOnly),
+            ("git grep TODO", ReadOnly),
+            ("find . -name x", ReadOnly),
+        ];
+        for (line, expected) in table {
+            assert_eq!(class(line), expected, "{}", line);
+        }
+    }
+
+    #[test]
+    fn global_options_are_skipped() {
+        use CommandClass::*;
+        assert_eq!(class("git -C ../other push --force"), Destructive);
+        assert_eq!(class("git --no-pager push -f"), Destructive);
+        assert_eq!(class("git -c core.pager=evil log"), Unknown);
+        assert_eq!(class("git --git-dir=../other/.git status"), Unknown);
+        assert_eq!(class("git --no-pager log"), ReadOnly);
+        assert_eq!(class("cargo +nightly test"), BuildTest);
+        assert_eq!(class("cargo -Z unstable-options build"), BuildTest);
+        assert_eq!(class("cargo +nightly install ripgrep"), Network);
+
+        let rules = rules(&["git *"], &["git push --force*"]);
+        let assessment = assess(
+            &argv("git -C ../other push --force origin"),
+            AutonomyLevel::FullAgent,
+            &rules,
+            &site(),
+        );
+        assert_eq!(assessment.verdict, Verdict::Block);
+        assert_eq!(assessment.rule.as_deref(), Some("git push --force*"));
+
+        let rules = self::rules(&[], &["rm *"]);
+        assert_eq!(
+            verdict("pnpm exec rm -rf .", AutonomyLevel::FullAgent, &rules),
+            Verdict::Block
+        );
+    }
+
+    #[test]
+    fn allow_rules_need_every_layer() {
+        let rules = rules(&["npx *"], &[]);
+        assert_eq!(
+            verdict("npx cowsay hi", AutonomyLevel::CmdK, &rules),
+            Verdict::Prompt
+        );
+        let rules = self::rules(&["npx *", "cowsay *"], &[]);
+        assert_eq!(
+            verdict("npx cowsay hi", AutonomyLevel::CmdK, &rules),
+            Verdict::Approve
+        );
+    }
+
+    #[test]
+    fn exec_hook_variables() {
+        let rules = rules(&["cargo *"], &[]);
+        let set = Site {
+            env: vec!["LD_PRELOAD".to_owned()],
+            ..site()
+        };
+        for line in ["ls", "cargo test"] {
+            let assessment = assess(&argv(line), AutonomyLevel::FullAgent, &rules, &set);
+            assert_eq!(assessment.verdict, Verdict::Block, "{}", line);
+            assert!(assessment.reason.contains("LD_PRELOAD"));
+        }
+        for name in [
+            "PATH",
+            "git_external_diff",
+            "GIT_PAGER",
+            "NODE_OPTIONS",
+            "DYLD_INSERT_LIBRARIES",
+        ] {
+            assert!(is_exec_hook(name), "{}", name);
+        }
+        assert!(!is_exec_hook("CI"));
+        assert!(!is_exec_hook("CARGO_HOME"));
+
+        let harmless = Site {
+            env: vec!["CI".to_owned()],
+            ..site()
+        };
+        assert_eq!(
+            assess(&argv("ls"), AutonomyLevel::CmdK, &rules, &harmless).verdict,
+            Verdict::Approve
+        );
+
+        let inherited = Site {
+            inherited: vec!["NODE_OPTIONS".to_owned()],
+            ..site()
+        };
+        for line in ["ls", "cargo test"] {
+            let assessment = assess(&argv(line), AutonomyLevel::FullAgent, &rules, &inherited);
+            assert_eq!(assessment.verdict, Verdict::Prompt, "{}", line);
+        }
+        assert_eq!(
+            assess(&argv("rm -rf ."), AutonomyLevel::CmdK, &rules, &inherited).verdict,
+            Verdict::Block
+        );
+    }
+
+    #[test]
+    fn levels_are_ordered() {
+        assert!(AutonomyLevel::Tab < AutonomyLevel::CmdK);
+        assert!(AutonomyLevel::CmdK < AutonomyLevel::FullAgent);
+    }
+
+    #[test]
+    fn which_takes_bare_names_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = Workspace::default();
+        workspace.open(dir.path()).unwrap();
+        assert_eq!(which("./ls", &workspace), None);
+        assert_eq!(which("/bin/sh", &workspace), None);
+        assert_eq!(which("definitely-not-a-program-here", &workspace), None);
+        #[cfg(unix)]
+        assert!(which("sh", &workspace).is_some_and(|path| path.is_absolute()));
+    }
+
+    #[test]
+    fn package_scripts_are_read() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{"scripts": {"test": "vitest", "build": "tsc"}}"#,
+        )
+        .unwrap();
+        let scripts = package_scripts(dir.path());
+        assert!(scripts.contains("test") && scripts.contains("build"));
+        assert!(package_scripts(&dir.path().join("missing")).is_empty());
+    }
+
+    #[test]
+    fn allow_rules_only_spare_the_prompt() {
+        let rules = rules(&["rm *", "curl *"], &[]);
+        assert_eq!(
+            verdict("curl example.com", AutonomyLevel::CmdK, &rules),
+            Verdict::Approve
+        );
+        assert_eq!(
+            verdict("rm -rf build", AutonomyLevel::CmdK, &rules),
+            Verdict::Block
+        );
+        assert_eq!(
+            verdict("curl example.com", AutonomyLevel::Tab, &rules),
+            Verdict::Block
+        );
+    }
+
+    #[test]
+    fn deny_rules_win() {
+        let rules = rules(&["git *"], &["git push *"]);
+        assert_eq!(
+            verdict("git push origin", AutonomyLevel::FullAgent, &rules),
+            Verdict::Block
+        );
+        assert_eq!(
+            verdict("git status", AutonomyLevel::FullAgent, &rules),
+            Verdict::Approve
+        );
+    }
+}
@@ -0,0 +1,358 @@
+//! Terminal sessions on a pseudo-terminal
+//!
+//! Each session runs a shell, or any program, on its own PTY, so colours,
+//! line editing and interactive programs work as in a real terminal. The
+//! caller picks the session id; output is emitted as `pty://output` events
+//! tagged with it as soon as it is read, and `pty://exit` follows once the
+//! program has ended and its output is drained.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use portable_pty::{ChildKiller, CommandBuilder, MasterPty, PtySize};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::workspace::{Workspace, WorkspaceError};
+
+/// Emitted with each piece of output
+pub const OUTPUT_EVENT: &str = "pty://output";
+
+/// Emitted once per session, after its last output
+pub const EXIT_EVENT: &str = "pty://exit";
+
+const READ_BUFFER: usize = 16 * 1024;
+
+#[derive(Debug, thiserror::Error, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum PtyError {
+    #[error("session {id} already exists")]
+    Duplicate { id: String },
+    #[error("no session {id}")]
+    NotFound { id: String },
+    #[error("cannot start {command}: {message}")]
+    Spawn { command: String, message: String },
+    #[error("terminal I/O failed: {message}")]
+    Io { message: String },
+    #[error(transparent)]
+    Sandbox { reason: WorkspaceError },
+}
+
+impl From<WorkspaceError> for PtyError {
+    fn from(reason: WorkspaceError) -> Self {
+        Self::Sandbox { reason }
+    }
+}
+
+impl PtyError {
+    fn io(err: impl std::fmt::Display) -> Self {
+        Self::Io {
+            message: err.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SpawnOptions {
+    /// Program to run; the user's login shell if unset
+    pub command: Option<String>,
+    pub args: Vec<String>,
+    /// Working directory inside an open root; the first root if unset
+    pub cwd: Option<PathBuf>,
+    /// Added to the app's environment
+    pub env: HashMap<String, String>,
+    pub cols: u16,
+    pub rows: u16,
+}
+
+impl Default for SpawnOptions {
+    fn default() -> Self {
+        Self {
+            command: None,
+            args: Vec::new(),
+            cwd: None,
+            env: HashMap::new(),
+            cols: 80,
+            rows: 24,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    pub session_id: String,
+    pub command: String,
+    pub cwd: PathBuf,
+    pub pid: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PtyOutput {
+    pub session_id: String,
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PtyExit {
+    pub session_id: String,
+    pub exit_code: u32,
+    /// The signal that ended the program, if one did
+    pub signal: Option<String>,
+}
+
+type Writer = Arc<Mutex<Box<dyn Write + Send>>>;
+
+struct Session {
+    /// Tells this session from a later one that reuses its id
+    generation: u64,
+    master: Box<dyn MasterPty + Send>,
+    /// Locked on its own, so a slow write holds up only this session
+    writer: Writer,
+    killer: Box<dyn ChildKiller + Send + Sync>,
+}
+
+/// Running sessions, managed as Tauri state
+#[derive(Default)]
+pub struct PtySessions {
+    sessions: Mutex<HashMap<String, Session>>,
+    generations: AtomicU64,
+}
+
+impl PtySessions {
+    fn with<T>(
+        &self,
+        id: &str,
+        f: impl FnOnce(&mut Session) -> Result<T, PtyError>,
+    ) -> Result<T, PtyError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions
+            .get_mut(id)
+            .ok_or_else(|| PtyError::NotFound { id: id.to_owned() })?;
+        f(session)
+    }
+
+    fn remove(&self, id: &str) -> Option<Session> {
+        self.sessions.lock().unwrap().remove(id)
+    }
+
+    /// Remove session `id` if it is still the one started as `generation`
+    fn remove_generation(&self, id: &str, generation: u64) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions
+            .get(id)
+            .is_some_and(|session| session.generation == generation)
+        {
+            sessions.remove(id);
+        }
+    }
+}
+
+/// Splits PTY output into strings without cutting characters apart
+#[derive(Default)]
+struct Utf8Decoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    fn decode(&mut self, bytes: &[u8]) -> String {
+        self.pending.extend_from_slice(bytes);
+        let complete = match std::str::from_utf8(&self.pending) {
+            Ok(_) => self.pending.len(),
+            // A character continues in the next read
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => self.pending.len(),
+        };
+        let rest = self.pending.split_off(complete);
+        let text = String::from_utf8_lossy(&self.pending).into_owned();
+        self.pending = rest;
+        text
+    }
+}
+
+/// Start a program on a new PTY as session `session_id`
+///
+/// Output arrives as `pty://output` events until `pty://exit`.
+#[tauri::command]
+pub fn pty_spawn(
+    app: AppHandle,
+    workspace: State<'_, Workspace>,
+    sessions: State<'_, PtySessions>,
+    session_id: String,
+    options: Option<SpawnOptions>,
+) -> Result<SessionInfo, PtyError> {
+    let options = options.unwrap_or_default();
+    let cwd = match &options.cwd {
+        Some(cwd) => {
+            let resolved = workspace.resolve(cwd)?;
+            if !resolved.is_dir() {
+                return Err(WorkspaceError::NotADirectory {
+                    path: cwd.display().to_string(),
+                }
+                .into());
+            }
+            resolved
+        }
+        // Never the app's own directory
+        None => workspace.pick_root(None)?.path,
+    };
+
+    let mut command = match &options.command {
+        Some(program) => {
+            let mut command = CommandBuilder::new(program);
+            command.args(&options.args);
+            command
+        }
+        None => CommandBuilder::new_default_prog(),
+    };
+    command.cwd(&cwd);
+    command.env("TERM", "xterm-256color");
+    command.env("COLORTERM", "truecolor");
+    for (key, value) in &options.env {
+        command.env(key, value);
+    }
+    let name = options
+        .command
+        .clone()
+        .unwrap_or_else(|| command.get_shell());
+
+    let mut running = sessions.sessions.lock().unwrap();
+    if running.contains_key(&session_id) {
+        return Err(PtyError::Duplicate { id: session_id });
+    }
+    let spawn_error = |e: &dyn std::fmt::Display| PtyError::Spawn {
+        command: name.clone(),
+        message: e.to_string(),
+    };
+    let pair = portable_pty::native_pty_system()
+        .openpty(PtySize {
+            rows: options.rows,
+            cols: options.cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        })
+        .map_err(|e| spawn_error(&e))?;
+    let mut child = pair
+        .slave
+        .spawn_command(command)
+        .map_err(|e| spawn_error(&e))?;
+    // Otherwise reading would never end
+    drop(pair.slave);
+    let mut reader = pair.master.try_clone_reader().map_err(PtyError::io)?;
+    let writer = pair.master.take_writer().map_err(PtyError::io)?;
+    let generation = sessions.generations.fetch_add(1, Ordering::Relaxed);
+    let info = SessionInfo {
+        session_id: session_id.clone(),
+        command: name,
+        cwd,
+        pid: child.process_id(),
+    };
+    running.insert(
+        session_id.clone(),
+        Session {
+            generation,
+            master: pair.master,
+            writer: Arc::new(Mutex::new(writer)),
+            killer: child.clone_killer(),
+        },
+    );
+    drop(running);
+
+    std::thread::spawn(move || {
+        let mut buffer = vec![0; READ_BUFFER];
+        let mut decoder = Utf8Decoder::default();
+        // Ends with an error rather than EOF on Linux once the program is
+        // gone
+        while let Ok(n @ 1..) = reader.read(&mut buffer) {
+            let data = decoder.decode(&buffer[..n]);
+            if !data.is_empty() {
+                let output = PtyOutput {
+                    session_id: session_id.clone(),
+                    data,
+                };
+                let _ = app.emit(OUTPUT_EVENT, output);
+            }
+        }
+        let status = child.wait();
+        app.state::<PtySessions>()
+            .remove_generation(&session_id, generation);
+        let exit = match status {
+            Ok(status) => PtyExit {
+                session_id,
+                exit_code: status.exit_code(),
+                signal: status.signal().map(str::to_owned),
+            },
+            Err(_) => PtyExit {
+                session_id,
+                exit_code: 1,
+                signal: None,
+            },
+        };
+        let _ = app.emit(EXIT_EVENT, exit);
+    });
+    Ok(info)
+}
+
+/// Type `data` into a session, as keystrokes or pasted text
+///
+/// Writing blocks while the program isn't reading, so it happens off the
+/// async runtime and without holding up other sessions.
+#[tauri::command]
+pub async fn pty_write(
+    sessions: State<'_, PtySessions>,
+    session_id: String,
+    data: String,
+) -> Result<(), PtyError> {
+    let writer = sessions.with(&session_id, |session| Ok(session.writer.clone()))?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut writer = writer.lock().unwrap();
+        writer
+            .write_all(data.as_bytes())
+            .and_then(|_| writer.flush())
+            .map_err(PtyError::io)
+    })
+    .await
+    .map_err(PtyError::io)?
+}
+
+/// Tell a session its terminal is now `cols` by `rows` characters
+#[tauri::command]
+pub fn pty_resize(
+    sessions: State<'_, PtySessions>,
+    session_id: String,
+    cols: u16,
+    rows: u16,
+) -> Result<(), PtyError> {
+    sessions.with(&session_id, |session| {
+        session
+            .master
+            .resize(PtySize {
+                rows,
+                cols,
+                pixel_width: 0,
+                pixel_height: 0,
+            })
+            .map_err(PtyError::io)
+    })
+}
+
+/// End a session; `false` if it already ended
+///
+/// Hangs up on the program and closes its terminal, which hangs up on
+/// whatever it started too. `pty://exit` follows as usual.
+#[tauri::command]
+pub fn pty_kill(sessions: State<'_, PtySessions>, session_id: String) -> bool {
+    match sessions.remove(&session_id) {
+        Some(mut session) => {
+            let _ = session.killer.kill();
+            true
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,761 @@
+//! Hybrid retrieval of code context for the agent
+//!
+//! Ranks the embedded chunks of the workspace by three signals: BM25 over
+//! their terms, cosine similarity of their vectors to the query, and how
+//! few imports separate their file from the one being edited. The best
+//! chunks are packed into a token budget, with the lines a better chunk
+//! already covers cut from the others, and each one is cited by file and
+//! line range.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::config::HenryConfig;
+use crate::embeddings::{self, EmbedError, Embeddings};
+use crate::graph::ImportGraphs;
+use crate::indexer::{IndexError, IndexState, Indexer};
+use crate::vectors::{self, Chunk, LiveChunks, VectorStore};
+use crate::workspace::{Workspace, WorkspaceError, WorkspaceRoot};
+
+/// Chunks each signal nominates per root
+const CANDIDATES: usize = 50;
+
+const KEYWORD_WEIGHT: f32 = 0.45;
+const VECTOR_WEIGHT: f32 = 0.4;
+const PROXIMITY_WEIGHT: f32 = 0.15;
+
+/// Times a term in the name of a chunk counts, so definitions rank above
+/// uses
+const SYMBOL_WEIGHT: u32 = 3;
+
+/// BM25 term frequency saturation and length normalization
+const BM25_K1: f32 = 1.2;
+const BM25_B: f32 = 0.75;
+
+/// Files further from the active file than this many imports get no
+/// proximity score
+const PROXIMITY_DEPTH: usize = 2;
+
+/// Chunks scoring below this share of the best one are left out
+const MIN_SCORE_RATIO: f32 = 0.25;
+
+/// Rough size of a token in bytes of code
+const BYTES_PER_TOKEN: usize = 4;
+
+/// Chunks cut down to fewer non-blank lines than this are dropped
+const MIN_REMAINING_LINES: usize = 3;
+
+#[derive(Debug, thiserror::Error, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum RetrievalError {
+    #[error(transparent)]
+    Index { reason: IndexError },
+    #[error(transparent)]
+    Embed { reason: EmbedError },
+    #[error(transparent)]
+    Sandbox { reason: WorkspaceError },
+}
+
+impl From<IndexError> for RetrievalError {
+    fn from(reason: IndexError) -> Self {
+        Self::Index { reason }
+    }
+}
+
+impl From<EmbedError> for RetrievalError {
+    fn from(reason: EmbedError) -> Self {
+        Self::Embed { reason }
+    }
+}
+
+impl From<WorkspaceError> for RetrievalError {
+    fn from(reason: WorkspaceError) -> Self {
+        Self::Sandbox { reason }
+    }
+}
+
+/// A chunk picked for the context
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextChunk {
+    pub path: PathBuf,
+    /// `path:start-end`, with the path relative to its root
+    pub citation: String,
+    pub symbol: Option<String>,
+    pub kind: Option<String>,
+    pub language: Option<String>,
+    /// 1-based, inclusive
+    pub start_line: u32,
+    pub end_line: u32,
+    pub text: String,
+    /// Blended score, from 0 to 1
+    pub score: f32,
+    /// BM25 score relative to the best candidate's
+    pub keyword: f32,
+    /// Cosine similarity relative to the best candidate's
+    pub vector: f32,
+    /// 1 in the active file, less the more imports away
+    pub proximity: f32,
+    /// Estimated tokens of the citation and text
+    pub tokens: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetrievedContext {
+    /// Best first
+    pub chunks: Vec<ContextChunk>,
+    pub tokens: usize,
+    pub budget_tokens: usize,
+    /// Relevant chunks that didn't fit the budget
+    pub omitted: usize,
+}
+
+/// BM25 postings over the chunks of one root
+struct KeywordIndex {
+    generation: u64,
+    /// Chunk ids and counts of each term
+    postings: HashMap<String, Vec<(u32, u32)>>,
+    /// Terms in each chunk
+    lengths: HashMap<u32, u32>,
+    average_length: f32,
+}
+
+impl KeywordIndex {
+    fn build(chunks: &LiveChunks) -> Self {
+        let mut postings: HashMap<String, Vec<(u32, u32)>> = HashMap::new();
+        let mut lengths = HashMap::new();
+        for (id, chunk) in chunks.iter() {
+            let mut counts: HashMap<String, u32> = HashMap::new();
+            let file_name = chunk.path.file_name().map(|n| n.to_string_lossy());
+            let weighted = [
+                (chunk.symbol.as_deref(), SYMBOL_WEIGHT),
+                (file_name.as_deref(), 1),
+                (Some(chunk.text.as_str()), 1),
+            ];
+            for (text, weight) in weighted {
+                for term in text.map(terms).unwrap_or_default() {
+                    *counts.entry(term).or_default() += weight;
+                }
+            }
+            lengths.insert(id, counts.values().sum());
+            for (term, count) in counts {
+                postings.entry(term).or_default().push((id, count));
+            }
+        }
+        let total: u64 = lengths.values().map(|&l| l as u64).sum();
+        Self {
+            generation: chunks.generation,
+            postings,
+            average_length: total as f32 / lengths.len().max(1) as f32,
+            lengths,
+        }
+    }
+
+    /// BM25 scores of the chunks holding any of `terms`
+    fn scores(&self, terms: &[String]) -> HashMap<u32, f32> {
+        let documents = self.lengths.len() as f32;
+        let mut scores: HashMap<u32, f32> = HashMap::new();
+        for term in terms {
+            let Some(postings) = self.postings.get(term) else {
+                continue;
+            };
+            let frequency = postings.len() as f32;
+            let idf = (1.0 + (documents - frequency + 0.5) / (frequency + 0.5)).ln();
+            for &(id, count) in postings {
+                let count = count as f32;
+                let length = self.lengths[&id] as f32 / self.average_length.max(1.0);
+                let saturation = BM25_K1 * (1.0 - BM25_B + BM25_B * length);
+                *scores.entry(id).or_default() +=
+                    idf * count * (BM25_K1 + 1.0) / (count + saturation);
+            }
+        }
+        scores
+    }
+}
+
+/// Terms of `text` for keyword search: identifier parts, and identifiers
+/// made of several parts as a whole
+fn terms(text: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    let words = text
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| !word.is_empty());
+    for word in words {
+        let parts = embeddings::split_identifier(word);
+        if parts.len() > 1 {
+            terms.push(parts.concat());
+        }
+        terms.extend(
+            parts
+                .into_iter()
+                .filter(|part| part.len() > 1 && !part.chars().all(|c| c.is_ascii_digit())),
+        );
+    }
+    terms
+}
+
+/// Keyword indexes per workspace root, managed as Tauri state
+#[derive(Default)]
+pub struct KeywordIndexes {
+    indexes: Mutex<HashMap<PathBuf, Arc<KeywordIndex>>>,
+}
+
+impl KeywordIndexes {
+    /// The keyword index over `chunks` of `root`, rebuilt if the chunks
+    /// changed since it was cached
+    fn index(&self, root: &Path, chunks: &LiveChunks) -> Arc<KeywordIndex> {
+        let cached = self.indexes.lock().unwrap().get(root).cloned();
+        match cached {
+            Some(index) if index.generation == chunks.generation => index,
+            _ => {
+                let index = Arc::new(KeywordIndex::build(chunks));
+                self.indexes
+                    .lock()
+                    .unwrap()
+                    .insert(root.to_path_buf(), Arc::clone(&index));
+                index
+            }
+        }
+    }
+}
+
+/// A chunk nominated by either signal, with raw scores
+struct Candidate {
+    root: PathBuf,
+    /// Path relative to `root`
+    chunk: Chunk,
+    keyword: f32,
+    vector: f32,
+    proximity: f32,
+    score: f32,
+}
+
+/// The chunks of `root` that best match `query` by keywords or by `vector`,
+/// a unit vector of the model the chunks were embedded with
+fn nominate(
+    keywords: &KeywordIndexes,
+    root: &Path,
+    chunks: &LiveChunks,
+    query: &str,
+    vector: Option<&[f32]>,
+) -> Vec<Candidate> {
+    let mut query_terms = terms(query);
+    query_terms.sort();
+    query_terms.dedup();
+    let bm25 = keywords.index(root, chunks).scores(&query_terms);
+    let mut ranked: Vec<(u32, f32)> = bm25.iter().map(|(&id, &score)| (id, score)).collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let mut ids: Vec<u32> = ranked.iter().take(CANDIDATES).map(|&(id, _)| id).collect();
+    if let Some(vector) = vector {
+        ids.extend(
+            chunks
+                .nearest(vector, CANDIDATES)
+                .into_iter()
+                .map(|(id, _)| id),
+        );
+    }
+    let mut seen = HashSet::new();
+    ids.into_iter()
+        .filter(|id| seen.insert(*id))
+        .filter_map(|id| {
+            Some(Candidate {
+                root: root.to_path_buf(),
+                chunk: chunks.get(id)?.clone(),
+                keyword: bm25.get(&id).copied().unwrap_or(0.0),
+                vector: vector
+                    .and_then(|vector| chunks.similarity(id, vector))
+                    .unwrap_or(0.0)
+                    .max(0.0),
+                proximity: 0.0,
+                score: 0.0,
+            })
+        })
+        .collect()
+}
+
+/// Blend the signals of `candidates`, each scaled to the best candidate's,
+/// and sort them best first
+fn rank(candidates: &mut [Candidate]) {
+    let best = |signal: fn(&Candidate) -> f32| {
+        candidates
+            .iter()
+            .map(signal)
+            .fold(0.0f32, f32::max)
+            .max(f32::EPSILON)
+    };
+    let keyword = best(|c| c.keyword);
+    let vector = best(|c| c.vector);
+    for candidate in candidates.iter_mut() {
+        candidate.keyword /= keyword;
+        candidate.vector /= vector;
+        candidate.score = KEYWORD_WEIGHT * candidate.keyword
+            + VECTOR_WEIGHT * candidate.vector
+            + PROXIMITY_WEIGHT * candidate.proximity;
+    }
+    candidates.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.chunk.path.cmp(&b.chunk.path))
+            .then(a.chunk.start_line.cmp(&b.chunk.start_line))
+    });
+}
+
+fn estimate_tokens(text: &str) -> usize {
+    text.len().div_ceil(BYTES_PER_TOKEN)
+}
+
+/// Lines `start..=end` of `chunk`, if its text has a line per line of its
+/// range
+fn slice_lines(chunk: &Chunk, start: u32, end: u32) -> Option<String> {
+    let lines: Vec<&str> = chunk.text.lines().collect();
+    if lines.len() != (chunk.end_line + 1 - chunk.start_line) as usize {
+        return None;
+    }
+    let from = (start - chunk.start_line) as usize;
+    let to = (end - chunk.start_line) as usize;
+    Some(lines[from..=to].join("\n"))
+}
+
+/// The part of `chunk` not already covered by `taken` line ranges of its
+/// file, or `None` if too little is left
+fn uncovered(chunk: &Chunk, taken: &[(u32, u32)]) -> Option<Chunk> {
+    let (mut start, mut end) = (chunk.start_line, chunk.end_line);
+    for &(from, to) in taken {
+        if from <= start && start <= to {
+            start = to + 1;
+        }
+        if from <= end && end <= to {
+            end = from.saturating_sub(1);
+        }
+        if start > end || (start < from && to < end) {
+            // Covered, or split around a better chunk
+            return None;
+        }
+    }
+    if (start, end) == (chunk.start_line, chunk.end_line) {
+        return Some(chunk.clone());
+    }
+    let text = slice_lines(chunk, start, end)?;
+    if text.lines().filter(|l| !l.trim().is_empty()).count() < MIN_REMAINING_LINES {
+        return None;
+    }
+    Some(Chunk {
+        start_line: start,
+        end_line: end,
+        text,
+        ..chunk.clone()
+    })
+}
+
+/// Cut `chunk` down to the lines that fit `budget` tokens
+fn truncate(chunk: &Chunk, citation: &str, budget: usize) -> Option<Chunk> {
+    let mut tokens = estimate_tokens(citation);
+    let mut end = None;
+    for (i, line) in chunk.text.lines().enumerate() {
+        tokens += estimate_tokens(line) + 1;
+        if tokens > budget {
+            break;
+        }
+        end = Some(i as u32);
+    }
+    let end = chunk.start_line + end?;
+    Some(Chunk {
+        end_line: end,
+        text: slice_lines(chunk, chunk.start_line, end)?,
+        ..chunk.clone()
+    })
+}
+
+/// Pack the best of `ranked` into `budget` tokens, skipping repeated code
+fn pack(ranked: Vec<Candidate>, budget: usize) -> RetrievedContext {
+    let cutoff = ranked.first().map_or(0.0, |c| c.score * MIN_SCORE_RATIO);
+    let mut context = RetrievedContext {
+        chunks: Vec::new(),
+        tokens: 0,
+        budget_tokens: budget,
+        omitted: 0,
+    };
+    let mut taken: HashMap<(PathBuf, PathBuf), Vec<(u32, u32)>> = HashMap::new();
+    let mut hashes = HashSet::new();
+    for candidate in ranked.into_iter().filter(|c| c.score >= cutoff) {
+        let file = (candidate.root.clone(), candidate.chunk.path.clone());
+        let ranges = taken.get(&file).map_or(&[][..], Vec::as_slice);
+        let Some(mut chunk) = uncovered(&candidate.chunk, ranges) else {
+            continue;
+        };
+        if !hashes.insert(candidate.chunk.hash.clone()) {
+            // The same code in another file
+            continue;
+        }
+        let citation = |chunk: &Chunk| {
+            format!(
+                "{}:{}-{}",
+                chunk.path.display(),
+                chunk.start_line,
+                chunk.end_line
+            )
+        };
+        let mut tokens = estimate_tokens(&citation(&chunk)) + estimate_tokens(&chunk.text);
+        if context.tokens + tokens > budget {
+            // Cut the best chunk down rather than return nothing
+            let cut = context
+                .chunks
+                .is_empty()
+                .then(|| truncate(&chunk, &citation(&chunk), budget))
+                .flatten();
+            let Some(cut) = cut else {
+                context.omitted += 1;
+                continue;
+            };
+            chunk = cut;
+            tokens = estimate_tokens(&citation(&chunk)) + estimate_tokens(&chunk.text);
+        }
+        taken
+            .entry(file)
+            .or_default()
+            .push((chunk.start_line, chunk.end_line));
+        context.tokens += tokens;
+        context.chunks.push(ContextChunk {
+            path: candidate.root.join(&chunk.path),
+            citation: citation(&chunk),
+            symbol: chunk.symbol,
+            kind: chunk.kind,
+            language: chunk.language,
+            start_line: chunk.start_line,
+            end_line: chunk.end_line,
+            text: chunk.text,
+            score: candidate.score,
+            keyword: candidate.keyword,
+            vector: candidate.vector,
+            proximity: candidate.proximity,
+            tokens,
+        });
+    }
+    context
+}
+
+/// Index and embed what changed in `root`, then embed `query` with the
+/// same model; `None` if the query has no vector
+fn prepare(
+    app: &AppHandle,
+    root: &WorkspaceRoot,
+    query: &str,
+) -> Result<Option<(String, Vec<f32>)>, RetrievalError> {
+    let indexer = app.state::<Indexer>();
+    if indexer.status(&root.path).state == IndexState::Missing {
+        indexer.build(root, false, |_| {})?;
+    }
+    let embedder = embeddings::from_config(&HenryConfig::load(&root.path).embeddings)?;
+    let synced = app.state::<Embeddings>().sync(
+        &indexer,
+        &app.state::<VectorStore>(),
+        &root.path,
+        embedder.as_ref(),
+        None,
+        |status| {
+            let _ = app.emit(embeddings::PROGRESS_EVENT, status);
+        },
+    );
+    match synced {
+        // Another run is embedding it; use what is stored so far
+        Ok(_) | Err(EmbedError::Busy { .. }) => {}
+        Err(e) => return Err(e.into()),
+    }
+    let mut vector = embedder
+        .embed(&[query.to_owned()])?
+        .pop()
+        .unwrap_or_default();
+    Ok(vectors::normalize(&mut vector)
+        .ok()
+        .map(|_| (embedder.id(), vector)))
+}
+
+/// Code context for `query`, packed into `budget_tokens`
+///
+/// Chunks are ranked by keywords, embeddings and, given `active_file`, by
+/// import distance to it. Searches `root`, or every open root, indexing
+/// and embedding whatever changed first.
+#[tauri::command]
+pub async fn retrieve_context(
+    app: AppHandle,
+    workspace: State<'_, Workspace>,
+    query: String,
+    budget_tokens: usize,
+    active_file: Option<String>,
+    root: Option<PathBuf>,
+) -> Result<RetrievedContext, RetrievalError> {
+    let roots = match root {
+        Some(root) => vec![workspace.pick_root(Some(&root))?],
+        None => workspace.roots(),
+    };
+    let active = active_file
+        .map(|path| workspace.locate(Path::new(&path)))
+        .transpose()?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let store = app.state::<VectorStore>();
+        let keywords = app.state::<KeywordIndexes>();
+        let mut candidates = Vec::new();
+        for root in &roots {
+            let vector = prepare(&app, root, &query)?;
+            candidates.extend(store.with_chunks(&root.path, |chunks| {
+                let vector = vector
+                    .as_ref()
+                    .filter(|(model, _)| chunks.model() == Some(model.as_str()))
+                    .map(|(_, vector)| vector.as_slice());
+                nominate(&keywords, &root.path, chunks, &query, vector)
+            }));
+        }
+
+        let active =
+            active.filter(|(active_root, _)| roots.iter().any(|r| r.path == active_root.path));
+        if let Some((active_root, relative)) = &active {
+            let distances = app.state::<ImportGraphs>().distances(
+                &app.state::<Indexer>(),
+                &active_root.path,
+                relative,
+                PROXIMITY_DEPTH,
+            )?;
+            for candidate in &mut candidates {
+                if candidate.root == active_root.path {
+                    if let Some(&distance) = distances.get(&candidate.chunk.path) {
+                        candidate.proximity = 1.0 / (1 + distance) as f32;
+                    }
+                }
+            }
+        }
+        rank(&mut candidates);
+        Ok(pack(candidates, budget_tokens))
+    })
+    .await
+    .map_err(|e| IndexError::Storage {
+        message: e.to_string(),
+    })?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_edit;
+    use crate::vectors::NewChunk;
+
+    fn chunk(path: &str, start_line: u32, lines: &[&str]) -> Chunk {
+        let text = lines.join("\n");
+        Chunk {
+            path: PathBuf::from(path),
+            symbol: None,
+            kind: None,
+            language: None,
+            start_line,
+            end_line: start_line + lines.len() as u32 - 1,
+            hash: fs_edit::content_hash(text.as_bytes()),
+            text,
+        }
+    }
+
+    fn candidate(chunk: Chunk, score: f32) -> Candidate {
+        Candidate {
+            root: PathBuf::from("/project"),
+            chunk,
+            keyword: score,
+            vector: 0.0,
+            proximity: 0.0,
+            score,
+        }
+    }
+
+    /// Lines `line 1` to `line {count}`
+    fn numbered(count: u32) -> Vec<String> {
+        (1..=count).map(|i| format!("line {i}")).collect()
+    }
+
+    fn lines(numbered: &[String]) -> Vec<&str> {
+        numbered.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn terms_split_identifiers() {
+        let found = terms("parseConfig(x, 42) read_file");
+        for term in ["parseconfig", "parse", "config", "readfile", "read", "file"] {
+            assert!(
+                found.iter().any(|t| t == term),
+                "{term} missing from {found:?}"
+            );
+        }
+        // Too short or just digits
+        assert!(!found.iter().any(|t| t == "x" || t == "42"));
+    }
+
+    #[test]
+    fn keyword_scores_follow_bm25() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = VectorStore::open(dir.path().join("vectors")).unwrap();
+        let root = Path::new("/project");
+        let new = |symbol: Option<&str>, text: &str| NewChunk {
+            symbol: symbol.map(str::to_owned),
+            kind: None,
+            language: None,
+            start_line: 1,
+            end_line: None,
+            text: text.to_owned(),
+            vector: vec![1.0, 0.0],
+        };
+        let files = [
+            (
+                "config.rs",
+                new(Some("load_settings"), "fn load_settings() { read(path) }"),
+            ),
+            (
+                "main.rs",
+                new(
+                    None,
+                    "fn main() { let loaded = load_settings(); \
+                     let server = start_server(loaded, port); server.run(worker_count) }",
+                ),
+            ),
+            ("util.rs", new(None, "fn read(path: &str) { open(path) }")),
+            ("other.rs", new(None, "fn unrelated() { nothing_here() }")),
+        ];
+        for (path, chunk) in files {
+            store
+                .upsert(root, Path::new(path), "model", None, vec![chunk])
+                .unwrap();
+        }
+        let scores = store.with_chunks(root, |chunks| {
+            let index = KeywordIndex::build(chunks);
+            let by_path = |scores: HashMap<u32, f32>| -> HashMap<String, f32> {
+                scores
+                    .into_iter()
+                    .map(|(id, score)| {
+                        let path = chunks.get(id).unwrap().path.display().to_string();
+                        (path, score)
+                    })
+                    .collect()
+            };
+            (
+                by_path(index.scores(&["settings".to_owned()])),
+                by_path(index.scores(&["read".to_owned(), "unrelated".to_owned()])),
+            )
+        });
+
+        let (settings, read) = scores;
+        // The definition outranks a use in a chunk as long, and files
+        // without the term score nothing
+        assert!(settings["config.rs"] > settings["main.rs"]);
+        assert!(!settings.contains_key("util.rs"));
+        // A term found in one chunk weighs more than one found in two
+        assert!(read["other.rs"] > read["util.rs"]);
+        assert_eq!(read.len(), 3);
+    }
+
+    #[test]
+    fn rank_scales_each_signal_to_the_best() {
+        let mut candidates = vec![
+            candidate(chunk("b.rs", 1, &["b"]), 2.0),
+            Candidate {
+                vector: 0.5,
+                ..candidate(chunk("a.rs", 1, &["a"]), 4.0)
+            },
+            Candidate {
+                proximity: 1.0,
+                ..candidate(chunk("c.rs", 1, &["c"]), 0.0)
+            },
+        ];
+        rank(&mut candidates);
+        let order: Vec<_> = candidates.iter().map(|c| c.chunk.path.clone()).collect();
+        assert_eq!(
+            order,
+            [Path::new("a.rs"), Path::new("b.rs"), Path::new("c.rs")]
+        );
+        assert_eq!(candidates[0].keyword, 1.0);
+        assert_eq!(candidates[0].vector, 1.0);
+        assert_eq!(candidates[0].score, KEYWORD_WEIGHT + VECTOR_WEIGHT);
+        assert_eq!(candidates[1].keyword, 0.5);
+        assert_eq!(candidates[2].score, PROXIMITY_WEIGHT);
+    }
+
+    #[test]
+    fn uncovered_trims_lines_a_better_chunk_has() {
+        let text = numbered(10);
+        let chunk = chunk("a.rs", 11, &lines(&text));
+        assert_eq!(uncovered(&chunk, &[]).unwrap().end_line, 20);
+        assert_eq!(uncovered(&chunk, &[(1, 5)]).unwrap().start_line, 11);
+
+        let tail = uncovered(&chunk, &[(5, 13)]).unwrap();
+        assert_eq!((tail.start_line, tail.end_line), (14, 20));
+        assert!(tail.text.starts_with("line 4\n"));
+        let head = uncovered(&chunk, &[(18, 30)]).unwrap();
+        assert_eq!((head.start_line, head.end_line), (11, 17));
+
+        // Covered, split around another chunk, or too little left
+        assert!(uncovered(&chunk, &[(11, 20)]).is_none());
+        assert!(uncovered(&chunk, &[(14, 15)]).is_none());
+        assert!(uncovered(&chunk, &[(11, 18)]).is_none());
+    }
+
+    #[test]
+    fn truncate_keeps_the_lines_that_fit() {
+        let text = numbered(10);
+        let chunk = chunk("a.rs", 1, &lines(&text));
+        // `a.rs:1-10` is 3 tokens, each line 2 and its break 1
+        let cut = truncate(&chunk, "a.rs:1-10", 3 + 3 * 4).unwrap();
+        assert_eq!((cut.start_line, cut.end_line), (1, 4));
+        assert_eq!(cut.text, "line 1\nline 2\nline 3\nline 4");
+        assert!(truncate(&chunk, "a.rs:1-10", 4).is_none());
+    }
+
+    #[test]
+    fn pack_skips_overlaps_and_repeats() {
+        let text = numbered(20);
+        let best = chunk("a.rs", 1, &lines(&text[..10]));
+        let overlapping = chunk("a.rs", 6, &lines(&text[5..20]));
+        let copy = Chunk {
+            path: PathBuf::from("b.rs"),
+            ..best.clone()
+        };
+        let weak = chunk("c.rs", 1, &["fn weak() {}"]);
+        let ranked = vec![
+            candidate(best, 1.0),
+            candidate(overlapping, 0.9),
+            candidate(copy, 0.8),
+            candidate(weak, 0.1),
+        ];
+        let context = pack(ranked, 10_000);
+
+        let citations: Vec<_> = context.chunks.iter().map(|c| c.citation.as_str()).collect();
+        assert_eq!(citations, ["a.rs:1-10", "a.rs:11-20"]);
+        assert!(context.chunks[1].text.starts_with("line 11\n"));
+        assert_eq!(context.omitted, 0);
+        assert_eq!(
+            context.tokens,
+            context.chunks.iter().map(|c| c.tokens).sum::<usize>()
+        );
+        assert_eq!(context.chunks[0].path, Path::new("/project/a.rs"));
+    }
+
+    #[test]
+    fn pack_cuts_the_best_chunk_to_the_budget() {
+        let text = numbered(10);
+        let first = chunk("a.rs", 1, &lines(&text));
+        let second = chunk("b.rs", 1, &["fn other() {}"]);
+        let context = pack(
+            vec![candidate(first.clone(), 1.0), candidate(second, 0.9)],
+            12,
+        );
+        assert_eq!(context.chunks.len(), 1);
+        assert_eq!(context.chunks[0].citation, "a.rs:1-3");
+        assert!(context.tokens <= 12);
+        assert_eq!(context.omitted, 1);
+
+        // Not even the citation fits
+        let context = pack(vec![candidate(first, 1.0)], 2);
+        assert!(context.chunks.is_empty());
+        assert_eq!(context.tokens, 0);
+        assert_eq!(context.omitted, 1);
+    }
+}
@@ -0,0 +1,628 @@
+//! Routes chats across providers
+//!
+//! The order comes from `.henryrc`: `ai.preferredModel`, then
+//! `ai.fallbackModel`. A provider that fails before it starts answering is
+//! retried with exponential backoff, then the next one is tried; once
+//! output has reached the caller there is no going back, so a failure
+//! after that, or an answer that goes quiet for too long, ends the
+//! request. Providers that keep failing are skipped for a while (a circuit
+//! breaker), and every answer says which provider served it.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tauri::ipc::Channel;
+use tauri::State;
+
+use crate::config::{AiConfig, HenryConfig, ModelChoice};
+use crate::llm::{
+    ChatMessage, ChatRequest, Completion, GenerateOptions, Generations, LlmError, ProviderConfig,
+    Stream, StreamEvent, ToolSpec,
+};
+use crate::workspace::{Workspace, WorkspaceError};
+
+/// Attempts per provider, the first included
+const MAX_ATTEMPTS: u32 = 3;
+
+const BACKOFF_BASE: Duration = Duration::from_millis(500);
+
+/// Longest wait before a retry; a provider asking for more is given up
+/// for the next one
+const BACKOFF_MAX: Duration = Duration::from_secs(8);
+
+/// Failed attempts in a row that open a provider's circuit
+const FAILURE_THRESHOLD: u32 = 3;
+
+/// How long an open circuit skips its provider
+const COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Loading a local model into memory takes a while
+const LOCAL_TIMEOUT: Duration = Duration::from_secs(120);
+
+const CLOUD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest silence between pieces of an answer once it has started
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, thiserror::Error, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum RouteError {
+    #[error(transparent)]
+    Workspace { reason: WorkspaceError },
+    #[error(transparent)]
+    Llm { reason: LlmError },
+    #[error("{provider} failed while answering: {reason}")]
+    Interrupted { provider: String, reason: LlmError },
+    #[error("no provider could answer")]
+    Exhausted { attempts: Vec<Attempt> },
+}
+
+impl From<WorkspaceError> for RouteError {
+    fn from(reason: WorkspaceError) -> Self {
+        Self::Workspace { reason }
+    }
+}
+
+impl From<LlmError> for RouteError {
+    fn from(reason: LlmError) -> Self {
+        Self::Llm { reason }
+    }
+}
+
+/// A chat for whichever model serves it
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RouteRequest {
+    pub messages: Vec<ChatMessage>,
+    #[serde(default)]
+    pub tools: Vec<ToolSpec>,
+    #[serde(default)]
+    pub options: GenerateOptions,
+}
+
+/// One entry of the policy
+#[derive(Debug, Clone)]
+pub struct Route {
+    pub choice: ModelChoice,
+    pub provider: ProviderConfig,
+    pub model: String,
+    /// How long to wait for the provider to start answering
+    pub timeout: Duration,
+}
+
+impl Route {
+    /// The routes `config` asks for, in order
+    pub fn policy(config: &AiConfig) -> Vec<Route> {
+        let mut choices = vec![config.preferred_model];
+        choices.extend(config.fallback_model);
+        choices.dedup();
+        choices
+            .into_iter()
+            .map(|choice| {
+                let settings = config.providers.get(choice);
+                let url = settings.url.clone();
+                let (provider, model, timeout) = match choice {
+                    ModelChoice::Local => (
+                        ProviderConfig::Ollama { url },
+                        std::env::var("OLLAMA_MODEL").unwrap_or_else(|_| "phi3:mini".to_owned()),
+                        LOCAL_TIMEOUT,
+                    ),
+                    ModelChoice::Openai => (
+                        ProviderConfig::OpenAi {
+                            base_url: url,
+                            api_key: None,
+                        },
+                        "gpt-4".to_owned(),
+                        CLOUD_TIMEOUT,
+                    ),
+                    ModelChoice::Claude => (
+                        ProviderConfig::Anthropic {
+                            base_url: url,
+                            api_key: None,
+                        },
+                        "claude-3-5-sonnet-latest".to_owned(),
+                        CLOUD_TIMEOUT,
+                    ),
+                };
+                Route {
+                    choice,
+                    provider,
+                    model: settings.model.clone().unwrap_or(model),
+                    timeout: settings.timeout_secs.map_or(timeout, Duration::from_secs),
+                }
+            })
+            .collect()
+    }
+}
+
+/// What became of one try of a route
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Attempt {
+    pub choice: ModelChoice,
+    /// `None` if no client could be made
+    pub provider: Option<String>,
+    pub model: String,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "outcome", rename_all = "camelCase")]
+pub enum Outcome {
+    Served,
+    Failed {
+        error: LlmError,
+    },
+    /// Its circuit was open
+    Skipped,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoutedCompletion {
+    #[serde(flatten)]
+    pub completion: Completion,
+    /// The provider that served it
+    pub provider: String,
+    pub choice: ModelChoice,
+    /// Every try, the serving one last
+    pub attempts: Vec<Attempt>,
+}
+
+/// Recent failures of one provider
+#[derive(Debug, Clone, Default)]
+struct Circuit {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CircuitStatus {
+    pub provider: String,
+    /// Failed attempts in a row
+    pub failures: u32,
+    /// How long the provider is still skipped; `None` if it is not
+    pub retry_in_ms: Option<u64>,
+}
+
+/// Circuits of the providers used so far, managed as Tauri state
+#[derive(Default)]
+pub struct Router {
+    circuits: Mutex<HashMap<String, Circuit>>,
+}
+
+impl Router {
+    /// Stream the next message of `request` as generation `id` from the
+    /// first of `routes` that answers
+    pub async fn chat(
+        &self,
+        generations: &Generations,
+        id: &str,
+        routes: &[Route],
+        request: &RouteRequest,
+        channel: Option<&Channel<StreamEvent>>,
+    ) -> Result<RoutedCompletion, RouteError> {
+        let mut completion = Completion::new(id, "");
+        let mut attempts = Vec::new();
+        // The route and provider tried last, and how it ended if it did
+        let mut current: Option<(&Route, String)> = None;
+        let mut result = None;
+        let cancelled = generations
+            .run(id, async {
+                for route in routes {
+                    let attempt = |provider: Option<String>, outcome, started: Instant| Attempt {
+                        choice: route.choice,
+                        provider,
+                        model: route.model.clone(),
+                        outcome,
+                        elapsed_ms: started.elapsed().as_millis() as u64,
+                    };
+                    let provider = match route.provider.build() {
+                        Ok(provider) => provider,
+                        Err(error) => {
+                            let outcome = Outcome::Failed { error };
+                            attempts.push(attempt(None, outcome, Instant::now()));
+                            continue;
+                        }
+                    };
+                    let key = provider.id();
+                    if !self.allows(&key) {
+                        attempts.push(attempt(Some(key), Outcome::Skipped, Instant::now()));
+                        continue;
+                    }
+                    current = Some((route, key.clone()));
+                    let request = ChatRequest {
+                        model: route.model.clone(),
+                        messages: request.messages.clone(),
+                        tools: request.tools.clone(),
+                        options: request.options.clone(),
+                    };
+
+                    for tries in 1..=MAX_ATTEMPTS {
+                        let started = Instant::now();
+                        completion = Completion::new(id, &route.model);
+                        let output = AtomicUsize::new(0);
+                        let mut stream = Stream::new(&mut completion, channel).watched(&output);
+                        let answer = provider.chat(&request, &mut stream);
+                        let answered = watch(answer, &output, route.timeout, IDLE_TIMEOUT).await;
+                        let error = match answered {
+                            Ok(()) => {
+                                self.succeeded(&key);
+                                attempts.push(attempt(Some(key), Outcome::Served, started));
+                                result = Some(Ok(()));
+                                return Ok(());
+                            }
+                            Err(error) => error,
+                        };
+                        self.failed(&key, &error);
+                        if output.load(Ordering::Relaxed) > 0 {
+                            result = Some(Err(RouteError::Interrupted {
+                                provider: key,
+                                reason: error,
+                            }));
+                            return Ok(());
+                        }
+                        let delay = backoff(&error, tries);
+                        let outcome = Outcome::Failed { error };
+                        attempts.push(attempt(Some(key.clone()), outcome, started));
+                        match delay {
+                            Some(delay) if tries < MAX_ATTEMPTS && self.allows(&key) => {
+                                tokio::time::sleep(delay).await
+                            }
+                            _ => break,
+                        }
+                    }
+                }
+                Ok(())
+            })
+            .await?;
+
+        match result {
+            _ if cancelled => completion.cancelled = true,
+            Some(Ok(())) => {}
+            Some(Err(error)) => return Err(error),
+            None => return Err(RouteError::Exhausted { attempts }),
+        }
+        let Some((route, provider)) = current else {
+            return Err(RouteError::Exhausted { attempts });
+        };
+        generations.record(&provider, &completion);
+        Ok(RoutedCompletion {
+            completion,
+            provider,
+            choice: route.choice,
+            attempts,
+        })
+    }
+
+    /// Whether `provider` may be tried; after the cooldown an open circuit
+    /// lets a try through, and one more failure opens it again
+    fn allows(&self, provider: &str) -> bool {
+        let circuits = self.circuits.lock().unwrap();
+        circuits
+            .get(provider)
+            .and_then(|circuit| circuit.open_until)
+            .is_none_or(|until| Instant::now() >= until)
+    }
+
+    fn succeeded(&self, provider: &str) {
+        self.circuits.lock().unwrap().remove(provider);
+    }
+
+    fn failed(&self, provider: &str, error: &LlmError) {
+        // Says nothing about the provider's health
+        if matches!(
+            error,
+            LlmError::ContextOverflow { .. } | LlmError::Duplicate { .. }
+        ) {
+            return;
+        }
+        let mut circuits = self.circuits.lock().unwrap();
+        let circuit = circuits.entry(provider.to_owned()).or_default();
+        circuit.failures += 1;
+        if circuit.failures >= FAILURE_THRESHOLD {
+            circuit.open_until = Some(Instant::now() + COOLDOWN);
+        }
+    }
+
+    pub fn status(&self) -> Vec<CircuitStatus> {
+        let now = Instant::now();
+        let mut status: Vec<_> = self
+            .circuits
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(provider, circuit)| CircuitStatus {
+                provider: provider.clone(),
+                failures: circuit.failures,
+                retry_in_ms: circuit
+                    .open_until
+                    .filter(|&until| until > now)
+                    .map(|until| (until - now).as_millis() as u64),
+            })
+            .collect();
+        status.sort_by(|a, b| a.provider.cmp(&b.provider));
+        status
+    }
+}
+
+/// Wait for `answer`, which counts its output in `pieces`; it fails if it
+/// doesn't start within `timeout`, or once it has, when nothing more
+/// arrives for a whole `idle` period
+async fn watch(
+    answer: impl Future<Output = Result<(), LlmError>>,
+    pieces: &AtomicUsize,
+    timeout: Duration,
+    idle: Duration,
+) -> Result<(), LlmError> {
+    let mut answer = std::pin::pin!(answer);
+    let mut wait = timeout;
+    let mut seen = 0;
+    loop {
+        if let Ok(answered) = tokio::time::timeout(wait, &mut answer).await {
+            return answered;
+        }
+        let now = pieces.load(Ordering::Relaxed);
+        if now == seen {
+            return Err(LlmError::Timeout {
+                seconds: wait.as_secs(),
+            });
+        }
+        seen = now;
+        wait = idle;
+    }
+}
+
+/// How long to wait before try `tries + 1` after `error`; `None` if
+/// trying again won't help
+fn backoff(error: &LlmError, tries: u32) -> Option<Duration> {
+    let exponential = BACKOFF_BASE * 2u32.pow(tries - 1);
+    match error {
+        LlmError::RateLimited {
+            retry_after_ms: Some(ms),
+            ..
+        } => Some(Duration::from_millis(*ms)).filter(|&delay| delay <= BACKOFF_MAX),
+        LlmError::Http { status, .. } if *status < 500 => None,
+        LlmError::Unavailable { .. }
+        | LlmError::Timeout { .. }
+        | LlmError::RateLimited { .. }
+        | LlmError::Http { .. }
+        | LlmError::Provider { .. } => Some(exponential.min(BACKOFF_MAX)),
+        LlmError::ModelNotFound { .. }
+        | LlmError::Unauthorized { .. }
+        | LlmError::ContextOverflow { .. }
+        | LlmError::Duplicate { .. } => None,
+    }
+}
+
+/// Stream the next message of a chat, token by token, to `on_event`, from
+/// the providers set in the `.henryrc` of `root` or the first open root
+///
+/// `request_id` is chosen by the caller, to cancel the generation with
+/// `cancel_generation`. The answer names the provider that served it and
+/// lists every provider tried.
+#[tauri::command]
+pub async fn route_chat(
+    workspace: State<'_, Workspace>,
+    generations: State<'_, Generations>,
+    router: State<'_, Router>,
+    request_id: String,
+    request: RouteRequest,
+    root: Option<PathBuf>,
+    on_event: Channel<StreamEvent>,
+) -> Result<RoutedCompletion, RouteError> {
+    let config = match (&root, workspace.roots().first()) {
+        (Some(root), _) => HenryConfig::load(&workspace.pick_root(Some(root))?.path),
+        (None, Some(first)) => HenryConfig::load(&first.path),
+        (None, None) => HenryConfig::default(),
+    };
+    let routes = Route::policy(&config.ai);
+    router
+        .chat(
+            &generations,
+            &request_id,
+            &routes,
+            &request,
+            Some(&on_event),
+        )
+        .await
+}
+
+/// Providers that failed lately and whether they are skipped
+#[tauri::command]
+pub fn router_status(router: State<'_, Router>) -> Vec<CircuitStatus> {
+    router.status()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate_limited(retry_after_ms: Option<u64>) -> LlmError {
+        LlmError::RateLimited {
+            message: "slow down".into(),
+            retry_after_ms,
+        }
+    }
+
+    fn http(status: u16) -> LlmError {
+        LlmError::Http {
+            status,
+            message: "failed".into(),
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let timeout = LlmError::Timeout { seconds: 30 };
+        assert_eq!(backoff(&timeout, 1), Some(Duration::from_millis(500)));
+        assert_eq!(backoff(&timeout, 2), Some(Duration::from_secs(1)));
+        assert_eq!(backoff(&http(503), 3), Some(Duration::from_secs(2)));
+        assert_eq!(backoff(&rate_limited(None), 10), Some(BACKOFF_MAX));
+    }
+
+    #[test]
+    fn backoff_follows_the_provider_or_gives_up() {
+        assert_eq!(
+            backoff(&rate_limited(Some(1500)), 1),
+            Some(Duration::from_millis(1500))
+        );
+        // Asked to wait longer than we would
+        assert_eq!(backoff(&rate_limited(Some(60_000)), 1), None);
+        assert_eq!(backoff(&http(400), 1), None);
+        let unauthorized = LlmError::Unauthorized {
+            message: "bad key".into(),
+        };
+        assert_eq!(backoff(&unauthorized, 1), None);
+        let overflow = LlmError::ContextOverflow {
+            message: "too long".into(),
+        };
+        assert_eq!(backoff(&overflow, 1), None);
+    }
+
+    #[test]
+    fn circuit_opens_after_repeated_failures() {
+        let router = Router::default();
+        for _ in 1..FAILURE_THRESHOLD {
+            router.failed("openai", &http(500));
+            assert!(router.allows("openai"));
+        }
+        router.failed("openai", &http(500));
+        assert!(!router.allows("openai"));
+        assert!(router.allows("anthropic"));
+
+        let status = router.status();
+        assert_eq!(status.len(), 1);
+        assert_eq!(status[0].failures, FAILURE_THRESHOLD);
+        assert!(status[0]
+            .retry_in_ms
+            .is_some_and(|ms| ms <= COOLDOWN.as_millis() as u64));
+
+        // Half open after the cooldown: one try goes through
+        router
+            .circuits
+            .lock()
+            .unwrap()
+            .get_mut("openai")
+            .unwrap()
+            .open_until = Some(Instant::now());
+        assert!(router.allows("openai"));
+        router.failed("openai", &http(500));
+        assert!(!router.allows("openai"));
+
+        router.succeeded("openai");
+        assert!(router.allows("openai"));
+        assert!(router.status().is_empty());
+    }
+
+    #[test]
+    fn prompt_errors_leave_the_circuit_closed() {
+        let router = Router::default();
+        let overflow = LlmError::ContextOverflow {
+            message: "too long".into(),
+        };
+        for _ in 0..FAILURE_THRESHOLD {
+            router.failed("openai", &overflow);
+        }
+        assert!(router.allows("openai"));
+        assert!(router.status().is_empty());
+    }
+
+    #[test]
+    fn policy_lists_preferred_then_fallback() {
+        let config: AiConfig = serde_json::from_value(serde_json::json!({
+            "preferredModel": "claude",
+            "fallbackModel": "openai",
+            "providers": {
+                "claude": { "model": "claude-sonnet", "timeoutSecs": 5 },
+                "openai": { "url": "http://localhost:1234/v1" }
+            }
+        }))
+        .unwrap();
+        let routes = Route::policy(&config);
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes[0].choice, ModelChoice::Claude);
+        assert_eq!(routes[0].model, "claude-sonnet");
+        assert_eq!(routes[0].timeout, Duration::from_secs(5));
+        assert_eq!(routes[1].choice, ModelChoice::Openai);
+        assert_eq!(routes[1].model, "gpt-4");
+        assert_eq!(routes[1].timeout, CLOUD_TIMEOUT);
+        assert!(matches!(
+            &routes[1].provider,
+            ProviderConfig::OpenAi { base_url: Some(url), api_key: None }
+                if url == "http://localhost:1234/v1"
+        ));
+    }
+
+    #[test]
+    fn policy_defaults_to_local_once() {
+        let config: AiConfig = serde_json::from_value(serde_json::json!({
+            "fallbackModel": "local"
+        }))
+        .unwrap();
+        let routes = Route::policy(&config);
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0].choice, ModelChoice::Local);
+        assert_eq!(routes[0].timeout, LOCAL_TIMEOUT);
+    }
+
+    /// An answer that passes on `pieces` pieces `gap` apart
+    async fn answer(output: &AtomicUsize, pieces: usize, gap: Duration) -> Result<(), LlmError> {
+        for _ in 0..pieces {
+            tokio::time::sleep(gap).await;
+            output.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn watch_times_out_before_the_answer_starts() {
+        let output = AtomicUsize::new(0);
+        let timeout = Duration::from_millis(50);
+        let result = tauri::async_runtime::block_on(watch(
+            answer(&output, 1, Duration::from_secs(5)),
+            &output,
+            timeout,
+            timeout,
+        ));
+        assert!(matches!(result, Err(LlmError::Timeout { .. })));
+    }
+
+    #[test]
+    fn watch_waits_while_pieces_keep_coming() {
+        let output = AtomicUsize::new(0);
+        // Longer than the timeout in all, but never quiet for long
+        let result = tauri::async_runtime::block_on(watch(
+            answer(&output, 10, Duration::from_millis(20)),
+            &output,
+            Duration::from_millis(60),
+            Duration::from_millis(60),
+        ));
+        assert!(result.is_ok());
+        assert_eq!(output.load(Ordering::Relaxed), 10);
+    }
+
+    #[test]
+    fn watch_gives_up_on_an_answer_that_stalls() {
+        let output = AtomicUsize::new(0);
+        let result = tauri::async_runtime::block_on(watch(
+            async {
+                output.fetch_add(1, Ordering::Relaxed);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok(())
+            },
+            &output,
+            Duration::from_millis(50),
+            Duration::from_millis(50),
+        ));
+        assert!(matches!(result, Err(LlmError::Timeout { .. })));
+    }
+}
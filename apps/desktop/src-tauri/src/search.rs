@@ -0,0 +1,648 @@
+//! Project-wide text search
+//!
+//! Runs ripgrep's searcher over the same walk as the project tree, so the
+//! ignore files and the denylist apply. Matches are streamed per file as
+//! `search://match` events while the walk runs; the command resolves with a
+//! summary once the search finishes, hits its result cap or is cancelled
+//! with `cancel_search`.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use grep_matcher::{LineTerminator, Matcher};
+use grep_regex::{RegexMatcher, RegexMatcherBuilder};
+use grep_searcher::{BinaryDetection, Searcher, SearcherBuilder, Sink, SinkContext, SinkMatch};
+use ignore::{DirEntry, ParallelVisitor, ParallelVisitorBuilder, WalkState};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, State};
+
+use crate::tree;
+use crate::workspace::{Workspace, WorkspaceError, WorkspaceRoot};
+
+/// Emitted once per file with matches
+pub const MATCH_EVENT: &str = "search://match";
+
+/// Matches reported when the caller doesn't set a cap
+const DEFAULT_MAX_RESULTS: usize = 2000;
+
+const MAX_CONTEXT: usize = 10;
+
+/// Longest line sent to the frontend, in bytes; one minified bundle would
+/// otherwise flood the event channel
+const MAX_LINE_LEN: usize = 1000;
+
+#[derive(Debug, thiserror::Error, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum SearchError {
+    #[error("invalid search pattern: {message}")]
+    InvalidPattern { message: String },
+    #[error("invalid glob {pattern}: {message}")]
+    InvalidGlob { pattern: String, message: String },
+    #[error("search {id} is already running")]
+    Duplicate { id: String },
+    #[error("search failed: {message}")]
+    Failed { message: String },
+    #[error(transparent)]
+    Sandbox { reason: WorkspaceError },
+}
+
+impl From<WorkspaceError> for SearchError {
+    fn from(reason: WorkspaceError) -> Self {
+        Self::Sandbox { reason }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SearchOptions {
+    /// Treat the query as a regular expression rather than literal text
+    pub regex: bool,
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    /// Only search files whose workspace-relative path matches one of these
+    pub include: Vec<String>,
+    /// Skip files and directories matching one of these
+    pub exclude: Vec<String>,
+    /// Lines of context before and after each match
+    pub context: usize,
+    pub max_results: Option<usize>,
+    /// Search this open root only, instead of all of them
+    pub root: Option<PathBuf>,
+}
+
+/// A matching line, or a context line around one
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchLine {
+    pub line: u64,
+    pub text: String,
+    /// Byte ranges of the matches within `text`, empty for context lines
+    pub ranges: Vec<[usize; 2]>,
+    pub context: bool,
+}
+
+/// Payload of `search://match`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileMatches {
+    pub search_id: String,
+    pub path: PathBuf,
+    /// In file order, context lines included
+    pub lines: Vec<SearchLine>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchSummary {
+    pub search_id: String,
+    /// Files with at least one match
+    pub files: usize,
+    pub matches: usize,
+    /// The result cap was hit, so there may be more matches
+    pub truncated: bool,
+    pub cancelled: bool,
+}
+
+/// Include and exclude globs, matched against workspace-relative paths
+struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl PathFilter {
+    fn new(options: &SearchOptions) -> Result<Self, SearchError> {
+        let set = |patterns: &[String]| {
+            let mut builder = GlobSetBuilder::new();
+            for pattern in patterns {
+                let glob = Glob::new(pattern).map_err(|e| SearchError::InvalidGlob {
+                    pattern: pattern.clone(),
+                    message: e.to_string(),
+                })?;
+                builder.add(glob);
+            }
+            builder.build().map_err(|e| SearchError::InvalidGlob {
+                pattern: patterns.join(", "),
+                message: e.to_string(),
+            })
+        };
+        Ok(Self {
+            include: match options.include.as_slice() {
+                [] => None,
+                include => Some(set(include)?),
+            },
+            exclude: set(&options.exclude)?,
+        })
+    }
+
+    fn excludes(&self, relative: &Path) -> bool {
+        self.exclude.is_match(relative)
+    }
+
+    fn includes(&self, relative: &Path) -> bool {
+        self.include
+            .as_ref()
+            .is_none_or(|set| set.is_match(relative))
+    }
+}
+
+fn matcher(query: &str, options: &SearchOptions) -> Result<RegexMatcher, SearchError> {
+    RegexMatcherBuilder::new()
+        .fixed_strings(!options.regex)
+        .case_insensitive(!options.case_sensitive)
+        .word(options.whole_word)
+        .crlf(true)
+        .build(query)
+        .map_err(|e| SearchError::InvalidPattern {
+            message: e.to_string(),
+        })
+}
+
+/// State shared by the walker threads of one search
+struct Run<'r, E> {
+    search_id: &'r str,
+    root: &'r WorkspaceRoot,
+    matcher: &'r RegexMatcher,
+    filter: &'r PathFilter,
+    /// Lines of context around matches
+    context: usize,
+    max_results: usize,
+    cancelled: &'r AtomicBool,
+    matches: &'r AtomicUsize,
+    files: &'r AtomicUsize,
+    truncated: &'r AtomicBool,
+    emit: &'r E,
+}
+
+impl<'r, E: Fn(FileMatches) + Sync> ParallelVisitorBuilder<'r> for Run<'r, E> {
+    fn build(&mut self) -> Box<dyn ParallelVisitor + 'r> {
+        Box::new(Visitor {
+            run: *self,
+            searcher: SearcherBuilder::new()
+                .line_number(true)
+                .line_terminator(LineTerminator::crlf())
+                .before_context(self.context)
+                .after_context(self.context)
+                .binary_detection(BinaryDetection::quit(0))
+                .build(),
+        })
+    }
+}
+
+impl<E> Clone for Run<'_, E> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<E> Copy for Run<'_, E> {}
+
+/// Per-thread searcher; a `Searcher` keeps its buffers, so it can't be
+/// shared
+struct Visitor<'r, E> {
+    run: Run<'r, E>,
+    searcher: Searcher,
+}
+
+impl<E: Fn(FileMatches) + Sync> ParallelVisitor for Visitor<'_, E> {
+    fn visit(&mut self, entry: Result<DirEntry, ignore::Error>) -> WalkState {
+        let run = self.run;
+        if run.cancelled.load(Ordering::Relaxed) || run.truncated.load(Ordering::Relaxed) {
+            return WalkState::Quit;
+        }
+        let Ok(entry) = entry else {
+            return WalkState::Continue;
+        };
+        let path = entry.path();
+        let relative = path.strip_prefix(&run.root.path).unwrap_or(path);
+        if entry.depth() > 0
+            && (run.root.denied_by(relative).is_some() || run.filter.excludes(relative))
+        {
+            return WalkState::Skip;
+        }
+        if !entry.file_type().is_some_and(|t| t.is_file()) || !run.filter.includes(relative) {
+            return WalkState::Continue;
+        }
+
+        let mut sink = FileSink {
+            run,
+            lines: Vec::new(),
+            matched: false,
+        };
+        // Unreadable files are skipped, like ripgrep does without --debug
+        if self
+            .searcher
+            .search_path(run.matcher, path, &mut sink)
+            .is_ok()
+            && sink.matched
+        {
+            run.files.fetch_add(1, Ordering::Relaxed);
+            (run.emit)(FileMatches {
+                search_id: run.search_id.to_string(),
+                path: path.to_path_buf(),
+                lines: sink.lines,
+            });
+        }
+        WalkState::Continue
+    }
+}
+
+struct FileSink<'r, E> {
+    run: Run<'r, E>,
+    lines: Vec<SearchLine>,
+    matched: bool,
+}
+
+impl<E> Sink for FileSink<'_, E> {
+    type Error = io::Error;
+
+    fn matched(&mut self, _: &Searcher, mat: &SinkMatch<'_>) -> Result<bool, io::Error> {
+        if self.run.cancelled.load(Ordering::Relaxed) {
+            return Ok(false);
+        }
+        // Reserve a slot, so concurrent files can't overshoot the cap
+        if self.run.matches.fetch_add(1, Ordering::Relaxed) >= self.run.max_results {
+            self.run.matches.fetch_sub(1, Ordering::Relaxed);
+            self.run.truncated.store(true, Ordering::Relaxed);
+            return Ok(false);
+        }
+        let bytes = trim_line(mat.bytes());
+        let mut ranges = Vec::new();
+        self.run
+            .matcher
+            .find_iter(bytes, |m| {
+                ranges.push([m.start(), m.end()]);
+                true
+            })
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        self.matched = true;
+        self.push(mat.line_number(), bytes, ranges, false);
+        Ok(true)
+    }
+
+    fn context(&mut self, _: &Searcher, context: &SinkContext<'_>) -> Result<bool, io::Error> {
+        self.push(
+            context.line_number(),
+            trim_line(context.bytes()),
+            Vec::new(),
+            true,
+        );
+        Ok(true)
+    }
+}
+
+impl<E> FileSink<'_, E> {
+    fn push(&mut self, line: Option<u64>, bytes: &[u8], ranges: Vec<[usize; 2]>, context: bool) {
+        let mut text = String::from_utf8_lossy(bytes).into_owned();
+        if text.len() > MAX_LINE_LEN {
+            let mut end = MAX_LINE_LEN;
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            text.truncate(end);
+        }
+        let ranges = ranges
+            .into_iter()
+            .filter(|[start, _]| *start < text.len())
+            .map(|[start, end]| [start, end.min(text.len())])
+            .collect();
+        self.lines.push(SearchLine {
+            line: line.unwrap_or(0),
+            text,
+            ranges,
+            context,
+        });
+    }
+}
+
+fn trim_line(bytes: &[u8]) -> &[u8] {
+    let end = bytes
+        .iter()
+        .rposition(|b| !matches!(b, b'\n' | b'\r'))
+        .map_or(0, |i| i + 1);
+    &bytes[..end]
+}
+
+/// Search `roots` for `query`, calling `emit` with each file's matches
+///
+/// Blocking; stops early once `cancelled` is set.
+pub fn search(
+    search_id: &str,
+    roots: &[WorkspaceRoot],
+    query: &str,
+    options: &SearchOptions,
+    cancelled: &AtomicBool,
+    emit: impl Fn(FileMatches) + Sync,
+) -> Result<SearchSummary, SearchError> {
+    let mut summary = SearchSummary {
+        search_id: search_id.to_string(),
+        ..SearchSummary::default()
+    };
+    if query.is_empty() {
+        return Ok(summary);
+    }
+    let matcher = matcher(query, options)?;
+    let filter = PathFilter::new(options)?;
+    let matches = AtomicUsize::new(0);
+    let files = AtomicUsize::new(0);
+    let truncated = AtomicBool::new(false);
+
+    for root in roots {
+        tree::walker(&root.path).build_parallel().visit(&mut Run {
+            search_id,
+            root,
+            matcher: &matcher,
+            filter: &filter,
+            context: options.context.min(MAX_CONTEXT),
+            max_results: options.max_results.unwrap_or(DEFAULT_MAX_RESULTS),
+            cancelled,
+            matches: &matches,
+            files: &files,
+            truncated: &truncated,
+            emit: &emit,
+        });
+    }
+    summary.files = files.into_inner();
+    summary.matches = matches.into_inner();
+    summary.truncated = truncated.into_inner();
+    summary.cancelled = cancelled.load(Ordering::Relaxed);
+    Ok(summary)
+}
+
+/// Cancellation flags of running searches, managed as Tauri state
+#[derive(Default)]
+pub struct Searches {
+    running: Mutex<HashMap<String, Arc<AtomicBool>>>,
+}
+
+impl Searches {
+    fn start(&self, id: &str) -> Result<Arc<AtomicBool>, SearchError> {
+        let mut running = self.running.lock().unwrap();
+        if running.contains_key(id) {
+            return Err(SearchError::Duplicate { id: id.to_string() });
+        }
+        let cancelled = Arc::new(AtomicBool::new(false));
+        running.insert(id.to_string(), Arc::clone(&cancelled));
+        Ok(cancelled)
+    }
+
+    fn finish(&self, id: &str) {
+        self.running.lock().unwrap().remove(id);
+    }
+
+    fn cancel(&self, id: &str) -> bool {
+        match self.running.lock().unwrap().get(id) {
+            Some(cancelled) => {
+                cancelled.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Search file contents across open workspaces
+///
+/// `search_id` is chosen by the caller, so it can match the streamed
+/// `search://match` events and cancel the search before it resolves.
+#[tauri::command]
+pub async fn search_text(
+    app: AppHandle,
+    workspace: State<'_, Workspace>,
+    searches: State<'_, Searches>,
+    search_id: String,
+    query: String,
+    options: Option<SearchOptions>,
+) -> Result<SearchSummary, SearchError> {
+    let options = options.unwrap_or_default();
+    let roots = match &options.root {
+        Some(root) => {
+            let resolved = workspace.resolve(root)?;
+            vec![workspace
+                .root_of(&resolved)
+                .ok_or_else(|| WorkspaceError::OutsideWorkspace {
+                    path: root.display().to_string(),
+                })?]
+        }
+        None => workspace.roots(),
+    };
+    if roots.is_empty() {
+        return Err(WorkspaceError::NoWorkspace.into());
+    }
+
+    let cancelled = searches.start(&search_id)?;
+    let id = search_id.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        search(&id, &roots, &query, &options, &cancelled, |matches| {
+            let _ = app.emit(MATCH_EVENT, matches);
+        })
+    })
+    .await;
+    searches.finish(&search_id);
+    result.unwrap_or_else(|e| {
+        Err(SearchError::Failed {
+            message: e.to_string(),
+        })
+    })
+}
+
+/// Stop a running search; `false` if it already finished
+#[tauri::command]
+pub fn cancel_search(searches: State<'_, Searches>, search_id: String) -> bool {
+    searches.cancel(&search_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use crate::workspace::Workspace;
+
+    /// A project with a few files, one of them denylisted
+    fn project() -> (tempfile::TempDir, WorkspaceRoot) {
+        let dir = tempfile::tempdir().unwrap();
+        let files = [
+            (
+                "src/main.rs",
+                "fn main() {\n    let total = add(1, 2);\n}\n",
+            ),
+            (
+                "src/lib.rs",
+                "pub fn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n// ADD more\n",
+            ),
+            ("docs/notes.md", "adding things up\nadd\n"),
+            (".env", "ADD_TOKEN=secret\n"),
+        ];
+        for (path, text) in files {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        let root = Workspace::default().open(dir.path()).unwrap();
+        (dir, root)
+    }
+
+    /// Matching lines by workspace-relative path
+    fn run(
+        root: &WorkspaceRoot,
+        query: &str,
+        options: SearchOptions,
+    ) -> (SearchSummary, Vec<(String, Vec<SearchLine>)>) {
+        let found = Mutex::new(Vec::new());
+        let summary = search(
+            "s1",
+            std::slice::from_ref(root),
+            query,
+            &options,
+            &AtomicBool::new(false),
+            |m| {
+                let path = m.path.strip_prefix(&root.path).unwrap();
+                let path = path.to_string_lossy().replace('\\', "/");
+                found.lock().unwrap().push((path, m.lines));
+            },
+        )
+        .unwrap();
+        let mut found = found.into_inner().unwrap();
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        (summary, found)
+    }
+
+    fn paths(found: &[(String, Vec<SearchLine>)]) -> Vec<&str> {
+        found.iter().map(|(path, _)| path.as_str()).collect()
+    }
+
+    #[test]
+    fn literal_search_ignores_case_and_skips_denied_files() {
+        let (_dir, root) = project();
+        let (summary, found) = run(&root, "add", SearchOptions::default());
+        assert_eq!(
+            paths(&found),
+            ["docs/notes.md", "src/lib.rs", "src/main.rs"]
+        );
+        assert_eq!(summary.files, 3);
+        assert_eq!(summary.matches, 5);
+        assert!(!summary.truncated && !summary.cancelled);
+
+        let lib = &found[1].1;
+        assert_eq!(lib[1].line, 4);
+        assert_eq!(lib[1].text, "// ADD more");
+        assert_eq!(lib[1].ranges, [[3, 6]]);
+    }
+
+    #[test]
+    fn case_word_and_regex_modes() {
+        let (_dir, root) = project();
+        let case_sensitive = SearchOptions {
+            case_sensitive: true,
+            ..SearchOptions::default()
+        };
+        let (summary, _) = run(&root, "ADD", case_sensitive);
+        assert_eq!(summary.matches, 1);
+
+        let word = SearchOptions {
+            whole_word: true,
+            ..SearchOptions::default()
+        };
+        let (_, found) = run(&root, "add", word);
+        let notes = &found[0].1;
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].line, 2);
+
+        // Literal text isn't a pattern
+        let (summary, _) = run(&root, "a + b", SearchOptions::default());
+        assert_eq!(summary.matches, 1);
+        let regex = SearchOptions {
+            regex: true,
+            ..SearchOptions::default()
+        };
+        let (summary, _) = run(&root, r"add\(\d", regex.clone());
+        assert_eq!(summary.matches, 1);
+        assert!(matches!(
+            search("s1", &[root], "(", &regex, &AtomicBool::new(false), |_| {}),
+            Err(SearchError::InvalidPattern { .. })
+        ));
+    }
+
+    #[test]
+    fn include_and_exclude_globs() {
+        let (_dir, root) = project();
+        let include = SearchOptions {
+            include: vec!["**/*.rs".into()],
+            ..SearchOptions::default()
+        };
+        let (_, found) = run(&root, "add", include);
+        assert_eq!(paths(&found), ["src/lib.rs", "src/main.rs"]);
+
+        let exclude = SearchOptions {
+            exclude: vec!["src".into()],
+            ..SearchOptions::default()
+        };
+        let (_, found) = run(&root, "add", exclude);
+        assert_eq!(paths(&found), ["docs/notes.md"]);
+
+        let invalid = SearchOptions {
+            include: vec!["a{".into()],
+            ..SearchOptions::default()
+        };
+        assert!(matches!(
+            search(
+                "s1",
+                &[root],
+                "add",
+                &invalid,
+                &AtomicBool::new(false),
+                |_| {}
+            ),
+            Err(SearchError::InvalidGlob { .. })
+        ));
+    }
+
+    #[test]
+    fn context_lines_are_marked() {
+        let (_dir, root) = project();
+        let options = SearchOptions {
+            context: 1,
+            include: vec!["src/main.rs".into()],
+            ..SearchOptions::default()
+        };
+        let (_, found) = run(&root, "total", options);
+        let lines = &found[0].1;
+        let numbers: Vec<_> = lines.iter().map(|l| (l.line, l.context)).collect();
+        assert_eq!(numbers, [(1, true), (2, false), (3, true)]);
+    }
+
+    #[test]
+    fn the_cap_truncates() {
+        let (_dir, root) = project();
+        let options = SearchOptions {
+            max_results: Some(2),
+            ..SearchOptions::default()
+        };
+        let (summary, found) = run(&root, "add", options);
+        assert_eq!(summary.matches, 2);
+        assert!(summary.truncated);
+        let lines: usize = found.iter().map(|(_, lines)| lines.len()).sum();
+        assert_eq!(lines, 2);
+    }
+
+    #[test]
+    fn a_cancelled_search_stops() {
+        let (_dir, root) = project();
+        let cancelled = AtomicBool::new(true);
+        let summary = search(
+            "s1",
+            &[root],
+            "add",
+            &SearchOptions::default(),
+            &cancelled,
+            |_| {},
+        )
+        .unwrap();
+        assert!(summary.cancelled);
+        assert_eq!(summary.matches, 0);
+    }
+}
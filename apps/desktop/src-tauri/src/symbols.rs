@@ -0,0 +1,924 @@
+//! Symbol extraction from tree-sitter parse trees
+//!
+//! Each supported language maps its declaration nodes to a [`SymbolKind`].
+//! Containers (classes, modules, impls…) are walked for members, so the
+//! result is a hierarchical outline; function bodies are not, so locals
+//! never show up. Signatures are the declaration text up to its body, and
+//! doc comments are the comments directly above a declaration (or a
+//! Python docstring).
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+use tree_sitter::{Language, Node, Parser, Tree};
+
+use crate::fs_edit::{self, FileEditError};
+use crate::language;
+use crate::workspace::Workspace;
+
+/// Longest signature kept, in characters
+const MAX_SIGNATURE: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SymbolKind {
+    Module,
+    Class,
+    Struct,
+    Interface,
+    Enum,
+    Impl,
+    Type,
+    Function,
+    Method,
+    Constructor,
+    Field,
+    Constant,
+    Variable,
+}
+
+impl SymbolKind {
+    /// Kinds whose members belong in the outline
+    pub fn is_container(self) -> bool {
+        matches!(
+            self,
+            Self::Module | Self::Class | Self::Struct | Self::Interface | Self::Enum | Self::Impl
+        )
+    }
+
+    /// Name as serialized
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Module => "module",
+            Self::Class => "class",
+            Self::Struct => "struct",
+            Self::Interface => "interface",
+            Self::Enum => "enum",
+            Self::Impl => "impl",
+            Self::Type => "type",
+            Self::Function => "function",
+            Self::Method => "method",
+            Self::Constructor => "constructor",
+            Self::Field => "field",
+            Self::Constant => "constant",
+            Self::Variable => "variable",
+        }
+    }
+}
+
+/// 1-based line and column; columns count bytes
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub range: Range,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doc: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub children: Vec<Symbol>,
+}
+
+struct Grammar {
+    language: Language,
+    /// Declaration node kinds and the symbol each one is
+    kinds: &'static [(&'static str, SymbolKind)],
+    /// Wrappers whose preceding comments document the declaration inside
+    doc_wrappers: &'static [&'static str],
+    /// Nodes never searched for declarations, e.g. closures
+    opaque: &'static [&'static str],
+}
+
+const SCRIPT_KINDS: &[(&str, SymbolKind)] = &[
+    ("function_declaration", SymbolKind::Function),
+    ("generator_function_declaration", SymbolKind::Function),
+    ("function_signature", SymbolKind::Function),
+    ("class_declaration", SymbolKind::Class),
+    ("abstract_class_declaration", SymbolKind::Class),
+    ("class", SymbolKind::Class),
+    ("interface_declaration", SymbolKind::Interface),
+    ("type_alias_declaration", SymbolKind::Type),
+    ("enum_declaration", SymbolKind::Enum),
+    ("internal_module", SymbolKind::Module),
+    ("module", SymbolKind::Module),
+    ("method_definition", SymbolKind::Method),
+    ("method_signature", SymbolKind::Method),
+    ("abstract_method_signature", SymbolKind::Method),
+    ("public_field_definition", SymbolKind::Field),
+    ("field_definition", SymbolKind::Field),
+    ("property_signature", SymbolKind::Field),
+    ("variable_declarator", SymbolKind::Variable),
+];
+
+const SCRIPT_WRAPPERS: &[&str] = &[
+    "export_statement",
+    "lexical_declaration",
+    "variable_declaration",
+];
+
+const SCRIPT_OPAQUE: &[&str] = &[
+    "arrow_function",
+    "function_expression",
+    "statement_block",
+    "call_expression",
+];
+
+const PYTHON_KINDS: &[(&str, SymbolKind)] = &[
+    ("class_definition", SymbolKind::Class),
+    ("function_definition", SymbolKind::Function),
+    ("assignment", SymbolKind::Variable),
+];
+
+const RUST_KINDS: &[(&str, SymbolKind)] = &[
+    ("mod_item", SymbolKind::Module),
+    ("struct_item", SymbolKind::Struct),
+    ("union_item", SymbolKind::Struct),
+    ("enum_item", SymbolKind::Enum),
+    ("trait_item", SymbolKind::Interface),
+    ("impl_item", SymbolKind::Impl),
+    ("type_item", SymbolKind::Type),
+    ("function_item", SymbolKind::Function),
+    ("function_signature_item", SymbolKind::Function),
+    ("const_item", SymbolKind::Constant),
+    ("static_item", SymbolKind::Constant),
+    ("macro_definition", SymbolKind::Function),
+    ("field_declaration", SymbolKind::Field),
+];
+
+const GO_KINDS: &[(&str, SymbolKind)] = &[
+    ("function_declaration", SymbolKind::Function),
+    ("method_declaration", SymbolKind::Method),
+    ("type_spec", SymbolKind::Type),
+    ("type_alias", SymbolKind::Type),
+    ("const_spec", SymbolKind::Constant),
+    ("var_spec", SymbolKind::Variable),
+    ("field_declaration", SymbolKind::Field),
+    ("method_elem", SymbolKind::Method),
+];
+
+const JAVA_KINDS: &[(&str, SymbolKind)] = &[
+    ("class_declaration", SymbolKind::Class),
+    ("record_declaration", SymbolKind::Class),
+    ("interface_declaration", SymbolKind::Interface),
+    ("annotation_type_declaration", SymbolKind::Interface),
+    ("enum_declaration", SymbolKind::Enum),
+    ("method_declaration", SymbolKind::Method),
+    ("constructor_declaration", SymbolKind::Constructor),
+    ("variable_declarator", SymbolKind::Field),
+];
+
+const CSHARP_KINDS: &[(&str, SymbolKind)] = &[
+    ("namespace_declaration", SymbolKind::Module),
+    ("file_scoped_namespace_declaration", SymbolKind::Module),
+    ("class_declaration", SymbolKind::Class),
+    ("record_declaration", SymbolKind::Class),
+    ("struct_declaration", SymbolKind::Struct),
+    ("interface_declaration", SymbolKind::Interface),
+    ("enum_declaration", SymbolKind::Enum),
+    ("delegate_declaration", SymbolKind::Type),
+    ("method_declaration", SymbolKind::Method),
+    ("constructor_declaration", SymbolKind::Constructor),
+    ("property_declaration", SymbolKind::Field),
+    ("variable_declarator", SymbolKind::Field),
+];
+
+/// Declarations whose keyword belongs in the signature of what they declare
+const SIGNATURE_WRAPPERS: &[&str] = &[
+    "lexical_declaration",
+    "variable_declaration",
+    "type_declaration",
+    "const_declaration",
+    "var_declaration",
+    "field_declaration",
+];
+
+const BLOCK_OPAQUE: &[&str] = &[
+    "block",
+    "lambda_expression",
+    "closure_expression",
+    "func_literal",
+];
+
+fn grammar(language: &str) -> Option<Grammar> {
+    let (language, kinds, doc_wrappers, opaque): (Language, _, &[&str], &[&str]) = match language {
+        "typescript" => (
+            tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            SCRIPT_KINDS,
+            SCRIPT_WRAPPERS,
+            SCRIPT_OPAQUE,
+        ),
+        "typescriptreact" => (
+            tree_sitter_typescript::LANGUAGE_TSX.into(),
+            SCRIPT_KINDS,
+            SCRIPT_WRAPPERS,
+            SCRIPT_OPAQUE,
+        ),
+        "javascript" | "javascriptreact" => (
+            tree_sitter_javascript::LANGUAGE.into(),
+            SCRIPT_KINDS,
+            SCRIPT_WRAPPERS,
+            SCRIPT_OPAQUE,
+        ),
+        "python" => (
+            tree_sitter_python::LANGUAGE.into(),
+            PYTHON_KINDS,
+            &["decorated_definition", "expression_statement"],
+            &["lambda"],
+        ),
+        "rust" => (
+            tree_sitter_rust::LANGUAGE.into(),
+            RUST_KINDS,
+            &[],
+            BLOCK_OPAQUE,
+        ),
+        "go" => (
+            tree_sitter_go::LANGUAGE.into(),
+            GO_KINDS,
+            &["type_declaration", "const_declaration", "var_declaration"],
+            BLOCK_OPAQUE,
+        ),
+        "java" => (
+            tree_sitter_java::LANGUAGE.into(),
+            JAVA_KINDS,
+            &["field_declaration", "constant_declaration"],
+            BLOCK_OPAQUE,
+        ),
+        "csharp" => (
+            tree_sitter_c_sharp::LANGUAGE.into(),
+            CSHARP_KINDS,
+            &["field_declaration", "variable_declaration"],
+            BLOCK_OPAQUE,
+        ),
+        _ => return None,
+    };
+    Some(Grammar {
+        language,
+        kinds,
+        doc_wrappers,
+        opaque,
+    })
+}
+
+/// Parse `text`, `None` for languages without a grammar
+pub fn parse(language: &str, text: &str) -> Option<Tree> {
+    let grammar = grammar(language)?;
+    let mut parser = Parser::new();
+    parser.set_language(&grammar.language).ok()?;
+    parser.parse(text, None)
+}
+
+/// Outline of `text`, empty for languages without a grammar
+pub fn extract(language: &str, text: &str) -> Vec<Symbol> {
+    parse(language, text).map_or_else(Vec::new, |tree| outline(language, &tree, text))
+}
+
+/// Outline of an already parsed `tree` of `text`
+pub fn outline(language: &str, tree: &Tree, text: &str) -> Vec<Symbol> {
+    let Some(grammar) = grammar(language) else {
+        return Vec::new();
+    };
+    let extractor = Extractor {
+        grammar: &grammar,
+        source: text.as_bytes(),
+        python: language == "python",
+    };
+    let mut symbols = Vec::new();
+    extractor.collect(tree.root_node(), None, &mut symbols);
+    symbols
+}
+
+struct Extractor<'g> {
+    grammar: &'g Grammar,
+    source: &'g [u8],
+    python: bool,
+}
+
+impl Extractor<'_> {
+    /// Add the declarations in the children of `node` to `out`
+    fn collect(&self, node: Node, container: Option<SymbolKind>, out: &mut Vec<Symbol>) {
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            match self.symbol(child, container) {
+                Some(mut symbol) => {
+                    if symbol.kind.is_container() {
+                        self.collect(child, Some(symbol.kind), &mut symbol.children);
+                    }
+                    out.push(symbol);
+                }
+                None if self.grammar.opaque.contains(&child.kind()) => {}
+                None => self.collect(child, container, out),
+            }
+        }
+    }
+
+    fn symbol(&self, node: Node, container: Option<SymbolKind>) -> Option<Symbol> {
+        let &(_, kind) = self.grammar.kinds.iter().find(|(k, _)| *k == node.kind())?;
+        let kind = self.refine(node, kind, container)?;
+        let name = self.name(node, kind)?;
+        Some(Symbol {
+            name,
+            kind,
+            range: range(node),
+            signature: self.signature(node),
+            doc: self.doc(node),
+            children: Vec::new(),
+        })
+    }
+
+    /// Adjust a kind for its context, or drop nodes that aren't
+    /// declarations there
+    fn refine(
+        &self,
+        node: Node,
+        kind: SymbolKind,
+        container: Option<SymbolKind>,
+    ) -> Option<SymbolKind> {
+        let in_type = container.is_some_and(|c| c != SymbolKind::Module);
+        Some(match kind {
+            SymbolKind::Function if in_type => SymbolKind::Method,
+            SymbolKind::Variable if self.python => {
+                // Only `NAME = …` at module or class level
+                let statement = node
+                    .parent()
+                    .filter(|p| p.kind() == "expression_statement")?;
+                let scope = statement.parent()?.kind();
+                if scope != "module" && !(scope == "block" && in_type) {
+                    return None;
+                }
+                if node.child_by_field_name("left")?.kind() != "identifier" {
+                    return None;
+                }
+                if in_type {
+                    SymbolKind::Field
+                } else {
+                    kind
+                }
+            }
+            SymbolKind::Variable => {
+                let declaration = node.parent()?;
+                let value = node.child_by_field_name("value");
+                if value.is_some_and(|v| {
+                    matches!(
+                        v.kind(),
+                        "arrow_function" | "function_expression" | "function"
+                    )
+                }) {
+                    SymbolKind::Function
+                } else if self.text(declaration).starts_with("const") {
+                    SymbolKind::Constant
+                } else {
+                    kind
+                }
+            }
+            // Go's `type X struct {…}` and `type Y interface {…}`
+            SymbolKind::Type if node.kind() == "type_spec" => {
+                match node.child_by_field_name("type").map(|t| t.kind()) {
+                    Some("struct_type") => SymbolKind::Struct,
+                    Some("interface_type") => SymbolKind::Interface,
+                    _ => kind,
+                }
+            }
+            other => other,
+        })
+    }
+
+    fn name(&self, node: Node, kind: SymbolKind) -> Option<String> {
+        if kind == SymbolKind::Impl {
+            let target = self.text(node.child_by_field_name("type")?);
+            return Some(match node.child_by_field_name("trait") {
+                Some(t) => format!("impl {} for {}", self.text(t), target),
+                None => format!("impl {}", target),
+            });
+        }
+        let name = node
+            .child_by_field_name("name")
+            .or_else(|| node.child_by_field_name("left"))
+            .or_else(|| {
+                // Rust tuple fields and Go embedded fields have no name
+                let mut cursor = node.walk();
+                let found = node.named_children(&mut cursor).find(|c| {
+                    matches!(
+                        c.kind(),
+                        "identifier"
+                            | "type_identifier"
+                            | "field_identifier"
+                            | "property_identifier"
+                    )
+                });
+                found
+            })?;
+        Some(self.text(name).to_string())
+    }
+
+    /// Declaration text up to its body, on one line
+    fn signature(&self, node: Node) -> Option<String> {
+        // Include `const`/`type`/… when the declaration holds just this one
+        let start = node
+            .parent()
+            .filter(|p| {
+                SIGNATURE_WRAPPERS.contains(&p.kind())
+                    && p.start_position().row == node.start_position().row
+            })
+            .map_or(node.start_byte(), |p| p.start_byte());
+        let body = node.child_by_field_name("body").or_else(|| {
+            // `const f = () => {…}` ends where the function body starts
+            node.child_by_field_name("value")
+                .and_then(|v| v.child_by_field_name("body"))
+        });
+        let end = match body {
+            Some(body) => body.start_byte(),
+            None => {
+                let value = node
+                    .child_by_field_name("value")
+                    .filter(|_| node.kind() != "variable_declarator");
+                let end = value.map_or(node.end_byte(), |v| v.start_byte());
+                // Bodiless declarations, e.g. Go structs or macros, keep
+                // their first line
+                self.source[start..end]
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .map_or(end, |i| start + i)
+            }
+        };
+        let raw = std::str::from_utf8(&self.source[start..end.max(start)]).ok()?;
+        let mut signature = raw.split_whitespace().collect::<Vec<_>>().join(" ");
+        let trimmed = signature.trim_end_matches(['{', ':', '=', ';', ' ']).len();
+        signature.truncate(trimmed);
+        if signature.chars().count() > MAX_SIGNATURE {
+            signature = signature.chars().take(MAX_SIGNATURE).collect::<String>() + "…";
+        }
+        (!signature.is_empty()).then_some(signature)
+    }
+
+    fn doc(&self, node: Node) -> Option<String> {
+        if self.python {
+            if let Some(doc) = self.docstring(node) {
+                return Some(doc);
+            }
+        }
+        // Comments sit above the outermost wrapper, e.g. `export`
+        let mut anchor = node;
+        while let Some(parent) = anchor
+            .parent()
+            .filter(|p| self.grammar.doc_wrappers.contains(&p.kind()))
+        {
+            anchor = parent;
+        }
+
+        let mut lines = Vec::new();
+        let mut next_row = anchor.start_position().row;
+        let mut sibling = anchor.prev_sibling();
+        while let Some(current) = sibling {
+            // Attributes and annotations may sit between the doc and the item
+            if matches!(
+                current.kind(),
+                "attribute_item" | "decorator" | "attribute_list"
+            ) {
+                next_row = current.start_position().row;
+                sibling = current.prev_sibling();
+                continue;
+            }
+            // Rust line comments end on the next row, past their newline
+            let end = current.end_position();
+            let last_row = if end.column == 0 && end.row > current.start_position().row {
+                end.row - 1
+            } else {
+                end.row
+            };
+            if !current.kind().contains("comment") || last_row + 1 < next_row {
+                break;
+            }
+            lines.push(strip_comment(self.text(current)));
+            next_row = current.start_position().row;
+            sibling = current.prev_sibling();
+        }
+        lines.reverse();
+        let doc = lines.join("\n").trim().to_string();
+        (!doc.is_empty()).then_some(doc)
+    }
+
+    /// A string literal as the first statement of a Python body
+    fn docstring(&self, node: Node) -> Option<String> {
+        let body = node.child_by_field_name("body")?;
+        let first = body.named_child(0)?;
+        let string = first
+            .named_child(0)
+            .filter(|_| first.kind() == "expression_statement")?;
+        if string.kind() != "string" {
+            return None;
+        }
+        let text = self.text(string);
+        let inner = text
+            .trim_start_matches(|c: char| c.is_ascii_alphabetic())
+            .trim_matches(|c| c == '"' || c == '\'');
+        Some(dedent(inner))
+    }
+
+    fn text(&self, node: Node) -> &str {
+        node.utf8_text(self.source).unwrap_or_default()
+    }
+}
+
+fn range(node: Node) -> Range {
+    let point = |p: tree_sitter::Point| Position {
+        line: p.row + 1,
+        column: p.column + 1,
+    };
+    Range {
+        start: point(node.start_position()),
+        end: point(node.end_position()),
+    }
+}
+
+/// Comment text without `//`, `///`, `#`, `/** */` or leading `*`
+fn strip_comment(comment: &str) -> String {
+    let comment = comment
+        .trim()
+        .trim_start_matches("/**")
+        .trim_start_matches("/*")
+        .trim_end_matches("*/");
+    comment
+        .lines()
+        .map(|line| {
+            let line = line.trim();
+            let line = line
+                .strip_prefix("//!")
+                .or_else(|| line.strip_prefix("///"))
+                .or_else(|| line.strip_prefix("//"))
+                .or_else(|| line.strip_prefix('#'))
+                .or_else(|| line.strip_prefix('*'))
+                .unwrap_or(line);
+            line.strip_prefix(' ').unwrap_or(line).trim_end()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+fn dedent(text: &str) -> String {
+    let indent = text
+        .lines()
+        .skip(1)
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    text.lines()
+        .enumerate()
+        .map(|(i, l)| {
+            if i == 0 {
+                l.trim()
+            } else {
+                l.get(indent..).unwrap_or(l.trim())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// Every symbol in an outline, parents before their children
+pub fn flatten(symbols: &[Symbol]) -> Vec<&Symbol> {
+    let mut all = Vec::new();
+    let mut stack: Vec<&Symbol> = symbols.iter().rev().collect();
+    while let Some(symbol) = stack.pop() {
+        all.push(symbol);
+        stack.extend(symbol.children.iter().rev());
+    }
+    all
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Outline {
+    pub language: Option<&'static str>,
+    pub symbols: Vec<Symbol>,
+}
+
+/// Parse a workspace file and return its symbol outline
+///
+/// Files in languages without a grammar get an empty outline.
+#[tauri::command]
+pub async fn get_symbols(
+    workspace: State<'_, Workspace>,
+    path: String,
+) -> Result<Outline, FileEditError> {
+    let target = workspace.resolve(Path::new(&path))?;
+    if !target.is_file() {
+        return Err(FileEditError::PathMissing { path });
+    }
+    let text = fs_edit::read_text_or_empty(&target)?;
+    let language = language::detect(&target);
+    let symbols = tauri::async_runtime::spawn_blocking(move || {
+        language.map_or_else(Vec::new, |l| extract(l, &text))
+    })
+    .await
+    .unwrap_or_default();
+    Ok(Outline { language, symbols })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `(depth, kind, name)` for every symbol, in outline order
+    fn shape(language: &str, text: &str) -> Vec<(usize, &'static str, String)> {
+        fn walk(symbols: &[Symbol], depth: usize, out: &mut Vec<(usize, &'static str, String)>) {
+            for symbol in symbols {
+                out.push((depth, symbol.kind.label(), symbol.name.clone()));
+                walk(&symbol.children, depth + 1, out);
+            }
+        }
+        let mut out = Vec::new();
+        walk(&extract(language, text), 0, &mut out);
+        out
+    }
+
+    fn find<'s>(symbols: &'s [Symbol], name: &str) -> &'s Symbol {
+        flatten(symbols)
+            .into_iter()
+            .find(|s| s.name == name)
+            .unwrap_or_else(|| panic!("no symbol {name}"))
+    }
+
+    fn s(depth: usize, kind: &'static str, name: &str) -> (usize, &'static str, String) {
+        (depth, kind, name.to_string())
+    }
+
+    const TYPESCRIPT: &str = "\
+/** Reads files */
+export class Reader {
+  private cache: Map<string, string>;
+  constructor(root: string) {
+    const inner = () => 1;
+  }
+  read(path: string): string {
+    return path;
+  }
+}
+
+export interface Options {
+  depth: number;
+}
+
+export type Id = string;
+export enum Mode { Fast, Slow }
+export const MAX = 10;
+let count = 0;
+export const load = async (path: string) => {
+  return path;
+};
+function helper() {}
+";
+
+    #[test]
+    fn typescript_outline() {
+        assert_eq!(
+            shape("typescript", TYPESCRIPT),
+            [
+                s(0, "class", "Reader"),
+                s(1, "field", "cache"),
+                s(1, "method", "constructor"),
+                s(1, "method", "read"),
+                s(0, "interface", "Options"),
+                s(1, "field", "depth"),
+                s(0, "type", "Id"),
+                s(0, "enum", "Mode"),
+                s(0, "constant", "MAX"),
+                s(0, "variable", "count"),
+                s(0, "function", "load"),
+                s(0, "function", "helper"),
+            ]
+        );
+        let symbols = extract("typescript", TYPESCRIPT);
+        let reader = find(&symbols, "Reader");
+        assert_eq!(reader.doc.as_deref(), Some("Reads files"));
+        assert_eq!(reader.range.start.line, 2);
+        assert_eq!(reader.range.end.line, 10);
+        assert_eq!(
+            find(&symbols, "read").signature.as_deref(),
+            Some("read(path: string): string")
+        );
+        assert_eq!(
+            find(&symbols, "load").signature.as_deref(),
+            Some("const load = async (path: string) =>")
+        );
+    }
+
+    #[test]
+    fn tsx_and_javascript_outlines() {
+        let tsx = "export function App(): JSX.Element {\n  return <div />;\n}\n";
+        assert_eq!(shape("typescriptreact", tsx), [s(0, "function", "App")]);
+
+        let js = "\
+// Counts things
+class Counter {
+  count = 0;
+  increment() {
+    this.count++;
+  }
+}
+const make = function () {};
+module.exports = { Counter };
+";
+        assert_eq!(
+            shape("javascript", js),
+            [
+                s(0, "class", "Counter"),
+                s(1, "field", "count"),
+                s(1, "method", "increment"),
+                s(0, "function", "make"),
+            ]
+        );
+        let symbols = extract("javascriptreact", js);
+        assert_eq!(
+            find(&symbols, "Counter").doc.as_deref(),
+            Some("Counts things")
+        );
+    }
+
+    const PYTHON: &str = "\
+LIMIT = 3
+
+class Store:
+    \"\"\"Keeps things.
+
+    Across restarts.
+    \"\"\"
+
+    kind = 'disk'
+
+    def __init__(self, path):
+        self.path = path
+
+    @property
+    def size(self):
+        total = 0
+        return total
+
+# Opens a store
+def open_store(path):
+    def inner():
+        pass
+    return Store(path)
+";
+
+    #[test]
+    fn python_outline() {
+        assert_eq!(
+            shape("python", PYTHON),
+            [
+                s(0, "variable", "LIMIT"),
+                s(0, "class", "Store"),
+                s(1, "field", "kind"),
+                s(1, "method", "__init__"),
+                s(1, "method", "size"),
+                s(0, "function", "open_store"),
+            ]
+        );
+        let symbols = extract("python", PYTHON);
+        assert_eq!(
+            find(&symbols, "Store").doc.as_deref(),
+            Some("Keeps things.\n\nAcross restarts.")
+        );
+        assert_eq!(
+            find(&symbols, "open_store").doc.as_deref(),
+            Some("Opens a store")
+        );
+        assert_eq!(
+            find(&symbols, "open_store").signature.as_deref(),
+            Some("def open_store(path)")
+        );
+    }
+
+    const RUST: &str = "\
+//! Crate docs
+
+/// A point
+#[derive(Debug)]
+pub struct Point {
+    pub x: i32,
+    y: i32,
+}
+
+pub struct Pair(u8, u8);
+
+pub enum Shape {
+    Circle,
+}
+
+pub trait Area {
+    fn area(&self) -> f64;
+}
+
+impl Area for Point {
+    fn area(&self) -> f64 {
+        let square = |v: f64| v * v;
+        square(1.0)
+    }
+}
+
+impl Point {
+    pub const ORIGIN: Point = Point { x: 0, y: 0 };
+}
+
+mod nested {
+    pub fn run() {}
+}
+
+macro_rules! twice {
+    ($e:expr) => {
+        $e;
+        $e
+    };
+}
+
+static NAME: &str = \"p\";
+type Grid = Vec<Point>;
+";
+
+    #[test]
+    fn rust_outline() {
+        assert_eq!(
+            shape("rust", RUST),
+            [
+                s(0, "struct", "Point"),
+                s(1, "field", "x"),
+                s(1, "field", "y"),
+                s(0, "struct", "Pair"),
+                s(0, "enum", "Shape"),
+                s(0, "interface", "Area"),
+                s(1, "method", "area"),
+                s(0, "impl", "impl Area for Point"),
+                s(1, "method", "area"),
+                s(0, "impl", "impl Point"),
+                s(1, "constant", "ORIGIN"),
+                s(0, "module", "nested"),
+                s(1, "function", "run"),
+                s(0, "function", "twice"),
+                s(0, "constant", "NAME"),
+                s(0, "type", "Grid"),
+            ]
+        );
+        let symbols = extract("rust", RUST);
+        let point = find(&symbols, "Point");
+        assert_eq!(point.doc.as_deref(), Some("A point"));
+        assert_eq!(point.signature.as_deref(), Some("pub struct Point"));
+        assert_eq!(point.range.start.line, 5);
+        assert_eq!(point.range.start.column, 1);
+        assert_eq!(
+            symbols[4].children[0].signature.as_deref(),
+            Some("fn area(&self) -> f64")
+        );
+        assert_eq!(
+            find(&symbols, "twice").signature.as_deref(),
+            Some("macro_rules! twice")
+        );
+    }
+
+    #[test]
+    fn languages_without_a_grammar_have_no_outline() {
+        assert!(extract("markdown", "# Title\n").is_empty());
+        assert!(parse("markdown", "# Title\n").is_none());
+    }
+
+    #[test]
+    fn long_signatures_are_cut() {
+        let params = (0..100)
+            .map(|i| format!("a{i}: u8"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let symbols = extract("rust", &format!("fn wide({params}) {{}}\n"));
+        let signature = symbols[0].signature.as_deref().unwrap();
+        assert_eq!(signature.chars().count(), MAX_SIGNATURE + 1);
+        assert!(signature.ends_with('…'));
+    }
+
+    #[test]
+    fn comments_lose_their_markers() {
+        assert_eq!(strip_comment("/// one\n/// two"), "one\ntwo");
+        assert_eq!(strip_comment("/**\n * one\n * two\n */"), "one\ntwo");
+        assert_eq!(strip_comment("# hash"), "hash");
+        assert_eq!(strip_comment("//! inner"), "inner");
+        assert_eq!(dedent("First\n    a\n      b\n    "), "First\na\n  b");
+    }
+}
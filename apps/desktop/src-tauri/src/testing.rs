@@ -0,0 +1,1337 @@
+//! Test runner
+//!
+//! Finds the project a set of changed files belongs to by walking up to the
+//! nearest manifest, detects its test framework (cargo, vitest, jest,
+//! pytest, go test, or a `package.json` test script), and runs it scoped to
+//! those files where the framework can. Each framework is asked for a
+//! machine-readable report — JUnit XML, Jest JSON, `go test -json`, TAP or
+//! libtest's own lines — which is parsed into per-test results with the
+//! file and line of each failure. Test commands are put to the command
+//! policy before they run.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, State};
+
+use crate::exec::{self, CommandOutput, ExecError, RunOptions};
+use crate::policy::{self, CommandPolicy};
+use crate::workspace::{Workspace, WorkspaceError};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Captured output per stream; libtest and `go test -json` report on stdout
+const MAX_OUTPUT: usize = 8 * 1024 * 1024;
+
+/// Tail of the combined output returned with each run
+const OUTPUT_TAIL: usize = 64 * 1024;
+
+/// Toolchain variables passed through on top of the usual allowlist
+const TOOLCHAIN_ENV: [&str; 10] = [
+    "CARGO_HOME",
+    "RUSTUP_HOME",
+    "RUSTUP_TOOLCHAIN",
+    "GOPATH",
+    "GOROOT",
+    "GOFLAGS",
+    "GOCACHE",
+    "VIRTUAL_ENV",
+    "PYTHONPATH",
+    "NODE_OPTIONS",
+];
+
+/// `path:line` or `path:line:column`, as found in stack traces, where
+/// ES modules show up as `file://` URLs
+static LOCATION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?:file://)?((?:\b[A-Za-z]:)?[\w.@/\\-]*[\w-]\.[A-Za-z]+):(\d+)(?::(\d+))?")
+        .unwrap()
+});
+
+/// Where a Rust panic happened: `panicked at src/lib.rs:10:5`
+static PANIC: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"panicked at (?:'.*', )?(\S+?):(\d+):(\d+)").unwrap());
+
+#[derive(Debug, thiserror::Error, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum TestError {
+    #[error("no test framework found for {path}")]
+    NotDetected { path: String },
+    #[error("cannot create a test report file: {message}")]
+    Report { message: String },
+    #[error(transparent)]
+    Exec { reason: ExecError },
+}
+
+impl From<ExecError> for TestError {
+    fn from(reason: ExecError) -> Self {
+        Self::Exec { reason }
+    }
+}
+
+impl From<WorkspaceError> for TestError {
+    fn from(reason: WorkspaceError) -> Self {
+        ExecError::from(reason).into()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Framework {
+    Cargo,
+    Vitest,
+    Jest,
+    Pytest,
+    Go,
+    /// The `test` script of `package.json`, whatever it runs
+    Script,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PackageManager {
+    Npm,
+    Pnpm,
+    Yarn,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReportFormat {
+    Libtest,
+    Junit,
+    JestJson,
+    GoJson,
+    /// Sniffed from the output of a test script
+    Tap,
+}
+
+/// Tests about to run in one project
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestPlan {
+    pub framework: Framework,
+    pub package_manager: Option<PackageManager>,
+    pub project: PathBuf,
+    /// Files or packages the run is limited to; empty runs everything
+    pub scope: Vec<String>,
+    pub report: ReportFormat,
+    /// The command, without the reporter's output file
+    pub argv: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Location {
+    pub file: PathBuf,
+    pub line: u32,
+    pub column: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestCase {
+    pub name: String,
+    /// File, module or package the test belongs to
+    pub suite: Option<String>,
+    pub status: TestStatus,
+    pub duration_ms: Option<u64>,
+    /// Failure message and stack, for failed tests
+    pub message: Option<String>,
+    /// Where the failure happened, as near as the report says
+    pub location: Option<Location>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestSummary {
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestRun {
+    pub plan: TestPlan,
+    /// The command exited cleanly and no test failed
+    pub success: bool,
+    pub summary: TestSummary,
+    pub tests: Vec<TestCase>,
+    /// Why per-test results are missing, if they are
+    pub report_error: Option<String>,
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+    pub duration_ms: u64,
+    /// The end of stderr and stdout
+    pub output: String,
+}
+
+/// What a project's manifests say about its tests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Detected {
+    framework: Framework,
+    package_manager: Option<PackageManager>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct PackageJson {
+    scripts: HashMap<String, String>,
+    dependencies: HashMap<String, serde_json::Value>,
+    dev_dependencies: HashMap<String, serde_json::Value>,
+}
+
+/// The test framework of the project in `dir`, from its manifests alone
+fn detect(dir: &Path, root: &Path) -> Option<Detected> {
+    let found = |framework| {
+        Some(Detected {
+            framework,
+            package_manager: None,
+        })
+    };
+    if dir.join("Cargo.toml").is_file() {
+        return found(Framework::Cargo);
+    }
+    if dir.join("go.mod").is_file() {
+        return found(Framework::Go);
+    }
+    if let Ok(text) = fs::read_to_string(dir.join("package.json")) {
+        let package: PackageJson = serde_json::from_str(&text).unwrap_or_default();
+        let script = package.scripts.get("test").map_or("", String::as_str);
+        let depends = |name: &str| {
+            package.dependencies.contains_key(name)
+                || package.dev_dependencies.contains_key(name)
+                || script.contains(name)
+        };
+        let framework = if depends("vitest") {
+            Some(Framework::Vitest)
+        } else if depends("jest") {
+            Some(Framework::Jest)
+        } else if !script.is_empty() && !script.contains("no test specified") {
+            Some(Framework::Script)
+        } else {
+            None
+        };
+        if let Some(framework) = framework {
+            return Some(Detected {
+                framework,
+                package_manager: Some(package_manager(dir, root)),
+            });
+        }
+    }
+    let python = [
+        "pytest.ini",
+        "pyproject.toml",
+        "setup.cfg",
+        "tox.ini",
+        "conftest.py",
+        "setup.py",
+    ];
+    if python.iter().any(|name| dir.join(name).is_file()) {
+        return found(Framework::Pytest);
+    }
+    None
+}
+
+/// The package manager whose lockfile is nearest, up to `root`
+fn package_manager(dir: &Path, root: &Path) -> PackageManager {
+    for dir in dir.ancestors().take_while(|d| d.starts_with(root)) {
+        if dir.join("pnpm-lock.yaml").is_file() {
+            return PackageManager::Pnpm;
+        }
+        if dir.join("yarn.lock").is_file() {
+            return PackageManager::Yarn;
+        }
+        if dir.join("package-lock.json").is_file() {
+            return PackageManager::Npm;
+        }
+    }
+    PackageManager::Npm
+}
+
+/// The nearest directory from `file` up to `root` with a test framework
+fn project_of(file: &Path, root: &Path) -> Option<(PathBuf, Detected)> {
+    file.ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(root))
+        .find_map(|dir| detect(dir, root).map(|detected| (dir.to_path_buf(), detected)))
+}
+
+/// Plans for the projects `changed` belongs to, or for `root` if empty
+pub fn plan(root: &Path, changed: &[PathBuf]) -> Result<Vec<TestPlan>, TestError> {
+    let mut projects: BTreeMap<PathBuf, (Detected, Vec<PathBuf>)> = BTreeMap::new();
+    for file in changed {
+        if let Some((dir, detected)) = project_of(file, root) {
+            projects
+                .entry(dir)
+                .or_insert_with(|| (detected, Vec::new()))
+                .1
+                .push(file.clone());
+        }
+    }
+    if projects.is_empty() {
+        let detected = detect(root, root).ok_or_else(|| TestError::NotDetected {
+            path: root.display().to_string(),
+        })?;
+        projects.insert(root.to_path_buf(), (detected, Vec::new()));
+    }
+    Ok(projects
+        .into_iter()
+        .map(|(project, (detected, files))| {
+            let relative: Vec<String> = files
+                .iter()
+                .filter_map(|file| file.strip_prefix(&project).ok())
+                .map(|file| file.to_string_lossy().replace('\\', "/"))
+                .collect();
+            let scope = scope(detected.framework, &relative);
+            let mut plan = TestPlan {
+                framework: detected.framework,
+                package_manager: detected.package_manager,
+                project,
+                scope,
+                report: report_format(detected.framework),
+                argv: Vec::new(),
+            };
+            plan.argv = command(&plan, None);
+            plan
+        })
+        .collect())
+}
+
+/// What a run can be limited to, given the changed files of a project
+fn scope(framework: Framework, files: &[String]) -> Vec<String> {
+    match framework {
+        // Both work out which tests depend on the files themselves
+        Framework::Vitest | Framework::Jest => files.to_vec(),
+        // Changed tests run alone; a changed module could break any test
+        Framework::Pytest => {
+            let python: Vec<&String> = files.iter().filter(|f| f.ends_with(".py")).collect();
+            let is_test = |file: &&String| {
+                let name = file.rsplit('/').next().unwrap_or(file);
+                name.starts_with("test_") || name.ends_with("_test.py")
+            };
+            if !python.is_empty() && python.iter().all(is_test) {
+                python.into_iter().cloned().collect()
+            } else {
+                Vec::new()
+            }
+        }
+        Framework::Go => {
+            let mut packages: Vec<String> = files
+                .iter()
+                .filter(|f| f.ends_with(".go"))
+                .map(|f| match f.rsplit_once('/') {
+                    Some((dir, _)) => format!("./{}", dir),
+                    None => ".".to_owned(),
+                })
+                .collect();
+            packages.sort();
+            packages.dedup();
+            packages
+        }
+        // Cargo is already limited to the package by running in its
+        // directory; a test script takes no arguments we can rely on
+        Framework::Cargo | Framework::Script => Vec::new(),
+    }
+}
+
+fn report_format(framework: Framework) -> ReportFormat {
+    match framework {
+        Framework::Cargo => ReportFormat::Libtest,
+        Framework::Vitest | Framework::Pytest => ReportFormat::Junit,
+        Framework::Jest => ReportFormat::JestJson,
+        Framework::Go => ReportFormat::GoJson,
+        Framework::Script => ReportFormat::Tap,
+    }
+}
+
+/// A package's own binary, run through its package manager
+fn package_bin(manager: Option<PackageManager>, bin: &str) -> Vec<String> {
+    let argv: &[&str] = match manager.unwrap_or(PackageManager::Npm) {
+        PackageManager::Npm => &["npm", "exec", "--no", "--", bin],
+        PackageManager::Pnpm => &["pnpm", "exec", bin],
+        PackageManager::Yarn => &["yarn", bin],
+    };
+    argv.iter().map(|arg| arg.to_string()).collect()
+}
+
+/// The command for `plan`, writing its report to `report` if the framework
+/// reports to a file
+fn command(plan: &TestPlan, report: Option<&Path>) -> Vec<String> {
+    let report = report.map(|path| path.display().to_string());
+    let mut argv: Vec<String> = match plan.framework {
+        Framework::Cargo => vec!["cargo".into(), "test".into()],
+        Framework::Go => vec!["go".into(), "test".into(), "-json".into()],
+        Framework::Pytest => vec!["pytest".into(), "-q".into()],
+        Framework::Vitest => {
+            let mut argv = package_bin(plan.package_manager, "vitest");
+            if !plan.scope.is_empty() {
+                argv.push("related".into());
+            }
+            argv.extend(["--run".into(), "--reporter=junit".into()]);
+            argv
+        }
+        Framework::Jest => {
+            let mut argv = package_bin(plan.package_manager, "jest");
+            argv.extend([
+                "--ci".into(),
+                "--json".into(),
+                "--testLocationInResults".into(),
+            ]);
+            if !plan.scope.is_empty() {
+                argv.push("--findRelatedTests".into());
+            }
+            argv
+        }
+        Framework::Script => {
+            let manager = plan.package_manager.unwrap_or(PackageManager::Npm);
+            let program = match manager {
+                PackageManager::Npm => "npm",
+                PackageManager::Pnpm => "pnpm",
+                PackageManager::Yarn => "yarn",
+            };
+            vec![program.into(), "test".into()]
+        }
+    };
+    if let Some(report) = report {
+        match plan.framework {
+            Framework::Vitest | Framework::Jest => argv.push(format!("--outputFile={}", report)),
+            Framework::Pytest => argv.push(format!("--junitxml={}", report)),
+            _ => {}
+        }
+    }
+    argv.extend(plan.scope.iter().cloned());
+    argv
+}
+
+/// Whether the framework writes its report to a file rather than stdout
+fn reports_to_file(framework: Framework) -> bool {
+    matches!(
+        framework,
+        Framework::Vitest | Framework::Jest | Framework::Pytest
+    )
+}
+
+/// The environment and output limit every test run gets
+fn run_options() -> RunOptions {
+    RunOptions {
+        env: [("CI", "true"), ("NO_COLOR", "1"), ("FORCE_COLOR", "0")]
+            .into_iter()
+            .map(|(name, value)| (name.to_owned(), value.to_owned()))
+            .collect(),
+        inherit_env: TOOLCHAIN_ENV.iter().map(|name| name.to_string()).collect(),
+        max_output_bytes: Some(MAX_OUTPUT),
+        ..RunOptions::default()
+    }
+}
+
+/// Run `plan` and collect per-test results
+fn run(plan: TestPlan, timeout: Duration) -> Result<TestRun, TestError> {
+    let report_file = if reports_to_file(plan.framework) {
+        let suffix = match plan.report {
+            ReportFormat::Junit => ".xml",
+            _ => ".json",
+        };
+        let file = tempfile::Builder::new()
+            .prefix("henry-tests-")
+            .suffix(suffix)
+            .tempfile()
+            .map_err(|e| TestError::Report {
+                message: e.to_string(),
+            })?;
+        Some(file)
+    } else {
+        None
+    };
+    let mut argv = command(&plan, report_file.as_ref().map(|file| file.path()));
+    // The program the command policy resolved, if it was asked
+    if let Some(program) = plan.argv.first() {
+        argv[0] = program.clone();
+    }
+    let options = RunOptions {
+        timeout_ms: Some(timeout.as_millis() as u64),
+        ..run_options()
+    };
+    let output = exec::run(&argv, &plan.project, &options)?;
+
+    let report = match &report_file {
+        Some(file) => fs::read_to_string(file.path())
+            .ok()
+            .filter(|text| !text.trim().is_empty())
+            .ok_or_else(|| "the test run wrote no report".to_owned()),
+        None => Ok(output.stdout.clone()),
+    };
+    let parsed = report.and_then(|text| match plan.report {
+        ReportFormat::Libtest => Ok(parse_libtest(&text, &plan.project)),
+        ReportFormat::Junit => parse_junit(&text, &plan.project),
+        ReportFormat::JestJson => parse_jest(&text, &plan.project),
+        ReportFormat::GoJson => Ok(parse_go(&text, &plan.project)),
+        ReportFormat::Tap => Ok(parse_tap(&text, &plan.project)),
+    });
+    let (tests, report_error) = match parsed {
+        Ok(tests) if tests.is_empty() && plan.report == ReportFormat::Tap => {
+            (tests, Some("the test script printed no TAP".to_owned()))
+        }
+        Ok(tests) if tests.is_empty() && output.exit_code != Some(0) => (
+            tests,
+            Some("the test run failed before reporting any test".to_owned()),
+        ),
+        Ok(tests) => (tests, None),
+        Err(message) => (Vec::new(), Some(message)),
+    };
+    Ok(finish(plan, output, tests, report_error))
+}
+
+fn finish(
+    plan: TestPlan,
+    output: CommandOutput,
+    tests: Vec<TestCase>,
+    report_error: Option<String>,
+) -> TestRun {
+    let mut summary = TestSummary::default();
+    for test in &tests {
+        match test.status {
+            TestStatus::Passed => summary.passed += 1,
+            TestStatus::Failed => summary.failed += 1,
+            TestStatus::Skipped => summary.skipped += 1,
+        }
+    }
+    let mut combined = output.stderr;
+    combined.push_str(&output.stdout);
+    let cut = combined.len().saturating_sub(OUTPUT_TAIL);
+    let cut = (cut..combined.len())
+        .find(|&i| combined.is_char_boundary(i))
+        .unwrap_or(combined.len());
+    TestRun {
+        plan,
+        success: output.exit_code == Some(0) && !output.timed_out && summary.failed == 0,
+        summary,
+        tests,
+        report_error,
+        exit_code: output.exit_code,
+        timed_out: output.timed_out,
+        duration_ms: output.duration_ms,
+        output: combined[cut..].to_owned(),
+    }
+}
+
+/// The most telling `path:line` in `text`: in `hint` if it is named,
+/// otherwise the first outside dependencies and runtime internals
+fn locate(text: &str, project: &Path, hint: Option<&str>) -> Option<Location> {
+    let found: Vec<Location> = LOCATION
+        .captures_iter(text)
+        .filter(|c| {
+            let file = &c[1];
+            !file.contains("node_modules") && !file.starts_with("node:")
+        })
+        .filter_map(|c| {
+            Some(Location {
+                file: in_project(&c[1], project),
+                line: c[2].parse().ok()?,
+                column: c.get(3).and_then(|m| m.as_str().parse().ok()),
+            })
+        })
+        .collect();
+    let hinted = hint.map(|hint| in_project(hint, project)).and_then(|hint| {
+        found
+            .iter()
+            .find(|location| location.file == hint || location.file.ends_with(&hint))
+    });
+    hinted
+        .or_else(|| found.iter().find(|location| location.file.exists()))
+        .or(found.first())
+        .cloned()
+}
+
+/// `file` made absolute against the project if it is relative there
+fn in_project(file: &str, project: &Path) -> PathBuf {
+    let path = Path::new(file);
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        project.join(path)
+    }
+}
+
+/// `cargo test`'s plain output: one `test name ... ok` line per test, then
+/// the captured output of each failure
+fn parse_libtest(text: &str, project: &Path) -> Vec<TestCase> {
+    let mut tests = Vec::new();
+    let mut suite: Option<String> = None;
+    // Test name and suite to index, for attaching failure output
+    let mut index = HashMap::new();
+    let mut failure: Option<(usize, Vec<&str>)> = None;
+    let attach = |failure: Option<(usize, Vec<&str>)>, tests: &mut Vec<TestCase>| {
+        if let Some((i, lines)) = failure {
+            let message = lines.join("\n").trim().to_owned();
+            let test: &mut TestCase = &mut tests[i];
+            test.location = PANIC
+                .captures(&message)
+                .and_then(|c| {
+                    Some(Location {
+                        file: in_project(&c[1], project),
+                        line: c[2].parse().ok()?,
+                        column: c[3].parse().ok(),
+                    })
+                })
+                .or_else(|| locate(&message, project, None));
+            test.message = Some(message);
+        }
+    };
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if let Some(rest) = trimmed.strip_prefix("Running ") {
+            attach(failure.take(), &mut tests);
+            // `Running unittests src/lib.rs (target/debug/deps/…)`
+            let rest = rest.strip_prefix("unittests ").unwrap_or(rest);
+            suite = Some(rest.split(" (").next().unwrap_or(rest).to_owned());
+            continue;
+        }
+        if let Some(rest) = trimmed.strip_prefix("Doc-tests ") {
+            attach(failure.take(), &mut tests);
+            suite = Some(format!("doc-tests {}", rest));
+            continue;
+        }
+        if let Some(name) = line
+            .strip_prefix("---- ")
+            .and_then(|rest| rest.strip_suffix(" stdout ----"))
+        {
+            attach(failure.take(), &mut tests);
+            failure = index
+                .get(&(suite.clone(), name.to_owned()))
+                .map(|&i| (i, Vec::new()));
+            continue;
+        }
+        if let Some((_, lines)) = &mut failure {
+            if line == "failures:" || line.starts_with("test result:") {
+                attach(failure.take(), &mut tests);
+            } else {
+                lines.push(line);
+            }
+            continue;
+        }
+        let Some((name, result)) = line
+            .strip_prefix("test ")
+            .and_then(|rest| rest.rsplit_once(" ... "))
+        else {
+            continue;
+        };
+        let status = match result {
+            "ok" => TestStatus::Passed,
+            "FAILED" => TestStatus::Failed,
+            r if r.starts_with("ignored") => TestStatus::Skipped,
+            _ => continue,
+        };
+        index.insert((suite.clone(), name.to_owned()), tests.len());
+        tests.push(TestCase {
+            name: name.to_owned(),
+            suite: suite.clone(),
+            status,
+            duration_ms: None,
+            message: None,
+            location: None,
+        });
+    }
+    attach(failure, &mut tests);
+    tests
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Option<String> {
+    element
+        .try_get_attribute(name)
+        .ok()
+        .flatten()
+        .and_then(|attr| attr.unescape_value().ok())
+        .map(|value| value.into_owned())
+}
+
+/// JUnit XML as written by pytest and vitest
+fn parse_junit(text: &str, project: &Path) -> Result<Vec<TestCase>, String> {
+    let mut reader = Reader::from_str(text);
+    let mut tests = Vec::new();
+    let mut suite: Option<String> = None;
+    let mut current: Option<(TestCase, Option<String>)> = None;
+    // Collecting the text of a failure or error element
+    let mut failure: Option<String> = None;
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| format!("unreadable JUnit report: {}", e))?;
+        match event {
+            Event::Start(ref e) | Event::Empty(ref e) if e.name().as_ref() == b"testsuite" => {
+                suite = attribute(e, "name");
+            }
+            Event::Start(ref e) | Event::Empty(ref e) if e.name().as_ref() == b"testcase" => {
+                let classname = attribute(e, "classname");
+                let file = attribute(e, "file");
+                let line = attribute(e, "line").and_then(|line| line.parse().ok());
+                let test = TestCase {
+                    name: attribute(e, "name").unwrap_or_default(),
+                    suite: classname.or_else(|| suite.clone()),
+                    status: TestStatus::Passed,
+                    duration_ms: attribute(e, "time")
+                        .and_then(|time| time.parse::<f64>().ok())
+                        .map(|secs| (secs * 1000.0).round() as u64),
+                    message: None,
+                    location: file.as_deref().zip(line).map(|(file, line)| Location {
+                        file: in_project(file, project),
+                        line,
+                        column: None,
+                    }),
+                };
+                let hint = file.or_else(|| test.suite.clone());
+                if matches!(event, Event::Empty(_)) {
+                    tests.push(test);
+                } else {
+                    current = Some((test, hint));
+                }
+            }
+            Event::Start(ref e) | Event::Empty(ref e)
+                if matches!(e.name().as_ref(), b"failure" | b"error") =>
+            {
+                if let Some((test, _)) = &mut current {
+                    test.status = TestStatus::Failed;
+                    test.message = attribute(e, "message");
+                }
+                if matches!(event, Event::Start(_)) {
+                    failure = Some(String::new());
+                }
+            }
+            Event::Start(ref e) | Event::Empty(ref e) if e.name().as_ref() == b"skipped" => {
+                if let Some((test, _)) = &mut current {
+                    test.status = TestStatus::Skipped;
+                }
+            }
+            Event::Text(e) => {
+                if let Some(failure) = &mut failure {
+                    failure.push_str(&e.unescape().unwrap_or_default());
+                }
+            }
+            Event::CData(e) => {
+                if let Some(failure) = &mut failure {
+                    failure.push_str(&String::from_utf8_lossy(&e));
+                }
+            }
+            Event::End(e) => match e.name().as_ref() {
+                b"failure" | b"error" => {
+                    let details = failure.take().unwrap_or_default();
+                    if let Some((test, hint)) = &mut current {
+                        let details = details.trim();
+                        test.location = locate(details, project, hint.as_deref())
+                            .or_else(|| test.location.take());
+                        test.message = match test.message.take() {
+                            Some(message) if !details.contains(&message) => {
+                                Some(format!("{}\n{}", message, details).trim().to_owned())
+                            }
+                            _ => Some(details.to_owned()),
+                        };
+                    }
+                }
+                b"testcase" => {
+                    if let Some((test, _)) = current.take() {
+                        tests.push(test);
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(tests)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JestReport {
+    #[serde(default)]
+    test_results: Vec<JestFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JestFile {
+    name: String,
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    assertion_results: Vec<JestTest>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JestTest {
+    full_name: String,
+    status: String,
+    duration: Option<f64>,
+    #[serde(default)]
+    failure_messages: Vec<String>,
+    location: Option<JestLocation>,
+}
+
+#[derive(Deserialize)]
+struct JestLocation {
+    line: u32,
+    column: Option<u32>,
+}
+
+/// The report of `jest --json`
+fn parse_jest(text: &str, project: &Path) -> Result<Vec<TestCase>, String> {
+    let report: JestReport =
+        serde_json::from_str(text).map_err(|e| format!("unreadable Jest report: {}", e))?;
+    let mut tests = Vec::new();
+    for file in report.test_results {
+        let suite = Path::new(&file.name)
+            .strip_prefix(project)
+            .map_or(file.name.clone(), |path| path.display().to_string());
+        // The file failed before any test ran, e.g. it does not compile
+        if file.assertion_results.is_empty() && file.status == "failed" {
+            tests.push(TestCase {
+                name: suite.clone(),
+                suite: Some(suite),
+                status: TestStatus::Failed,
+                duration_ms: None,
+                location: locate(&file.message, project, Some(&file.name)),
+                message: Some(file.message),
+            });
+            continue;
+        }
+        for test in file.assertion_results {
+            let status = match test.status.as_str() {
+                "passed" => TestStatus::Passed,
+                "failed" => TestStatus::Failed,
+                _ => TestStatus::Skipped,
+            };
+            let message = test.failure_messages.join("\n");
+            let location = locate(&message, project, Some(&file.name)).or_else(|| {
+                test.location.map(|at| Location {
+                    file: PathBuf::from(&file.name),
+                    line: at.line,
+                    column: at.column,
+                })
+            });
+            tests.push(TestCase {
+                name: test.full_name,
+                suite: Some(suite.clone()),
+                status,
+                duration_ms: test.duration.map(|ms| ms.round() as u64),
+                message: (!message.is_empty()).then_some(message),
+                location: location.filter(|_| status == TestStatus::Failed),
+            });
+        }
+    }
+    Ok(tests)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GoEvent {
+    action: String,
+    #[serde(default)]
+    package: String,
+    test: Option<String>,
+    elapsed: Option<f64>,
+    output: Option<String>,
+}
+
+/// The directory of Go package `package`, from the module path in `go.mod`
+fn go_package_dir(project: &Path, module: Option<&str>, package: &str) -> PathBuf {
+    match module.and_then(|module| package.strip_prefix(module)) {
+        Some(rest) => project.join(rest.trim_start_matches('/')),
+        None => project.to_path_buf(),
+    }
+}
+
+/// The event stream of `go test -json`
+fn parse_go(text: &str, project: &Path) -> Vec<TestCase> {
+    let go_mod = fs::read_to_string(project.join("go.mod")).unwrap_or_default();
+    let module = go_mod
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("module "))
+        .map(|module| module.trim().trim_matches('"'));
+    let mut output: HashMap<(String, Option<String>), String> = HashMap::new();
+    let mut tests = Vec::new();
+    for line in text.lines() {
+        let Ok(event) = serde_json::from_str::<GoEvent>(line) else {
+            continue;
+        };
+        let key = (event.package.clone(), event.test.clone());
+        let status = match event.action.as_str() {
+            "output" => {
+                let text = event.output.unwrap_or_default();
+                output.entry(key).or_default().push_str(&text);
+                continue;
+            }
+            "pass" => TestStatus::Passed,
+            "fail" => TestStatus::Failed,
+            "skip" => TestStatus::Skipped,
+            _ => continue,
+        };
+        let log = output.remove(&key).unwrap_or_default();
+        // Test output names files in the package, build errors from the
+        // module root
+        let dir = match event.test {
+            Some(_) => go_package_dir(project, module, &event.package),
+            None => project.to_path_buf(),
+        };
+        // A package result only matters when it failed without any test
+        // failing, e.g. it does not build
+        let name = match event.test {
+            Some(name) => name,
+            None if status == TestStatus::Failed
+                && !tests.iter().any(|t: &TestCase| {
+                    t.suite.as_deref() == Some(event.package.as_str())
+                        && t.status == TestStatus::Failed
+                }) =>
+            {
+                event.package.clone()
+            }
+            None => continue,
+        };
+        let failed = status == TestStatus::Failed;
+        tests.push(TestCase {
+            name,
+            suite: Some(event.package),
+            status,
+            duration_ms: event.elapsed.map(|secs| (secs * 1000.0).round() as u64),
+            location: failed.then(|| locate(&log, &dir, None)).flatten(),
+            message: failed.then_some(log),
+        });
+    }
+    tests
+}
+
+/// TAP, as printed by `node --test`, tape and friends
+///
+/// Subtests are indented under their parent, whose own result line follows
+/// them; only the innermost tests are counted, with the parent as suite.
+fn parse_tap(text: &str, project: &Path) -> Vec<TestCase> {
+    let mut tests: Vec<TestCase> = Vec::new();
+    // Indentation of each test, and of the last result line of any kind
+    let mut depths: Vec<usize> = Vec::new();
+    let mut last_depth = 0;
+    // The test the next diagnostics belong to; `None` after a parent
+    let mut current: Option<usize> = None;
+    // YAML diagnostics after a test, up to `...`
+    let mut diagnostics: Option<Vec<&str>> = None;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if let Some(block) = &mut diagnostics {
+            if trimmed != "..." {
+                block.push(trimmed);
+                continue;
+            }
+            let Some(test) = current.map(|i| &mut tests[i]) else {
+                diagnostics = None;
+                continue;
+            };
+            test.duration_ms = block
+                .iter()
+                .find_map(|line| line.strip_prefix("duration_ms:"))
+                .and_then(|ms| ms.trim().parse::<f64>().ok())
+                .map(|ms| ms.round() as u64);
+            if test.status == TestStatus::Failed {
+                let block = block.join("\n");
+                test.location = locate(&block, project, None);
+                test.message = Some(block);
+            }
+            diagnostics = None;
+            continue;
+        }
+        if trimmed == "---" {
+            diagnostics = Some(Vec::new());
+            continue;
+        }
+        let (ok, rest) = if let Some(rest) = trimmed.strip_prefix("not ok") {
+            (false, rest)
+        } else if let Some(rest) = trimmed.strip_prefix("ok") {
+            (true, rest)
+        } else {
+            continue;
+        };
+        // `okay` or `not okay` is output, not a test point
+        if rest.starts_with(|c: char| !c.is_whitespace()) {
+            continue;
+        }
+        // ` 3 - name # SKIP reason`
+        let rest = rest
+            .trim_start()
+            .trim_start_matches(|c: char| c.is_ascii_digit());
+        let rest = rest.trim_start().trim_start_matches('-').trim();
+        let (name, directive) = match rest.split_once(" # ") {
+            Some((name, directive)) => (name, directive.to_ascii_uppercase()),
+            None => (rest, String::new()),
+        };
+
+        let depth = line.len() - line.trim_start().len();
+        let parent = last_depth > depth;
+        last_depth = depth;
+        if parent {
+            // Sums up the subtests just read
+            for (test, _) in tests
+                .iter_mut()
+                .zip(&depths)
+                .rev()
+                .take_while(|(_, &d)| d > depth)
+            {
+                test.suite.get_or_insert_with(|| name.to_owned());
+            }
+            // A failing parent is kept: it can fail on its own, in a hook,
+            // with every subtest passing
+            if ok {
+                current = None;
+                continue;
+            }
+        }
+        let status = if directive.starts_with("SKIP") || directive.starts_with("TODO") {
+            TestStatus::Skipped
+        } else if ok {
+            TestStatus::Passed
+        } else {
+            TestStatus::Failed
+        };
+        current = Some(tests.len());
+        depths.push(depth);
+        tests.push(TestCase {
+            name: name.to_owned(),
+            suite: None,
+            status,
+            duration_ms: None,
+            message: None,
+            location: None,
+        });
+    }
+    tests
+}
+
+/// Open root `root` and the changed files resolved inside the workspace
+fn resolve(
+    workspace: &Workspace,
+    root: Option<&Path>,
+    changed: &[PathBuf],
+) -> Result<(PathBuf, Vec<PathBuf>), TestError> {
+    let root = workspace.pick_root(root)?.path;
+    let changed = changed
+        .iter()
+        .map(|file| workspace.resolve(file))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((root, changed))
+}
+
+/// The test runs `run_tests` would start, without starting them
+#[tauri::command]
+pub fn detect_tests(
+    workspace: State<'_, Workspace>,
+    root: Option<PathBuf>,
+    changed: Option<Vec<PathBuf>>,
+) -> Result<Vec<TestPlan>, TestError> {
+    let (root, changed) = resolve(&workspace, root.as_deref(), &changed.unwrap_or_default())?;
+    plan(&root, &changed)
+}
+
+/// Run the tests of the projects `changed` files belong to, limited to
+/// those files where the framework allows, or all tests of `root`
+///
+/// Each test command goes through the command policy first, like any
+/// other command run for the agent.
+#[tauri::command]
+pub async fn run_tests(
+    app: AppHandle,
+    workspace: State<'_, Workspace>,
+    policy: State<'_, CommandPolicy>,
+    root: Option<PathBuf>,
+    changed: Option<Vec<PathBuf>>,
+    timeout_ms: Option<u64>,
+) -> Result<Vec<TestRun>, TestError> {
+    let (root, changed) = resolve(&workspace, root.as_deref(), &changed.unwrap_or_default())?;
+    let plans = authorize(&app, &workspace, &policy, plan(&root, &changed)?).await?;
+    run_plans(plans, timeout_ms).await
+}
+
+/// Put each of `plans` to the command policy, asking the user where it
+/// says to; the plans come back with the programs the policy resolved
+pub async fn authorize(
+    app: &AppHandle,
+    workspace: &Workspace,
+    policy: &CommandPolicy,
+    mut plans: Vec<TestPlan>,
+) -> Result<Vec<TestPlan>, TestError> {
+    let options = run_options();
+    for plan in &mut plans {
+        plan.argv =
+            policy::authorize(app, workspace, policy, &plan.argv, &plan.project, &options).await?;
+    }
+    Ok(plans)
+}
+
+/// Run plans from [`plan`] one after another
+pub async fn run_plans(
+    plans: Vec<TestPlan>,
+    timeout_ms: Option<u64>,
+) -> Result<Vec<TestRun>, TestError> {
+    let timeout = timeout_ms.map_or(DEFAULT_TIMEOUT, Duration::from_millis);
+    tauri::async_runtime::spawn_blocking(move || {
+        plans
+            .into_iter()
+            .map(|plan| run(plan, timeout))
+            .collect::<Result<Vec<_>, _>>()
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Err(ExecError::Wait {
+            program: String::new(),
+            message: e.to_string(),
+        }
+        .into())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(name: &str) -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/reports")
+            .join(name);
+        fs::read_to_string(&path).unwrap()
+    }
+
+    fn find<'a>(tests: &'a [TestCase], name: &str) -> &'a TestCase {
+        tests.iter().find(|t| t.name == name).unwrap()
+    }
+
+    fn at(file: &str, line: u32, column: Option<u32>) -> Option<Location> {
+        Some(Location {
+            file: PathBuf::from(file),
+            line,
+            column,
+        })
+    }
+
+    #[test]
+    fn libtest_output() {
+        let tests = parse_libtest(&report("libtest.txt"), Path::new("/project"));
+        let names: Vec<_> = tests
+            .iter()
+            .map(|t| (t.suite.as_deref().unwrap(), t.name.as_str(), t.status))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("src/lib.rs", "math::adds", TestStatus::Passed),
+                ("src/lib.rs", "math::divides", TestStatus::Failed),
+                ("src/lib.rs", "math::slow", TestStatus::Skipped),
+                ("tests/api.rs", "adds", TestStatus::Passed),
+                (
+                    "doc-tests demo",
+                    "src/lib.rs - add (line 3)",
+                    TestStatus::Passed
+                ),
+            ]
+        );
+        let failed = &tests[1];
+        assert_eq!(failed.location, at("/project/src/math.rs", 21, Some(9)));
+        let message = failed.message.as_deref().unwrap();
+        assert!(message.contains("left: 2"));
+        assert!(!message.contains("failures:"));
+    }
+
+    #[test]
+    fn junit_report() {
+        let tests = parse_junit(&report("junit.xml"), Path::new("/project")).unwrap();
+        assert_eq!(tests.len(), 4);
+        assert_eq!(find(&tests, "math > adds").status, TestStatus::Passed);
+        assert_eq!(find(&tests, "math > adds").duration_ms, Some(2));
+        assert_eq!(find(&tests, "math > later").status, TestStatus::Skipped);
+
+        let failed = find(&tests, "math > divides");
+        assert_eq!(failed.status, TestStatus::Failed);
+        assert_eq!(failed.suite.as_deref(), Some("src/math.test.ts"));
+        assert_eq!(
+            failed.location,
+            at("/project/src/math.test.ts", 12, Some(19))
+        );
+        assert!(failed
+            .message
+            .as_deref()
+            .unwrap()
+            .starts_with("AssertionError: expected 2 to be 3"));
+
+        let pytest = find(&tests, "test_get");
+        assert_eq!(pytest.suite.as_deref(), Some("tests.test_api"));
+        assert_eq!(pytest.location, at("/project/tests/test_api.py", 7, None));
+    }
+
+    #[test]
+    fn junit_garbage_is_an_error() {
+        assert!(parse_junit("<testsuite><testcase></testsuite>", Path::new("/")).is_err());
+    }
+
+    #[test]
+    fn jest_report() {
+        let tests = parse_jest(&report("jest.json"), Path::new("/project")).unwrap();
+        let names: Vec<_> = tests.iter().map(|t| (t.name.as_str(), t.status)).collect();
+        assert_eq!(
+            names,
+            [
+                ("math adds", TestStatus::Passed),
+                ("math divides", TestStatus::Failed),
+                ("math later", TestStatus::Skipped),
+                ("src/broken.test.js", TestStatus::Failed),
+            ]
+        );
+        assert_eq!(tests[0].suite.as_deref(), Some("src/math.test.js"));
+        assert_eq!(tests[0].location, None);
+        assert_eq!(tests[1].duration_ms, Some(5));
+        assert_eq!(
+            tests[1].location,
+            at("/project/src/math.test.js", 9, Some(19))
+        );
+        assert_eq!(
+            tests[3].location,
+            at("/project/src/broken.test.js", 4, Some(7))
+        );
+        assert!(tests[3].message.as_deref().unwrap().contains("SyntaxError"));
+    }
+
+    #[test]
+    fn go_events() {
+        let project = tempfile::tempdir().unwrap();
+        fs::write(
+            project.path().join("go.mod"),
+            "module example.com/demo\n\ngo 1.22\n",
+        )
+        .unwrap();
+        let tests = parse_go(&report("go.jsonl"), project.path());
+        let names: Vec<_> = tests.iter().map(|t| (t.name.as_str(), t.status)).collect();
+        // The failing package isn't reported again next to its failing test
+        assert_eq!(
+            names,
+            [
+                ("TestAdd", TestStatus::Passed),
+                ("TestDiv", TestStatus::Failed),
+                ("TestLater", TestStatus::Skipped),
+                ("example.com/demo/broken", TestStatus::Failed),
+            ]
+        );
+        assert_eq!(tests[1].suite.as_deref(), Some("example.com/demo/calc"));
+        assert_eq!(tests[1].duration_ms, Some(20));
+        assert!(tests[1]
+            .message
+            .as_deref()
+            .unwrap()
+            .contains("got 2, want 3"));
+        let in_package = project.path().join("calc/calc_test.go");
+        assert_eq!(tests[1].location.as_ref().unwrap().file, in_package);
+        assert_eq!(tests[1].location.as_ref().unwrap().line, 14);
+        let in_module = project.path().join("broken/broken.go");
+        assert_eq!(tests[3].location.as_ref().unwrap().file, in_module);
+    }
+
+    #[test]
+    fn tap_with_node_subtests() {
+        let tests = parse_tap(&report("tap.txt"), Path::new("/project"));
+        let names: Vec<_> = tests
+            .iter()
+            .map(|t| (t.suite.as_deref(), t.name.as_str(), t.status))
+            .collect();
+        // The failing `math` line is kept after the subtests it sums up
+        assert_eq!(
+            names,
+            [
+                (Some("math"), "adds", TestStatus::Passed),
+                (Some("math"), "divides", TestStatus::Failed),
+                (None, "math", TestStatus::Failed),
+                (None, "strings", TestStatus::Skipped),
+                (None, "todo later", TestStatus::Skipped),
+            ]
+        );
+        assert_eq!(tests[0].duration_ms, Some(1));
+        assert_eq!(tests[0].message, None);
+        let failed = &tests[1];
+        assert!(failed.message.as_deref().unwrap().contains("2 !== 3"));
+        assert_eq!(
+            failed.location,
+            at("/project/test/math.test.mjs", 9, Some(10))
+        );
+        // The parent's diagnostics don't land on its last subtest
+        assert_eq!(failed.duration_ms, Some(1));
+        assert_eq!(tests[2].duration_ms, Some(3));
+        assert!(tests[2]
+            .message
+            .as_deref()
+            .unwrap()
+            .contains("1 subtest failed"));
+    }
+
+    #[test]
+    fn tap_failing_parents_are_kept() {
+        let tests = parse_tap(&report("tap_parents.txt"), Path::new("/project"));
+        let names: Vec<_> = tests
+            .iter()
+            .map(|t| (t.suite.as_deref(), t.name.as_str(), t.status))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                (Some("db"), "connects", TestStatus::Passed),
+                (Some("db"), "queries", TestStatus::Passed),
+                (None, "db", TestStatus::Failed),
+                (None, "boots", TestStatus::Failed),
+            ]
+        );
+        let db = &tests[2];
+        assert!(db.message.as_deref().unwrap().contains("after hook failed"));
+        assert_eq!(db.location, at("/project/test/db.test.mjs", 3, Some(1)));
+        assert_eq!(db.duration_ms, Some(13));
+        assert!(tests[3]
+            .message
+            .as_deref()
+            .unwrap()
+            .contains("did not start"));
+    }
+
+    #[test]
+    fn tap_nested_suites_keep_the_innermost_parent() {
+        let text = [
+            "        ok 1 - inner leaf",
+            "    ok 1 - inner",
+            "    ok 2 - outer leaf",
+            "ok 1 - outer",
+            "not okay",
+            "ok 2 - top",
+        ]
+        .join("\n");
+        let tests = parse_tap(&text, Path::new("/project"));
+        let names: Vec<_> = tests
+            .iter()
+            .map(|t| (t.suite.as_deref(), t.name.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                (Some("inner"), "inner leaf"),
+                (Some("outer"), "outer leaf"),
+                (None, "top"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,458 @@
+//! Project file tree
+//!
+//! A workspace is walked once in parallel, honouring `.gitignore`, `.ignore`
+//! and `.henryignore`, and the result is cached per root. The frontend then
+//! pages through one directory at a time with `list_project_tree`, so
+//! expanding a folder never touches the disk. Watcher batches rescan only
+//! the directories they touch.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
+
+use ignore::{DirEntry, ParallelVisitor, ParallelVisitorBuilder, WalkBuilder, WalkState};
+use serde::Serialize;
+use tauri::State;
+
+use crate::language;
+use crate::watcher::FsChanged;
+use crate::workspace::{Workspace, WorkspaceError, WorkspaceRoot};
+
+/// Project-specific ignore file, same syntax as `.gitignore`
+pub const IGNORE_FILE: &str = ".henryignore";
+
+/// Ignore files read in every directory, lowest precedence first
+pub const IGNORE_FILES: &[&str] = &[".gitignore", ".ignore", IGNORE_FILE];
+
+/// Nodes per page when the caller doesn't ask for a size
+const DEFAULT_PAGE_SIZE: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NodeKind {
+    File,
+    Directory,
+    Symlink,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TreeNode {
+    pub name: String,
+    pub path: PathBuf,
+    pub kind: NodeKind,
+    pub size: u64,
+    pub mtime_ms: Option<u64>,
+    pub language: Option<&'static str>,
+    /// Visible entries in a directory, `None` for files
+    pub child_count: Option<usize>,
+}
+
+/// One page of a directory's children
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TreePage {
+    pub path: PathBuf,
+    pub nodes: Vec<TreeNode>,
+    pub total: usize,
+    /// Offset of the next page, `None` on the last one
+    pub next_offset: Option<usize>,
+}
+
+/// A walk configured with the workspace's ignore rules
+///
+/// Hidden files are included, since dotfiles like `.github` belong in the
+/// tree; `.git` itself never is.
+pub fn walker(root: &Path) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(root);
+    builder
+        .hidden(false)
+        .require_git(false)
+        .add_custom_ignore_filename(IGNORE_FILE)
+        .filter_entry(|entry| entry.file_name() != ".git");
+    builder
+}
+
+/// Every visible directory's children, sorted directories first
+#[derive(Clone)]
+struct Snapshot {
+    children: HashMap<PathBuf, Vec<TreeNode>>,
+}
+
+impl Snapshot {
+    fn scan(root: &WorkspaceRoot) -> Self {
+        let mut snapshot = Self {
+            children: walk(root, &root.path, None),
+        };
+        let dirs: Vec<PathBuf> = snapshot.children.keys().cloned().collect();
+        snapshot.settle(dirs);
+        snapshot
+    }
+
+    /// Bring the listings `changed` touched up to date
+    ///
+    /// Only the parents of changed paths are listed again; whole subtrees
+    /// are rescanned just for new or changed directories and for changed
+    /// ignore files, whose rules reach everything below them.
+    fn apply(&mut self, root: &WorkspaceRoot, changed: &FsChanged) {
+        let mut subtrees = BTreeSet::new();
+        let mut listings = BTreeSet::new();
+        let gone = changed
+            .removed
+            .iter()
+            .chain(changed.renamed.iter().map(|r| &r.from));
+        for path in gone {
+            self.children.retain(|dir, _| !dir.starts_with(path));
+        }
+        let all = changed
+            .created
+            .iter()
+            .chain(&changed.modified)
+            .chain(&changed.removed)
+            .chain(changed.renamed.iter().flat_map(|r| [&r.from, &r.to]));
+        for path in all {
+            let Some(parent) = path.parent().filter(|p| p.starts_with(&root.path)) else {
+                continue;
+            };
+            let name = path.file_name().and_then(|n| n.to_str());
+            if name.is_some_and(|n| IGNORE_FILES.contains(&n)) {
+                subtrees.insert(parent.to_path_buf());
+            } else if path.is_dir() {
+                subtrees.insert(path.clone());
+            }
+            listings.insert(parent.to_path_buf());
+        }
+
+        let mut touched = Vec::new();
+        let covered = |dir: &Path, by: &BTreeSet<PathBuf>| {
+            by.iter()
+                .any(|other| other != dir && dir.starts_with(other))
+        };
+        for dir in subtrees.iter().filter(|d| !covered(d, &subtrees)) {
+            self.children.retain(|d, _| !d.starts_with(dir));
+            let found = walk(root, dir, None);
+            touched.extend(found.keys().cloned());
+            touched.extend(dir.parent().map(Path::to_path_buf));
+            self.children.extend(found);
+        }
+        for dir in &listings {
+            if subtrees.iter().any(|s| dir.starts_with(s)) {
+                continue;
+            }
+            self.children.remove(dir);
+            self.children.extend(walk(root, dir, Some(1)));
+            touched.push(dir.clone());
+            touched.extend(dir.parent().map(Path::to_path_buf));
+        }
+        self.settle(touched);
+    }
+
+    /// Set the child counts in the listings of `dirs` and sort them
+    fn settle(&mut self, dirs: impl IntoIterator<Item = PathBuf>) {
+        for dir in dirs {
+            let Some(mut nodes) = self.children.remove(&dir) else {
+                continue;
+            };
+            for node in nodes.iter_mut() {
+                if node.kind == NodeKind::Directory {
+                    node.child_count = Some(self.children.get(&node.path).map_or(0, Vec::len));
+                }
+            }
+            nodes.sort_by(|a, b| {
+                (a.kind != NodeKind::Directory)
+                    .cmp(&(b.kind != NodeKind::Directory))
+                    .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+            });
+            self.children.insert(dir, nodes);
+        }
+    }
+}
+
+/// Visible entries below `dir`, down to `depth` levels, by parent directory
+fn walk(root: &WorkspaceRoot, dir: &Path, depth: Option<usize>) -> HashMap<PathBuf, Vec<TreeNode>> {
+    let found = Mutex::new(Vec::new());
+    walker(dir)
+        .max_depth(depth)
+        .build_parallel()
+        .visit(&mut CollectorBuilder {
+            root,
+            found: &found,
+        });
+    let mut children: HashMap<PathBuf, Vec<TreeNode>> = HashMap::new();
+    for (parent, node) in found.into_inner().unwrap() {
+        children.entry(parent).or_default().push(node);
+    }
+    children
+}
+
+struct CollectorBuilder<'s> {
+    root: &'s WorkspaceRoot,
+    found: &'s Mutex<Vec<(PathBuf, TreeNode)>>,
+}
+
+impl<'s> ParallelVisitorBuilder<'s> for CollectorBuilder<'s> {
+    fn build(&mut self) -> Box<dyn ParallelVisitor + 's> {
+        Box::new(Collector {
+            root: self.root,
+            found: self.found,
+            local: Vec::new(),
+        })
+    }
+}
+
+/// Per-thread buffer, merged into the shared list when the walk ends
+struct Collector<'s> {
+    root: &'s WorkspaceRoot,
+    found: &'s Mutex<Vec<(PathBuf, TreeNode)>>,
+    local: Vec<(PathBuf, TreeNode)>,
+}
+
+impl ParallelVisitor for Collector<'_> {
+    fn visit(&mut self, entry: Result<DirEntry, ignore::Error>) -> WalkState {
+        let Ok(entry) = entry else {
+            return WalkState::Continue;
+        };
+        if entry.depth() == 0 {
+            return WalkState::Continue;
+        }
+        let path = entry.path();
+        let relative = path.strip_prefix(&self.root.path).unwrap_or(path);
+        if self.root.denied_by(relative).is_some() {
+            return WalkState::Skip;
+        }
+        let (Some(parent), Some(node)) = (path.parent(), node(&entry)) else {
+            return WalkState::Continue;
+        };
+        self.local.push((parent.to_path_buf(), node));
+        WalkState::Continue
+    }
+}
+
+impl Drop for Collector<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut found) = self.found.lock() {
+            found.append(&mut self.local);
+        }
+    }
+}
+
+fn node(entry: &DirEntry) -> Option<TreeNode> {
+    let file_type = entry.file_type()?;
+    let kind = if file_type.is_symlink() {
+        NodeKind::Symlink
+    } else if file_type.is_dir() {
+        NodeKind::Directory
+    } else {
+        NodeKind::File
+    };
+    let metadata = entry.metadata().ok();
+    let mtime_ms = metadata
+        .as_ref()
+        .and_then(|m| m.modified().ok())
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64);
+    Some(TreeNode {
+        name: entry.file_name().to_string_lossy().into_owned(),
+        path: entry.path().to_path_buf(),
+        kind,
+        size: metadata.map_or(0, |m| m.len()),
+        mtime_ms,
+        language: match kind {
+            NodeKind::Directory => None,
+            _ => language::detect(entry.path()),
+        },
+        child_count: None,
+    })
+}
+
+/// Cached tree snapshots per workspace root, managed as Tauri state
+#[derive(Default)]
+pub struct ProjectTree {
+    snapshots: Mutex<HashMap<PathBuf, Arc<Snapshot>>>,
+}
+
+impl ProjectTree {
+    /// Drop the cached snapshot of `root` so the next listing rescans it
+    pub fn invalidate(&self, root: &Path) {
+        self.snapshots.lock().unwrap().remove(root);
+    }
+
+    /// Fold a watcher batch into the cached snapshot of `root`, if it has one
+    pub fn apply_changes(&self, root: &WorkspaceRoot, changed: &FsChanged) {
+        let Some(cached) = self.snapshots.lock().unwrap().get(&root.path).cloned() else {
+            return;
+        };
+        let mut snapshot = Snapshot::clone(&cached);
+        snapshot.apply(root, changed);
+        let mut snapshots = self.snapshots.lock().unwrap();
+        // A rescan that finished meanwhile has seen these changes already
+        if snapshots
+            .get(&root.path)
+            .is_some_and(|s| Arc::ptr_eq(s, &cached))
+        {
+            snapshots.insert(root.path.clone(), Arc::new(snapshot));
+        }
+    }
+
+    async fn snapshot(
+        &self,
+        root: WorkspaceRoot,
+        refresh: bool,
+    ) -> Result<Arc<Snapshot>, WorkspaceError> {
+        let cached = self.snapshots.lock().unwrap().get(&root.path).cloned();
+        if let Some(snapshot) = cached.filter(|_| !refresh) {
+            return Ok(snapshot);
+        }
+        let path = root.path.clone();
+        let snapshot = tauri::async_runtime::spawn_blocking(move || Snapshot::scan(&root))
+            .await
+            .map(Arc::new)
+            .map_err(|e| WorkspaceError::Io {
+                path: path.display().to_string(),
+                message: e.to_string(),
+            })?;
+        self.snapshots
+            .lock()
+            .unwrap()
+            .insert(path, Arc::clone(&snapshot));
+        Ok(snapshot)
+    }
+}
+
+/// List a page of the children of directory `path` inside a workspace
+///
+/// The first call for a root walks the whole project; later calls are
+/// served from the snapshot until `refresh` is set.
+#[tauri::command]
+pub async fn list_project_tree(
+    workspace: State<'_, Workspace>,
+    tree: State<'_, ProjectTree>,
+    path: PathBuf,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    refresh: Option<bool>,
+) -> Result<TreePage, WorkspaceError> {
+    let dir = workspace.resolve(&path)?;
+    if !dir.is_dir() {
+        return Err(WorkspaceError::NotADirectory {
+            path: path.display().to_string(),
+        });
+    }
+    let root = workspace
+        .root_of(&dir)
+        .ok_or_else(|| WorkspaceError::OutsideWorkspace {
+            path: path.display().to_string(),
+        })?;
+    let snapshot = tree.snapshot(root, refresh.unwrap_or(false)).await?;
+
+    // Ignored and empty directories have no entry
+    let children = snapshot.children.get(&dir).map_or(&[][..], Vec::as_slice);
+    let offset = offset.unwrap_or(0).min(children.len());
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).max(1);
+    let end = offset.saturating_add(limit).min(children.len());
+    Ok(TreePage {
+        path: dir,
+        nodes: children[offset..end].to_vec(),
+        total: children.len(),
+        next_offset: (end < children.len()).then_some(end),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn names(snapshot: &Snapshot, dir: &Path) -> Vec<String> {
+        snapshot
+            .children
+            .get(dir)
+            .into_iter()
+            .flatten()
+            .map(|node| match node.child_count {
+                Some(count) => format!("{}/ ({})", node.name, count),
+                None => node.name.clone(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn batches_rescan_only_the_directories_they_touch() {
+        let project = tempfile::tempdir().unwrap();
+        let root = Workspace::default().open(project.path()).unwrap();
+        let base = root.path.clone();
+        for file in ["src/a.rs", "docs/guide.md", "lib/x.rs"] {
+            fs::create_dir_all(base.join(file).parent().unwrap()).unwrap();
+            fs::write(base.join(file), "").unwrap();
+        }
+        let mut snapshot = Snapshot::scan(&root);
+
+        fs::write(base.join("src/b.rs"), "").unwrap();
+        fs::create_dir_all(base.join("src/nested/deep")).unwrap();
+        fs::write(base.join("src/nested/deep/c.rs"), "").unwrap();
+        fs::remove_dir_all(base.join("docs")).unwrap();
+        // Not part of the batch, so `lib` keeps its listing
+        fs::write(base.join("lib/y.rs"), "").unwrap();
+        snapshot.apply(
+            &root,
+            &FsChanged {
+                root: base.clone(),
+                created: vec![base.join("src/b.rs"), base.join("src/nested")],
+                removed: vec![base.join("docs")],
+                ..FsChanged::default()
+            },
+        );
+
+        assert_eq!(names(&snapshot, &base), ["lib/ (1)", "src/ (3)"]);
+        assert_eq!(
+            names(&snapshot, &base.join("src")),
+            ["nested/ (1)", "a.rs", "b.rs"]
+        );
+        assert_eq!(names(&snapshot, &base.join("src/nested/deep")), ["c.rs"]);
+        assert!(!snapshot.children.contains_key(&base.join("docs")));
+        assert_eq!(names(&snapshot, &base.join("lib")), ["x.rs"]);
+    }
+
+    #[test]
+    fn changed_ignore_files_rescan_their_subtree() {
+        let project = tempfile::tempdir().unwrap();
+        let root = Workspace::default().open(project.path()).unwrap();
+        let base = root.path.clone();
+        fs::create_dir_all(base.join("src/out")).unwrap();
+        fs::write(base.join("src/main.rs"), "").unwrap();
+        fs::write(base.join("src/out/debug.log"), "").unwrap();
+        let mut snapshot = Snapshot::scan(&root);
+        assert_eq!(names(&snapshot, &base.join("src/out")), ["debug.log"]);
+
+        fs::write(base.join(".gitignore"), "*.log\n").unwrap();
+        snapshot.apply(
+            &root,
+            &FsChanged {
+                root: base.clone(),
+                created: vec![base.join(".gitignore")],
+                ..FsChanged::default()
+            },
+        );
+        assert_eq!(names(&snapshot, &base), ["src/ (2)", ".gitignore"]);
+        assert_eq!(names(&snapshot, &base.join("src")), ["out/ (0)", "main.rs"]);
+        assert!(!snapshot.children.contains_key(&base.join("src/out")));
+
+        // A file in a subdirectory still sees the root's rules
+        fs::write(base.join("src/trace.log"), "").unwrap();
+        fs::write(base.join("src/lib.rs"), "").unwrap();
+        snapshot.apply(
+            &root,
+            &FsChanged {
+                root: base.clone(),
+                created: vec![base.join("src/trace.log"), base.join("src/lib.rs")],
+                ..FsChanged::default()
+            },
+        );
+        assert_eq!(
+            names(&snapshot, &base.join("src")),
+            ["out/ (0)", "lib.rs", "main.rs"]
+        );
+    }
+}
@@ -0,0 +1,1057 @@
+//! Embedded vector store for semantic search
+//!
+//! Replaces the LanceDB placeholder in `packages/vectordb`. Each workspace
+//! root has a collection of chunk embeddings keyed by file and symbol,
+//! searched by cosine similarity. Small collections and narrow filters are
+//! scanned exactly; everything else goes through an HNSW graph. Replaced
+//! chunks are tombstoned and the collection is rebuilt once most of it is
+//! dead. Collections are saved with bincode under the app data directory,
+//! at most every few seconds while they change and once more on exit.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use globset::{Glob, GlobMatcher};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::fs_edit::{self, FileEditError};
+use crate::hnsw::{self, Hnsw, Vectors};
+use crate::language;
+use crate::workspace::{Workspace, WorkspaceError};
+
+/// Bumped whenever the on-disk format changes; older collections are dropped
+const STORE_VERSION: u32 = 2;
+
+/// Up to this many candidates, searches compare every vector
+const EXACT_SCAN_LIMIT: usize = 2048;
+
+/// Graph candidates kept per search, before filtering
+const EF_SEARCH: usize = 192;
+
+const DEFAULT_TOP_K: usize = 10;
+
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, thiserror::Error, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum VectorError {
+    #[error("expected vectors of dimension {expected}, got {found}")]
+    DimensionMismatch { expected: usize, found: usize },
+    #[error("vectors must be finite and non-zero")]
+    InvalidVector,
+    #[error("collection holds vectors of model {expected}, not {found}")]
+    ModelMismatch { expected: String, found: String },
+    #[error("invalid path glob {pattern}: {message}")]
+    InvalidGlob { pattern: String, message: String },
+    #[error(transparent)]
+    Sandbox { reason: WorkspaceError },
+    #[error("vector storage failed: {message}")]
+    Storage { message: String },
+}
+
+impl From<WorkspaceError> for VectorError {
+    fn from(reason: WorkspaceError) -> Self {
+        Self::Sandbox { reason }
+    }
+}
+
+impl From<io::Error> for VectorError {
+    fn from(err: io::Error) -> Self {
+        Self::Storage {
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<bincode::Error> for VectorError {
+    fn from(err: bincode::Error) -> Self {
+        Self::Storage {
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<FileEditError> for VectorError {
+    fn from(err: FileEditError) -> Self {
+        Self::Storage {
+            message: err.to_string(),
+        }
+    }
+}
+
+/// A stored piece of a file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Chunk {
+    /// Relative to the root in storage, absolute in search results
+    pub path: PathBuf,
+    pub symbol: Option<String>,
+    /// Symbol kind, e.g. `function` or `class`
+    pub kind: Option<String>,
+    pub language: Option<String>,
+    /// 1-based, inclusive
+    pub start_line: u32,
+    pub end_line: u32,
+    pub text: String,
+    /// Content hash of `text`, to reuse its vector when it moves
+    pub hash: String,
+}
+
+/// A chunk to store, with its embedding
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewChunk {
+    pub symbol: Option<String>,
+    pub kind: Option<String>,
+    /// Detected from the file name if missing
+    pub language: Option<String>,
+    pub start_line: u32,
+    pub end_line: Option<u32>,
+    #[serde(default)]
+    pub text: String,
+    pub vector: Vec<f32>,
+}
+
+impl NewChunk {
+    fn into_parts(self, relative: &Path) -> (Chunk, Vec<f32>) {
+        let chunk = Chunk {
+            path: relative.to_path_buf(),
+            symbol: self.symbol,
+            kind: self.kind,
+            language: self
+                .language
+                .or_else(|| language::detect(relative).map(str::to_owned)),
+            start_line: self.start_line,
+            end_line: self.end_line.unwrap_or(self.start_line),
+            hash: fs_edit::content_hash(self.text.as_bytes()),
+            text: self.text,
+        };
+        (chunk, self.vector)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct VectorFilter {
+    pub language: Option<String>,
+    /// Glob over paths relative to the root
+    pub path: Option<String>,
+}
+
+struct Filter {
+    language: Option<String>,
+    path: Option<GlobMatcher>,
+}
+
+impl Filter {
+    fn new(filter: VectorFilter) -> Result<Self, VectorError> {
+        let path = filter
+            .path
+            .map(|pattern| {
+                Glob::new(&pattern)
+                    .map(|glob| glob.compile_matcher())
+                    .map_err(|e| VectorError::InvalidGlob {
+                        pattern,
+                        message: e.to_string(),
+                    })
+            })
+            .transpose()?;
+        Ok(Self {
+            language: filter.language,
+            path,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.language.is_none() && self.path.is_none()
+    }
+
+    fn allows_path(&self, path: &Path) -> bool {
+        self.path.as_ref().is_none_or(|glob| glob.is_match(path))
+    }
+
+    fn allows_language(&self, chunk: &Chunk) -> bool {
+        self.language.is_none() || chunk.language == self.language
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VectorHit {
+    pub root: PathBuf,
+    #[serde(flatten)]
+    pub chunk: Chunk,
+    /// Cosine similarity to the query, from -1 to 1
+    pub score: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VectorStatus {
+    pub root: PathBuf,
+    pub chunks: usize,
+    pub files: usize,
+    /// Model the vectors came from, `None` before any were stored
+    pub model: Option<String>,
+    /// 0 before any vectors were stored
+    pub dimension: usize,
+}
+
+/// The chunks of one root
+#[derive(Serialize, Deserialize)]
+struct Collection {
+    version: u32,
+    root: PathBuf,
+    model: Option<String>,
+    dimension: usize,
+    /// Content hash of each file when it was chunked
+    sources: HashMap<PathBuf, String>,
+    chunks: Vec<Chunk>,
+    /// Unit vectors of `chunks`, `dimension` floats each
+    vectors: Vec<f32>,
+    deleted: Vec<bool>,
+    dead: usize,
+    graph: Hnsw,
+    /// Live chunk ids of each file
+    #[serde(skip)]
+    by_file: HashMap<PathBuf, Vec<u32>>,
+    /// A live chunk id for each chunk hash
+    #[serde(skip)]
+    by_hash: HashMap<String, u32>,
+    /// Changes whenever the collection does, so derived data can be cached
+    #[serde(skip)]
+    generation: u64,
+    #[serde(skip)]
+    dirty: bool,
+    #[serde(skip)]
+    saved: Option<Instant>,
+}
+
+impl Collection {
+    fn new(root: &Path) -> Self {
+        Self {
+            version: STORE_VERSION,
+            root: root.to_path_buf(),
+            model: None,
+            dimension: 0,
+            sources: HashMap::new(),
+            chunks: Vec::new(),
+            vectors: Vec::new(),
+            deleted: Vec::new(),
+            dead: 0,
+            graph: Hnsw::default(),
+            by_file: HashMap::new(),
+            by_hash: HashMap::new(),
+            generation: 0,
+            dirty: false,
+            saved: None,
+        }
+    }
+
+    fn live(&self) -> usize {
+        self.chunks.len() - self.dead
+    }
+
+    /// Rebuild `by_file` and `by_hash` from the chunks
+    fn index_files(&mut self) {
+        self.by_file.clear();
+        self.by_hash.clear();
+        for (id, chunk) in self.chunks.iter().enumerate() {
+            if !self.deleted[id] {
+                self.by_file
+                    .entry(chunk.path.clone())
+                    .or_default()
+                    .push(id as u32);
+                self.by_hash.insert(chunk.hash.clone(), id as u32);
+            }
+        }
+    }
+
+    fn vectors(&self) -> Vectors<'_> {
+        Vectors {
+            data: &self.vectors,
+            dimension: self.dimension,
+        }
+    }
+
+    /// Replace the chunks of `relative`, embedded by `model` from content
+    /// with hash `source`
+    fn replace(
+        &mut self,
+        relative: &Path,
+        model: &str,
+        source: Option<String>,
+        chunks: Vec<NewChunk>,
+    ) -> Result<usize, VectorError> {
+        let mut parts = Vec::with_capacity(chunks.len());
+        for chunk in chunks {
+            let (chunk, mut vector) = chunk.into_parts(relative);
+            normalize(&mut vector)?;
+            parts.push((chunk, vector));
+        }
+        // Any model goes while nothing else is stored, any dimension while
+        // no other vectors are
+        let replaced = self.by_file.get(relative).map_or(0, Vec::len);
+        let alone = self.live() == replaced;
+        let fresh = alone && self.sources.keys().all(|path| path == relative);
+        if !fresh && self.model.as_deref() != Some(model) {
+            return Err(VectorError::ModelMismatch {
+                expected: self.model.clone().unwrap_or_default(),
+                found: model.to_owned(),
+            });
+        }
+        let expected = match parts.first() {
+            Some((_, vector)) if alone => vector.len(),
+            _ => self.dimension,
+        };
+        if let Some((_, vector)) = parts.iter().find(|(_, v)| v.len() != expected) {
+            return Err(VectorError::DimensionMismatch {
+                expected,
+                found: vector.len(),
+            });
+        }
+
+        self.remove(|path| path == relative);
+        if self.model.as_deref() != Some(model) {
+            self.clear();
+            self.model = Some(model.to_owned());
+        }
+        if expected != self.dimension {
+            self.compact();
+            self.dimension = expected;
+        }
+        let stored = parts.len();
+        for (chunk, vector) in parts {
+            let id = self.chunks.len() as u32;
+            self.by_file.entry(chunk.path.clone()).or_default().push(id);
+            self.by_hash.insert(chunk.hash.clone(), id);
+            self.chunks.push(chunk);
+            self.vectors.extend_from_slice(&vector);
+            self.deleted.push(false);
+            let vectors = Vectors {
+                data: &self.vectors,
+                dimension: self.dimension,
+            };
+            self.graph.insert(vectors);
+        }
+        if let Some(source) = source {
+            self.sources.insert(relative.to_path_buf(), source);
+        }
+        self.dirty = true;
+        Ok(stored)
+    }
+
+    /// Stored vectors for chunks with these hashes
+    fn cached(&self, hashes: &[String]) -> Vec<Option<Vec<f32>>> {
+        let vectors = self.vectors();
+        hashes
+            .iter()
+            .map(|hash| {
+                let id = *self.by_hash.get(hash)? as usize;
+                Some(vectors.data[id * vectors.dimension..(id + 1) * vectors.dimension].to_vec())
+            })
+            .collect()
+    }
+
+    /// Tombstone the chunks of every file matching `matches`, returning how
+    /// many were removed
+    fn remove(&mut self, matches: impl Fn(&Path) -> bool) -> usize {
+        let paths: Vec<PathBuf> = self
+            .by_file
+            .keys()
+            .filter(|path| matches(path))
+            .cloned()
+            .collect();
+        let mut removed = 0;
+        for path in paths {
+            for id in self.by_file.remove(&path).unwrap_or_default() {
+                let hash = &self.chunks[id as usize].hash;
+                if self.by_hash.get(hash) == Some(&id) {
+                    self.by_hash.remove(hash);
+                }
+                self.deleted[id as usize] = true;
+                removed += 1;
+            }
+        }
+        let sources = self.sources.len();
+        self.sources.retain(|path, _| !matches(path));
+        self.dead += removed;
+        self.dirty |= removed > 0 || self.sources.len() < sources;
+        if self.dead * 2 > self.chunks.len() {
+            self.compact();
+        }
+        removed
+    }
+
+    fn clear(&mut self) {
+        let root = std::mem::take(&mut self.root);
+        *self = Self {
+            generation: self.generation,
+            dirty: true,
+            saved: self.saved,
+            ..Self::new(&root)
+        };
+    }
+
+    /// Drop tombstoned chunks and rebuild the graph over the rest
+    fn compact(&mut self) {
+        let dimension = self.dimension;
+        let mut chunks = Vec::with_capacity(self.live());
+        let mut vectors = Vec::with_capacity(self.live() * dimension);
+        for (id, chunk) in std::mem::take(&mut self.chunks).into_iter().enumerate() {
+            if !self.deleted[id] {
+                chunks.push(chunk);
+                vectors.extend_from_slice(&self.vectors[id * dimension..(id + 1) * dimension]);
+            }
+        }
+        let mut graph = Hnsw::default();
+        for id in 0..chunks.len() {
+            graph.insert(Vectors {
+                data: &vectors[..(id + 1) * dimension],
+                dimension,
+            });
+        }
+        self.deleted = vec![false; chunks.len()];
+        self.chunks = chunks;
+        self.vectors = vectors;
+        self.graph = graph;
+        self.dead = 0;
+        self.dirty = true;
+        self.index_files();
+    }
+
+    /// Ids and similarities of the `k` live chunks closest to `query` (a unit
+    /// vector) that pass `filter`, best first
+    fn search(&self, query: &[f32], k: usize, filter: &Filter) -> Vec<(u32, f32)> {
+        let live = self.live();
+        if live == 0 || k == 0 {
+            return Vec::new();
+        }
+        let allowed: Option<Vec<u32>> = (!filter.is_empty()).then(|| {
+            self.by_file
+                .iter()
+                .filter(|(path, _)| filter.allows_path(path))
+                .flat_map(|(_, ids)| ids)
+                .copied()
+                .filter(|&id| filter.allows_language(&self.chunks[id as usize]))
+                .collect()
+        });
+        let candidates = allowed.as_ref().map_or(live, Vec::len);
+        // A narrow filter would discard most graph results, so scan instead
+        if candidates <= EXACT_SCAN_LIMIT || candidates * 10 < live {
+            return self.exact(query, k, allowed);
+        }
+
+        // Widen the beam by the share of nodes that can't be returned
+        let ef = (k.max(EF_SEARCH) * self.chunks.len()).div_ceil(candidates);
+        let hits: Vec<(u32, f32)> = self
+            .graph
+            .search(self.vectors(), query, ef)
+            .into_iter()
+            .filter(|&(id, _)| {
+                let chunk = &self.chunks[id as usize];
+                !self.deleted[id as usize]
+                    && filter.allows_path(&chunk.path)
+                    && filter.allows_language(chunk)
+            })
+            .take(k)
+            .map(|(id, distance)| (id, 1.0 - distance))
+            .collect();
+        if hits.len() < k.min(candidates) {
+            return self.exact(query, k, allowed);
+        }
+        hits
+    }
+
+    /// Compare `query` with every live chunk, or with the `allowed` ones
+    fn exact(&self, query: &[f32], k: usize, allowed: Option<Vec<u32>>) -> Vec<(u32, f32)> {
+        let ids = allowed.unwrap_or_else(|| {
+            (0..self.chunks.len() as u32)
+                .filter(|&id| !self.deleted[id as usize])
+                .collect()
+        });
+        let vectors = self.vectors();
+        let mut scored: Vec<(u32, f32)> = ids
+            .into_iter()
+            .map(|id| {
+                let start = id as usize * vectors.dimension;
+                let vector = &vectors.data[start..start + vectors.dimension];
+                (id, hnsw::dot(query, vector))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        scored.truncate(k);
+        scored
+    }
+}
+
+/// The live chunks of one root, by id
+///
+/// Ids are only stable while `generation` is.
+pub struct LiveChunks<'c> {
+    pub generation: u64,
+    collection: &'c Collection,
+}
+
+impl LiveChunks<'_> {
+    /// Model the vectors came from, `None` before any were stored
+    pub fn model(&self) -> Option<&str> {
+        self.collection.model.as_deref()
+    }
+
+    /// Paths are relative to the root
+    pub fn get(&self, id: u32) -> Option<&Chunk> {
+        let collection = self.collection;
+        (!*collection.deleted.get(id as usize)?).then(|| &collection.chunks[id as usize])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &Chunk)> {
+        let collection = self.collection;
+        collection
+            .chunks
+            .iter()
+            .enumerate()
+            .filter(|(id, _)| !collection.deleted[*id])
+            .map(|(id, chunk)| (id as u32, chunk))
+    }
+
+    /// Ids and similarities of the `k` chunks closest to `query`, a unit
+    /// vector, best first; empty if its dimension doesn't match
+    pub fn nearest(&self, query: &[f32], k: usize) -> Vec<(u32, f32)> {
+        if query.len() != self.collection.dimension {
+            return Vec::new();
+        }
+        let filter = Filter {
+            language: None,
+            path: None,
+        };
+        self.collection.search(query, k, &filter)
+    }
+
+    /// Cosine similarity of a chunk to `query`, a unit vector
+    pub fn similarity(&self, id: u32, query: &[f32]) -> Option<f32> {
+        let vectors = self.collection.vectors();
+        if query.len() != vectors.dimension || self.get(id).is_none() {
+            return None;
+        }
+        let start = id as usize * vectors.dimension;
+        Some(hnsw::dot(
+            query,
+            &vectors.data[start..start + vectors.dimension],
+        ))
+    }
+}
+
+/// Scale `vector` to unit length
+pub fn normalize(vector: &mut [f32]) -> Result<(), VectorError> {
+    let norm = hnsw::dot(vector, vector).sqrt();
+    if vector.is_empty() || !norm.is_normal() {
+        return Err(VectorError::InvalidVector);
+    }
+    vector.iter_mut().for_each(|x| *x /= norm);
+    Ok(())
+}
+
+type SharedCollection = Arc<RwLock<Collection>>;
+
+/// Loaded collections, managed as Tauri state
+pub struct VectorStore {
+    dir: PathBuf,
+    collections: Mutex<HashMap<PathBuf, SharedCollection>>,
+    generations: AtomicU64,
+}
+
+impl VectorStore {
+    pub fn open(dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            collections: Mutex::new(HashMap::new()),
+            generations: AtomicU64::new(0),
+        })
+    }
+
+    /// Replace the chunks of the file at `relative` under `root`, returning
+    /// how many were stored
+    ///
+    /// `source` is the hash of the file content the chunks were cut from.
+    /// Vectors of a different `model` are refused unless nothing else is
+    /// stored. Blocking; every chunk is linked into the graph as it is added.
+    pub fn upsert(
+        &self,
+        root: &Path,
+        relative: &Path,
+        model: &str,
+        source: Option<String>,
+        chunks: Vec<NewChunk>,
+    ) -> Result<usize, VectorError> {
+        let collection = self.loaded(root);
+        let mut collection = collection.write().unwrap();
+        let stored = collection.replace(relative, model, source, chunks)?;
+        collection.generation = self.next_generation();
+        self.persist(&mut collection, false)?;
+        Ok(stored)
+    }
+
+    /// Content hashes of the files stored from `model`, by relative path;
+    /// empty if the collection holds another model
+    pub fn sources(&self, root: &Path, model: &str) -> HashMap<PathBuf, String> {
+        let collection = self.loaded(root);
+        let collection = collection.read().unwrap();
+        if collection.model.as_deref() == Some(model) {
+            collection.sources.clone()
+        } else {
+            HashMap::new()
+        }
+    }
+
+    /// Vectors already stored from `model` for chunks with these content
+    /// hashes
+    pub fn cached(&self, root: &Path, model: &str, hashes: &[String]) -> Vec<Option<Vec<f32>>> {
+        let collection = self.loaded(root);
+        let collection = collection.read().unwrap();
+        if collection.model.as_deref() == Some(model) {
+            collection.cached(hashes)
+        } else {
+            vec![None; hashes.len()]
+        }
+    }
+
+    /// Remove the chunks of `relative` and of any file under it, returning
+    /// how many were removed
+    pub fn delete(&self, root: &Path, relative: &Path) -> Result<usize, VectorError> {
+        let collection = self.loaded(root);
+        let mut collection = collection.write().unwrap();
+        let removed = collection.remove(|path| path.starts_with(relative));
+        collection.generation = self.next_generation();
+        self.persist(&mut collection, false)?;
+        Ok(removed)
+    }
+
+    pub fn clear(&self, root: &Path) -> Result<(), VectorError> {
+        let collection = self.loaded(root);
+        let mut collection = collection.write().unwrap();
+        collection.clear();
+        collection.generation = self.next_generation();
+        self.persist(&mut collection, true)
+    }
+
+    /// The `k` chunks closest to `query` across `roots`, best first
+    pub fn search(
+        &self,
+        roots: &[PathBuf],
+        query: &[f32],
+        k: usize,
+        filter: VectorFilter,
+    ) -> Result<Vec<VectorHit>, VectorError> {
+        let filter = Filter::new(filter)?;
+        let mut query = query.to_vec();
+        normalize(&mut query)?;
+        let mut hits = Vec::new();
+        for root in roots {
+            let collection = self.loaded(root);
+            let collection = collection.read().unwrap();
+            if collection.live() == 0 {
+                continue;
+            }
+            if collection.dimension != query.len() {
+                return Err(VectorError::DimensionMismatch {
+                    expected: collection.dimension,
+                    found: query.len(),
+                });
+            }
+            hits.extend(
+                collection
+                    .search(&query, k, &filter)
+                    .into_iter()
+                    .map(|(id, score)| {
+                        let chunk = &collection.chunks[id as usize];
+                        VectorHit {
+                            root: root.clone(),
+                            chunk: Chunk {
+                                path: root.join(&chunk.path),
+                                ..chunk.clone()
+                            },
+                            score,
+                        }
+                    }),
+            );
+        }
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(k);
+        Ok(hits)
+    }
+
+    /// Run `f` over the live chunks of `root`
+    pub fn with_chunks<T>(&self, root: &Path, f: impl FnOnce(&LiveChunks) -> T) -> T {
+        let collection = self.loaded(root);
+        let collection = collection.read().unwrap();
+        f(&LiveChunks {
+            generation: collection.generation,
+            collection: &collection,
+        })
+    }
+
+    pub fn status(&self, root: &Path) -> VectorStatus {
+        let collection = self.loaded(root);
+        let collection = collection.read().unwrap();
+        VectorStatus {
+            root: root.to_path_buf(),
+            chunks: collection.live(),
+            files: collection.by_file.len(),
+            model: collection.model.clone(),
+            dimension: collection.dimension,
+        }
+    }
+
+    /// Save every collection with unsaved changes
+    ///
+    /// Tries them all; the first failure is returned.
+    pub fn flush(&self) -> Result<(), VectorError> {
+        let collections: Vec<SharedCollection> =
+            self.collections.lock().unwrap().values().cloned().collect();
+        let mut result = Ok(());
+        for collection in collections {
+            let saved = self.persist(&mut collection.write().unwrap(), true);
+            result = result.and(saved);
+        }
+        result
+    }
+
+    /// The collection of `root`, from memory or disk, or a new empty one
+    fn loaded(&self, root: &Path) -> SharedCollection {
+        let mut collections = self.collections.lock().unwrap();
+        if let Some(collection) = collections.get(root) {
+            return Arc::clone(collection);
+        }
+        // Unreadable or outdated collections start over empty
+        let collection = fs::read(self.collection_path(root))
+            .ok()
+            .and_then(|bytes| bincode::deserialize::<Collection>(&bytes).ok())
+            .filter(|c| c.version == STORE_VERSION && c.root == root)
+            .map(|mut c| {
+                c.index_files();
+                c
+            })
+            .unwrap_or_else(|| Collection::new(root));
+        let collection = Collection {
+            generation: self.next_generation(),
+            ..collection
+        };
+        let collection = Arc::new(RwLock::new(collection));
+        collections.insert(root.to_path_buf(), Arc::clone(&collection));
+        collection
+    }
+
+    fn next_generation(&self) -> u64 {
+        self.generations.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn collection_path(&self, root: &Path) -> PathBuf {
+        let key = fs_edit::content_hash(root.as_os_str().as_encoded_bytes());
+        self.dir.join(format!("{}.bin", &key[..16]))
+    }
+
+    /// Save `collection` if it changed, unless it was saved less than
+    /// `SAVE_INTERVAL` ago and `force` is not set
+    fn persist(&self, collection: &mut Collection, force: bool) -> Result<(), VectorError> {
+        let recent = collection
+            .saved
+            .is_some_and(|saved| saved.elapsed() < SAVE_INTERVAL);
+        if !collection.dirty || (recent && !force) {
+            return Ok(());
+        }
+        // Streamed straight into a temp file; collections can be large
+        let mut file = tempfile::NamedTempFile::new_in(&self.dir)?;
+        let mut writer = BufWriter::new(file.as_file_mut());
+        bincode::serialize_into(&mut writer, &*collection)?;
+        writer.flush()?;
+        drop(writer);
+        file.as_file().sync_all()?;
+        file.persist(self.collection_path(&collection.root))
+            .map_err(|e| e.error)?;
+        fs_edit::sync_dir(&self.dir);
+        collection.dirty = false;
+        collection.saved = Some(Instant::now());
+        Ok(())
+    }
+}
+
+fn join_error(err: tauri::Error) -> VectorError {
+    VectorError::Storage {
+        message: err.to_string(),
+    }
+}
+
+/// Store the chunks of a file, embedded by `model`, replacing its previous
+/// ones
+#[tauri::command]
+pub async fn vector_upsert(
+    app: AppHandle,
+    workspace: State<'_, Workspace>,
+    path: String,
+    model: String,
+    chunks: Vec<NewChunk>,
+) -> Result<usize, VectorError> {
+    let (root, relative) = workspace.locate(Path::new(&path))?;
+    tauri::async_runtime::spawn_blocking(move || {
+        app.state::<VectorStore>()
+            .upsert(&root.path, &relative, &model, None, chunks)
+    })
+    .await
+    .map_err(join_error)?
+}
+
+/// Remove the chunks of a file, or of every file under a directory
+#[tauri::command]
+pub async fn vector_delete_file(
+    app: AppHandle,
+    workspace: State<'_, Workspace>,
+    path: String,
+) -> Result<usize, VectorError> {
+    let (root, relative) = workspace.locate(Path::new(&path))?;
+    tauri::async_runtime::spawn_blocking(move || {
+        app.state::<VectorStore>().delete(&root.path, &relative)
+    })
+    .await
+    .map_err(join_error)?
+}
+
+/// Cosine top-k over the chunks of `root`, or of every open root
+#[tauri::command]
+pub async fn vector_search(
+    workspace: State<'_, Workspace>,
+    store: State<'_, VectorStore>,
+    vector: Vec<f32>,
+    k: Option<usize>,
+    filter: Option<VectorFilter>,
+    root: Option<PathBuf>,
+) -> Result<Vec<VectorHit>, VectorError> {
+    let roots = match root {
+        Some(root) => vec![workspace.pick_root(Some(&root))?.path],
+        None => workspace.roots().into_iter().map(|r| r.path).collect(),
+    };
+    store.search(
+        &roots,
+        &vector,
+        k.unwrap_or(DEFAULT_TOP_K),
+        filter.unwrap_or_default(),
+    )
+}
+
+#[tauri::command]
+pub async fn vector_status(
+    workspace: State<'_, Workspace>,
+    store: State<'_, VectorStore>,
+    root: Option<PathBuf>,
+) -> Result<VectorStatus, VectorError> {
+    let root = workspace.pick_root(root.as_deref())?;
+    Ok(store.status(&root.path))
+}
+
+/// Drop every chunk of a root
+#[tauri::command]
+pub async fn vector_clear(
+    workspace: State<'_, Workspace>,
+    store: State<'_, VectorStore>,
+    root: Option<PathBuf>,
+) -> Result<(), VectorError> {
+    let root = workspace.pick_root(root.as_deref())?;
+    store.clear(&root.path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODEL: &str = "test-model";
+
+    /// Vectors from a fixed seed, not normalized
+    fn random_vector(dimension: usize, seed: &mut u64) -> Vec<f32> {
+        (0..dimension)
+            .map(|_| {
+                *seed ^= *seed << 13;
+                *seed ^= *seed >> 7;
+                *seed ^= *seed << 17;
+                (*seed >> 40) as f32 / (1u64 << 24) as f32 - 0.5
+            })
+            .collect()
+    }
+
+    fn chunks(count: usize, dimension: usize, seed: &mut u64) -> Vec<NewChunk> {
+        (0..count)
+            .map(|i| NewChunk {
+                symbol: Some(format!("f{i}")),
+                kind: None,
+                language: None,
+                start_line: i as u32 + 1,
+                end_line: None,
+                text: format!("chunk {i} {seed}"),
+                vector: random_vector(dimension, seed),
+            })
+            .collect()
+    }
+
+    fn no_filter() -> Filter {
+        Filter::new(VectorFilter::default()).unwrap()
+    }
+
+    #[test]
+    fn graph_search_recalls_the_exact_scan() {
+        let (dimension, k) = (16, 10);
+        let mut seed = 5;
+        let mut collection = Collection::new(Path::new("/project"));
+        // Enough chunks that searches go through the graph
+        for file in 0..25 {
+            let path = PathBuf::from(format!("src/f{file}.rs"));
+            let new = chunks(100, dimension, &mut seed);
+            collection.replace(&path, MODEL, None, new).unwrap();
+        }
+        assert!(collection.live() > EXACT_SCAN_LIMIT);
+
+        let mut found = 0;
+        for _ in 0..30 {
+            let mut query = random_vector(dimension, &mut seed);
+            normalize(&mut query).unwrap();
+            let graph: Vec<u32> = collection
+                .search(&query, k, &no_filter())
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect();
+            let exact = collection.exact(&query, k, None);
+            found += exact.iter().filter(|(id, _)| graph.contains(id)).count();
+        }
+        let recall = found as f32 / (30 * k) as f32;
+        assert!(recall >= 0.95, "recall {recall}");
+    }
+
+    #[test]
+    fn replaced_chunks_are_tombstoned_then_compacted() {
+        let dimension = 4;
+        let mut seed = 17;
+        let mut collection = Collection::new(Path::new("/project"));
+        let (a, b) = (Path::new("a.rs"), Path::new("b.rs"));
+        collection
+            .replace(a, MODEL, Some("a1".into()), chunks(2, dimension, &mut seed))
+            .unwrap();
+        collection
+            .replace(b, MODEL, Some("b1".into()), chunks(3, dimension, &mut seed))
+            .unwrap();
+        let kept = chunks(1, dimension, &mut seed);
+        let mut query = kept[0].vector.clone();
+        normalize(&mut query).unwrap();
+        collection
+            .replace(a, MODEL, Some("a2".into()), kept)
+            .unwrap();
+
+        // Tombstoned, not yet compacted
+        assert_eq!((collection.chunks.len(), collection.dead), (6, 2));
+        assert_eq!(collection.live(), 4);
+        let hits = collection.search(&query, 10, &no_filter());
+        assert_eq!(hits.len(), 4);
+        assert!(hits.iter().all(|&(id, _)| !collection.deleted[id as usize]));
+        assert_eq!(hits[0].0, 5);
+        assert!((hits[0].1 - 1.0).abs() < 1e-5);
+
+        // Most of it dead now, so it is rebuilt
+        assert_eq!(collection.remove(|path| path == b), 3);
+        assert_eq!((collection.chunks.len(), collection.dead), (1, 0));
+        assert_eq!(
+            collection
+                .graph
+                .search(collection.vectors(), &query, 4)
+                .len(),
+            1
+        );
+        assert_eq!(
+            collection.by_file.keys().collect::<Vec<_>>(),
+            [&a.to_path_buf()]
+        );
+        assert_eq!(collection.sources.get(a).map(String::as_str), Some("a2"));
+        assert!(!collection.sources.contains_key(b));
+        let hash = collection.chunks[0].hash.clone();
+        assert_eq!(collection.cached(&[hash]), [Some(query)]);
+    }
+
+    #[test]
+    fn mismatched_vectors_are_refused() {
+        let mut seed = 23;
+        let mut collection = Collection::new(Path::new("/project"));
+        collection
+            .replace(Path::new("a.rs"), MODEL, None, chunks(2, 4, &mut seed))
+            .unwrap();
+        assert!(matches!(
+            collection.replace(Path::new("b.rs"), "other", None, chunks(1, 4, &mut seed)),
+            Err(VectorError::ModelMismatch { .. })
+        ));
+        assert!(matches!(
+            collection.replace(Path::new("b.rs"), MODEL, None, chunks(1, 8, &mut seed)),
+            Err(VectorError::DimensionMismatch {
+                expected: 4,
+                found: 8
+            })
+        ));
+        let mut zero = chunks(1, 4, &mut seed);
+        zero[0].vector = vec![0.0; 4];
+        assert!(matches!(
+            collection.replace(Path::new("b.rs"), MODEL, None, zero),
+            Err(VectorError::InvalidVector)
+        ));
+        // Replacing the only file may switch the dimension
+        collection
+            .replace(Path::new("a.rs"), MODEL, None, chunks(1, 8, &mut seed))
+            .unwrap();
+        assert_eq!(collection.dimension, 8);
+    }
+
+    #[test]
+    fn collections_survive_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let roots = [PathBuf::from("/project")];
+        let root = &roots[0];
+        let mut seed = 31;
+        let mut query = random_vector(8, &mut seed);
+        normalize(&mut query).unwrap();
+
+        let store = VectorStore::open(dir.path().to_path_buf()).unwrap();
+        for file in ["a.rs", "b.rs", "c.rs"] {
+            store
+                .upsert(
+                    root,
+                    Path::new(file),
+                    MODEL,
+                    Some(file.into()),
+                    chunks(4, 8, &mut seed),
+                )
+                .unwrap();
+        }
+        store.delete(root, Path::new("b.rs")).unwrap();
+        let before = store
+            .search(&roots, &query, 5, VectorFilter::default())
+            .unwrap();
+        store.flush().unwrap();
+        drop(store);
+
+        let store = VectorStore::open(dir.path().to_path_buf()).unwrap();
+        let status = store.status(root);
+        assert_eq!((status.chunks, status.files, status.dimension), (8, 2, 8));
+        assert_eq!(status.model.as_deref(), Some(MODEL));
+        assert_eq!(store.sources(root, MODEL).len(), 2);
+        let after = store
+            .search(&roots, &query, 5, VectorFilter::default())
+            .unwrap();
+        let summary = |hits: &[VectorHit]| -> Vec<(PathBuf, u32, f32)> {
+            hits.iter()
+                .map(|h| (h.chunk.path.clone(), h.chunk.start_line, h.score))
+                .collect()
+        };
+        assert_eq!(summary(&before), summary(&after));
+        assert!(after.iter().all(|h| !h.chunk.path.ends_with("b.rs")));
+    }
+}
@@ -0,0 +1,373 @@
+//! Filesystem watcher for open workspaces
+//!
+//! Each open root gets a recursive watcher whose events are debounced, so a
+//! burst like a `git checkout` arrives as one `fs://changed` event with the
+//! net created/modified/removed/renamed paths. Paths hidden by the ignore
+//! files or the denylist are dropped, as are our own temp files.
+//!
+//! The event goes out first; the code index and the vectors then catch up
+//! on a worker thread per root, which takes the batches in order.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use notify_debouncer_full::notify::event::{ModifyKind, RenameMode};
+use notify_debouncer_full::notify::{self, EventKind, RecommendedWatcher, RecursiveMode};
+use notify_debouncer_full::{
+    new_debouncer, DebounceEventResult, DebouncedEvent, Debouncer, RecommendedCache,
+};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+
+use crate::embeddings::Embeddings;
+use crate::indexer::Indexer;
+use crate::tree::{ProjectTree, IGNORE_FILES};
+use crate::vectors::VectorStore;
+use crate::workspace::{WorkspaceError, WorkspaceRoot};
+
+/// Event emitted for every debounced batch of changes
+pub const CHANGED_EVENT: &str = "fs://changed";
+
+/// Emitted when the watcher failed, or the index or the vectors could not
+/// take a batch
+pub const SYNC_FAILED_EVENT: &str = "fs://sync-failed";
+
+/// Quiet period before a burst of events is flushed
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Prefixes of the temp files `fs_edit` and `batch` create while writing
+const OWN_TEMP_PREFIXES: &[&str] = &[".henry-edit-", ".henry-delete-"];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rename {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// Net changes under one root since the previous event
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FsChanged {
+    pub root: PathBuf,
+    pub created: Vec<PathBuf>,
+    pub modified: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+    pub renamed: Vec<Rename>,
+}
+
+impl FsChanged {
+    fn is_empty(&self) -> bool {
+        self.created.is_empty()
+            && self.modified.is_empty()
+            && self.removed.is_empty()
+            && self.renamed.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SyncStage {
+    /// The watcher itself; changes may have been missed
+    Watch,
+    Index,
+    Embeddings,
+}
+
+/// Why changes under `root` are missing from the tree, the index or the
+/// vectors
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncFailed {
+    pub root: PathBuf,
+    pub stage: SyncStage,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Created,
+    Modified,
+    Removed,
+}
+
+/// Folds a batch of raw events into net changes per path
+#[derive(Default)]
+struct Batch {
+    changes: HashMap<PathBuf, Change>,
+    order: Vec<PathBuf>,
+    renamed: Vec<Rename>,
+}
+
+impl Batch {
+    fn record(&mut self, path: PathBuf, change: Change) {
+        use Change::*;
+        match self.changes.get(&path).copied() {
+            None => {
+                self.order.push(path.clone());
+                self.changes.insert(path, change);
+            }
+            Some(previous) => {
+                let net = match (previous, change) {
+                    (Created, Modified) => Some(Created),
+                    (Created, Removed) => None,
+                    (Removed, Created) => Some(Modified),
+                    (_, next) => Some(next),
+                };
+                match net {
+                    Some(net) => self.changes.insert(path, net),
+                    None => self.changes.remove(&path),
+                };
+            }
+        }
+    }
+
+    fn finish(mut self, root: &Path) -> FsChanged {
+        let mut changed = FsChanged {
+            root: root.to_path_buf(),
+            renamed: std::mem::take(&mut self.renamed),
+            ..FsChanged::default()
+        };
+        for path in self.order {
+            match self.changes.remove(&path) {
+                Some(Change::Created) => changed.created.push(path),
+                Some(Change::Modified) => changed.modified.push(path),
+                Some(Change::Removed) => changed.removed.push(path),
+                None => {}
+            }
+        }
+        changed
+    }
+}
+
+/// Ignore rules for one root, loaded per directory as events arrive
+struct IgnoreRules {
+    root: WorkspaceRoot,
+    dirs: HashMap<PathBuf, Option<Gitignore>>,
+}
+
+impl IgnoreRules {
+    fn new(root: WorkspaceRoot) -> Self {
+        Self {
+            root,
+            dirs: HashMap::new(),
+        }
+    }
+
+    /// Whether `path` is hidden from the project, as `tree::walker` would
+    /// hide it
+    fn ignored(&mut self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root.path) else {
+            return true;
+        };
+        if relative.components().any(|c| c.as_os_str() == ".git") {
+            return true;
+        }
+        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+            if OWN_TEMP_PREFIXES.iter().any(|p| name.starts_with(p)) {
+                return true;
+            }
+            // A changed ignore file invalidates the rules of its directory
+            if IGNORE_FILES.contains(&name) {
+                if let Some(parent) = path.parent() {
+                    self.dirs.remove(parent);
+                }
+            }
+        }
+        if self.root.denied_by(relative).is_some() {
+            return true;
+        }
+
+        // Every ancestor up to the root, deepest rule first, as in git. The
+        // path may already be gone, so fall back to treating it as a file.
+        let is_dir = path.is_dir();
+        let mut dir = path.parent();
+        while let Some(current) = dir.filter(|d| d.starts_with(&self.root.path)) {
+            let rules = self
+                .dirs
+                .entry(current.to_path_buf())
+                .or_insert_with(|| load_ignore(current));
+            if let Some(rules) = rules {
+                match rules.matched_path_or_any_parents(path, is_dir) {
+                    Match::Ignore(_) => return true,
+                    Match::Whitelist(_) => return false,
+                    Match::None => {}
+                }
+            }
+            dir = current.parent();
+        }
+        false
+    }
+}
+
+fn load_ignore(dir: &Path) -> Option<Gitignore> {
+    let mut builder = GitignoreBuilder::new(dir);
+    let mut any = false;
+    for name in IGNORE_FILES {
+        let file = dir.join(name);
+        if file.is_file() {
+            any |= builder.add(file).is_none();
+        }
+    }
+    any.then(|| builder.build().ok()).flatten()
+}
+
+fn collect(rules: &mut IgnoreRules, events: Vec<DebouncedEvent>) -> FsChanged {
+    let mut batch = Batch::default();
+    for event in events {
+        let paths = &event.paths;
+        match event.kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if paths.len() == 2 => {
+                let (from, to) = (&paths[0], &paths[1]);
+                match (rules.ignored(from), rules.ignored(to)) {
+                    (true, true) => {}
+                    (true, false) => batch.record(to.clone(), Change::Created),
+                    (false, true) => batch.record(from.clone(), Change::Removed),
+                    (false, false) => batch.renamed.push(Rename {
+                        from: from.clone(),
+                        to: to.clone(),
+                    }),
+                }
+            }
+            kind => {
+                for path in paths {
+                    let change = match kind {
+                        EventKind::Create(_) => Change::Created,
+                        EventKind::Remove(_) => Change::Removed,
+                        EventKind::Modify(ModifyKind::Name(RenameMode::From)) => Change::Removed,
+                        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => Change::Created,
+                        EventKind::Modify(ModifyKind::Name(_)) if path.exists() => Change::Created,
+                        EventKind::Modify(ModifyKind::Name(_)) => Change::Removed,
+                        EventKind::Modify(_) | EventKind::Any => Change::Modified,
+                        EventKind::Access(_) | EventKind::Other => continue,
+                    };
+                    if !rules.ignored(path) {
+                        batch.record(path.clone(), change);
+                    }
+                }
+            }
+        }
+    }
+    batch.finish(&rules.root.path)
+}
+
+/// Fold `changed` into the index and then the vectors of its root,
+/// emitting `fs://sync-failed` for a stage that fails
+fn sync_changes<R: Runtime>(app: &AppHandle<R>, changed: &FsChanged) {
+    let failed = |stage, message: String| {
+        let _ = app.emit(
+            SYNC_FAILED_EVENT,
+            SyncFailed {
+                root: changed.root.clone(),
+                stage,
+                message,
+            },
+        );
+    };
+    let Some(indexer) = app.try_state::<Indexer>() else {
+        return;
+    };
+    if let Err(e) = indexer.apply_changes(changed) {
+        failed(SyncStage::Index, e.to_string());
+    }
+    // Embeds from the index, so only once it has the changes
+    if let (Some(embeddings), Some(store)) = (
+        app.try_state::<Embeddings>(),
+        app.try_state::<VectorStore>(),
+    ) {
+        if let Err(e) = embeddings.apply_changes(&indexer, &store, changed) {
+            failed(SyncStage::Embeddings, e.to_string());
+        }
+    }
+}
+
+/// Report watcher `errors` for `root` and drop its tree snapshot, since
+/// the changes they lost can be anywhere
+fn watch_failed<R: Runtime>(app: &AppHandle<R>, root: &Path, errors: &[notify::Error]) {
+    if let Some(tree) = app.try_state::<ProjectTree>() {
+        tree.invalidate(root);
+    }
+    let message = errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ");
+    let _ = app.emit(
+        SYNC_FAILED_EVENT,
+        SyncFailed {
+            root: root.to_path_buf(),
+            stage: SyncStage::Watch,
+            message,
+        },
+    );
+}
+
+/// Running watchers per workspace root, managed as Tauri state
+#[derive(Default)]
+pub struct Watchers {
+    active: Mutex<HashMap<PathBuf, Debouncer<RecommendedWatcher, RecommendedCache>>>,
+}
+
+impl Watchers {
+    /// Start watching `root`, replacing any watcher it already had
+    pub fn watch<R: Runtime>(
+        &self,
+        app: &AppHandle<R>,
+        root: &WorkspaceRoot,
+    ) -> Result<(), WorkspaceError> {
+        let io_error = |e: notify_debouncer_full::notify::Error| WorkspaceError::Io {
+            path: root.path.display().to_string(),
+            message: e.to_string(),
+        };
+        // Ends with the debouncer, which holds the only sender
+        let (sync, batches) = mpsc::channel::<FsChanged>();
+        let worker = app.clone();
+        thread::spawn(move || {
+            for changed in batches {
+                sync_changes(&worker, &changed);
+            }
+        });
+
+        let app = app.clone();
+        let tree_root = root.clone();
+        let mut rules = IgnoreRules::new(root.clone());
+        let mut debouncer = new_debouncer(DEBOUNCE, None, move |result: DebounceEventResult| {
+            let events = match result {
+                Ok(events) => events,
+                Err(errors) => {
+                    watch_failed(&app, &tree_root.path, &errors);
+                    return;
+                }
+            };
+            let changed = collect(&mut rules, events);
+            if changed.is_empty() {
+                return;
+            }
+            if let Some(tree) = app.try_state::<ProjectTree>() {
+                tree.apply_changes(&tree_root, &changed);
+            }
+            let _ = app.emit(CHANGED_EVENT, &changed);
+            let _ = sync.send(changed);
+        })
+        .map_err(io_error)?;
+        debouncer
+            .watch(&root.path, RecursiveMode::Recursive)
+            .map_err(io_error)?;
+
+        self.active
+            .lock()
+            .unwrap()
+            .insert(root.path.clone(), debouncer);
+        Ok(())
+    }
+
+    /// Stop watching `root`; the watcher thread shuts down when dropped
+    pub fn unwatch(&self, root: &Path) {
+        self.active.lock().unwrap().remove(root);
+    }
+}
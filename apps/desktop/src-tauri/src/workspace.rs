@@ -0,0 +1,346 @@
+//! Project roots the backend is allowed to touch
+//!
+//! Every file command resolves its path through [`Workspace::resolve`], which
+//! canonicalizes it, follows symlinks and refuses anything outside the open
+//! roots or matching the denylist.
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::RwLock;
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::Serialize;
+
+use crate::config::HenryConfig;
+
+/// Paths that are never readable or writable through file commands,
+/// in addition to a project's `security.denyPaths`
+const DEFAULT_DENYLIST: &[&str] = &[
+    "**/.git",
+    "**/.git/**",
+    "**/.env",
+    "**/.env.*",
+    "**/*.pem",
+    "**/*.key",
+    "**/*.p12",
+    "**/*.pfx",
+    "**/id_rsa*",
+    "**/id_dsa*",
+    "**/id_ecdsa*",
+    "**/id_ed25519*",
+    "**/.ssh/**",
+];
+
+#[derive(Debug, Clone, thiserror::Error, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum WorkspaceError {
+    #[error("no workspace is open")]
+    NoWorkspace,
+    #[error("{path} is outside the open workspaces")]
+    OutsideWorkspace { path: String },
+    #[error("{path} is protected by the workspace denylist ({pattern})")]
+    Denied { path: String, pattern: String },
+    #[error("{path} is not a directory")]
+    NotADirectory { path: String },
+    #[error("{path} is too broad to open as a workspace")]
+    TooBroad { path: String },
+    #[error("opening {path} as a workspace was not confirmed")]
+    Refused { path: String },
+    #[error("invalid denylist pattern {pattern}: {message}")]
+    InvalidPattern { pattern: String, message: String },
+    #[error("cannot resolve {path}: {message}")]
+    Io { path: String, message: String },
+}
+
+impl WorkspaceError {
+    fn io(path: &Path, err: io::Error) -> Self {
+        Self::Io {
+            path: path.display().to_string(),
+            message: err.to_string(),
+        }
+    }
+}
+
+/// An open project root
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceRoot {
+    pub path: PathBuf,
+    pub deny_patterns: Vec<String>,
+    #[serde(skip)]
+    deny: GlobSet,
+}
+
+impl WorkspaceRoot {
+    fn open(path: &Path) -> Result<Self, WorkspaceError> {
+        let path = fs::canonicalize(path).map_err(|e| WorkspaceError::io(path, e))?;
+        if !path.is_dir() {
+            return Err(WorkspaceError::NotADirectory {
+                path: path.display().to_string(),
+            });
+        }
+        if too_broad(&path) {
+            return Err(WorkspaceError::TooBroad {
+                path: path.display().to_string(),
+            });
+        }
+        let config = HenryConfig::load(&path);
+        let deny_patterns = with_defaults(config.security.deny_paths);
+        let deny = build_globset(&deny_patterns)?;
+        Ok(Self {
+            path,
+            deny_patterns,
+            deny,
+        })
+    }
+
+    /// The denylist pattern matching `relative`, if any
+    pub fn denied_by(&self, relative: &Path) -> Option<&str> {
+        self.deny
+            .matches(relative)
+            .first()
+            .map(|&i| self.deny_patterns[i].as_str())
+    }
+}
+
+fn with_defaults(extra: Vec<String>) -> Vec<String> {
+    DEFAULT_DENYLIST
+        .iter()
+        .map(|p| p.to_string())
+        .chain(extra)
+        .collect()
+}
+
+/// The filesystem root, the home directory, or a directory above it
+fn too_broad(path: &Path) -> bool {
+    let home = std::env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" })
+        .and_then(|home| fs::canonicalize(home).ok());
+    path.parent().is_none() || home.is_some_and(|home| home.starts_with(path))
+}
+
+fn build_globset(patterns: &[String]) -> Result<GlobSet, WorkspaceError> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        // On case-insensitive filesystems `.ENV` is the same file as `.env`
+        let glob = GlobBuilder::new(pattern)
+            .case_insensitive(cfg!(any(target_os = "macos", windows)))
+            .build()
+            .map_err(|e| WorkspaceError::InvalidPattern {
+                pattern: pattern.clone(),
+                message: e.to_string(),
+            })?;
+        builder.add(glob);
+    }
+    builder.build().map_err(|e| WorkspaceError::InvalidPattern {
+        pattern: patterns.join(", "),
+        message: e.to_string(),
+    })
+}
+
+/// Open project roots, managed as Tauri state
+#[derive(Default)]
+pub struct Workspace {
+    roots: RwLock<Vec<WorkspaceRoot>>,
+}
+
+impl Workspace {
+    /// Record `path` as a project root, reloading its denylist if it is
+    /// already open
+    pub fn open(&self, path: &Path) -> Result<WorkspaceRoot, WorkspaceError> {
+        let root = WorkspaceRoot::open(path)?;
+        let mut roots = self.roots.write().unwrap();
+        roots.retain(|r| r.path != root.path);
+        roots.push(root.clone());
+        Ok(root)
+    }
+
+    /// Forget a project root; returns whether it was open
+    pub fn close(&self, path: &Path) -> bool {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let mut roots = self.roots.write().unwrap();
+        let before = roots.len();
+        roots.retain(|r| r.path != path);
+        roots.len() != before
+    }
+
+    pub fn roots(&self) -> Vec<WorkspaceRoot> {
+        self.roots.read().unwrap().clone()
+    }
+
+    /// Add `patterns` to the denylist of an open root; nothing already on
+    /// it is ever taken off
+    pub fn extend_denylist(
+        &self,
+        root: &Path,
+        patterns: Vec<String>,
+    ) -> Result<WorkspaceRoot, WorkspaceError> {
+        let root = fs::canonicalize(root).map_err(|e| WorkspaceError::io(root, e))?;
+        let mut roots = self.roots.write().unwrap();
+        let entry = roots.iter_mut().find(|r| r.path == root).ok_or_else(|| {
+            WorkspaceError::OutsideWorkspace {
+                path: root.display().to_string(),
+            }
+        })?;
+        let mut deny_patterns = entry.deny_patterns.clone();
+        for pattern in patterns {
+            if !deny_patterns.contains(&pattern) {
+                deny_patterns.push(pattern);
+            }
+        }
+        entry.deny = build_globset(&deny_patterns)?;
+        entry.deny_patterns = deny_patterns;
+        Ok(entry.clone())
+    }
+
+    /// The open root containing the canonical path `path`
+    pub fn root_of(&self, path: &Path) -> Option<WorkspaceRoot> {
+        innermost(&self.roots.read().unwrap(), path).cloned()
+    }
+
+    /// Resolve `path` to a canonical location inside an open root
+    ///
+    /// Relative paths are taken relative to the first open root. The path
+    /// does not need to exist, but its nearest existing ancestor is
+    /// canonicalized so symlinks cannot point outside the workspace.
+    pub fn resolve(&self, path: &Path) -> Result<PathBuf, WorkspaceError> {
+        let roots = self.roots.read().unwrap();
+        let primary = roots.first().ok_or(WorkspaceError::NoWorkspace)?;
+        let absolute = if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            primary.path.join(path)
+        };
+        let resolved = canonicalize_lenient(&absolute)?;
+
+        let root =
+            innermost(&roots, &resolved).ok_or_else(|| WorkspaceError::OutsideWorkspace {
+                path: path.display().to_string(),
+            })?;
+        let relative = resolved.strip_prefix(&root.path).unwrap_or(&resolved);
+        if let Some(pattern) = root.denied_by(relative) {
+            return Err(WorkspaceError::Denied {
+                path: path.display().to_string(),
+                pattern: pattern.to_string(),
+            });
+        }
+        Ok(resolved)
+    }
+
+    /// Resolve `path` and split it into its open root and the path relative
+    /// to that root
+    pub fn locate(&self, path: &Path) -> Result<(WorkspaceRoot, PathBuf), WorkspaceError> {
+        let target = self.resolve(path)?;
+        let outside = || WorkspaceError::OutsideWorkspace {
+            path: path.display().to_string(),
+        };
+        let root = self.root_of(&target).ok_or_else(outside)?;
+        let relative = target.strip_prefix(&root.path).map_err(|_| outside())?;
+        Ok((root, relative.to_path_buf()))
+    }
+
+    /// The open root `root` points into, or the first open root
+    pub fn pick_root(&self, root: Option<&Path>) -> Result<WorkspaceRoot, WorkspaceError> {
+        match root {
+            Some(root) => Ok(self.locate(root)?.0),
+            None => self
+                .roots()
+                .into_iter()
+                .next()
+                .ok_or(WorkspaceError::NoWorkspace),
+        }
+    }
+}
+
+/// The most deeply nested root containing `path`
+fn innermost<'a>(roots: &'a [WorkspaceRoot], path: &Path) -> Option<&'a WorkspaceRoot> {
+    roots
+        .iter()
+        .filter(|r| path.starts_with(&r.path))
+        .max_by_key(|r| r.path.components().count())
+}
+
+/// Canonicalize the longest existing prefix of `path` and append the rest
+///
+/// The remaining components must be plain names; `..` after a missing
+/// directory could otherwise climb out of the canonicalized prefix.
+fn canonicalize_lenient(path: &Path) -> Result<PathBuf, WorkspaceError> {
+    let mut existing = path;
+    let mut missing = Vec::new();
+    loop {
+        match fs::canonicalize(existing) {
+            Ok(mut resolved) => {
+                for name in missing.iter().rev() {
+                    resolved.push(name);
+                }
+                return Ok(resolved);
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let (Some(parent), Some(Component::Normal(name))) =
+                    (existing.parent(), existing.components().next_back())
+                else {
+                    return Err(WorkspaceError::OutsideWorkspace {
+                        path: path.display().to_string(),
+                    });
+                };
+                missing.push(name.to_os_string());
+                existing = parent;
+            }
+            Err(e) => return Err(WorkspaceError::io(path, e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_denylist_only_grows() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = Workspace::default();
+        workspace.open(dir.path()).unwrap();
+        let root = workspace
+            .extend_denylist(dir.path(), vec!["secrets/**".to_owned()])
+            .unwrap();
+        assert!(root.denied_by(Path::new("secrets/a")).is_some());
+        let root = workspace.extend_denylist(dir.path(), Vec::new()).unwrap();
+        assert!(root.denied_by(Path::new("secrets/a")).is_some());
+        assert!(root.denied_by(Path::new(".env")).is_some());
+        assert!(workspace.resolve(&dir.path().join("secrets/a")).is_err());
+    }
+
+    #[test]
+    fn denylist_case_follows_the_filesystem() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = WorkspaceRoot::open(dir.path()).unwrap();
+        assert!(root.denied_by(Path::new(".env")).is_some());
+        assert_eq!(
+            root.denied_by(Path::new(".ENV")).is_some(),
+            cfg!(any(target_os = "macos", windows))
+        );
+    }
+
+    #[test]
+    fn broad_roots_are_refused() {
+        let top = std::env::temp_dir()
+            .ancestors()
+            .last()
+            .unwrap()
+            .to_path_buf();
+        assert!(matches!(
+            WorkspaceRoot::open(&top),
+            Err(WorkspaceError::TooBroad { .. })
+        ));
+        if let Some(home) = std::env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" }) {
+            if Path::new(&home).is_dir() {
+                assert!(matches!(
+                    WorkspaceRoot::open(Path::new(&home)),
+                    Err(WorkspaceError::TooBroad { .. })
+                ));
+            }
+        }
+        let dir = tempfile::tempdir().unwrap();
+        assert!(WorkspaceRoot::open(dir.path()).is_ok());
+    }
+}
@@ -7,6 +7,7 @@ import { Editor } from '@monaco-editor/react';
 
 import { MenuBar } from './components/MenuBar';
 import { saveFileToDisk, openFileFromDisk, saveProjectFiles, type ProjectFile } from './utils/fileOperations';
+import { readWorkspaceFile } from './utils/permissions';
 import { FileTree } from './components/FileTree';
 import { Terminal } from './components/Terminal';
 import { CommandPalette } from './components/CommandPalette';
@@ -708,9 +709,8 @@ Enjoy your project!`
             }}
             onFileOpen={async (path) => {
               try {
-                // Read file directly using Tauri FS plugin
-                const { readTextFile } = await import('@tauri-apps/plugin-fs');
-                const content = await readTextFile(path);
+                // Read through the backend, which keeps reads inside the workspace
+                const content = await readWorkspaceFile(path);
                 
                 // Update or create tab for this file
                 const existingTab = tabs.find(t => t.path === path);
@@ -776,9 +776,8 @@ Enjoy your project!`
             <Terminal 
               height={300}
               onClose={() => setShowTerminal(false)}
-              onCommandExecute={(command, result) => {
-                // Agent can use terminal results
-                console.log(`Terminal executed: ${command}`, result);
+              onCommandExecute={(command) => {
+                console.log(`Terminal executed: ${command}`);
               }}
             />
           )}
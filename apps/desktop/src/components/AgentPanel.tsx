@@ -23,6 +23,8 @@ interface Message {
   isPlan?: boolean;
   planSteps?: Array<{ id: string; description: string; status?: string }>;
   plan?: AgentPlan;
+  /** Native plan the approved plan runs as, in the desktop app */
+  planId?: string;
 }
 
 interface AgentPanelProps {
@@ -186,10 +188,10 @@ export function AgentPanel({
     }
   };
 
-  // Update agent service when model or autonomy changes
-  useEffect(() => {
-    agentService.setAutonomyLevel(autonomyLevel);
-  }, [autonomyLevel, agentService]);
+  // Raising the level may be declined, so the slider follows what took effect
+  const changeAutonomyLevel = async (level: AutonomyLevel) => {
+    setAutonomyLevel(await agentService.setAutonomyLevel(level));
+  };
 
   const handleKeyDown = (e: React.KeyboardEvent) => {
     if (e.key === 'Enter' && !e.shiftKey) {
@@ -257,7 +259,7 @@ export function AgentPanel({
         />
         <AutonomySlider
           level={autonomyLevel}
-          onLevelChange={setAutonomyLevel}
+          onLevelChange={changeAutonomyLevel}
         />
       </div>
 
@@ -276,6 +278,7 @@ export function AgentPanel({
                     </div>
                     <PlanApproval
                       plan={message.plan}
+                      planId={message.planId}
                       onApprove={async (approvedPlan) => {
                         // Execute the approved plan
                         try {
@@ -292,6 +295,12 @@ export function AgentPanel({
                             status: 'executing' as const,
                             createdAt: Date.now()
                           };
+                          // The plan view follows the native run, if there is one
+                          if ('__TAURI__' in window) {
+                            setMessages(prev => prev.map(m =>
+                              m.id === message.id ? { ...m, planId: task.id } : m
+                            ));
+                          }
                           
                           const result = await agentService.executeTask(task);
                           
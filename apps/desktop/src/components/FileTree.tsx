@@ -13,7 +13,7 @@ import {
   FiFileText,
   FiFolderPlus
 } from 'react-icons/fi';
-import { readDirectory, selectFolder, type FileSystemEntry } from '../utils/fileSystem';
+import { openWorkspace, pickWorkspace, readDirectory, type FileSystemEntry } from '../utils/fileSystem';
 import './FileTree.css';
 
 interface FileNode {
@@ -172,6 +172,7 @@ export function FileTree({ onFileSelect, rootPath, onFileOpen }: FileTreeProps)
       
       setIsLoading(true);
       try {
+        await openWorkspace(currentRoot);
         const entries = await loadDirectory(currentRoot);
         setFileTree(entries);
       } catch (error) {
@@ -186,7 +187,7 @@ export function FileTree({ onFileSelect, rootPath, onFileOpen }: FileTreeProps)
 
   // Handle opening folder dialog
   const handleOpenFolder = async () => {
-    const selected = await selectFolder();
+    const selected = await pickWorkspace();
     if (selected) {
       setCurrentRoot(selected);
     }
@@ -3,8 +3,19 @@
  * Shows AI-generated plan and allows user to approve/edit steps before execution
  */
 
-import { useState } from 'react';
-import { FiCheck, FiX, FiEdit2, FiPlay, FiChevronDown, FiChevronRight } from 'react-icons/fi';
+import { useEffect, useState } from 'react';
+import { FiCheck, FiX, FiEdit2, FiPlay, FiChevronDown, FiChevronRight, FiRotateCcw } from 'react-icons/fi';
+import {
+  approveNativePlanStep,
+  cancelNativePlan,
+  getNativePlan,
+  onPlanProgress,
+  resumeNativePlan,
+  rollbackNativePlan,
+  type NativePlanState,
+  type NativePlanStatus,
+  type NativeStepState
+} from '@henry-ai/local-ai';
 import { AgentPlan, AgentPlanStep } from '../services/ai/agent';
 import './PlanApproval.css';
 
@@ -13,12 +24,69 @@ interface PlanApprovalProps {
   onApprove: (plan: AgentPlan) => void;
   onReject: () => void;
   onEditStep?: (stepId: string, newDescription: string) => void;
+  /** Id of the native plan running this plan; shows its progress */
+  planId?: string;
 }
 
-export function PlanApproval({ plan, onApprove, onReject, onEditStep }: PlanApprovalProps) {
+export function PlanApproval({ plan, onApprove, onReject, onEditStep, planId }: PlanApprovalProps) {
   const [expandedSteps, setExpandedSteps] = useState<Set<string>>(new Set(plan.steps.map(s => s.id)));
   const [editingStep, setEditingStep] = useState<string | null>(null);
   const [editValue, setEditValue] = useState('');
+  const [runStatus, setRunStatus] = useState<NativePlanStatus | null>(null);
+  const [stepStates, setStepStates] = useState<Record<string, NativeStepState>>({});
+  const [actionError, setActionError] = useState<string | null>(null);
+
+  useEffect(() => {
+    if (!planId) {
+      return;
+    }
+    const apply = (state: NativePlanState) => {
+      setRunStatus(state.status);
+      setStepStates(Object.fromEntries(state.steps.map(step => [step.id, step])));
+    };
+
+    let cancelled = false;
+    let unlisten: (() => void) | undefined;
+    onPlanProgress(progress => {
+      if (progress.planId !== planId) {
+        return;
+      }
+      setRunStatus(progress.status);
+      if (progress.step) {
+        const step = progress.step;
+        setStepStates(prev => ({ ...prev, [step.id]: step }));
+      }
+    }).then(stop => {
+      if (cancelled) {
+        stop();
+      } else {
+        unlisten = stop;
+      }
+    });
+    // Catch up on a plan that started before this view, e.g. before a restart
+    getNativePlan(planId).then(state => {
+      if (!cancelled) {
+        apply(state);
+      }
+    }, () => {
+      // Not started yet; progress events will follow
+    });
+
+    return () => {
+      cancelled = true;
+      unlisten?.();
+    };
+  }, [planId]);
+
+  const runAction = async (action: () => Promise<NativePlanState>) => {
+    try {
+      setActionError(null);
+      const state = await action();
+      setRunStatus(state.status);
+    } catch (error: any) {
+      setActionError(error?.message || JSON.stringify(error));
+    }
+  };
 
   const toggleStep = (stepId: string) => {
     const newExpanded = new Set(expandedSteps);
@@ -136,7 +204,33 @@ export function PlanApproval({ plan, onApprove, onReject, onEditStep }: PlanAppr
               <span className={`plan-step-type plan-step-type-${step.type}`}>
                 {step.type}
               </span>
+              {stepStates[step.id] && (
+                <span className={`plan-step-status plan-step-status-${stepStates[step.id].status}`}>
+                  {STEP_STATUS_LABELS[stepStates[step.id].status]}
+                </span>
+              )}
             </div>
+            {stepStates[step.id]?.status === 'awaitingApproval' && planId && (
+              <div className="plan-step-approval">
+                <button
+                  className="plan-approve-btn"
+                  onClick={() => runAction(() => approveNativePlanStep(planId, step.id, true))}
+                >
+                  <FiCheck size={14} />
+                  Approve step
+                </button>
+                <button
+                  className="plan-reject-btn"
+                  onClick={() => runAction(() => approveNativePlanStep(planId, step.id, false))}
+                >
+                  <FiX size={14} />
+                  Decline
+                </button>
+              </div>
+            )}
+            {stepStates[step.id]?.error && (
+              <pre className="plan-step-error">{stepStates[step.id].error}</pre>
+            )}
             {expandedSteps.has(step.id) && step.target && (
               <div className="plan-step-details">
                 <div className="plan-step-detail">
@@ -153,23 +247,80 @@ export function PlanApproval({ plan, onApprove, onReject, onEditStep }: PlanAppr
         ))}
       </div>
 
-      <div className="plan-approval-actions">
-        <button
-          className="plan-approve-btn"
-          onClick={() => onApprove(plan)}
-        >
-          <FiPlay size={16} />
-          Execute Plan
-        </button>
-        <button
-          className="plan-reject-btn"
-          onClick={onReject}
-        >
-          <FiX size={16} />
-          Cancel
-        </button>
-      </div>
+      {actionError && <div className="plan-risks">{actionError}</div>}
+
+      {planId && runStatus ? (
+        <div className="plan-approval-actions">
+          <span className={`plan-run-status plan-run-status-${runStatus}`}>
+            {PLAN_STATUS_LABELS[runStatus]}
+          </span>
+          {(runStatus === 'paused' || runStatus === 'failed') && (
+            <button
+              className="plan-approve-btn"
+              onClick={() => runAction(() => resumeNativePlan(planId))}
+            >
+              <FiPlay size={16} />
+              {runStatus === 'failed' ? 'Retry Step' : 'Resume'}
+            </button>
+          )}
+          {(runStatus === 'running' || runStatus === 'awaitingApproval' || runStatus === 'paused') && (
+            <button
+              className="plan-reject-btn"
+              onClick={() => runAction(() => cancelNativePlan(planId))}
+            >
+              <FiX size={16} />
+              Stop
+            </button>
+          )}
+          {runStatus !== 'running' && runStatus !== 'rolledBack' && (
+            <button
+              className="plan-reject-btn"
+              onClick={() => runAction(() => rollbackNativePlan(planId))}
+              title="Undo the file changes of every step"
+            >
+              <FiRotateCcw size={16} />
+              Roll Back
+            </button>
+          )}
+        </div>
+      ) : (
+        <div className="plan-approval-actions">
+          <button
+            className="plan-approve-btn"
+            onClick={() => onApprove(plan)}
+          >
+            <FiPlay size={16} />
+            Execute Plan
+          </button>
+          <button
+            className="plan-reject-btn"
+            onClick={onReject}
+          >
+            <FiX size={16} />
+            Cancel
+          </button>
+        </div>
+      )}
     </div>
   );
 }
 
+const STEP_STATUS_LABELS: Record<NativeStepState['status'], string> = {
+  pending: 'Pending',
+  awaitingApproval: 'Needs approval',
+  running: 'Running',
+  completed: 'Done',
+  failed: 'Failed',
+  rolledBack: 'Rolled back'
+};
+
+const PLAN_STATUS_LABELS: Record<NativePlanStatus, string> = {
+  running: 'Running…',
+  awaitingApproval: 'Waiting for approval',
+  paused: 'Paused by restart',
+  completed: 'Completed',
+  failed: 'Failed',
+  cancelled: 'Cancelled',
+  rolledBack: 'Rolled back'
+};
+
@@ -1,97 +1,119 @@
 /**
  * Terminal Component - Cursor-style integrated terminal
+ * Hosts the user's login shell on a PTY for as long as the panel is open, so
+ * pipes, quoting, `cd` and variables behave as in any terminal; keystrokes go
+ * straight to the shell.
  */
 
 import { useState, useRef, useEffect } from 'react';
 import { FiTerminal, FiX, FiChevronDown } from 'react-icons/fi';
-import { TerminalExecutor } from '../services/terminal/executor';
+import { PtySession } from '../services/terminal/pty';
+import { emptyScreen, keyInput, writeScreen, type Screen } from '../services/terminal/screen';
 import './Terminal.css';
 
 interface TerminalProps {
   height?: number;
   onClose?: () => void;
-  onCommandExecute?: (command: string, result: string) => void;
+  /** Called with each line the user enters */
+  onCommandExecute?: (command: string) => void;
 }
 
+// Character cell of the 13px monospace font, for the PTY size
+const CELL_WIDTH = 7.8;
+const CELL_HEIGHT = 19.5;
+
 export function Terminal({ height = 300, onClose, onCommandExecute }: TerminalProps) {
   const [isMinimized, setIsMinimized] = useState(false);
-  const [output, setOutput] = useState<string[]>([
-    'Welcome to Henry Ugo\'s Personal AI Terminal',
-    'Type commands below or use the agent commands...',
-    ''
-  ]);
-  const [command, setCommand] = useState('');
-  const [isExecuting, setIsExecuting] = useState(false);
-  const [currentDir, setCurrentDir] = useState<string>('');
-  const inputRef = useRef<HTMLInputElement>(null);
+  const [screen, setScreen] = useState<Screen>(emptyScreen);
+  const [exited, setExited] = useState<string | null>(null);
+  const [generation, setGeneration] = useState(0);
+  const sessionRef = useRef<PtySession | null>(null);
   const outputRef = useRef<HTMLDivElement>(null);
-  const executorRef = useRef(new TerminalExecutor());
+  // What the user typed since the last Enter, for `onCommandExecute`
+  const lineRef = useRef('');
+  const onCommandRef = useRef(onCommandExecute);
+  onCommandRef.current = onCommandExecute;
+
+  useEffect(() => {
+    let cancelled = false;
+    setScreen(emptyScreen());
+    setExited(null);
+    PtySession.spawn({
+      onData: data => setScreen(prev => writeScreen(prev, data)),
+      onExit: exit => {
+        sessionRef.current = null;
+        setExited(exit.signal
+          ? `[shell ended by ${exit.signal}]`
+          : `[shell exited with code ${exit.exitCode}]`);
+      }
+    }, terminalSize(outputRef.current)).then(session => {
+      if (cancelled) {
+        session.kill();
+        return;
+      }
+      sessionRef.current = session;
+    }).catch((error: Error) => {
+      if (!cancelled) setExited(`[error] ${error.message}`);
+    });
+    return () => {
+      cancelled = true;
+      sessionRef.current?.kill();
+      sessionRef.current = null;
+    };
+  }, [generation]);
+
+  useEffect(() => {
+    const element = outputRef.current;
+    if (!element) return;
+    element.focus();
+    const observer = new ResizeObserver(() => {
+      const { cols, rows } = terminalSize(element);
+      sessionRef.current?.resize(cols, rows).catch(() => {});
+    });
+    observer.observe(element);
+    return () => observer.disconnect();
+  }, [isMinimized]);
 
   useEffect(() => {
     if (outputRef.current) {
       outputRef.current.scrollTop = outputRef.current.scrollHeight;
     }
-  }, [output]);
+  }, [screen, exited]);
 
-  useEffect(() => {
-    // Get current directory on mount
-    executorRef.current.getCwd().then(dir => {
-      setCurrentDir(dir);
+  const send = (data: string) => {
+    sessionRef.current?.write(data).catch((error: unknown) => {
+      setExited(`[error] ${JSON.stringify(error)}`);
     });
-  }, []);
+  };
 
-  const handleCommand = async (e: React.FormEvent) => {
+  const handleKeyDown = (e: React.KeyboardEvent) => {
+    if (exited) {
+      // Enter starts a new shell
+      if (e.key === 'Enter') setGeneration(g => g + 1);
+      return;
+    }
+    const input = keyInput(e);
+    if (input === null) return;
     e.preventDefault();
-    if (!command.trim() || isExecuting) return;
-
-    const cmd = command.trim();
-    setCommand('');
-    setIsExecuting(true);
-
-    // Add command to output
-    setOutput(prev => [...prev, `${currentDir ? `[${currentDir}]` : ''}$ ${cmd}`, '']);
-
-    try {
-      // Execute command
-      const result = await executorRef.current.execute(cmd);
-
-      // Update output
-      if (result.stdout) {
-        const stdoutLines = result.stdout.split('\n').filter(line => line.trim());
-        setOutput(prev => [...prev, ...stdoutLines]);
-      }
-
-      if (result.stderr) {
-        const stderrLines = result.stderr.split('\n').filter(line => line.trim());
-        setOutput(prev => [...prev, ...stderrLines.map(line => `[error] ${line}`)]);
-      }
-
-      if (!result.success) {
-        setOutput(prev => [...prev, `[exit code: ${result.exitCode}]`]);
-      }
-
-      // Notify parent component
-      if (onCommandExecute) {
-        onCommandExecute(cmd, result.stdout + (result.stderr ? `\n${result.stderr}` : ''));
-      }
-
-      // Update current directory for 'cd' command
-      if (cmd.startsWith('cd ')) {
-        const newDir = await executorRef.current.getCwd();
-        setCurrentDir(newDir);
-      }
-    } catch (error: any) {
-      setOutput(prev => [...prev, `[error] ${error.message || 'Command execution failed'}`]);
-    } finally {
-      setIsExecuting(false);
-      setOutput(prev => [...prev, '']); // Add blank line after command
+    if (input === '\r') {
+      const line = lineRef.current.trim();
+      lineRef.current = '';
+      if (line) onCommandRef.current?.(line);
+    } else if (input === '\x7f') {
+      lineRef.current = lineRef.current.slice(0, -1);
+    } else if (input === '\x03' || input === '\x15') {
+      lineRef.current = '';
+    } else if (input.length === 1 && input >= ' ') {
+      lineRef.current += input;
     }
+    send(input);
   };
 
-  const handleKeyDown = (e: React.KeyboardEvent) => {
-    if (e.key === 'Escape') {
-      onClose?.();
-    }
+  const handlePaste = (e: React.ClipboardEvent) => {
+    e.preventDefault();
+    const text = e.clipboardData.getData('text');
+    lineRef.current += text;
+    send(text);
   };
 
   if (isMinimized) {
@@ -114,8 +136,9 @@ export function Terminal({ height = 300, onClose, onCommandExecute }: TerminalPr
     );
   }
 
+  const lastLine = screen.lines.length - 1;
   return (
-    <div className="terminal" style={{ height: `${height}px` }} onKeyDown={handleKeyDown}>
+    <div className="terminal" style={{ height: `${height}px` }}>
       <div className="terminal-header">
         <div className="terminal-header-left">
           <FiTerminal size={14} />
@@ -138,29 +161,39 @@ export function Terminal({ height = 300, onClose, onCommandExecute }: TerminalPr
           </button>
         </div>
       </div>
-      <div className="terminal-content" ref={outputRef}>
-        {output.map((line, index) => (
+      <div
+        className="terminal-content"
+        ref={outputRef}
+        tabIndex={0}
+        onKeyDown={handleKeyDown}
+        onPaste={handlePaste}
+      >
+        {screen.lines.map((line, index) => (
           <div key={index} className="terminal-line">
-            {line}
+            {index === lastLine && !exited ? (
+              <>
+                {line.slice(0, screen.column)}
+                <span className="terminal-cursor">{line[screen.column] ?? ' '}</span>
+                {line.slice(screen.column + 1)}
+              </>
+            ) : line || ' '}
           </div>
         ))}
+        {exited && (
+          <div className="terminal-line">{exited} Press Enter to start a new shell.</div>
+        )}
       </div>
-      <form className="terminal-input-container" onSubmit={handleCommand}>
-        <span className="terminal-prompt">
-          {currentDir ? `[${currentDir}]` : ''}$
-        </span>
-        <input
-          ref={inputRef}
-          className="terminal-input"
-          type="text"
-          value={command}
-          onChange={(e) => setCommand(e.target.value)}
-          placeholder={isExecuting ? "Executing..." : "Type a command..."}
-          autoFocus
-          disabled={isExecuting}
-        />
-      </form>
     </div>
   );
 }
 
+/**
+ * The terminal size in characters that fits `element`
+ */
+function terminalSize(element: HTMLElement | null): { cols: number; rows: number } {
+  if (!element) return { cols: 80, rows: 24 };
+  return {
+    cols: Math.max(20, Math.floor((element.clientWidth - 24) / CELL_WIDTH)),
+    rows: Math.max(5, Math.floor((element.clientHeight - 16) / CELL_HEIGHT))
+  };
+}
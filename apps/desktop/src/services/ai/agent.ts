@@ -5,7 +5,17 @@
 
 import { AIModel, selectBestModel } from './models';
 import { UnifiedAIClient, ChatRequest } from './api';
+import { invoke } from '@tauri-apps/api/core';
 import { TerminalExecutor } from '../terminal/executor';
+import { createAgentTools } from './tools';
+import {
+  onPlanProgress,
+  startNativePlan,
+  type NativePlan,
+  type NativePlanOperation,
+  type NativePlanStatus,
+  type NativePlanValidation
+} from '@henry-ai/local-ai';
 
 export enum AutonomyLevel {
   TAB = 'tab',              // Light assist - only autocomplete
@@ -39,6 +49,12 @@ export interface AgentPlanStep {
   command?: string;
   dependencies?: string[];
   status?: 'pending' | 'approved' | 'executing' | 'completed' | 'failed';
+  /** What the step does, for the native plan executor; inferred from `type` if unset */
+  operation?: NativePlanOperation;
+  /** Pause before this step until the user approves it */
+  requiresApproval?: boolean;
+  /** Checks after the step; a failure rolls the step back */
+  validate?: NativePlanValidation[];
 }
 
 export interface CodebaseContext {
@@ -63,6 +79,7 @@ export class AgentService {
   private tasks: Map<string, AgentTask> = new Map();
   private apiClient?: UnifiedAIClient;
   private terminalExecutor?: TerminalExecutor;
+  private tools = createAgentTools();
 
   constructor(
     model?: AIModel,
@@ -209,6 +226,11 @@ export class AgentService {
       throw new Error('Plan required for full agent mode');
     }
 
+    const nativePlan = this.toNativePlan(task);
+    if (nativePlan) {
+      return this.executeNativePlan(task.plan, nativePlan);
+    }
+
     const results: string[] = [];
     
     for (const step of task.plan.steps) {
@@ -226,6 +248,96 @@ export class AgentService {
     return results.join('\n');
   }
 
+  /**
+   * The plan as typed operations for the native executor
+   * Read and search steps change nothing and are left out. Returns null
+   * outside the desktop app, or if a write step has no `operation`.
+   */
+  private toNativePlan(task: AgentTask): NativePlan | null {
+    if (!task.plan || typeof window === 'undefined' || !('__TAURI__' in window)) {
+      return null;
+    }
+
+    const steps: NativePlan['steps'] = [];
+    for (const step of task.plan.steps) {
+      let operation = step.operation;
+      if (!operation) {
+        if (step.type === 'execute' && step.command) {
+          operation = { type: 'command', argv: step.command.trim().split(/\s+/) };
+        } else if (step.type === 'test') {
+          operation = step.command
+            ? { type: 'command', argv: step.command.trim().split(/\s+/) }
+            : { type: 'test', paths: step.target ? [step.target] : [] };
+        } else if (step.type === 'write' || step.type === 'execute') {
+          return null;
+        } else {
+          continue;
+        }
+      }
+      steps.push({
+        id: step.id,
+        description: step.description,
+        operation,
+        requiresApproval: step.requiresApproval,
+        validate: step.validate
+      });
+    }
+
+    return {
+      // Plan ids name files in the backend
+      id: task.id.replace(/[^A-Za-z0-9-]/g, '-'),
+      goal: task.description,
+      steps
+    };
+  }
+
+  /**
+   * Run a plan in the backend and wait until it stops
+   * Each file step is checkpointed, so a failed step is rolled back on its
+   * own; a plan waiting for approval keeps waiting until the user decides in
+   * the plan view.
+   */
+  private async executeNativePlan(plan: AgentPlan, nativePlan: NativePlan): Promise<string> {
+    const finished: NativePlanStatus[] = ['completed', 'failed', 'cancelled', 'rolledBack'];
+    const results = new Map<string, string>();
+    let unlisten: (() => void) | undefined;
+
+    const status = await new Promise<NativePlanStatus>((resolve, reject) => {
+      onPlanProgress(progress => {
+        if (progress.planId !== nativePlan.id) {
+          return;
+        }
+        const step = progress.step && plan.steps.find(s => s.id === progress.step!.id);
+        if (step && progress.step) {
+          const state = progress.step;
+          if (state.status === 'running') {
+            step.status = 'executing';
+          } else if (state.status === 'completed' || state.status === 'failed') {
+            step.status = state.status;
+            const detail = state.status === 'completed' ? state.output : state.error;
+            results.set(step.id, `${state.status === 'completed' ? '✓' : '✗'} ${step.description}${detail ? `: ${detail}` : ''}`);
+          }
+        }
+        if (finished.includes(progress.status)) {
+          resolve(progress.status);
+        }
+      })
+        .then(stop => {
+          unlisten = stop;
+          return startNativePlan(nativePlan);
+        })
+        .catch(reject);
+    }).finally(() => unlisten?.());
+
+    const lines = nativePlan.steps
+      .map(step => results.get(step.id))
+      .filter((line): line is string => Boolean(line));
+    if (status !== 'completed') {
+      lines.push(`Plan ${status === 'rolledBack' ? 'rolled back' : status}`);
+    }
+    return lines.join('\n');
+  }
+
   /**
    * Execute a single plan step
    */
@@ -235,12 +347,20 @@ export class AgentService {
         return 'Read operation completed';
       case 'write':
         return `Written to ${step.target}`;
-      case 'search':
-        return `Search completed: ${step.target}`;
+      case 'search': {
+        if (!step.target) {
+          return 'No search query specified';
+        }
+        const result = await this.tools.get('search_code')!.execute({ query: step.target });
+        if (!result.success) {
+          throw new Error(result.error);
+        }
+        return result.output;
+      }
       case 'execute':
         if (step.command && this.terminalExecutor) {
           try {
-            const result = await this.terminalExecutor.execute(step.command);
+            const result = await this.terminalExecutor.executeForAgent(step.command);
             if (result.success) {
               return `Executed: ${step.command}\n${result.stdout}`;
             } else {
@@ -256,7 +376,7 @@ export class AgentService {
           try {
             // Try common test commands
             const testCmd = step.command || 'npm test';
-            const result = await this.terminalExecutor.execute(testCmd);
+            const result = await this.terminalExecutor.executeForAgent(testCmd);
             if (result.success) {
               return `Tests passed\n${result.stdout}`;
             } else {
@@ -317,10 +437,23 @@ export class AgentService {
   }
 
   /**
-   * Set autonomy level
+   * Set autonomy level, returning the level in effect
+   *
+   * In the desktop app the backend enforces it for the commands the agent
+   * runs, and asks the user to confirm a raise; a declined raise keeps the
+   * current level.
    */
-  setAutonomyLevel(level: AutonomyLevel): void {
+  async setAutonomyLevel(level: AutonomyLevel): Promise<AutonomyLevel> {
+    if (typeof window !== 'undefined' && '__TAURI__' in window) {
+      try {
+        level = await invoke<AutonomyLevel>('set_autonomy_level', { level });
+      } catch (error) {
+        console.error('Failed to set autonomy level:', error);
+        return this.autonomyLevel;
+      }
+    }
     this.autonomyLevel = level;
+    return level;
   }
 
   /**
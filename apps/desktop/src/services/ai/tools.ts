@@ -0,0 +1,17 @@
+/**
+ * The agent's tools in the desktop app
+ * The core package defines the tools; this supplies the host services they
+ * call into. Imported from the `tools` entry point, which stays clear of the
+ * Node-only parts of the core package.
+ */
+
+import { createStandardTools, ToolRegistry } from '@henry-ai/core/tools';
+import { findText } from '../codebase/textSearch';
+
+export function createAgentTools(): ToolRegistry {
+  const registry = new ToolRegistry();
+  for (const tool of createStandardTools({ searchText: findText })) {
+    registry.register(tool);
+  }
+  return registry;
+}
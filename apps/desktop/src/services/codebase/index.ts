@@ -5,3 +5,6 @@
 export * from './intelligence';
 export { CodebaseIntelligenceService, type CodeSearchResult, type CodebaseQuery, type CodebaseInsight } from './intelligence';
 
+export * from './nativeIndex';
+
+export * from './textSearch';
@@ -5,6 +5,17 @@
 
 import { AIModel } from '../ai/models';
 import { UnifiedAIClient, ChatRequest } from '../ai/api';
+import {
+  findDependents,
+  findImports,
+  getSymbols,
+  impactOfChange,
+  retrieveContext,
+  type CodeSymbol
+} from './nativeIndex';
+
+/** Tokens of code `semanticSearch` retrieves */
+const DEFAULT_BUDGET_TOKENS = 4000;
 
 export interface CodeSearchResult {
   file: string;
@@ -12,6 +23,9 @@ export interface CodeSearchResult {
   score: number;
   context?: {
     line?: number;
+    endLine?: number;
+    /** `path:start-end`, relative to the workspace root */
+    citation?: string;
     symbols?: string[];
   };
 }
@@ -72,66 +86,29 @@ export class CodebaseIntelligenceService {
 
   /**
    * Semantic search across codebase
-   * Uses AI to find relevant code based on natural language query
+   * Ranks indexed code by keywords, embeddings and, given the file being edited,
+   * import distance to it
    */
-  async semanticSearch(query: string, limit: number = 10): Promise<CodeSearchResult[]> {
-    if (this.indexedFiles.size === 0) {
-      return [];
-    }
-
-    // If no API client, use simple text matching
-    if (!this.apiClient) {
-      return this.textSearch(query, limit);
-    }
-
+  async semanticSearch(
+    query: string,
+    limit: number = 10,
+    options: { activeFile?: string; budgetTokens?: number } = {}
+  ): Promise<CodeSearchResult[]> {
     try {
-      // Use AI to understand query and find relevant code
-      const searchPrompt = `Given this codebase, find code relevant to: "${query}"
-
-Return a list of files and code snippets that match the query. For each result, provide:
-- File path
-- Relevant code snippet (2-3 lines)
-- Why it's relevant
-
-Codebase files:\n${Array.from(this.indexedFiles.entries())
-  .slice(0, 20) // Limit context size
-  .map(([path, data]) => `\n=== ${path} ===\n${data.content.slice(0, 500)}...`)
-  .join('\n')}`;
-
-      const chatRequest: ChatRequest = {
-        messages: [
-          {
-            role: 'system',
-            content: 'You are a codebase search assistant. Find and return relevant code snippets based on queries.'
-          },
-          {
-            role: 'user',
-            content: searchPrompt
-          }
-        ],
-        maxTokens: 2048,
-        temperature: 0.3
-      };
-
-      const response = await this.apiClient.getChatCompletion(this.model, chatRequest);
-
-      // Parse AI response to extract file paths and snippets
-      // This is simplified - in production, use structured output
-      const results: CodeSearchResult[] = [];
-      const fileMatches = response.matchAll(/=== ([^\n]+) ===/g);
-
-      for (const match of fileMatches) {
-        const filePath = match[1];
-        if (this.indexedFiles.has(filePath)) {
-          results.push({
-            file: filePath,
-            content: this.indexedFiles.get(filePath)!.content.slice(0, 200),
-            score: 0.8
-          });
+      const context = await retrieveContext(query, options.budgetTokens ?? DEFAULT_BUDGET_TOKENS, {
+        activeFile: options.activeFile
+      });
+      return context.chunks.slice(0, limit).map(chunk => ({
+        file: chunk.path,
+        content: chunk.text,
+        score: chunk.score,
+        context: {
+          line: chunk.startLine,
+          endLine: chunk.endLine,
+          citation: chunk.citation,
+          symbols: chunk.symbol ? [chunk.symbol] : undefined
         }
-      }
-
-      return results.slice(0, limit);
+      }));
     } catch (error) {
       console.error('Semantic search error:', error);
       return this.textSearch(query, limit);
@@ -242,12 +219,12 @@ Provide a detailed answer with references to specific files and code snippets.`
   /**
    * Get code context for a file
    */
-  getFileContext(filePath: string, lineNumber?: number): {
+  async getFileContext(filePath: string, lineNumber?: number): Promise<{
     file: string;
     content: string;
     symbols?: string[];
     imports?: string[];
-  } | null {
+  } | null> {
     const fileData = this.indexedFiles.get(filePath);
     if (!fileData) return null;
 
@@ -258,11 +235,18 @@ Provide a detailed answer with references to specific files and code snippets.`
       imports.push(match[1]);
     }
 
-    // Extract symbols (simplified - would use AST in production)
+    // Symbols from the backend's parse tree, outermost first
     const symbols: string[] = [];
-    const functionMatches = fileData.content.matchAll(/(?:function|const|export\s+function)\s+(\w+)/g);
-    for (const match of functionMatches) {
-      symbols.push(match[1]);
+    try {
+      const outline = await getSymbols(filePath);
+      const pending: CodeSymbol[] = [...outline.symbols];
+      while (pending.length > 0) {
+        const symbol = pending.shift()!;
+        symbols.push(symbol.name);
+        pending.push(...(symbol.children ?? []));
+      }
+    } catch (error) {
+      console.warn('Symbol outline unavailable:', error);
     }
 
     return {
@@ -274,59 +258,22 @@ Provide a detailed answer with references to specific files and code snippets.`
   }
 
   /**
-   * Find related files (based on imports, exports, usage)
+   * Find related files: what the file imports and what imports it, from the
+   * backend's import graph
    */
-  findRelatedFiles(filePath: string): string[] {
-    const fileData = this.indexedFiles.get(filePath);
-    if (!fileData) return [];
-
-    const related = new Set<string>();
-
-    // Find imports
-    const importMatches = fileData.content.matchAll(/from\s+['"](.+?)['"]/g);
-    for (const match of importMatches) {
-      const importPath = match[1];
-      // Try to resolve to actual file
-      const resolvedPath = this.resolveImport(filePath, importPath);
-      if (resolvedPath && this.indexedFiles.has(resolvedPath)) {
-        related.add(resolvedPath);
-      }
-    }
-
-    // Find exports and see where they're used
-    const exportMatches = fileData.content.matchAll(/export\s+(?:const|function|class|default)\s+(\w+)/g);
-    for (const exportName of exportMatches) {
-      // Search for usage in other files
-      for (const [otherPath, otherData] of this.indexedFiles.entries()) {
-        if (otherPath !== filePath && otherData.content.includes(exportName[1])) {
-          related.add(otherPath);
-        }
-      }
-    }
-
-    return Array.from(related);
-  }
-
-  /**
-   * Resolve import path to actual file path
-   */
-  private resolveImport(fromFile: string, importPath: string): string | null {
-    // Simplified resolution - in production, use proper module resolution
-    // This is a basic implementation
-    if (importPath.startsWith('.')) {
-      // Relative import
-      const dir = fromFile.substring(0, fromFile.lastIndexOf('/'));
-      return `${dir}/${importPath}`.replace(/\/+/g, '/');
-    }
-
-    // Try to find in indexed files
-    for (const path of this.indexedFiles.keys()) {
-      if (path.endsWith(importPath) || path.includes(importPath)) {
-        return path;
-      }
+  async findRelatedFiles(filePath: string): Promise<string[]> {
+    try {
+      const [imports, dependents] = await Promise.all([
+        findImports(filePath),
+        findDependents(filePath, 1)
+      ]);
+      const related = new Set([...imports, ...dependents].map(link => link.path));
+      related.delete(filePath);
+      return Array.from(related);
+    } catch (error) {
+      console.warn('Import graph unavailable:', error);
+      return [];
     }
-
-    return null;
   }
 
   /**
@@ -340,8 +287,24 @@ Provide a detailed answer with references to specific files and code snippets.`
     breakingChanges: string[];
     suggestions: string[];
   }> {
-    const relatedFiles = this.findRelatedFiles(filePath);
-    const affectedFiles = [filePath, ...relatedFiles];
+    // Top-level symbols the description names; none means the whole file
+    let symbols: string[] = [];
+    try {
+      const outline = await getSymbols(filePath);
+      symbols = outline.symbols
+        .map(symbol => symbol.name)
+        .filter(name => changeDescription.includes(name));
+    } catch (error) {
+      console.warn('Symbol outline unavailable:', error);
+    }
+
+    const affectedFiles = [filePath];
+    try {
+      const impact = await impactOfChange(filePath, symbols);
+      affectedFiles.push(...[...impact.direct, ...impact.transitive].map(link => link.path));
+    } catch (error) {
+      console.warn('Import graph unavailable:', error);
+    }
 
     // Use AI to analyze impact
     if (this.apiClient) {
@@ -0,0 +1,226 @@
+/**
+ * Native code index
+ * Thin wrappers over the Rust indexer commands. The index is built per
+ * workspace root and kept up to date from filesystem events by the backend.
+ */
+
+import { invoke } from '@tauri-apps/api/core';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
+
+export type IndexState = 'missing' | 'indexing' | 'ready';
+
+export interface IndexStatus {
+  root: string;
+  state: IndexState;
+  files: number;
+  symbols: number;
+  indexedAtMs: number | null;
+  processed: number;
+  total: number;
+}
+
+export interface IndexSearchHit {
+  root: string;
+  path: string;
+  kind: 'file' | 'symbol';
+  name: string;
+  symbolKind: string | null;
+  line: number | null;
+  language: string | null;
+  score: number;
+}
+
+/**
+ * Build or refresh the index of `root` (the first open workspace by default)
+ * Resolves when the build finishes; use `onIndexProgress` to follow it.
+ */
+export async function indexWorkspace(root?: string, force = false): Promise<IndexStatus> {
+  return invoke<IndexStatus>('index_workspace', { root, force });
+}
+
+export async function indexStatus(root?: string): Promise<IndexStatus> {
+  return invoke<IndexStatus>('index_status', { root });
+}
+
+/**
+ * Search indexed file names and symbols across open workspaces
+ */
+export async function indexSearch(
+  query: string,
+  options: { root?: string; limit?: number } = {}
+): Promise<IndexSearchHit[]> {
+  return invoke<IndexSearchHit[]>('index_search', { query, ...options });
+}
+
+export function onIndexProgress(handler: (status: IndexStatus) => void): Promise<UnlistenFn> {
+  return listen<IndexStatus>('index://progress', ({ payload }) => handler(payload));
+}
+
+export interface SymbolRange {
+  start: { line: number; column: number };
+  end: { line: number; column: number };
+}
+
+export interface CodeSymbol {
+  name: string;
+  kind:
+    | 'module' | 'class' | 'struct' | 'interface' | 'enum' | 'impl' | 'type'
+    | 'function' | 'method' | 'constructor' | 'field' | 'constant' | 'variable';
+  range: SymbolRange;
+  signature?: string;
+  doc?: string;
+  children?: CodeSymbol[];
+}
+
+export interface SymbolOutline {
+  language: string | null;
+  symbols: CodeSymbol[];
+}
+
+/**
+ * Hierarchical symbol outline of a workspace file, from its tree-sitter parse
+ */
+export async function getSymbols(path: string): Promise<SymbolOutline> {
+  return invoke<SymbolOutline>('get_symbols', { path });
+}
+
+/** A file on the other end of an import, from the native import graph */
+export interface ImportLink {
+  path: string;
+  /** Imports between `path` and the queried file; 1 is a direct import */
+  depth: number;
+  /** The file one step closer to the queried file */
+  via: string;
+  names: string[];
+  /** Line of the import statement in the importing file */
+  line: number;
+}
+
+export interface ChangeImpact {
+  path: string;
+  symbols: string[];
+  direct: ImportLink[];
+  transitive: ImportLink[];
+  risk: 'low' | 'medium' | 'high';
+}
+
+export interface CodeReference {
+  path: string;
+  line: number;
+  column: number;
+  text: string;
+  definition: boolean;
+}
+
+/**
+ * Workspace files imported by `path`
+ */
+export async function findImports(path: string): Promise<ImportLink[]> {
+  return invoke<ImportLink[]>('find_imports', { path });
+}
+
+/**
+ * Files importing `path`, directly or through up to `depth` levels of imports
+ */
+export async function findDependents(path: string, depth?: number): Promise<ImportLink[]> {
+  return invoke<ImportLink[]>('find_dependents', { path, depth });
+}
+
+/**
+ * Files affected by changing `symbols` of `path`, or the whole file if none are given
+ */
+export async function impactOfChange(path: string, symbols?: string[]): Promise<ChangeImpact> {
+  return invoke<ChangeImpact>('impact_of_change', { path, symbols });
+}
+
+/**
+ * Occurrences of `symbol`; with `path`, only where the definition in that file is visible
+ */
+export async function findReferences(
+  symbol: string,
+  options: { path?: string; root?: string } = {}
+): Promise<CodeReference[]> {
+  return invoke<CodeReference[]>('find_references', { symbol, ...options });
+}
+
+export interface EmbedStatus {
+  root: string;
+  model: string;
+  processed: number;
+  total: number;
+  /** Chunks sent to the embedder */
+  embedded: number;
+  /** Chunks whose stored vector was reused */
+  reused: number;
+  /** Chunks dropped with their files */
+  removed: number;
+}
+
+/**
+ * Embed the files of `root` that changed since the last run into the vector store,
+ * with the embedder from `.henryrc` (offline hashing by default)
+ * Afterwards the backend keeps the vectors in sync with file changes.
+ */
+export async function embedWorkspace(root?: string): Promise<EmbedStatus> {
+  return invoke<EmbedStatus>('embed_workspace', { root });
+}
+
+export function onEmbedProgress(handler: (status: EmbedStatus) => void): Promise<UnlistenFn> {
+  return listen<EmbedStatus>('embed://progress', ({ payload }) => handler(payload));
+}
+
+export interface SyncFailed {
+  root: string;
+  stage: 'watch' | 'index' | 'embeddings';
+  message: string;
+}
+
+/**
+ * Follow file changes the watcher missed, or the index or the vectors could not take
+ * They stay stale until the next `indexWorkspace` or `embedWorkspace`.
+ */
+export function onSyncFailed(handler: (failure: SyncFailed) => void): Promise<UnlistenFn> {
+  return listen<SyncFailed>('fs://sync-failed', ({ payload }) => handler(payload));
+}
+
+/** A chunk of code picked for an agent's context */
+export interface ContextChunk {
+  path: string;
+  /** `path:start-end`, relative to the workspace root */
+  citation: string;
+  symbol: string | null;
+  kind: string | null;
+  language: string | null;
+  startLine: number;
+  endLine: number;
+  text: string;
+  /** Blended score from 0 to 1 */
+  score: number;
+  keyword: number;
+  vector: number;
+  /** 1 in the active file, less the more imports away */
+  proximity: number;
+  tokens: number;
+}
+
+export interface RetrievedContext {
+  /** Best first */
+  chunks: ContextChunk[];
+  tokens: number;
+  budgetTokens: number;
+  /** Relevant chunks that didn't fit the budget */
+  omitted: number;
+}
+
+/**
+ * Code relevant to `query`, ranked by keywords, embeddings and import distance to
+ * `activeFile`, and packed into `budgetTokens`
+ * Indexes and embeds whatever changed first, so the first call on a workspace is slow.
+ */
+export async function retrieveContext(
+  query: string,
+  budgetTokens: number,
+  options: { activeFile?: string; root?: string } = {}
+): Promise<RetrievedContext> {
+  return invoke<RetrievedContext>('retrieve_context', { query, budgetTokens, ...options });
+}
@@ -0,0 +1,96 @@
+/**
+ * Project-wide text search
+ * Wraps the Rust `search_text` command. Matches stream in per file while the
+ * search runs; the returned promise resolves with a summary when it ends.
+ */
+
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+
+export interface TextSearchOptions {
+  /** Treat the query as a regular expression instead of literal text */
+  regex?: boolean;
+  caseSensitive?: boolean;
+  wholeWord?: boolean;
+  /** Globs over workspace-relative paths, e.g. `src/**` or `*.ts` */
+  include?: string[];
+  exclude?: string[];
+  /** Context lines before and after each match */
+  context?: number;
+  maxResults?: number;
+  /** Search one open root instead of all of them */
+  root?: string;
+}
+
+export interface TextSearchLine {
+  line: number;
+  text: string;
+  /** Byte ranges of the matches in `text`; empty for context lines */
+  ranges: Array<[number, number]>;
+  context: boolean;
+}
+
+export interface TextSearchFile {
+  searchId: string;
+  path: string;
+  lines: TextSearchLine[];
+}
+
+export interface TextSearchSummary {
+  searchId: string;
+  files: number;
+  matches: number;
+  truncated: boolean;
+  cancelled: boolean;
+}
+
+/**
+ * Search file contents, calling `onFile` as each file's matches arrive
+ * Aborting `signal` cancels the search; the summary then has `cancelled` set.
+ */
+export async function searchText(
+  query: string,
+  options: TextSearchOptions = {},
+  onFile?: (file: TextSearchFile) => void,
+  signal?: AbortSignal
+): Promise<TextSearchSummary> {
+  const searchId = crypto.randomUUID();
+  const unlisten = await listen<TextSearchFile>('search://match', ({ payload }) => {
+    if (payload.searchId === searchId) {
+      onFile?.(payload);
+    }
+  });
+  const abort = () => {
+    void cancelSearch(searchId);
+  };
+  signal?.addEventListener('abort', abort);
+  try {
+    return await invoke<TextSearchSummary>('search_text', { searchId, query, options });
+  } finally {
+    signal?.removeEventListener('abort', abort);
+    unlisten();
+  }
+}
+
+export async function cancelSearch(searchId: string): Promise<boolean> {
+  return invoke<boolean>('cancel_search', { searchId });
+}
+
+/**
+ * Matching lines as `{ path, line, text }`, without context, for callers
+ * that just want a list such as the agent's `search_code` tool
+ */
+export async function findText(
+  query: string,
+  options: TextSearchOptions = {}
+): Promise<Array<{ path: string; line: number; text: string }>> {
+  const found: Array<{ path: string; line: number; text: string }> = [];
+  await searchText(query, { ...options, context: 0 }, file => {
+    for (const line of file.lines) {
+      if (!line.context) {
+        found.push({ path: file.path, line: line.line, text: line.text });
+      }
+    }
+  });
+  return found;
+}
@@ -3,8 +3,8 @@
  * Manages project-specific rules and memories stored in .cursor/rules.mdc
  */
 
-import { readTextFile, writeTextFile } from '@tauri-apps/plugin-fs';
 import { join } from '@tauri-apps/api/path';
+import { applyEditBatch, readWorkspaceFile, requestBatchPermission } from '../../utils/permissions';
 
 export interface ProjectRule {
   id: string;
@@ -46,7 +46,7 @@ export class RulesMemoryService {
 
     try {
       const rulesPath = await join(this.projectRoot, '.cursor', 'rules.mdc');
-      const content = await readTextFile(rulesPath);
+      const content = await readWorkspaceFile(rulesPath);
       
       // Parse .mdc format (Markdown with metadata)
       const parsed = this.parseRulesFile(content);
@@ -66,7 +66,7 @@ export class RulesMemoryService {
     try {
       const rulesPath = await join(this.projectRoot, '.cursor', 'rules.mdc');
       const content = this.serializeRulesFile(Array.from(this.rules.values()));
-      await writeTextFile(rulesPath, content);
+      await this.writeProjectFile(rulesPath, content);
     } catch (error: any) {
       console.error('Failed to save rules:', error);
       throw new Error(`Failed to save rules: ${error.message}`);
@@ -183,6 +183,21 @@ export class RulesMemoryService {
     await this.saveMemories();
   }
 
+  /**
+   * Write a file under the project root once the user approves it
+   * Goes through the backend batch commands, which create `.cursor/` if needed.
+   */
+  private async writeProjectFile(path: string, content: string): Promise<void> {
+    const exists = await readWorkspaceFile(path).then(() => true, () => false);
+    const ops = [exists ? { op: 'edit' as const, path, content } : { op: 'create' as const, path, content }];
+    const permission = await requestBatchPermission(ops);
+    const result = await applyEditBatch(ops, permission);
+    if (!result.applied) {
+      const failure = result.results.find(r => r.error);
+      throw new Error(failure?.error?.message ?? failure?.error?.kind ?? `Could not write ${path}`);
+    }
+  }
+
   /**
    * Load memories from storage
    */
@@ -191,7 +206,7 @@ export class RulesMemoryService {
 
     try {
       const memoriesPath = await join(this.projectRoot, '.cursor', 'memories.json');
-      const content = await readTextFile(memoriesPath);
+      const content = await readWorkspaceFile(memoriesPath);
       const memories: ProjectMemory[] = JSON.parse(content);
       this.memories = new Map(memories.map(m => [m.id, m]));
     } catch (error) {
@@ -209,7 +224,7 @@ export class RulesMemoryService {
     try {
       const memoriesPath = await join(this.projectRoot, '.cursor', 'memories.json');
       const content = JSON.stringify(Array.from(this.memories.values()), null, 2);
-      await writeTextFile(memoriesPath, content);
+      await this.writeProjectFile(memoriesPath, content);
     } catch (error: any) {
       console.error('Failed to save memories:', error);
     }
@@ -1,9 +1,16 @@
 /**
  * Terminal Command Executor
- * Executes shell commands via Tauri shell plugin
+ * Programs the app runs for itself run on a PTY, one per call; the
+ * terminal panel hosts its own persistent shell. The agent's commands go through the Rust `run_agent_command` command and
+ * its policy: argv only, never a shell string, in a directory inside the
+ * workspace with a scrubbed env.
  */
 
-import { Command } from '@tauri-apps/plugin-shell';
+import { invoke } from '@tauri-apps/api/core';
+import { PtySession, type PtyExit } from './pty';
+
+/** How long `execute` lets a program run */
+const DEFAULT_TIMEOUT_MS = 30_000;
 
 // Helper function to get platform
 async function getPlatform(): Promise<string> {
@@ -23,62 +30,125 @@ export interface CommandResult {
   success: boolean;
 }
 
+export interface RunCommandOptions {
+  /** Working directory inside an open workspace; the first root if unset */
+  cwd?: string;
+  /** Variables to set, on top of PATH, HOME and the like */
+  env?: Record<string, string>;
+  /** More variables to pass through from the app's environment */
+  inheritEnv?: string[];
+  timeoutMs?: number;
+  /** Bytes kept of each of stdout and stderr; the middle is cut */
+  maxOutputBytes?: number;
+}
+
+export interface RunCommandOutput {
+  /** null if a signal ended the program */
+  exitCode: number | null;
+  signal: number | null;
+  timedOut: boolean;
+  durationMs: number;
+  stdout: string;
+  stderr: string;
+  stdoutTruncated: number;
+  stderrTruncated: number;
+}
+
+export type CommandClass = 'readOnly' | 'buildTest' | 'network' | 'destructive' | 'unknown';
+
+export interface CommandDecision {
+  atMs: number;
+  argv: string[];
+  cwd: string;
+  level: 'tab' | 'cmd-k' | 'full-agent';
+  class: CommandClass;
+  verdict: 'approve' | 'prompt' | 'block';
+  /** The `.henryrc` rule that decided, if one did */
+  rule: string | null;
+  reason: string;
+  /** Whether the command went on to run */
+  approved: boolean;
+}
+
+/**
+ * Run `argv` for the agent, subject to the command policy
+ * The whole process group is killed on timeout and once the program exits.
+ * Depending on the autonomy level and `.henryrc` rules it runs straight away,
+ * after the user approves it in a native dialog, or not at all; blocked and
+ * refused commands reject with a `blocked` or `refused` error.
+ */
+export async function runAgentCommand(argv: string[], options: RunCommandOptions = {}): Promise<RunCommandOutput> {
+  return invoke<RunCommandOutput>('run_agent_command', { argv, options });
+}
+
+/**
+ * Logged command policy decisions, newest first
+ */
+export async function commandDecisions(limit?: number): Promise<CommandDecision[]> {
+  return invoke<CommandDecision[]>('command_decisions', { limit });
+}
+
 export class TerminalExecutor {
-  private processes: Map<string, Command> = new Map();
+  private processes: Map<string, unknown> = new Map();
 
   /**
-   * Execute a shell command
+   * Run one program with `args` on a PTY and collect its output
+   * There is no shell, so pipes, `&&`, quoting and `cd` don't apply, and
+   * nothing carries over to the next call; the Terminal panel hosts a
+   * persistent shell for that. Input is closed right away, so a program
+   * that reads it sees end of file rather than waiting, and one still
+   * running after `timeoutMs` is killed. The PTY merges stderr into stdout.
    */
-  async execute(command: string, args: string[] = [], cwd?: string): Promise<CommandResult> {
+  async execute(
+    program: string,
+    args: string[] = [],
+    cwd?: string,
+    timeoutMs = DEFAULT_TIMEOUT_MS
+  ): Promise<CommandResult> {
+    let output = '';
+    let stderr = '';
+    let exitCode = 1;
+    let success = false;
     try {
-      // Parse command and args if command is a string
-      let cmdName = command;
-      let cmdArgs = args;
-
-      // Handle commands like "npm install" by splitting
-      if (command.includes(' ') && args.length === 0) {
-        const parts = command.split(/\s+/);
-        cmdName = parts[0];
-        cmdArgs = parts.slice(1);
-      }
-
-      // Use Tauri shell plugin to execute command
-      // Handle working directory by wrapping command
-      let shellProcess: Command;
-      
-      if (cwd) {
-        const platformName = await getPlatform();
-        if (platformName === 'win32') {
-          // On Windows, use cmd with /C to change directory
-          const fullCommand = `cd /d "${cwd}" && ${cmdName} ${cmdArgs.join(' ')}`;
-          shellProcess = Command.create('cmd', ['/C', fullCommand]);
-        } else {
-          // On Unix, use sh -c with cd
-          const fullCommand = `cd "${cwd}" && ${cmdName} ${cmdArgs.join(' ')}`;
-          shellProcess = Command.create('sh', ['-c', fullCommand]);
+      for await (const data of this.stream(program, args, cwd, timeoutMs)) {
+        if (typeof data === 'string') {
+          output += data;
+          continue;
         }
-      } else {
-        shellProcess = Command.create(cmdName, cmdArgs);
+        const { exit, timedOut } = data;
+        stderr = timedOut
+          ? `[timed out after ${timeoutMs}ms]`
+          : exit.signal ? `[ended by ${exit.signal}]` : '';
+        exitCode = exit.exitCode;
+        success = exit.exitCode === 0 && !exit.signal && !timedOut;
       }
+    } catch (error: any) {
+      stderr = error?.message || JSON.stringify(error) || 'Command execution failed';
+    }
+    return { stdout: plainText(output), stderr, exitCode, success };
+  }
 
-      const result = await shellProcess.execute();
-
-      // Tauri shell Command returns output object with stdout/stderr
-      // The exact structure may vary, so we handle both possibilities
-      const stdout = (typeof result.stdout === 'string' ? result.stdout : '') || '';
-      const stderr = (typeof result.stderr === 'string' ? result.stderr : '') || '';
-      const exitCode = (typeof result.code === 'number' ? result.code : 0);
+  /**
+   * Execute a command on the agent's behalf, subject to the command policy
+   */
+  async executeForAgent(command: string, args: string[] = [], cwd?: string): Promise<CommandResult> {
+    try {
+      const result = await runAgentCommand(splitCommand(command, args), { cwd });
+      const exitCode = result.exitCode ?? 1;
+      const stderr = result.timedOut
+        ? `${result.stderr}\n[timed out after ${result.durationMs}ms]`
+        : result.stderr;
 
       return {
-        stdout,
+        stdout: result.stdout,
         stderr,
         exitCode,
-        success: exitCode === 0
+        success: exitCode === 0 && !result.timedOut
       };
     } catch (error: any) {
       return {
         stdout: '',
-        stderr: error.message || 'Command execution failed',
+        stderr: error?.message || JSON.stringify(error) || 'Command execution failed',
         exitCode: 1,
         success: false
       };
@@ -86,44 +156,64 @@ export class TerminalExecutor {
   }
 
   /**
-   * Execute command and stream output
+   * Run one program like `execute`, yielding its output as it arrives
+   * Failing to start throws; the last line is `[exit code: N]` if the
+   * program failed.
    */
   async *streamExecute(
-    command: string,
+    program: string,
     args: string[] = [],
-    cwd?: string
+    cwd?: string,
+    timeoutMs = DEFAULT_TIMEOUT_MS
   ): AsyncGenerator<string, void, unknown> {
-    try {
-      let cmdName = command;
-      let cmdArgs = args;
-
-      if (command.includes(' ') && args.length === 0) {
-        const parts = command.split(/\s+/);
-        cmdName = parts[0];
-        cmdArgs = parts.slice(1);
-      }
-
-      const shellProcess = Command.create(cmdName, cmdArgs);
-
-      // Note: Tauri shell plugin doesn't support streaming in the same way
-      // For now, we'll execute and yield the full output
-      const output = await shellProcess.execute();
-      
-      if (output.stdout) {
-        const lines = output.stdout.split('\n');
-        for (const line of lines) {
-          yield line;
-        }
+    for await (const data of this.stream(program, args, cwd, timeoutMs)) {
+      if (typeof data === 'string') {
+        yield plainText(data);
+      } else if (data.timedOut) {
+        yield `[timed out after ${timeoutMs}ms]`;
+      } else if (data.exit.exitCode !== 0 || data.exit.signal) {
+        yield `[exit code: ${data.exit.signal ?? data.exit.exitCode}]`;
       }
+    }
+  }
 
-      if (output.stderr) {
-        const lines = output.stderr.split('\n');
-        for (const line of lines) {
-          yield `[stderr] ${line}`;
-        }
+  /**
+   * Output of `program` on a new PTY as it arrives, then how it ended
+   */
+  private async *stream(
+    program: string,
+    args: string[],
+    cwd: string | undefined,
+    timeoutMs: number
+  ): AsyncGenerator<string | { exit: PtyExit; timedOut: boolean }, void, unknown> {
+    const queue: string[] = [];
+    // Set from the exit handler
+    const state: { exit: PtyExit | null } = { exit: null };
+    let wake: () => void = () => {};
+    const session = await PtySession.spawn(
+      {
+        onData: data => { queue.push(data); wake(); },
+        onExit: exit => { state.exit = exit; wake(); }
+      },
+      { command: program, args, cwd }
+    );
+    let timedOut = false;
+    const timer = setTimeout(() => {
+      timedOut = true;
+      session.kill().catch(() => {});
+    }, timeoutMs);
+    // End of file for a program reading its input
+    session.write('\x04').catch(() => {});
+    try {
+      while (true) {
+        while (queue.length > 0) yield queue.shift()!;
+        if (state.exit) break;
+        await new Promise<void>(resolve => { wake = resolve; });
       }
-    } catch (error: any) {
-      yield `[error] ${error.message}`;
+      yield { exit: state.exit, timedOut };
+    } finally {
+      clearTimeout(timer);
+      if (!state.exit) session.kill().catch(() => {});
     }
   }
 
@@ -174,7 +264,7 @@ export class TerminalExecutor {
       const platform = await this.detectPlatform();
       if (platform === 'win32') {
         // On Windows, 'cd' without args prints current directory
-        const result = await this.execute('cd', []);
+        const result = await this.execute('cmd', ['/C', 'cd']);
         return result.stdout.trim() || '';
       } else {
         const result = await this.execute('pwd', []);
@@ -193,3 +283,22 @@ export class TerminalExecutor {
   }
 }
 
+/**
+ * Handle commands like "npm install" by splitting on whitespace
+ */
+function splitCommand(command: string, args: string[]): string[] {
+  if (command.includes(' ') && args.length === 0) {
+    return command.trim().split(/\s+/);
+  }
+  return [command, ...args];
+}
+
+/**
+ * PTY output without colour and cursor escapes, with plain newlines
+ */
+function plainText(output: string): string {
+  return output
+    .replace(/\x1b\][^\x07\x1b]*(?:\x07|\x1b\\)/g, '')
+    .replace(/\x1b\[[0-9;?]*[ -\/]*[@-~]/g, '')
+    .replace(/\r\n?/g, '\n');
+}
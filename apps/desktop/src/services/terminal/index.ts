@@ -4,4 +4,5 @@
 
 export * from './executor';
 export { TerminalExecutor, type CommandResult } from './executor';
-
+export * from './pty';
+export * from './screen';
@@ -0,0 +1,107 @@
+/**
+ * PTY terminal sessions
+ * Wraps the Rust `pty_*` commands. A session runs a real shell (or any
+ * program) on a pseudo-terminal; its output streams in as it is written, so
+ * colours, prompts and interactive programs behave as in a native terminal.
+ */
+
+import { invoke } from '@tauri-apps/api/core';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
+
+export interface PtySpawnOptions {
+  /** Program to run; the user's login shell if unset */
+  command?: string;
+  args?: string[];
+  /** Working directory inside an open workspace; the first root if unset */
+  cwd?: string;
+  env?: Record<string, string>;
+  cols?: number;
+  rows?: number;
+}
+
+export interface PtySessionInfo {
+  sessionId: string;
+  command: string;
+  cwd: string;
+  pid: number | null;
+}
+
+export interface PtyExit {
+  sessionId: string;
+  exitCode: number;
+  /** The signal that ended the program, if one did */
+  signal: string | null;
+}
+
+export interface PtyHandlers {
+  onData: (data: string) => void;
+  onExit?: (exit: PtyExit) => void;
+}
+
+/**
+ * A running terminal session
+ */
+export class PtySession {
+  private constructor(
+    readonly info: PtySessionInfo,
+    private readonly unlisten: UnlistenFn[]
+  ) {}
+
+  /**
+   * Start a session; handlers are attached before it starts, so no output is missed
+   */
+  static async spawn(handlers: PtyHandlers, options: PtySpawnOptions = {}): Promise<PtySession> {
+    const sessionId = crypto.randomUUID();
+    const unlisten: UnlistenFn[] = [];
+    const stop = () => unlisten.forEach(fn => fn());
+
+    unlisten.push(await listen<{ sessionId: string; data: string }>('pty://output', ({ payload }) => {
+      if (payload.sessionId === sessionId) handlers.onData(payload.data);
+    }));
+    unlisten.push(await listen<PtyExit>('pty://exit', ({ payload }) => {
+      if (payload.sessionId !== sessionId) return;
+      stop();
+      handlers.onExit?.(payload);
+    }));
+
+    try {
+      const info = await invoke<PtySessionInfo>('pty_spawn', { sessionId, options });
+      return new PtySession(info, unlisten);
+    } catch (error) {
+      stop();
+      throw new Error(`Terminal failed to start: ${JSON.stringify(error)}`);
+    }
+  }
+
+  get id(): string {
+    return this.info.sessionId;
+  }
+
+  /**
+   * Send keystrokes or pasted text
+   */
+  async write(data: string): Promise<void> {
+    await invoke('pty_write', { sessionId: this.id, data });
+  }
+
+  /**
+   * Tell the program the terminal's new size in characters
+   */
+  async resize(cols: number, rows: number): Promise<void> {
+    await invoke('pty_resize', { sessionId: this.id, cols, rows });
+  }
+
+  /**
+   * End the session; the exit handler still runs
+   */
+  async kill(): Promise<boolean> {
+    return invoke<boolean>('pty_kill', { sessionId: this.id });
+  }
+
+  /**
+   * Stop receiving output without ending the session
+   */
+  detach(): void {
+    this.unlisten.forEach(fn => fn());
+  }
+}
@@ -0,0 +1,127 @@
+/**
+ * Terminal screen
+ * A plain-text rendering of PTY output: enough of the VT100 control
+ * sequences a shell prompt and line editing use (carriage return,
+ * backspace, cursor left/right, erase line, clear screen); colours and
+ * everything else are dropped.
+ */
+
+/** Lines kept; older ones scroll away */
+const MAX_LINES = 5000;
+
+export interface Screen {
+  lines: string[];
+  /** Cursor column in the last line */
+  column: number;
+  /** An escape sequence cut off at the end of the last chunk */
+  pending: string;
+}
+
+export function emptyScreen(): Screen {
+  return { lines: [''], column: 0, pending: '' };
+}
+
+// CSI with its parameters and final byte, OSC up to BEL or ST, or another
+// two-byte escape
+const ESCAPE = /^\x1b(?:\[([0-9;?]*)[ -\/]*([@-~])|\][^\x07\x1b]*(?:\x07|\x1b\\)|[^\[\]])/;
+
+/**
+ * The screen after `data` is written to it
+ */
+export function writeScreen(screen: Screen, data: string): Screen {
+  const lines = screen.lines.slice();
+  let column = screen.column;
+  const text = screen.pending + data;
+  let pending = '';
+
+  const put = (char: string) => {
+    const line = lines[lines.length - 1].padEnd(column);
+    lines[lines.length - 1] = line.slice(0, column) + char + line.slice(column + 1);
+    column += 1;
+  };
+
+  let i = 0;
+  while (i < text.length) {
+    const char = text[i];
+    if (char === '\x1b') {
+      const match = ESCAPE.exec(text.slice(i));
+      if (!match) {
+        // Wait for the rest of the sequence
+        pending = text.slice(i);
+        break;
+      }
+      const [sequence, params = '', command] = match;
+      const count = Math.max(1, parseInt(params, 10) || 1);
+      const line = lines[lines.length - 1];
+      switch (command) {
+        case 'C':
+          column += count;
+          break;
+        case 'D':
+          column = Math.max(0, column - count);
+          break;
+        case 'K':
+          lines[lines.length - 1] = params === '2' ? '' : line.slice(0, column);
+          break;
+        case 'J':
+          if (params === '2' || params === '3') {
+            lines.splice(0, lines.length, '');
+            column = 0;
+          }
+          break;
+      }
+      i += sequence.length;
+      continue;
+    }
+    if (char === '\n') {
+      lines.push('');
+      column = 0;
+    } else if (char === '\r') {
+      column = 0;
+    } else if (char === '\b') {
+      column = Math.max(0, column - 1);
+    } else if (char === '\t') {
+      column += 8 - (column % 8);
+    } else if (char >= ' ' && char !== '\x7f') {
+      put(char);
+    }
+    i += 1;
+  }
+  return { lines: lines.slice(-MAX_LINES), column, pending };
+}
+
+/**
+ * What a key press sends to the program, or `null` to leave it to the page
+ */
+export function keyInput(event: {
+  key: string;
+  ctrlKey: boolean;
+  altKey: boolean;
+  metaKey: boolean;
+}): string | null {
+  if (event.metaKey) return null;
+  if (event.ctrlKey && event.key.length === 1) {
+    const code = event.key.toUpperCase().charCodeAt(0);
+    // Ctrl+A is 0x01 … Ctrl+Z is 0x1a, Ctrl+[ is Escape
+    if (code >= 0x40 && code <= 0x5f) return String.fromCharCode(code - 0x40);
+    return null;
+  }
+  const keys: Record<string, string> = {
+    Enter: '\r',
+    Backspace: '\x7f',
+    Tab: '\t',
+    Escape: '\x1b',
+    ArrowUp: '\x1b[A',
+    ArrowDown: '\x1b[B',
+    ArrowRight: '\x1b[C',
+    ArrowLeft: '\x1b[D',
+    Home: '\x1b[H',
+    End: '\x1b[F',
+    Delete: '\x1b[3~',
+    PageUp: '\x1b[5~',
+    PageDown: '\x1b[6~'
+  };
+  const input = keys[event.key] ?? (event.key.length === 1 ? event.key : null);
+  if (input === null) return null;
+  return event.altKey ? `\x1b${input}` : input;
+}
@@ -1,8 +1,11 @@
 /**
  * File operations using Tauri APIs
+ * Saving goes through backend commands that show the native dialog
+ * themselves; the webview has no write access to the file system.
  */
-import { writeTextFile, readTextFile } from '@tauri-apps/plugin-fs';
-import { save, open } from '@tauri-apps/plugin-dialog';
+import { invoke } from '@tauri-apps/api/core';
+import { readTextFile } from '@tauri-apps/plugin-fs';
+import { open } from '@tauri-apps/plugin-dialog';
 
 // Check if we're in Tauri environment
 function isTauri(): boolean {
@@ -18,8 +21,9 @@ export async function saveFileToDisk(content: string, defaultName: string = 'unt
   }
   
   try {
-    const filePath = await save({
-      defaultPath: defaultName,
+    return await invoke<string | null>('save_file_as', {
+      content,
+      defaultName,
       filters: [
         {
           name: 'Web Files',
@@ -31,15 +35,9 @@ export async function saveFileToDisk(content: string, defaultName: string = 'unt
         }
       ]
     });
-
-    if (filePath) {
-      await writeTextFile(filePath, content);
-      return filePath;
-    }
-    return null;
   } catch (error: any) {
     console.error('Error saving file:', error);
-    throw new Error(`Failed to save file: ${error.message}`);
+    throw new Error(`Failed to save file: ${error.message ?? error.kind ?? error}`);
   }
 }
 
@@ -90,44 +88,12 @@ export async function saveProjectFiles(files: ProjectFile[]): Promise<string[]>
     throw new Error('File operations are only available in Tauri environment');
   }
   
-  const savedPaths: string[] = [];
-  
   try {
-    // Ask user to select a directory by selecting the first file location
-    const firstFilePath = await save({
-      defaultPath: 'project/index.html',
-      filters: [
-        {
-          name: 'HTML Files',
-          extensions: ['html']
-        },
-        {
-          name: 'All Files',
-          extensions: ['*']
-        }
-      ]
-    });
-
-    if (!firstFilePath) {
-      return [];
-    }
-
-    // Extract directory path (works for both Windows and Unix)
-    const lastSlash = Math.max(firstFilePath.lastIndexOf('/'), firstFilePath.lastIndexOf('\\'));
-    const baseDir = lastSlash >= 0 ? firstFilePath.substring(0, lastSlash) : '';
-
-    for (const file of files) {
-      // Handle both forward and backward slashes
-      const separator = baseDir.includes('\\') ? '\\' : '/';
-      const fullPath = `${baseDir}${separator}${file.path}`;
-      await writeTextFile(fullPath, file.content);
-      savedPaths.push(fullPath);
-    }
-
-    return savedPaths;
+    // The backend asks for the folder and keeps every file inside it
+    return await invoke<string[]>('save_project_files', { files });
   } catch (error: any) {
     console.error('Error saving project files:', error);
-    throw new Error(`Failed to save project: ${error.message}`);
+    throw new Error(`Failed to save project: ${error.message ?? error.kind ?? error}`);
   }
 }
 
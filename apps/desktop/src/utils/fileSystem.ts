@@ -1,8 +1,8 @@
 /**
  * File system utilities using Tauri FS plugin
  */
-import { readDir, stat } from '@tauri-apps/plugin-fs';
-import { open as openDialog } from '@tauri-apps/plugin-dialog';
+import { stat } from '@tauri-apps/plugin-fs';
+import { invoke } from '@tauri-apps/api/core';
 
 // Check if we're in Tauri environment
 function isTauri(): boolean {
@@ -15,65 +15,103 @@ export interface FileSystemEntry {
   type: 'file' | 'directory';
   size?: number;
   modified?: number;
+  language?: string;
+}
+
+interface TreeNode {
+  name: string;
+  path: string;
+  kind: 'file' | 'directory' | 'symlink';
+  size: number;
+  mtimeMs: number | null;
+  language: string | null;
+  childCount: number | null;
+}
+
+interface TreePage {
+  path: string;
+  nodes: TreeNode[];
+  total: number;
+  nextOffset: number | null;
+}
+
+interface OpenedWorkspace {
+  path: string;
+  denyPatterns: string[];
+  /** Set when changes on disk won't be picked up, e.g. past the OS watch limit */
+  watchError: string | null;
+}
+
+/**
+ * Register a folder as a workspace root with the backend
+ * File commands, including the project tree, only work inside open roots.
+ * A folder that isn't open yet needs the user's OK in a native dialog.
+ */
+export async function openWorkspace(rootPath: string): Promise<void> {
+  if (!isTauri()) {
+    return;
+  }
+  const opened = await invoke<OpenedWorkspace>('open_workspace', { path: rootPath });
+  if (opened.watchError) {
+    console.warn(`Not watching ${opened.path} for changes: ${opened.watchError}`);
+  }
 }
 
 /**
  * Read directory contents
+ * Listed by the backend from a cached, gitignore-aware walk of the workspace;
+ * entries come back sorted with directories first.
  */
-export async function readDirectory(dirPath: string): Promise<FileSystemEntry[]> {
+export async function readDirectory(dirPath: string, refresh = false): Promise<FileSystemEntry[]> {
   if (!isTauri()) {
     return [];
   }
 
   try {
-    const entries = await readDir(dirPath);
     const result: FileSystemEntry[] = [];
-
-    for (const entry of entries) {
-      const fullPath = `${dirPath}/${entry.name}`.replace(/\/+/g, '/');
-      const entryStat = await stat(fullPath);
-      
-      result.push({
-        name: entry.name,
-        path: fullPath,
-        type: entryStat.isDirectory ? 'directory' : 'file',
-        size: entryStat.size,
-        modified: entryStat.mtime ? new Date(entryStat.mtime).getTime() : undefined
+    let offset: number | null = 0;
+    while (offset !== null) {
+      const page: TreePage = await invoke<TreePage>('list_project_tree', {
+        path: dirPath,
+        offset,
+        refresh: refresh && offset === 0
       });
-    }
-
-    // Sort: directories first, then files, both alphabetically
-    result.sort((a, b) => {
-      if (a.type !== b.type) {
-        return a.type === 'directory' ? -1 : 1;
+      for (const node of page.nodes) {
+        result.push({
+          name: node.name,
+          path: node.path,
+          type: node.kind === 'directory' ? 'directory' : 'file',
+          size: node.size,
+          modified: node.mtimeMs ?? undefined,
+          language: node.language ?? undefined
+        });
       }
-      return a.name.localeCompare(b.name);
-    });
-
+      offset = page.nextOffset;
+    }
     return result;
   } catch (error: any) {
     console.error('Error reading directory:', error);
-    throw new Error(`Failed to read directory: ${error.message}`);
+    throw new Error(`Failed to read directory: ${error.message ?? error.kind ?? error}`);
   }
 }
 
 /**
- * Open folder dialog and return selected path
+ * Let the user pick a folder in the native dialog and open it as a
+ * workspace root; returns its path, or null if they cancel
  */
-export async function selectFolder(): Promise<string | null> {
+export async function pickWorkspace(): Promise<string | null> {
   if (!isTauri()) {
     return null;
   }
 
   try {
-    const selected = await openDialog({
-      directory: true,
-      multiple: false
-    });
-
-    return selected && typeof selected === 'string' ? selected : null;
+    const opened = await invoke<OpenedWorkspace | null>('pick_workspace');
+    if (opened?.watchError) {
+      console.warn(`Not watching ${opened.path} for changes: ${opened.watchError}`);
+    }
+    return opened?.path ?? null;
   } catch (error: any) {
-    console.error('Error selecting folder:', error);
+    console.error('Error opening folder:', error);
     return null;
   }
 }
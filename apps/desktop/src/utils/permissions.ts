@@ -4,44 +4,169 @@
 import { invoke } from '@tauri-apps/api/core';
 
 /**
- * Request permission to write a file (shows confirmation dialog)
+ * Structured diff returned by the Rust `compute_diff` engine
+ */
+export interface DiffLine {
+  kind: 'context' | 'added' | 'removed';
+  oldLine: number | null;
+  newLine: number | null;
+  content: string;
+  segments?: { text: string; changed: boolean }[];
+}
+
+export interface DiffHunk {
+  oldStart: number;
+  oldLines: number;
+  newStart: number;
+  newLines: number;
+  lines: DiffLine[];
+}
+
+export interface FileDiff {
+  filePath: string;
+  hunks: DiffHunk[];
+  lineChanges: { added: number; removed: number; modified: number };
+  unifiedDiff: string;
+}
+
+/**
+ * Diff two versions of a file in the Rust backend
+ */
+export async function computeDiff(
+  oldContent: string,
+  newContent: string,
+  filePath?: string
+): Promise<FileDiff> {
+  return invoke<FileDiff>('compute_diff', { oldContent, newContent, filePath });
+}
+
+export interface PatchedFile {
+  path: string;
+  baseHash: string | null;
+  deleted: boolean;
+  content: string;
+  applied: { index: number; line: number; offset: number; fuzz: number; whitespaceInsensitive: boolean }[];
+  rejected: {
+    index: number;
+    reason: 'contextNotFound' | 'alreadyApplied' | 'ambiguous' | 'emptySearch';
+    text: string;
+  }[];
+}
+
+/**
+ * Apply a unified diff or SEARCH/REPLACE blocks in the Rust backend
+ * Nothing is written; each file's resulting content is returned for preview.
+ * `filePath` is used when the patch does not name its file.
+ */
+export async function applyPatch(patch: string, filePath?: string): Promise<PatchedFile[]> {
+  return invoke<PatchedFile[]>('apply_patch', { patch, filePath });
+}
+
+/**
+ * Single-use approval issued by the backend permission broker
+ */
+export interface WriteGrant {
+  token: string;
+  expiresInMs: number;
+}
+
+export interface WritePermission {
+  grant: WriteGrant | null;
+  baseHash: string | null;
+  diff: FileDiff;
+}
+
+/**
+ * Request permission to write a file
+ * The backend computes the preview from the file on disk and shows a native
+ * confirmation dialog; the returned grant is only set if the user approved.
  */
 export async function requestFileWritePermission(
   filePath: string,
-  diffPreview: string
-): Promise<boolean> {
-  // Show native confirmation dialog
-  const confirmed = confirm(
-    `Apply changes to ${filePath}?\n\nPreview:\n${diffPreview.substring(0, 500)}...`
-  );
-
-  if (!confirmed) {
-    return false;
-  }
-
-  // Call Tauri command (permission is enforced by capabilities)
+  newContent: string
+): Promise<WritePermission | null> {
   try {
-    const result = await invoke<boolean>('request_file_write_permission', {
+    return await invoke<WritePermission>('request_file_write_permission', {
       filePath,
-      diffPreview
+      newContent
     });
-    return result;
   } catch (error) {
     console.error('Permission request failed:', error);
-    return false;
+    return null;
   }
 }
 
 /**
- * Apply file edit through Tauri (respects permissions)
+ * Apply file edit through Tauri
+ * `permission` must be an approved result of `requestFileWritePermission`
+ * for this exact path and content. Resolves to the journal transaction id,
+ * which `undo_transaction` accepts; pass `transactionId` to group edits.
  */
-export async function applyFileEdit(_filePath: string, _content: string): Promise<void> {
-  // Note: In Tauri v2, file operations should use the fs plugin
-  // This is a placeholder - the actual implementation would use
-  // @tauri-apps/plugin-fs
-  
-  // For now, we'll use a different approach:
-  // The core agent will handle file writes, and Tauri's capabilities
-  // will control access
+export async function applyFileEdit(
+  filePath: string,
+  content: string,
+  permission: WritePermission,
+  transactionId?: string
+): Promise<string> {
+  if (!permission.grant) {
+    throw new Error(`Write to ${filePath} was not approved`);
+  }
+
+  const applied = await invoke<{ transactionId: string }>('apply_file_edit', {
+    filePath,
+    content,
+    grantToken: permission.grant.token,
+    expectedHash: permission.baseHash ?? undefined,
+    transactionId
+  });
+  return applied.transactionId;
 }
 
+export type EditOp =
+  | { op: 'create'; path: string; content: string }
+  | { op: 'edit'; path: string; content: string; expectedHash?: string }
+  | { op: 'delete'; path: string; expectedHash?: string }
+  | { op: 'rename'; from: string; to: string; expectedHash?: string };
+
+export interface BatchPermission {
+  grant: WriteGrant | null;
+  previews: FileDiff[];
+}
+
+export interface BatchResult {
+  applied: boolean;
+  transactionId: string | null;
+  results: { path: string; status: string; error?: { kind: string; message?: string } }[];
+}
+
+/**
+ * Ask the user to approve a list of file operations in one native dialog
+ */
+export async function requestBatchPermission(ops: EditOp[]): Promise<BatchPermission> {
+  return invoke<BatchPermission>('request_batch_permission', { ops });
+}
+
+/**
+ * Apply an approved list of file operations all-or-nothing
+ */
+export async function applyEditBatch(
+  ops: EditOp[],
+  permission: BatchPermission,
+  transactionId?: string
+): Promise<BatchResult> {
+  if (!permission.grant) {
+    throw new Error(`Changes to ${ops.length} files were not approved`);
+  }
+  return invoke<BatchResult>('apply_edit_batch', {
+    ops,
+    grantToken: permission.grant.token,
+    transactionId
+  });
+}
+
+/**
+ * Read a text file inside an open workspace
+ */
+export async function readWorkspaceFile(filePath: string): Promise<string> {
+  return invoke<string>('read_text_file', { filePath });
+}
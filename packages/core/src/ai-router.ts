@@ -1,10 +1,12 @@
 import type { AIRequest, AIResponse } from './types'
-import { generateStream, isOllamaRunning, streamOpenAI } from '@henry-ai/local-ai'
+import { generateStream, isNative, isOllamaRunning, streamOpenAI, streamRouted } from '@henry-ai/local-ai'
 import type { OllamaRequest } from '@henry-ai/local-ai'
 
 /**
  * AI Router - intelligently routes requests to local or cloud AI
  * Auto-fallback strategy: local → OpenAI → Claude
+ * In the desktop app the backend routes by the `.henryrc` policy, with
+ * retries, timeouts and circuit breaking.
  * 
  * Streaming version with automatic fallback
  */
@@ -12,6 +14,9 @@ export class AIRouter {
   private openAIApiKey?: string
   private claudeApiKey?: string
 
+  /** The provider that served the last request, if known */
+  lastProvider?: string
+
   constructor(options?: {
     openAIApiKey?: string
     claudeApiKey?: string
@@ -29,6 +34,18 @@ export class AIRouter {
     temperature?: number
     maxTokens?: number
   }): AsyncGenerator<string, void, unknown> {
+    if (isNative()) {
+      const completion = yield* streamRouted({
+        messages: [{ role: 'user', content: prompt }],
+        options: {
+          temperature: options?.temperature || 0.7,
+          maxTokens: options?.maxTokens || 2000
+        }
+      })
+      this.lastProvider = completion.provider
+      return
+    }
+
     // Try Ollama first (local)
     if (await isOllamaRunning()) {
       const ollamaModel = options?.model || process.env.OLLAMA_MODEL || 'phi3:mini'
@@ -47,6 +64,7 @@ export class AIRouter {
         }
         
         yield* generateStream(ollamaRequest)
+        this.lastProvider = 'ollama'
         return // Success, exit early
       } catch (error: any) {
         console.warn(`⚠️  Ollama failed: ${error.message}, falling back to cloud AI`)
@@ -65,6 +83,7 @@ export class AIRouter {
           temperature: options?.temperature || 0.7,
           maxTokens: options?.maxTokens || 2000
         })
+        this.lastProvider = 'openai'
         return // Success, exit early
       } catch (error: any) {
         console.warn(`⚠️  OpenAI failed: ${error.message}`)
@@ -125,7 +144,7 @@ export class AIRouter {
       
       return {
         content,
-        model: 'auto',
+        model: this.lastProvider || 'auto',
         latency
       }
     } catch (error: any) {
@@ -10,3 +10,4 @@ export * from './agent-executor';
 export * from './commands';
 export * from './ai-router';
 export * from './ai-stream';
+export * from './tools';
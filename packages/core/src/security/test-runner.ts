@@ -5,6 +5,8 @@ import * as fs from 'fs/promises'
 import { exec } from 'child_process'
 import { promisify } from 'util'
 import * as path from 'path'
+import { isNative, runNativeTests } from '@henry-ai/local-ai'
+import type { NativeTestCase } from '@henry-ai/local-ai'
 import { Sandbox } from './sandbox'
 
 const execAsync = promisify(exec)
@@ -14,6 +16,8 @@ export interface TestResult {
   output: string
   error?: string
   exitCode: number
+  /** Per-test results, when the tests ran natively */
+  tests?: NativeTestCase[]
 }
 
 export interface TestConfig {
@@ -25,9 +29,11 @@ export interface TestConfig {
 export class TestRunner {
   private sandbox: Sandbox
   private defaultTestCommand: string
+  private configuredCommand?: string
 
   constructor(sandbox: Sandbox, config: TestConfig = {}) {
     this.sandbox = sandbox
+    this.configuredCommand = config.testCommand
     this.defaultTestCommand = config.testCommand || this.detectTestCommand()
   }
 
@@ -52,8 +58,15 @@ export class TestRunner {
 
   /**
    * Run tests for the project
+   * In the desktop app the framework is detected from the project manifests
+   * and, given `changedFiles`, only the affected tests run.
    */
-  async runTests(cwd: string = process.cwd(), timeout: number = 60000): Promise<TestResult> {
+  async runTests(cwd?: string, timeout: number = 60000, changedFiles: string[] = []): Promise<TestResult> {
+    if (isNative() && !this.configuredCommand) {
+      return this.runNativeTests(cwd, timeout, changedFiles)
+    }
+
+    cwd = cwd ?? process.cwd()
     const testCommand = process.env.TEST_COMMAND || this.defaultTestCommand
     
     try {
@@ -86,6 +99,32 @@ export class TestRunner {
     }
   }
 
+  /**
+   * Run tests through the desktop app's native test runner
+   */
+  private async runNativeTests(cwd: string | undefined, timeout: number, changedFiles: string[]): Promise<TestResult> {
+    try {
+      const runs = await runNativeTests({ root: cwd, changed: changedFiles, timeoutMs: timeout })
+      const failed = runs.find(run => !run.success)
+      const output = runs.map(run => `$ ${run.plan.argv.join(' ')}\n${run.output}`).join('\n')
+
+      return {
+        success: !failed,
+        output,
+        error: failed && (failed.timedOut ? `Tests timed out after ${failed.durationMs}ms` : failed.reportError ?? undefined),
+        exitCode: failed ? failed.exitCode ?? 1 : 0,
+        tests: runs.flatMap(run => run.tests)
+      }
+    } catch (error: any) {
+      return {
+        success: false,
+        output: '',
+        error: error?.message || JSON.stringify(error),
+        exitCode: 1
+      }
+    }
+  }
+
   /**
    * Apply edit and run tests, rollback on failure
    */
@@ -97,7 +136,7 @@ export class TestRunner {
     await this.sandbox.applyEdit(filePath, true) // Always create backup
 
     // Run tests
-    const testResult = await this.runTests()
+    const testResult = await this.runTests(undefined, undefined, [filePath])
 
     if (!testResult.success && autoRollback) {
       // Rollback on test failure
@@ -55,10 +55,23 @@ export class ToolRegistry {
   }
 }
 
+/**
+ * Host capabilities the standard tools call into
+ * The core package has no filesystem or process access of its own; the
+ * desktop app supplies these.
+ */
+export interface ToolServices {
+  /** Project-wide text search, e.g. the desktop app's `findText` */
+  searchText?: (
+    query: string,
+    options: { regex?: boolean; maxResults?: number }
+  ) => Promise<Array<{ path: string; line: number; text: string }>>
+}
+
 /**
  * Standard code editing tools
  */
-export const createStandardTools = (): Tool[] => {
+export const createStandardTools = (services: ToolServices = {}): Tool[] => {
   return [
     {
       name: 'read_file',
@@ -91,16 +104,38 @@ export const createStandardTools = (): Tool[] => {
     },
     {
       name: 'search_code',
-      description: 'Search codebase using semantic search',
+      description: 'Search file contents across the codebase for text or a regular expression',
       parameters: [
-        { name: 'query', type: 'string', description: 'Search query', required: true },
+        { name: 'query', type: 'string', description: 'Text or pattern to search for', required: true },
+        { name: 'regex', type: 'boolean', description: 'Treat the query as a regular expression', required: false },
         { name: 'limit', type: 'number', description: 'Number of results', required: false }
       ],
       execute: async (args, _context) => {
-        // TODO: Implement semantic search using vector database
-        return {
-          success: true,
-          output: `Search results for: ${args.query}`
+        if (!services.searchText) {
+          return {
+            success: false,
+            output: '',
+            error: 'Code search is not available in this environment'
+          }
+        }
+        try {
+          const matches = await services.searchText(args.query, {
+            regex: args.regex === true,
+            maxResults: args.limit ?? 50
+          })
+          return {
+            success: true,
+            output: matches.length > 0
+              ? matches.map(m => `${m.path}:${m.line}: ${m.text}`).join('\n')
+              : `No matches for: ${args.query}`,
+            metadata: { count: matches.length }
+          }
+        } catch (error) {
+          return {
+            success: false,
+            output: '',
+            error: error instanceof Error ? error.message : String(error)
+          }
         }
       }
     },
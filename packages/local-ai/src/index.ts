@@ -1,9 +1,10 @@
+export * from './native'
 export * from './ollama'
 export * from './ollama-check'
 export * from './openai'
 export * from './types'
 
 // Re-export types for convenience
-export type { OllamaRequest } from './ollama'
+export type { OllamaRequest, OllamaChatMessage } from './ollama'
 export type { OpenAIRequest, OpenAIStreamOptions } from './openai'
 
@@ -0,0 +1,418 @@
+/**
+ * Bridge to the LLM clients of the desktop backend
+ * Inside the desktop app, generations run in Rust and stream their tokens back
+ * through a Tauri channel, so they need no HTTP access from the webview.
+ */
+
+import { Channel, invoke, isTauri } from '@tauri-apps/api/core';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
+
+export interface TokenUsage {
+  promptTokens: number;
+  completionTokens: number;
+}
+
+export interface NativeToolCall {
+  id: string | null;
+  name: string;
+  /** JSON object, as the model wrote it */
+  arguments: string;
+}
+
+export type NativeStreamEvent =
+  | { event: 'token'; text: string }
+  | { event: 'toolCall'; index: number; id: string | null; name: string | null; arguments: string }
+  | { event: 'done'; usage: TokenUsage };
+
+export interface NativeCompletion {
+  requestId: string;
+  model: string;
+  text: string;
+  toolCalls: NativeToolCall[];
+  usage: TokenUsage;
+  finishReason: string | null;
+  cancelled: boolean;
+}
+
+/** Sampling settings understood by every native client */
+export interface NativeGenerateOptions {
+  temperature?: number;
+  topP?: number;
+  maxTokens?: number;
+  stop?: string[];
+}
+
+/** Which provider serves a native chat and where to reach it */
+export type NativeProvider =
+  | { kind: 'ollama'; url?: string }
+  | { kind: 'openai'; baseUrl?: string; apiKey?: string }
+  | { kind: 'anthropic'; baseUrl?: string; apiKey?: string };
+
+export interface NativeChatMessage {
+  role: 'system' | 'user' | 'assistant' | 'tool';
+  content: string;
+  toolCalls?: NativeToolCall[];
+  toolCallId?: string;
+}
+
+export interface NativeToolSpec {
+  name: string;
+  description?: string;
+  /** JSON schema of the arguments */
+  parameters: Record<string, unknown>;
+}
+
+export interface NativeChatRequest {
+  model: string;
+  messages: NativeChatMessage[];
+  tools?: NativeToolSpec[];
+  options?: NativeGenerateOptions;
+}
+
+export interface NativeUsageTotals {
+  provider: string;
+  model: string;
+  requests: number;
+  promptTokens: number;
+  completionTokens: number;
+}
+
+export interface RouteAttempt {
+  choice: 'local' | 'openai' | 'claude';
+  provider: string | null;
+  model: string;
+  outcome: 'served' | 'failed' | 'skipped';
+  error?: { kind: string; [key: string]: unknown };
+  elapsedMs: number;
+}
+
+export interface RoutedCompletion extends NativeCompletion {
+  /** The provider that served it */
+  provider: string;
+  choice: RouteAttempt['choice'];
+  attempts: RouteAttempt[];
+}
+
+export interface NativeTestCase {
+  name: string;
+  /** File, module or package the test belongs to */
+  suite: string | null;
+  status: 'passed' | 'failed' | 'skipped';
+  durationMs: number | null;
+  message: string | null;
+  /** Where the failure happened, as near as the report says */
+  location: { file: string; line: number; column: number | null } | null;
+}
+
+export interface NativeTestPlan {
+  framework: 'cargo' | 'vitest' | 'jest' | 'pytest' | 'go' | 'script';
+  packageManager: 'npm' | 'pnpm' | 'yarn' | null;
+  project: string;
+  /** Files or packages the run is limited to; empty runs everything */
+  scope: string[];
+  report: 'libtest' | 'junit' | 'jestJson' | 'goJson' | 'tap';
+  argv: string[];
+}
+
+export interface NativeTestRun {
+  plan: NativeTestPlan;
+  /** The command exited cleanly and no test failed */
+  success: boolean;
+  summary: { passed: number; failed: number; skipped: number };
+  tests: NativeTestCase[];
+  /** Why per-test results are missing, if they are */
+  reportError: string | null;
+  exitCode: number | null;
+  timedOut: boolean;
+  durationMs: number;
+  /** The end of stderr and stdout */
+  output: string;
+}
+
+export interface NativeTestOptions {
+  /** Project root; the first open root if unset */
+  root?: string;
+  /** Limit the run to the projects, and where possible the tests, these files affect */
+  changed?: string[];
+  timeoutMs?: number;
+}
+
+export type NativeEditOp =
+  | { op: 'create'; path: string; content: string }
+  | { op: 'edit'; path: string; content: string; expectedHash?: string }
+  | { op: 'delete'; path: string; expectedHash?: string }
+  | { op: 'rename'; from: string; to: string; expectedHash?: string };
+
+export type NativePlanOperation =
+  /** Applied all-or-nothing and checkpointed in the edit journal */
+  | { type: 'files'; ops: NativeEditOp[] }
+  /** Run through the command policy; must exit 0 */
+  | { type: 'command'; argv: string[]; cwd?: string; timeoutMs?: number }
+  /** Tests affected by `paths`, or by every file the plan changed so far */
+  | { type: 'test'; paths?: string[] };
+
+export type NativePlanValidation =
+  | { type: 'tests' }
+  | { type: 'command'; argv: string[] };
+
+export interface NativePlanStep {
+  id: string;
+  description: string;
+  operation: NativePlanOperation;
+  /** Pause before this step until `approveNativePlanStep` */
+  requiresApproval?: boolean;
+  /** Checks after the operation; a failure rolls the step back */
+  validate?: NativePlanValidation[];
+}
+
+export interface NativePlan {
+  /** ASCII letters, digits and `-` */
+  id: string;
+  goal: string;
+  /** Root commands and tests run in; the first open root if unset */
+  root?: string;
+  steps: NativePlanStep[];
+}
+
+export type NativePlanStatus =
+  | 'running'
+  | 'awaitingApproval'
+  | 'paused'
+  | 'completed'
+  | 'failed'
+  | 'cancelled'
+  | 'rolledBack';
+
+export interface NativeStepState {
+  id: string;
+  status: 'pending' | 'awaitingApproval' | 'running' | 'completed' | 'failed' | 'rolledBack';
+  approved: boolean;
+  /** Journal transaction holding the step's file changes */
+  checkpoint: string | null;
+  output: string | null;
+  error: string | null;
+  startedAtMs: number | null;
+  finishedAtMs: number | null;
+}
+
+export interface NativePlanState {
+  plan: NativePlan;
+  status: NativePlanStatus;
+  root: string;
+  steps: NativeStepState[];
+  createdAtMs: number;
+  updatedAtMs: number;
+}
+
+export interface NativePlanProgress {
+  planId: string;
+  status: NativePlanStatus;
+  /** The step that changed, if one did */
+  stepIndex: number | null;
+  step: NativeStepState | null;
+  /** Why the plan stopped, when no step says so */
+  error: string | null;
+}
+
+export interface NativePlanList {
+  /** Newest first */
+  plans: NativePlanState[];
+  /** Saved plan files that could not be read */
+  unreadable: { path: string; error: string }[];
+}
+
+export interface CircuitStatus {
+  provider: string;
+  failures: number;
+  /** How long the provider is still skipped; null if it is not */
+  retryInMs: number | null;
+}
+
+/**
+ * Whether the native clients are available, i.e. this runs in the desktop app
+ */
+export function isNative(): boolean {
+  return isTauri();
+}
+
+/**
+ * Stop a running native generation; false if it already finished
+ */
+export async function cancelGeneration(requestId: string): Promise<boolean> {
+  return invoke<boolean>('cancel_generation', { requestId });
+}
+
+/**
+ * Tokens used per provider and model since the app started
+ */
+export async function nativeUsage(): Promise<NativeUsageTotals[]> {
+  return invoke<NativeUsageTotals[]>('llm_usage');
+}
+
+/**
+ * Stream the next message of a chat from any native provider
+ * Tool calls are collected in the completion the generator returns.
+ */
+export function streamChat(
+  provider: NativeProvider,
+  request: NativeChatRequest,
+  signal?: AbortSignal
+): AsyncGenerator<string, NativeCompletion, unknown> {
+  return streamNative('llm_chat', { provider, request }, signal);
+}
+
+/**
+ * Stream the next message of a chat from the providers set in `.henryrc`
+ * `ai.preferredModel` and `ai.fallbackModel`, with retries and fallback
+ * The completion the generator returns names the provider that served it.
+ */
+export function streamRouted(
+  request: Omit<NativeChatRequest, 'model'>,
+  signal?: AbortSignal
+): AsyncGenerator<string, RoutedCompletion, unknown> {
+  return streamNative('route_chat', { request }, signal) as AsyncGenerator<string, RoutedCompletion, unknown>;
+}
+
+/**
+ * Providers that failed lately and whether the router skips them
+ */
+export async function routerStatus(): Promise<CircuitStatus[]> {
+  return invoke<CircuitStatus[]>('router_status');
+}
+
+/**
+ * The test runs `runNativeTests` would start, detected from the project manifests
+ */
+export async function detectNativeTests(options: Omit<NativeTestOptions, 'timeoutMs'> = {}): Promise<NativeTestPlan[]> {
+  return invoke<NativeTestPlan[]>('detect_tests', { ...options });
+}
+
+/**
+ * Run the project's tests natively and get per-test results
+ */
+export async function runNativeTests(options: NativeTestOptions = {}): Promise<NativeTestRun[]> {
+  return invoke<NativeTestRun[]>('run_tests', { ...options });
+}
+
+/**
+ * Start running a plan in the backend, one checkpointed step at a time
+ * Progress arrives through `onPlanProgress`.
+ */
+export async function startNativePlan(plan: NativePlan): Promise<NativePlanState> {
+  return invoke<NativePlanState>('start_plan', { plan });
+}
+
+/**
+ * Approve or decline the step a plan waits on; declining cancels the plan
+ */
+export async function approveNativePlanStep(planId: string, stepId: string, approved: boolean): Promise<NativePlanState> {
+  return invoke<NativePlanState>('approve_plan_step', { planId, stepId, approved });
+}
+
+/**
+ * Continue a plan paused by an app restart, or retry the step it failed at
+ */
+export async function resumeNativePlan(planId: string): Promise<NativePlanState> {
+  return invoke<NativePlanState>('resume_plan', { planId });
+}
+
+/**
+ * Stop a plan; a running plan stops once its current step ends
+ */
+export async function cancelNativePlan(planId: string): Promise<NativePlanState> {
+  return invoke<NativePlanState>('cancel_plan', { planId });
+}
+
+/**
+ * Undo the file changes of every step of a plan; commands are not undone
+ */
+export async function rollbackNativePlan(planId: string): Promise<NativePlanState> {
+  return invoke<NativePlanState>('rollback_plan', { planId });
+}
+
+/**
+ * Saved plans, including ones from before the app restarted
+ */
+export async function listNativePlans(): Promise<NativePlanList> {
+  return invoke<NativePlanList>('list_plans');
+}
+
+export async function getNativePlan(planId: string): Promise<NativePlanState> {
+  return invoke<NativePlanState>('get_plan', { planId });
+}
+
+/**
+ * Call `listener` on every plan and step transition
+ */
+export async function onPlanProgress(listener: (progress: NativePlanProgress) => void): Promise<UnlistenFn> {
+  return listen<NativePlanProgress>('plan://progress', event => listener(event.payload));
+}
+
+/**
+ * Run a streaming command and yield its tokens as they arrive
+ * Aborting `signal`, or leaving the loop early, cancels the generation.
+ */
+export async function* streamNative(
+  command: string,
+  args: Record<string, unknown>,
+  signal?: AbortSignal
+): AsyncGenerator<string, NativeCompletion, unknown> {
+  const requestId = crypto.randomUUID();
+  const queue: string[] = [];
+  let wake: (() => void) | null = null;
+  let finished = false;
+  let completion: NativeCompletion | undefined;
+  let failure: unknown;
+
+  const onEvent = new Channel<NativeStreamEvent>();
+  onEvent.onmessage = message => {
+    if (message.event === 'token') {
+      queue.push(message.text);
+      wake?.();
+    }
+  };
+  invoke<NativeCompletion>(command, { ...args, requestId, onEvent })
+    .then(result => {
+      completion = result;
+    }, error => {
+      failure = error;
+    })
+    .finally(() => {
+      finished = true;
+      wake?.();
+    });
+
+  const cancel = () => {
+    void cancelGeneration(requestId);
+  };
+  signal?.addEventListener('abort', cancel, { once: true });
+
+  let streamed = 0;
+  try {
+    while (true) {
+      const text = queue.shift();
+      if (text !== undefined) {
+        streamed += text.length;
+        yield text;
+      } else if (finished) {
+        break;
+      } else {
+        await new Promise<void>(resolve => {
+          wake = resolve;
+        });
+        wake = null;
+      }
+    }
+  } finally {
+    signal?.removeEventListener('abort', cancel);
+    if (!finished) cancel();
+  }
+
+  if (failure !== undefined) {
+    throw new Error(`Generation failed: ${JSON.stringify(failure)}`);
+  }
+  // The result can overtake the last channel messages
+  const rest = completion!.text.slice(streamed);
+  if (rest) yield rest;
+  return completion!;
+}
@@ -3,13 +3,33 @@
  */
 
 import axios from 'axios';
+import { invoke } from '@tauri-apps/api/core';
+import { isNative } from './native';
 
-const OLLAMA_URL = process.env.OLLAMA_URL || 'http://localhost:11434';
+const OLLAMA_HOST = (typeof process !== 'undefined' && process.env.OLLAMA_URL) || undefined;
+const OLLAMA_URL = OLLAMA_HOST || 'http://localhost:11434';
+
+export interface OllamaHealth {
+  url: string;
+  running: boolean;
+  version: string | null;
+  models: string[];
+}
+
+/**
+ * Ask the desktop backend whether Ollama answers and which models it has
+ */
+export async function ollamaHealth(): Promise<OllamaHealth> {
+  return invoke<OllamaHealth>('ollama_health', { url: OLLAMA_HOST });
+}
 
 /**
  * Check if Ollama server is running and accessible
  */
 export async function isOllamaRunning(): Promise<boolean> {
+  if (isNative()) {
+    return (await ollamaHealth()).running;
+  }
   try {
     const response = await axios.get(`${OLLAMA_URL}/api/tags`, {
       timeout: 2000 // 2 second timeout
@@ -24,6 +44,9 @@ export async function isOllamaRunning(): Promise<boolean> {
  * Get list of available Ollama models
  */
 export async function getAvailableModels(): Promise<string[]> {
+  if (isNative()) {
+    return (await ollamaHealth()).models;
+  }
   try {
     const response = await axios.get(`${OLLAMA_URL}/api/tags`);
     const data = response.data as { models?: Array<{ name: string }> };
@@ -32,4 +55,3 @@ export async function getAvailableModels(): Promise<string[]> {
     return [];
   }
 }
-
@@ -1,28 +1,56 @@
 import axios from 'axios';
+import { isNative, streamNative, type NativeGenerateOptions } from './native';
 
-<<<<<<< HEAD
-const OLLAMA_URL = 'http://localhost:11434/api/generate';
-=======
-const OLLAMA_URL = process.env.OLLAMA_URL || 'http://localhost:11434/api/generate';
->>>>>>> 95459d513bf131b98dcf1635953ff16ab4512523
+const OLLAMA_HOST = (typeof process !== 'undefined' && process.env.OLLAMA_URL) || undefined;
+const OLLAMA_URL = `${OLLAMA_HOST || 'http://localhost:11434'}/api/generate`;
 
 export interface OllamaRequest {
   model: string;
   prompt: string;
+  system?: string;
   stream?: boolean;
   options?: Record<string, any>;
 }
 
-export async function* generateStream(request: OllamaRequest) {
-<<<<<<< HEAD
-  const response = await axios.post(OLLAMA_URL, request, {
-=======
+export interface OllamaChatMessage {
+  role: 'system' | 'user' | 'assistant' | 'tool';
+  content: string;
+}
+
+/** Ollama's option names to those of the native client */
+function nativeOptions(options: Record<string, any> = {}): NativeGenerateOptions {
+  return {
+    temperature: options.temperature,
+    topP: options.top_p,
+    maxTokens: options.num_predict,
+    stop: options.stop
+  };
+}
+
+/**
+ * Stream the completion of a prompt token by token
+ * In the desktop app the request runs in the backend; aborting `signal` cancels it.
+ */
+export async function* generateStream(request: OllamaRequest, signal?: AbortSignal) {
+  if (isNative()) {
+    yield* streamNative('ollama_generate', {
+      request: {
+        model: request.model,
+        prompt: request.prompt,
+        system: request.system,
+        options: nativeOptions(request.options)
+      },
+      url: OLLAMA_HOST
+    }, signal);
+    return;
+  }
+
   const response = await axios.post(OLLAMA_URL, {
     ...request,
     stream: true
   }, {
->>>>>>> 95459d513bf131b98dcf1635953ff16ab4512523
-    responseType: 'stream'
+    responseType: 'stream',
+    signal
   });
 
   for await (const chunk of response.data) {
@@ -41,4 +69,19 @@ export async function* generateStream(request: OllamaRequest) {
   }
 }
 
+/**
+ * Stream the next message of a chat token by token; desktop app only
+ */
+export async function* chatStream(
+  model: string,
+  messages: OllamaChatMessage[],
+  options?: Record<string, any>,
+  signal?: AbortSignal
+) {
+  yield* streamNative('ollama_chat', {
+    request: { model, messages, options: nativeOptions(options) },
+    url: OLLAMA_HOST
+  }, signal);
+}
+
 // Usage: for await (const token of generateStream({model: 'codellama', prompt: '...'})) { ... }
@@ -2,6 +2,8 @@
  * OpenAI Streaming API
  */
 
+import { isNative, streamChat } from './native';
+
 export interface OpenAIRequest {
   model?: string;
   prompt: string;
@@ -19,6 +21,7 @@ export interface OpenAIStreamOptions {
 
 /**
  * Stream responses from OpenAI API
+ * In the desktop app the request runs in the backend.
  */
 export async function* streamOpenAI(
   prompt: string,
@@ -26,6 +29,15 @@ export async function* streamOpenAI(
 ): AsyncGenerator<string, void, unknown> {
   const { apiKey, model = 'gpt-4', temperature = 0.7, maxTokens = 2000 } = options;
 
+  if (isNative()) {
+    yield* streamChat({ kind: 'openai', apiKey }, {
+      model,
+      messages: [{ role: 'user', content: prompt }],
+      options: { temperature, maxTokens }
+    });
+    return;
+  }
+
   try {
     const response = await fetch('https://api.openai.com/v1/chat/completions', {
       method: 'POST',
@@ -1,5 +1,11 @@
 import { z } from 'zod'
 
+const ProviderSettingsSchema = z.object({
+  model: z.string().optional(),
+  url: z.string().optional(),
+  timeoutSecs: z.number().optional()
+})
+
 /**
  * Configuration schema for .henryrc files
  */
@@ -11,14 +17,31 @@ export const HenryConfigSchema = z.object({
   }).optional(),
   ai: z.object({
     preferredModel: z.enum(['local', 'openai', 'claude']).default('local'),
-    fallbackModel: z.enum(['openai', 'claude']).optional()
+    fallbackModel: z.enum(['openai', 'claude']).optional(),
+    providers: z.object({
+      local: ProviderSettingsSchema.optional(),
+      openai: ProviderSettingsSchema.optional(),
+      claude: ProviderSettingsSchema.optional()
+    }).optional()
   }).optional(),
   code: z.object({
     style: z.string().optional(),
     namingConvention: z.enum(['camelCase', 'PascalCase', 'snake_case', 'kebab-case']).default('camelCase'),
     indentSize: z.number().default(2)
   }).optional(),
-  commands: z.record(z.string(), z.string()).optional()
+  commands: z.record(z.string(), z.string()).optional(),
+  security: z.object({
+    denyPaths: z.array(z.string()).default([]),
+    commands: z.object({
+      allow: z.array(z.string()).default([]),
+      deny: z.array(z.string()).default([])
+    }).optional()
+  }).optional(),
+  embeddings: z.object({
+    provider: z.enum(['hashing', 'ollama']).default('hashing'),
+    model: z.string().optional(),
+    url: z.string().optional()
+  }).optional()
 })
 
 export type HenryConfig = z.infer<typeof HenryConfigSchema>
@@ -1,201 +1,138 @@
-// Note: LanceDB Node.js client - adjust import based on actual package
-// import { connect } from 'lancedb' or similar
-// Placeholder for now - will use actual LanceDB client when available
-const connect = async (_path: string) => {
-  // Placeholder implementation
-  return {
-    createTable: async () => ({}),
-    openTable: async () => ({
-      add: async () => {},
-      search: () => ({
-        limit: () => ({
-          execute: async () => []
-        })
-      }),
-      delete: async () => {}
-    }),
-    dropTable: async () => {}
-  }
-}
+import { invoke } from '@tauri-apps/api/core'
 import type { CodeEmbedding, SearchResult, EmbeddingOptions } from './types'
 import { CodeEmbedder } from './embedder'
 
+/** A stored chunk as returned by the native vector store */
+interface VectorHit {
+  root: string
+  path: string
+  symbol: string | null
+  kind: string | null
+  language: string | null
+  startLine: number
+  endLine: number
+  text: string
+  score: number
+}
+
+export interface VectorFilter {
+  language?: string
+  /** Glob over paths relative to the workspace root */
+  path?: string
+}
+
 /**
- * LanceDB wrapper for code embeddings storage and semantic search
+ * Code embeddings storage and semantic search
+ * Backed by the native vector store of the desktop app, which keeps one
+ * collection per workspace root under the app data directory.
+ * Embeddings are keyed by file: adding embeddings for a file replaces what
+ * was stored for it before.
  */
 export class VectorDatabase {
-  private db: any = null
-  private table: any = null
   private embedder: CodeEmbedder
-  private dbPath: string
+  private root?: string
 
-  constructor(dbPath: string = '.henry/vectordb') {
-    this.dbPath = dbPath
-    this.embedder = new CodeEmbedder()
+  /** `root` limits the database to one open workspace root */
+  constructor(root?: string) {
+    this.root = root
+    this.embedder = new CodeEmbedder(root)
   }
 
   async initialize(options?: EmbeddingOptions): Promise<void> {
     await this.embedder.initialize(options)
-
-    try {
-      this.db = await connect(this.dbPath)
-      
-      // Create or open code_embeddings table
-      const schema = {
-        id: 'string',
-        filePath: 'string',
-        code: 'string',
-        embedding: this.embedder.getDimension(),
-        language: 'string',
-        symbol: 'string?',
-        line: 'int32',
-        column: 'int32?',
-        type: 'string?'
-      }
-
-      try {
-        this.table = await this.db.openTable('code_embeddings')
-      } catch {
-        // Table doesn't exist, create it
-        this.table = await this.db.createTable('code_embeddings', schema)
-      }
-    } catch (error) {
-      throw new Error(`Failed to initialize vector database: ${error}`)
-    }
   }
 
   async addEmbedding(embedding: CodeEmbedding): Promise<void> {
-    if (!this.table) {
-      await this.initialize()
-    }
-
-    if (!embedding.embedding || embedding.embedding.length === 0) {
-      // Generate embedding if not provided
-      embedding.embedding = await this.embedder.embed(embedding.code)
-    }
-
-    try {
-      await this.table.add([{
-        id: embedding.id,
-        filePath: embedding.filePath,
-        code: embedding.code,
-        embedding: embedding.embedding,
-        language: embedding.metadata.language,
-        symbol: embedding.metadata.symbol || null,
-        line: embedding.metadata.line,
-        column: embedding.metadata.column || null,
-        type: embedding.metadata.type || null
-      }])
-    } catch (error) {
-      throw new Error(`Failed to add embedding: ${error}`)
-    }
+    await this.addEmbeddings([embedding])
   }
 
+  /** Precomputed vectors must come from the same model as the embedder's */
   async addEmbeddings(embeddings: CodeEmbedding[]): Promise<void> {
-    if (!this.table) {
-      await this.initialize()
+    await this.embedder.initialize()
+    // Only embed what the caller didn't
+    const missing = embeddings.filter(e => !e.embedding || e.embedding.length === 0)
+    const vectors = await this.embedder.embedBatch(missing.map(e => e.code))
+    missing.forEach((embedding, index) => {
+      embedding.embedding = vectors[index]
+    })
+
+    const byFile = new Map<string, CodeEmbedding[]>()
+    for (const embedding of embeddings) {
+      const rows = byFile.get(embedding.filePath) ?? []
+      rows.push(embedding)
+      byFile.set(embedding.filePath, rows)
     }
 
-    // Generate embeddings in batch
-    const codes = embeddings.map(e => e.code)
-    const embeddingVectors = await this.embedder.embedBatch(codes)
-
-    const rows = embeddings.map((embedding, index) => ({
-      id: embedding.id,
-      filePath: embedding.filePath,
-      code: embedding.code,
-      embedding: embedding.embedding.length > 0 
-        ? embedding.embedding 
-        : embeddingVectors[index],
-      language: embedding.metadata.language,
-      symbol: embedding.metadata.symbol || null,
-      line: embedding.metadata.line,
-      column: embedding.metadata.column || null,
-      type: embedding.metadata.type || null
-    }))
-
     try {
-      await this.table.add(rows)
+      for (const [path, rows] of byFile) {
+        await invoke<number>('vector_upsert', {
+          path,
+          model: this.embedder.getModel(),
+          chunks: rows.map(row => ({
+            symbol: row.metadata.symbol ?? null,
+            kind: row.metadata.type ?? null,
+            language: row.metadata.language || null,
+            startLine: row.metadata.line,
+            text: row.code,
+            vector: row.embedding
+          }))
+        })
+      }
     } catch (error) {
-      throw new Error(`Failed to add embeddings: ${error}`)
+      throw new Error(`Failed to add embeddings: ${JSON.stringify(error)}`)
     }
   }
 
   async search(
-    query: string, 
-    options?: { limit?: number; threshold?: number }
+    query: string,
+    options?: { limit?: number; threshold?: number; filter?: VectorFilter }
   ): Promise<SearchResult[]> {
-    if (!this.table) {
-      await this.initialize()
-    }
-
     const limit = options?.limit || 10
-    const threshold = options?.threshold || 0.5
+    const threshold = options?.threshold ?? 0.5
 
-    // Generate query embedding
     const queryEmbedding = await this.embedder.embed(query)
 
     try {
-      // Perform vector similarity search
-      const results = await this.table
-        .search(queryEmbedding)
-        .limit(limit)
-        .execute()
+      const hits = await invoke<VectorHit[]>('vector_search', {
+        vector: queryEmbedding,
+        k: limit,
+        filter: options?.filter,
+        root: this.root
+      })
 
-      return results
-        .filter((r: any) => r._distance >= threshold)
-        .map((r: any) => ({
-          id: r.id,
-          filePath: r.filePath,
-          code: r.code,
-          score: 1 - r._distance, // Convert distance to similarity score
+      return hits
+        .filter(hit => hit.score >= threshold)
+        .map(hit => ({
+          id: `${hit.path}:${hit.startLine}`,
+          filePath: hit.path,
+          code: hit.text,
+          score: hit.score,
           metadata: {
-            language: r.language,
-            symbol: r.symbol,
-            line: r.line,
-            column: r.column,
-            type: r.type
+            language: hit.language ?? '',
+            symbol: hit.symbol ?? undefined,
+            line: hit.startLine,
+            type: (hit.kind ?? undefined) as CodeEmbedding['metadata']['type']
           }
         }))
     } catch (error) {
-      throw new Error(`Search failed: ${error}`)
+      throw new Error(`Search failed: ${JSON.stringify(error)}`)
     }
   }
 
+  /** Remove the embeddings of a file, or of every file under a directory */
   async deleteByFilePath(filePath: string): Promise<void> {
-    if (!this.table) {
-      await this.initialize()
-    }
-
     try {
-      // LanceDB delete operation
-      await this.table.delete(`filePath = '${filePath}'`)
+      await invoke<number>('vector_delete_file', { path: filePath })
     } catch (error) {
-      throw new Error(`Failed to delete embeddings: ${error}`)
+      throw new Error(`Failed to delete embeddings: ${JSON.stringify(error)}`)
     }
   }
 
   async clear(): Promise<void> {
-    if (!this.db) {
-      await this.initialize()
-    }
-
     try {
-      await this.db.dropTable('code_embeddings')
-      this.table = await this.db.createTable('code_embeddings', {
-        id: 'string',
-        filePath: 'string',
-        code: 'string',
-        embedding: this.embedder.getDimension(),
-        language: 'string',
-        symbol: 'string?',
-        line: 'int32',
-        column: 'int32?',
-        type: 'string?'
-      })
+      await invoke('vector_clear', { root: this.root })
     } catch (error) {
-      throw new Error(`Failed to clear database: ${error}`)
+      throw new Error(`Failed to clear database: ${JSON.stringify(error)}`)
     }
   }
 }
-
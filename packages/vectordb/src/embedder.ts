@@ -1,68 +1,56 @@
-import { pipeline } from '@xenova/transformers'
+import { invoke } from '@tauri-apps/api/core'
 import type { EmbeddingOptions } from './types'
 
+interface Embedded {
+  model: string
+  vectors: number[][]
+}
+
 /**
- * Code embedding generator using local transformers
- * Default: All-MiniLM-L6-v2 (384 dimensions, fast)
+ * Code embedding generator
+ * Runs in the desktop backend with the embedder configured in `.henryrc`:
+ * offline feature hashing by default, or a local Ollama model.
  */
 export class CodeEmbedder {
-  private model: any = null // Pipeline type from @xenova/transformers
-  private modelName: string = 'Xenova/all-MiniLM-L6-v2'
+  private model: string | null = null
+  private dimension = 0
+  private root?: string
+
+  /** `root` picks the workspace whose `.henryrc` configures the embedder */
+  constructor(root?: string) {
+    this.root = root
+  }
 
   async initialize(_options?: EmbeddingOptions): Promise<void> {
     if (this.model) return
-
-    try {
-      this.model = await pipeline(
-        'feature-extraction',
-        this.modelName,
-        {
-          quantized: true // Use quantized model for faster loading
-        } as any // Type assertion to bypass device option type issue
-      )
-    } catch (error) {
-      throw new Error(`Failed to load embedding model: ${error}`)
-    }
+    await this.embedBatch([''])
   }
 
   async embed(code: string): Promise<number[]> {
-    if (!this.model) {
-      await this.initialize()
-    }
-
-    if (!this.model) {
-      throw new Error('Embedding model not initialized')
-    }
+    const [vector] = await this.embedBatch([code])
+    return vector
+  }
 
+  async embedBatch(codeSnippets: string[]): Promise<number[][]> {
     try {
-      const output = await this.model(code, {
-        pooling: 'mean',
-        normalize: true
+      const embedded = await invoke<Embedded>('embed_texts', {
+        texts: codeSnippets,
+        root: this.root
       })
-
-      // Extract embedding vector from output
-      return Array.from(output.data) as number[]
+      this.model = embedded.model
+      this.dimension = embedded.vectors[0]?.length ?? this.dimension
+      return embedded.vectors
     } catch (error) {
-      throw new Error(`Failed to generate embedding: ${error}`)
+      throw new Error(`Failed to generate embeddings: ${JSON.stringify(error)}`)
     }
   }
 
-  async embedBatch(codeSnippets: string[]): Promise<number[][]> {
-    if (!this.model) {
-      await this.initialize()
-    }
-
-    // Process in parallel for better performance
-    const embeddings = await Promise.all(
-      codeSnippets.map(code => this.embed(code))
-    )
-
-    return embeddings
+  /** Model the vectors come from, known after the first call */
+  getModel(): string | null {
+    return this.model
   }
 
   getDimension(): number {
-    // All-MiniLM-L6-v2 has 384 dimensions
-    return 384
+    return this.dimension
   }
 }
-